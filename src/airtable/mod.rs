@@ -0,0 +1,147 @@
+//! Appending a record per run to an Airtable table, a lightweight index of the archive that
+//! people can browse and filter without touching the output directory or Drive.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::console;
+use crate::crossword::{self, Download};
+
+const API_URL: &str = "https://api.airtable.com/v0";
+
+/// Environment variable read for the access token when the config doesn't set one
+pub const TOKEN_ENV: &str = "AIRTABLE_API_KEY";
+
+/// The `[airtable]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AirtableConfig {
+    /// Personal access token with `data.records:write` on the base; defaults to `AIRTABLE_API_KEY`
+    pub api_key: Option<String>,
+    /// Base ID, starting with `app`
+    pub base: String,
+    /// Table name or ID
+    pub table: String,
+}
+
+impl AirtableConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.base.starts_with("app") {
+            problems.push(format!("[airtable] base '{}' is not a base ID (app...)", self.base));
+        }
+        if self.table.is_empty() {
+            problems.push("[airtable] table is empty".to_string());
+        }
+        if self.api_key.is_none() && std::env::var(TOKEN_ENV).is_err() {
+            problems.push(format!("[airtable] needs an api_key or {}", TOKEN_ENV));
+        }
+        problems
+    }
+
+    fn api_key(&self) -> Result<String> {
+        match &self.api_key {
+            Some(key) => Ok(key.clone()),
+            None => std::env::var(TOKEN_ENV).with_context(|| format!("{} is not set", TOKEN_ENV)),
+        }
+    }
+}
+
+/// The fields of the run's record; Airtable creates missing select options thanks to `typecast`
+fn record_fields(date: NaiveDate, result: &Result<Download>, link: Option<String>) -> Value {
+    let mut fields = json!({
+        "Date": date.to_string(),
+        "Status": crossword::status(result),
+    });
+    match result {
+        Ok(download) => {
+            let file = Path::new(&download.filename).file_name().unwrap_or_default();
+            fields["File"] = json!(file.to_string_lossy());
+            fields["Page"] = json!(download.page);
+        }
+        Err(e) => fields["Error"] = json!(crate::error::hints::describe(e)),
+    }
+    if let Some(link) = link {
+        fields["Link"] = json!(link);
+    }
+    fields
+}
+
+/// Appends a record for the run of `date`
+pub async fn append(config: &Config, airtable: &AirtableConfig, date: NaiveDate, result: &Result<Download>) -> Result<()> {
+    let body = json!({
+        "records": [{ "fields": record_fields(date, result, crossword::link(config, date, result)) }],
+        "typecast": true,
+    });
+    reqwest::Client::new()
+        .post(format!("{}/{}/{}", API_URL, airtable.base, airtable.table))
+        .bearer_auth(airtable.api_key()?)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .context("Failed to reach Airtable")?
+        .error_for_status()
+        .context("Airtable refused the record")?;
+    console::progress!("Appended {} to Airtable table {}", date, airtable.table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_record_fields() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let download = Download {
+            filename: "/tmp/crossword_2024-03-20.jpg".to_string(),
+            mime_type: "image/jpeg",
+            page: 2,
+            crossword_url: String::new(),
+            image_url: String::new(),
+            comparison: None,
+            timings: Vec::new(),
+            link: None,
+            uploads: BTreeMap::new(),
+            puzzle: Default::default(),
+            confidence: None,
+            needs_review: false,
+            storage: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let link = Some("https://drive.google.com/file/d/file-id/view".to_string());
+        assert_eq!(
+            record_fields(date, &Ok(download), link),
+            json!({
+                "Date": "2024-03-20",
+                "Status": "Downloaded",
+                "File": "crossword_2024-03-20.jpg",
+                "Page": 2,
+                "Link": "https://drive.google.com/file/d/file-id/view",
+            })
+        );
+
+        let fields = record_fields(date, &Err(anyhow::anyhow!("Could not find crossword")), None);
+        assert_eq!(
+            fields,
+            json!({ "Date": "2024-03-20", "Status": "Failed", "Error": "Could not find crossword" })
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let airtable: AirtableConfig = toml::from_str(
+            r#"api_key = "patXXXX"
+base = "Crosswords"
+table = "Runs""#,
+        )
+        .unwrap();
+        assert_eq!(airtable.validate(), vec!["[airtable] base 'Crosswords' is not a base ID (app...)"]);
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use chrono::{Datelike, Months, NaiveDate};
+use clap::ValueEnum;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::cache::ImageCache;
+use crate::config::Config;
+use crate::console;
+use crate::imaging;
+use crate::naming;
+use crate::pipeline::{self, Artifact, Sink};
+use crate::storage;
+use crate::types::PuzzleInfo;
+
+pub mod pdf;
+
+/// Container a month of crosswords is compiled into
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ArchiveFormat {
+    Zip,
+    Pdf,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Pdf => "pdf",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::Pdf => "application/pdf",
+        }
+    }
+}
+
+/// Bundles every crossword of `month` found in the output directory into one file
+/// and uploads it to every sink, returning the archive's path
+pub async fn compile_month(
+    config: &Config,
+    month: NaiveDate,
+    format: ArchiveFormat,
+    sinks: Vec<Arc<dyn Sink>>,
+) -> Result<PathBuf> {
+    let files = month_files(config, month)?;
+    if files.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No crosswords for {} found in {}",
+            month.format("%Y-%m"),
+            config.output_dir.display()
+        ));
+    }
+    console::progress!("Compiling {} crosswords for {}", files.len(), month.format("%B %Y"));
+
+    let path = config
+        .output_dir
+        .join(format!("crosswords_{}.{}", month.format("%Y-%m"), format.extension()));
+    match format {
+        ArchiveFormat::Zip => {
+            let entries = files
+                .iter()
+                .map(|file| {
+                    let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    Ok((name, fs::read(file)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            write_zip(&path, &entries)?;
+        }
+        ArchiveFormat::Pdf => {
+            let pages = files.iter().map(fs::read).collect::<std::io::Result<Vec<_>>>()?;
+            storage::write_atomic(&path, &pdf::from_images(&pages)?)?;
+        }
+    }
+    console::progress!("Archive written to {}", path.display());
+
+    let artifact = Artifact::new(path.to_string_lossy(), format.mime_type()).dated(month);
+    pipeline::run(artifact, Vec::new(), sinks).await?;
+
+    Ok(path)
+}
+
+/// The saved crossword of each day of the month, in date order, skipping missing days.
+/// Days missing from the output directory are put back from the image cache first.
+fn month_files(config: &Config, month: NaiveDate) -> Result<Vec<PathBuf>> {
+    let first = month.with_day(1).context("Invalid month")?;
+    let next = first + Months::new(1);
+
+    let mut files = Vec::new();
+    for date in first.iter_days().take_while(|date| *date < next) {
+        files.extend(archive(config, date)?);
+    }
+    Ok(files)
+}
+
+/// Makes sure the crossword for `date` is saved in the output directory, writing it back
+/// from the image cache when it was renamed or deleted. Returns where it is, or `None` when
+/// neither has it.
+pub fn archive(config: &Config, date: NaiveDate) -> Result<Option<PathBuf>> {
+    if let Some(path) = archived_file(config, date)? {
+        return Ok(Some(path));
+    }
+
+    // A renamed or deleted copy comes back from the image cache rather than the site
+    let locale = naming::parse_locale(&config.locale)?;
+    let cache = ImageCache::open(&config.output_dir)?;
+    if let Some((object, image)) = cache.get("crossword", date) {
+        let extension = imaging::extension(&image.mime_type);
+        let name = naming::render_puzzle_filename(&config.filename_template, locale, date, extension, &image.puzzle)?;
+        let path = config.output_dir.join(name);
+        let data = fs::read(&object).with_context(|| format!("Failed to read {}", object.display()))?;
+        storage::save(&path, &data, &config.permissions)?;
+        console::progress!("Restored {} from the image cache", path.display());
+        return Ok(Some(path));
+    }
+    Ok(None)
+}
+
+/// The crossword saved for `date` in the output directory under its usual name, in whatever
+/// format it came. Only looks; see [`archive`] to restore a missing one.
+pub fn archived_file(config: &Config, date: NaiveDate) -> Result<Option<PathBuf>> {
+    let locale = naming::parse_locale(&config.locale)?;
+    // A name with the puzzle number in it has the number read back when it was saved
+    let puzzle = match config.filename_template.contains('{') {
+        true => ImageCache::open(&config.output_dir)?
+            .entry("crossword", date)
+            .map(|image| image.puzzle.clone())
+            .unwrap_or_default(),
+        false => PuzzleInfo::default(),
+    };
+    for (_, extension) in imaging::EXTENSIONS {
+        let name = naming::render_puzzle_filename(&config.filename_template, locale, date, extension, &puzzle)?;
+        let path = config.output_dir.join(name);
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Writes named in-memory files into a new zip archive
+pub fn write_zip(path: &Path, entries: &[(String, Vec<u8>)]) -> Result<()> {
+    let file = storage::temp_file(path)?;
+    let mut zip = ZipWriter::new(file);
+    for (name, contents) in entries {
+        zip.start_file(name.as_str(), SimpleFileOptions::default())?;
+        zip.write_all(contents)?;
+    }
+    storage::persist(zip.finish()?, path)
+}
+
+/// Re-encodes any image as a baseline RGB JPEG
+fn to_jpeg(data: &[u8]) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data).context("Failed to decode crossword image")?;
+    let mut jpeg = Vec::new();
+    image.to_rgb8().write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)?;
+    Ok(jpeg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use zip::ZipArchive;
+
+    fn png() -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(4, 6, Rgb([200, 10, 10]))
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn config(dir: &Path) -> Config {
+        Config {
+            output_dir: dir.to_path_buf(),
+            ..Config::default()
+        }
+    }
+
+    fn save(dir: &Path, name: &str) {
+        fs::write(dir.join(name), png()).unwrap();
+    }
+
+    #[test]
+    fn test_month_files_in_date_order() {
+        let dir = tempfile::tempdir().unwrap();
+        save(dir.path(), "crossword_2024-03-20.jpg");
+        save(dir.path(), "crossword_2024-03-02.png");
+        save(dir.path(), "crossword_2024-04-01.jpg");
+        save(dir.path(), "crossword_2024-02-29.jpg");
+
+        let files = month_files(&config(dir.path()), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()).unwrap();
+
+        assert_eq!(
+            files,
+            vec![dir.path().join("crossword_2024-03-02.png"), dir.path().join("crossword_2024-03-20.jpg")]
+        );
+    }
+
+    #[test]
+    fn test_archive_restores_from_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date, b"\x89PNG puzzle", "image/png").unwrap();
+
+        // e.g. deleted to save space, or saved under an older filename template; looking
+        // doesn't write it back
+        assert!(archived_file(&config(dir.path()), date).unwrap().is_none());
+        let path = archive(&config(dir.path()), date).unwrap().unwrap();
+
+        assert_eq!(path, dir.path().join("crossword_2024-03-20.png"));
+        assert_eq!(fs::read(&path).unwrap(), b"\x89PNG puzzle");
+        assert_eq!(archived_file(&config(dir.path()), date).unwrap(), Some(path));
+        assert!(archive(&config(dir.path()), date.succ_opt().unwrap()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compile_month_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        save(dir.path(), "crossword_2024-03-01.jpg");
+        save(dir.path(), "crossword_2024-03-31.jpg");
+
+        let month = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let path = compile_month(&config(dir.path()), month, ArchiveFormat::Zip, Vec::new()).await.unwrap();
+
+        assert_eq!(path, dir.path().join("crosswords_2024-03.zip"));
+        let archive = ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec!["crossword_2024-03-01.jpg", "crossword_2024-03-31.jpg"]);
+    }
+
+    #[tokio::test]
+    async fn test_compile_month_pdf() {
+        let dir = tempfile::tempdir().unwrap();
+        save(dir.path(), "crossword_2024-03-01.png");
+        save(dir.path(), "crossword_2024-03-02.png");
+
+        let month = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let path = compile_month(&config(dir.path()), month, ArchiveFormat::Pdf, Vec::new()).await.unwrap();
+
+        let pdf = fs::read(&path).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(String::from_utf8_lossy(&pdf).contains("/Count 2"));
+    }
+
+    #[tokio::test]
+    async fn test_compile_empty_month() {
+        let dir = tempfile::tempdir().unwrap();
+        let month = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+
+        let error = compile_month(&config(dir.path()), month, ArchiveFormat::Zip, Vec::new()).await.unwrap_err();
+
+        assert!(error.to_string().contains("No crosswords for 2024-03"));
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::imaging::{self, metadata};
+use crate::types::PuzzleInfo;
+
+/// One image per page, each page sized to its image at 72 dpi.
+///
+/// PDF readers decode JPEG natively (`DCTDecode`), so JPEG scans are embedded as-is
+/// and other formats are converted to JPEG first.
+pub fn from_images(images: &[Vec<u8>]) -> Result<Vec<u8>> {
+    with_puzzle(images, &PuzzleInfo::default())
+}
+
+/// Like [`from_images`], with the puzzle's number as the document's title and its setter as
+/// the author, when they are known
+pub fn with_puzzle(images: &[Vec<u8>], puzzle: &PuzzleInfo) -> Result<Vec<u8>> {
+    let mut pdf = Writer::default();
+    pdf.buffer.extend_from_slice(b"%PDF-1.4\n");
+
+    // Objects 1 and 2 are the catalog and page tree; each page takes three more
+    let page_ids: Vec<usize> = (0..images.len()).map(|i| 3 + i * 3).collect();
+    pdf.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+    pdf.object(
+        2,
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), images.len()).as_bytes(),
+    );
+
+    for (data, page_id) in images.iter().zip(page_ids) {
+        let jpeg = match imaging::sniff_mime_type(data) {
+            Some("image/jpeg") => data.clone(),
+            _ => super::to_jpeg(data)?,
+        };
+        let decoded = image::load_from_memory(&jpeg)?;
+        let (width, height) = (decoded.width(), decoded.height());
+        let color_space = match decoded.color() {
+            image::ColorType::L8 => "/DeviceGray",
+            _ => "/DeviceRGB",
+        };
+        let (contents_id, image_id) = (page_id + 1, page_id + 2);
+
+        pdf.object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                width, height, image_id, contents_id
+            )
+            .as_bytes(),
+        );
+        pdf.stream(contents_id, "", format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", width, height).as_bytes());
+        pdf.stream(
+            image_id,
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8 /Filter /DCTDecode",
+                width, height, color_space
+            ),
+            &jpeg,
+        );
+    }
+
+    let mut info = Vec::new();
+    if let Some(title) = metadata::title(puzzle) {
+        info.push(format!("/Title {}", text_string(&title)));
+    }
+    if let Some(setter) = &puzzle.setter {
+        info.push(format!("/Author {}", text_string(setter)));
+    }
+    if !info.is_empty() {
+        let info_id = 3 + images.len() * 3;
+        pdf.object(info_id, format!("<< {} >>", info.join(" ")).as_bytes());
+        pdf.info = Some(info_id);
+    }
+
+    Ok(pdf.finish())
+}
+
+/// `text` as a hex string in UTF-16, which takes any character without escaping
+fn text_string(text: &str) -> String {
+    let hex: String = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect();
+    format!("<FEFF{}>", hex)
+}
+
+#[derive(Default)]
+struct Writer {
+    buffer: Vec<u8>,
+    /// Object number and byte offset of each object written so far
+    offsets: Vec<(usize, usize)>,
+    /// Object number of the document information, if any
+    info: Option<usize>,
+}
+
+impl Writer {
+    fn object(&mut self, id: usize, body: &[u8]) {
+        self.offsets.push((id, self.buffer.len()));
+        writeln!(self.buffer, "{} 0 obj", id).unwrap();
+        self.buffer.extend_from_slice(body);
+        self.buffer.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        let mut body = format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.object(id, &body);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.offsets.sort();
+        let xref = self.buffer.len();
+        write!(self.buffer, "xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1).unwrap();
+        for (_, offset) in &self.offsets {
+            writeln!(self.buffer, "{:010} 00000 n ", offset).unwrap();
+        }
+        write!(
+            self.buffer,
+            "trailer\n<< /Size {} /Root 1 0 R{} >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            self.info.map(|id| format!(" /Info {} 0 R", id)).unwrap_or_default(),
+            xref
+        )
+        .unwrap();
+        self.buffer
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::archive;
+use crate::cache::ImageCache;
+use crate::clock::{ist, Clock};
+use crate::config::Config;
+use crate::console;
+use crate::crossword::{self, pending::PendingUploads, Region};
+use crate::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::pipeline::Sink;
+use crate::status::DateRow;
+use crate::storage;
+
+/// File in the output directory recording how each backfilled date went
+const STATE_FILE: &str = ".backfill-state.json";
+
+/// Limits on how hard a backfill may hit the site, so it can run unattended over several nights
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackfillConfig {
+    /// Requests allowed in any rolling hour
+    pub max_requests_per_hour: Option<u32>,
+    /// Time of day requests may be made, in IST, e.g. `"02:00-05:00"`
+    pub window: Option<TimeWindow>,
+}
+
+impl BackfillConfig {
+    pub fn validate(&self) -> Vec<String> {
+        match self.max_requests_per_hour {
+            Some(0) => vec!["backfill.max_requests_per_hour must be at least 1".to_string()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// When a backfill started at `now` can make its first request: once the window opens
+    pub fn first_request(&self, now: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let wait = self.window.map_or(Duration::ZERO, |window| window.wait(now));
+        now + TimeDelta::from_std(wait).unwrap_or_default()
+    }
+}
+
+/// A daily window such as `02:00-05:00`; it may wrap past midnight, as in `22:00-05:00`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long from `now` until the window opens; zero while it is open
+    fn wait(&self, now: DateTime<FixedOffset>) -> Duration {
+        let local = now.with_timezone(&ist()).naive_local();
+        if self.contains(local.time()) {
+            return Duration::ZERO;
+        }
+
+        let mut opens = local.date().and_time(self.start);
+        if opens <= local {
+            opens += TimeDelta::days(1);
+        }
+        (opens - local).to_std().unwrap_or_default()
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid time window '{}'; use HH:MM-HH:MM, e.g. 02:00-05:00", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid());
+        let window = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!("Time window '{}' is empty", s));
+        }
+        Ok(window)
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        window.to_string()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// Wraps a client to hold requests back until the backfill window is open and the
+/// hourly budget has room, sleeping as long as it takes
+pub struct Throttle<C> {
+    inner: C,
+    settings: BackfillConfig,
+    clock: Arc<dyn Clock>,
+    // Held while waiting, so queued requests go out one at a time
+    sent: Mutex<VecDeque<DateTime<FixedOffset>>>,
+}
+
+impl<C: HttpClient> Throttle<C> {
+    pub fn new(inner: C, settings: BackfillConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            settings,
+            clock,
+            sent: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+/// How long to hold the next request so it lands inside the window and within the hourly budget
+fn delay(settings: &BackfillConfig, sent: &VecDeque<DateTime<FixedOffset>>, now: DateTime<FixedOffset>) -> Duration {
+    let window = settings.window.map(|window| window.wait(now)).unwrap_or_default();
+
+    let budget = match settings.max_requests_per_hour {
+        Some(limit) if sent.len() >= limit as usize => {
+            // The oldest request still counting against the budget frees a slot an hour after it was sent
+            let oldest = sent[sent.len() - limit as usize];
+            (oldest + TimeDelta::hours(1) - now).to_std().unwrap_or_default()
+        }
+        _ => Duration::ZERO,
+    };
+
+    window.max(budget)
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Throttle<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let mut sent = self.sent.lock().await;
+        loop {
+            let now = self.clock.now();
+            while sent.front().is_some_and(|&time| now - time >= TimeDelta::hours(1)) {
+                sent.pop_front();
+            }
+
+            let wait = delay(&self.settings, &sent, now);
+            if wait.is_zero() {
+                sent.push_back(now);
+                break;
+            }
+            console::progress!("Backfill paused for {} minutes", wait.as_secs().div_ceil(60));
+            tokio::time::sleep(wait).await;
+        }
+        drop(sent);
+
+        self.inner.send(request).await
+    }
+}
+
+/// How a backfilled date went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Downloaded,
+    Failed,
+    /// Failed, then taken out of the queue by hand so backfills leave it be
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DateState {
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// Every date a backfill has tried, saved after each one so an interrupted backfill resumes
+/// with the dates it hadn't finished, even once the earlier downloads have left the output
+/// directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackfillState {
+    pub dates: BTreeMap<NaiveDate, DateState>,
+}
+
+impl BackfillState {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(STATE_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse backfill state {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read backfill state {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("Failed to write backfill state {}", path.display()))
+    }
+
+    fn cancelled(&self, date: NaiveDate) -> bool {
+        self.dates.get(&date).is_some_and(|state| state.outcome == Outcome::Cancelled)
+    }
+
+    fn downloaded(&self, date: NaiveDate) -> bool {
+        self.dates.get(&date).is_some_and(|state| state.outcome == Outcome::Downloaded)
+    }
+
+    fn record(&mut self, date: NaiveDate, outcome: Outcome, error: Option<String>, at: DateTime<Utc>) {
+        self.dates.insert(date, DateState { outcome, error, at });
+    }
+}
+
+/// What a backfill did with each date of its range
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: Vec<(NaiveDate, String)>,
+    /// Every date of the range, in order
+    pub rows: Vec<DateRow>,
+}
+
+/// Downloads every date from `to` back to `from` that isn't in the output directory yet and
+/// wasn't downloaded by an earlier backfill, newest first. Days the `[publication]` calendar
+/// has no crossword on are passed over.
+///
+/// A failed date doesn't stop the run; running the same range again retries only what is missing.
+pub async fn run(
+    client: &dyn HttpClient,
+    from: NaiveDate,
+    to: NaiveDate,
+    config: &Config,
+    sinks: Vec<Arc<dyn Sink>>,
+) -> Result<Summary> {
+    // Saved dates whose uploads failed still need their run to retry them
+    let pending = PendingUploads::load(&PendingUploads::path(&config.output_dir))?;
+    let state_path = BackfillState::path(&config.output_dir);
+    let mut state = BackfillState::load(&state_path)?;
+    let done = state.dates.range(from..=to).filter(|(_, date)| date.outcome == Outcome::Downloaded).count();
+    if done > 0 {
+        console::progress!("Resuming the backfill: {} dates were already downloaded", done);
+    }
+
+    let mut summary = Summary::default();
+    for date in to.iter_days().rev().take_while(|date| *date >= from) {
+        if let Some(reason) = config.publication.skipped(date, config.puzzle) {
+            tracing::info!("Skipping {}: {}", date, reason);
+            summary.skipped += 1;
+            summary.rows.push(DateRow::new(&ImageCache::open(&config.output_dir)?, date, "no crossword", None));
+            continue;
+        }
+        if state.cancelled(date) {
+            summary.skipped += 1;
+            summary.rows.push(DateRow::new(&ImageCache::open(&config.output_dir)?, date, "cancelled", None));
+            continue;
+        }
+        if !pending.contains(Region::crossword(config).name, date) {
+            if let Some(file) = archive::archive(config, date)? {
+                summary.skipped += 1;
+                summary.rows.push(DateRow::new(&ImageCache::open(&config.output_dir)?, date, "archived", Some(&file)));
+                continue;
+            }
+            if state.downloaded(date) {
+                summary.skipped += 1;
+                summary.rows.push(DateRow::new(&ImageCache::open(&config.output_dir)?, date, "backfilled", None));
+                continue;
+            }
+        }
+
+        let result = crossword::download_crossword(client, date, config, sinks.clone()).await;
+        let at = config.clock.now().with_timezone(&Utc);
+        let cache = ImageCache::open(&config.output_dir)?;
+        match result {
+            Ok(download) => {
+                state.record(date, Outcome::Downloaded, None, at);
+                summary.downloaded += 1;
+                let row = DateRow::new(&cache, date, "downloaded", Some(Path::new(&download.filename)));
+                summary.rows.push(DateRow {
+                    page: Some(download.page),
+                    destination: download.link.or(row.destination),
+                    ..row
+                });
+            }
+            Err(e) => {
+                tracing::warn!("Backfill of {} failed: {:#}", date, e);
+                state.record(date, Outcome::Failed, Some(format!("{:#}", e)), at);
+                summary.failed.push((date, format!("{:#}", e)));
+                summary.rows.push(DateRow {
+                    error: Some(format!("{:#}", e)),
+                    ..DateRow::new(&cache, date, "failed", None)
+                });
+            }
+        }
+        state.save(&state_path)?;
+    }
+    summary.failed.reverse();
+    summary.rows.reverse();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::http::vcr::Replayer;
+
+    fn at(timestamp: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap()
+    }
+
+    fn window(s: &str) -> BackfillConfig {
+        BackfillConfig {
+            window: Some(s.parse().unwrap()),
+            ..BackfillConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_time_window() {
+        let window: TimeWindow = "02:00-05:00".parse().unwrap();
+        assert_eq!(window.to_string(), "02:00-05:00");
+        assert!("02:00".parse::<TimeWindow>().unwrap_err().contains("HH:MM-HH:MM"));
+        assert!("2am-5am".parse::<TimeWindow>().is_err());
+        assert!("03:00-03:00".parse::<TimeWindow>().unwrap_err().contains("empty"));
+
+        let config: BackfillConfig = toml::from_str("window = \"22:30-05:00\"").unwrap();
+        assert_eq!(config.window.unwrap().to_string(), "22:30-05:00");
+        assert!(toml::from_str::<BackfillConfig>("window = \"nightly\"").is_err());
+    }
+
+    #[test]
+    fn test_window_delay_in_ist() {
+        let settings = window("02:00-05:00");
+        let sent = VecDeque::new();
+
+        assert_eq!(delay(&settings, &sent, at("2024-03-20T03:00:00+05:30")), Duration::ZERO);
+        // 20:00 UTC is 01:30 IST the next morning
+        assert_eq!(delay(&settings, &sent, at("2024-03-20T20:00:00+00:00")), Duration::from_secs(30 * 60));
+        // Once it has closed, wait for tomorrow's
+        assert_eq!(delay(&settings, &sent, at("2024-03-20T05:00:00+05:30")), Duration::from_secs(21 * 3600));
+    }
+
+    #[test]
+    fn test_window_wrapping_midnight() {
+        let settings = window("22:00-02:00");
+        let sent = VecDeque::new();
+
+        assert_eq!(delay(&settings, &sent, at("2024-03-20T23:30:00+05:30")), Duration::ZERO);
+        assert_eq!(delay(&settings, &sent, at("2024-03-21T01:00:00+05:30")), Duration::ZERO);
+        assert_eq!(delay(&settings, &sent, at("2024-03-21T12:00:00+05:30")), Duration::from_secs(10 * 3600));
+    }
+
+    #[test]
+    fn test_hourly_budget_delay() {
+        let settings = BackfillConfig {
+            max_requests_per_hour: Some(2),
+            ..BackfillConfig::default()
+        };
+        let sent: VecDeque<_> = [at("2024-03-20T02:00:00+05:30"), at("2024-03-20T02:20:00+05:30")].into();
+
+        assert_eq!(delay(&settings, &sent, at("2024-03-20T02:30:00+05:30")), Duration::from_secs(30 * 60));
+        assert_eq!(delay(&settings, &VecDeque::from([sent[1]]), at("2024-03-20T02:30:00+05:30")), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_throttle_passes_requests_within_limits() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_slice_2024-03-20.json");
+        let settings = BackfillConfig {
+            max_requests_per_hour: Some(100),
+            window: Some("02:00-05:00".parse().unwrap()),
+        };
+        let throttle = Throttle::new(
+            Replayer::load(&fixture).unwrap(),
+            settings,
+            Arc::new(FixedClock::at("2024-03-20T03:00:00+05:30")),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+
+        // The day before has no recording, so it fails without stopping the run
+        let summary = run(&throttle, date.pred_opt().unwrap(), date, &config, Vec::new()).await.unwrap();
+        assert_eq!(summary.downloaded, 1);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, date.pred_opt().unwrap());
+        assert_eq!(throttle.sent.lock().await.len(), 5);
+        let statuses: Vec<&str> = summary.rows.iter().map(|row| row.status).collect();
+        assert_eq!(statuses, vec!["failed", "downloaded"]);
+        assert_eq!(summary.rows[1].page, Some(2));
+
+        // Dates already in the output directory are skipped
+        let summary = run(&throttle, date, date, &config, Vec::new()).await.unwrap();
+        assert_eq!((summary.downloaded, summary.skipped), (0, 1));
+
+        // Every date tried is recorded, so a later backfill skips what was downloaded even
+        // once it has left the output directory, and retries only the failure
+        let state = BackfillState::load(&BackfillState::path(dir.path())).unwrap();
+        assert_eq!(state.dates[&date].outcome, Outcome::Downloaded);
+        assert_eq!(state.dates[&date.pred_opt().unwrap()].outcome, Outcome::Failed);
+        fs::remove_file(dir.path().join("crossword_2024-03-20.jpg")).unwrap();
+        fs::remove_dir_all(dir.path().join(".cache")).unwrap();
+        let summary = run(&throttle, date.pred_opt().unwrap(), date, &config, Vec::new()).await.unwrap();
+        assert_eq!((summary.downloaded, summary.skipped, summary.failed.len()), (0, 1, 1));
+        assert_eq!(summary.rows[1].status, "backfilled");
+
+        // A day without a crossword isn't tried at all
+        let sent = throttle.sent.lock().await.len();
+        let mut config = config;
+        config.publication.no_crossword_on = vec![chrono::Weekday::Tue];
+        let summary = run(&throttle, date.pred_opt().unwrap(), date, &config, Vec::new()).await.unwrap();
+        assert_eq!((summary.skipped, summary.failed.len()), (2, 0));
+        assert_eq!(summary.rows[0].status, "no crossword");
+        assert_eq!(throttle.sent.lock().await.len(), sent);
+    }
+}
@@ -0,0 +1,302 @@
+//! Browsing the archive in a terminal UI: every archived date from the manifest with what is
+//! known about it, and keys to download it again, upload it again, open or preview it. Meant for
+//! managing the archive over SSH on a headless box. Needs the `tui` feature.
+
+// Without the terminal the model is only exercised by its tests
+#![cfg_attr(not(feature = "tui"), allow(dead_code))]
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::sync::Arc;
+
+use crate::clock::SystemClock;
+use crate::config::Config;
+use crate::crossword::{self, pending::PendingUploads, Region};
+use crate::http;
+use crate::imaging::preview::{self, Protocol};
+use crate::learning::History;
+use crate::signed_url;
+use crate::status::export::{self, ManifestEntry};
+
+#[cfg(feature = "tui")]
+mod terminal;
+
+/// One archived date with what the other state files know about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowseEntry {
+    pub manifest: ManifestEntry,
+    pub status: &'static str,
+    pub page: Option<u32>,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+/// A key, as far as the browser cares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Esc,
+    Char(char),
+}
+
+/// Something to do outside the UI, picked with a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Redownload(NaiveDate),
+    Reupload(NaiveDate),
+    /// The uploaded copy's link, or the saved file
+    Open(String),
+    /// Shows the saved file in the terminal
+    Preview(String),
+    Quit,
+}
+
+/// Rows a page key moves by
+const PAGE: usize = 10;
+
+/// The list of archived dates, newest first, and which one is selected
+pub struct Browser {
+    pub entries: Vec<BrowseEntry>,
+    pub selected: usize,
+    /// Outcome of the last action, shown below the list
+    pub message: Option<String>,
+}
+
+impl Browser {
+    pub fn load(config: &Config) -> Result<Self> {
+        Ok(Self {
+            entries: entries(config)?,
+            selected: 0,
+            message: None,
+        })
+    }
+
+    /// Reads the state files again, keeping the same date selected when it is still there
+    pub fn reload(&mut self, config: &Config) -> Result<()> {
+        let date = self.current().map(|entry| entry.manifest.date);
+        self.entries = entries(config)?;
+        self.selected = date
+            .and_then(|date| self.entries.iter().position(|entry| entry.manifest.date == date))
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    pub fn current(&self) -> Option<&BrowseEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Moves the selection, or returns the action the key stands for
+    pub fn key(&mut self, key: Key) -> Option<Action> {
+        let last = self.entries.len().saturating_sub(1);
+        match key {
+            Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(PAGE),
+            Key::PageDown => self.selected = (self.selected + PAGE).min(last),
+            Key::Home | Key::Char('g') => self.selected = 0,
+            Key::End | Key::Char('G') => self.selected = last,
+            Key::Esc | Key::Char('q') => return Some(Action::Quit),
+            Key::Char('d') => return self.current().map(|entry| Action::Redownload(entry.manifest.date)),
+            Key::Char('u') => return self.current().map(|entry| Action::Reupload(entry.manifest.date)),
+            Key::Char('o') => {
+                let entry = self.current()?;
+                let target = entry.manifest.link.clone().or_else(|| entry.manifest.file.clone());
+                if target.is_none() {
+                    self.message = Some(format!("{} has no upload or saved file to open", entry.manifest.date));
+                }
+                return target.map(Action::Open);
+            }
+            Key::Char('p') => {
+                let entry = self.current()?;
+                let file = entry.manifest.file.clone();
+                if file.is_none() {
+                    self.message = Some(format!("{} has no saved file to preview", entry.manifest.date));
+                }
+                return file.map(Action::Preview);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Label and value of everything known about the selected date
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        let Some(entry) = self.current() else {
+            return Vec::new();
+        };
+        let manifest = &entry.manifest;
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        vec![
+            ("Date", manifest.date.format("%A, %d %B %Y").to_string()),
+            ("Status", entry.status.to_string()),
+            ("File", or_none(manifest.file.clone())),
+            ("Size", or_none(manifest.size.map(|size| format!("{} bytes", size)))),
+            ("Type", manifest.mime_type.clone()),
+            ("SHA-256", manifest.sha256.clone()),
+            ("Dimensions", or_none(entry.dimensions.map(|(width, height)| format!("{}x{}", width, height)))),
+            ("Page", or_none(entry.page.map(|page| page.to_string()))),
+            ("Puzzle", or_none(manifest.number.map(|number| format!("No. {}", number)))),
+            ("Setter", or_none(manifest.setter.clone())),
+            ("Uploaded to", or_none((!manifest.uploaded_to.is_empty()).then(|| manifest.uploaded_to.join(", ")))),
+            ("Link", or_none(manifest.link.clone())),
+        ]
+    }
+}
+
+/// Every archived date, newest first
+fn entries(config: &Config) -> Result<Vec<BrowseEntry>> {
+    let pending = PendingUploads::load(&PendingUploads::path(&config.output_dir))?;
+    let history = History::load(&History::path(&config.output_dir))?;
+    let matches = history.regions.get("crossword").map(Vec::as_slice).unwrap_or_default();
+
+    let mut entries: Vec<BrowseEntry> = export::manifest(config)?
+        .into_iter()
+        .map(|manifest| {
+            let status = if pending.contains(Region::crossword(config).name, manifest.date) {
+                "pending upload"
+            } else if manifest.file.is_some() {
+                "archived"
+            } else {
+                "cached"
+            };
+            let record = matches.iter().find(|record| record.date == manifest.date);
+            BrowseEntry {
+                status,
+                page: record.map(|record| record.page),
+                dimensions: record.and_then(|record| record.dimensions),
+                manifest,
+            }
+        })
+        .collect();
+    entries.reverse();
+    Ok(entries)
+}
+
+/// Runs an action picked in the browser, returning a line saying how it went
+pub async fn perform(action: &Action, config: &Config) -> Result<String> {
+    match action {
+        Action::Redownload(date) => {
+            config.validate()?;
+            let client = http::build_client(config, Arc::new(SystemClock))?;
+            let sinks = signed_url::sinks(config, false)?;
+            let download = crossword::download_crossword(client.as_ref(), *date, config, sinks).await?;
+            let mut message = format!("Downloaded {} again: {}", date, download.filename);
+            for warning in &download.warnings {
+                message.push_str(&format!(" (warning: {})", warning.message));
+            }
+            Ok(message)
+        }
+        Action::Reupload(date) => {
+            config.validate()?;
+            let sinks = signed_url::sinks(config, false)?;
+            let filename = crossword::repair(*date, config, sinks).await?;
+            Ok(format!("Uploaded {} again: {}", date, filename))
+        }
+        Action::Open(target) => {
+            open(target)?;
+            Ok(format!("Opened {}", target))
+        }
+        Action::Preview(file) => {
+            let data = std::fs::read(file).with_context(|| format!("Failed to read {}", file))?;
+            print!("{}", preview::render(&data, Protocol::Auto, preview::MAX_WIDTH)?);
+            Ok(file.clone())
+        }
+        Action::Quit => Ok(String::new()),
+    }
+}
+
+/// Hands a link or file to the desktop's opener; on a headless box that fails, and the
+/// error names the target so it can be copied instead
+fn open(target: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let status = std::process::Command::new(opener)
+        .arg(target)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        _ => anyhow::bail!("Could not open {} with {}; copy it from here instead", target, opener),
+    }
+}
+
+/// Opens the browser on the archive in the output directory
+#[cfg(feature = "tui")]
+pub async fn run(config: &Config) -> Result<()> {
+    terminal::run(Browser::load(config)?, config).await
+}
+
+#[cfg(not(feature = "tui"))]
+pub async fn run(_config: &Config) -> Result<()> {
+    anyhow::bail!("This build has no terminal UI; rebuild with `--features tui`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ImageCache;
+    use crate::learning::MatchRecord;
+    use crate::types::Rect;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_browse_archive() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), b"first", "image/jpeg").unwrap();
+        cache.store("crossword", date(21), b"second", "image/png").unwrap();
+        cache.record_upload("crossword", date(20), "drive", "file-id").unwrap();
+        fs::write(dir.path().join("crossword_2024-03-20.jpg"), b"first").unwrap();
+        let mut history = History::default();
+        history.record(
+            "crossword",
+            MatchRecord {
+                date: date(20),
+                page: 2,
+                rect: Rect { x1: 0, y1: 1625, x2: 1000, y2: 2775 },
+                dimensions: Some((1000, 1150)),
+            },
+        );
+        history.save(&History::path(dir.path())).unwrap();
+
+        let mut browser = Browser::load(&config).unwrap();
+        let dates: Vec<NaiveDate> = browser.entries.iter().map(|entry| entry.manifest.date).collect();
+        assert_eq!(dates, vec![date(21), date(20)]);
+        assert_eq!(browser.entries[0].status, "cached");
+
+        // Only in the cache, so there is nothing to open
+        assert_eq!(browser.key(Key::Char('o')), None);
+        assert!(browser.message.as_deref().unwrap().contains("no upload or saved file"));
+
+        assert_eq!(browser.key(Key::PageDown), None);
+        assert_eq!(browser.selected, 1);
+        assert_eq!(browser.key(Key::Char('d')), Some(Action::Redownload(date(20))));
+        assert_eq!(browser.key(Key::Char('u')), Some(Action::Reupload(date(20))));
+        let saved = dir.path().join("crossword_2024-03-20.jpg").display().to_string();
+        assert_eq!(browser.key(Key::Char('p')), Some(Action::Preview(saved)));
+        assert_eq!(
+            browser.key(Key::Char('o')),
+            Some(Action::Open("https://drive.google.com/file/d/file-id/view".to_string()))
+        );
+        let details = browser.details();
+        assert!(details.contains(&("Status", "archived".to_string())));
+        assert!(details.contains(&("Dimensions", "1000x1150".to_string())));
+        assert!(details.contains(&("Page", "2".to_string())));
+
+        cache.store("crossword", date(22), b"third", "image/png").unwrap();
+        browser.reload(&config).unwrap();
+        assert_eq!(browser.entries.len(), 3);
+        assert_eq!(browser.current().unwrap().manifest.date, date(20));
+        assert_eq!(browser.key(Key::Char('q')), Some(Action::Quit));
+    }
+}
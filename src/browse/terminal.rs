@@ -0,0 +1,105 @@
+//! Drawing the browser with ratatui and feeding it keys from crossterm
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::{self, BufRead, Write};
+
+use super::{perform, Action, Browser, Key};
+use crate::config::Config;
+use crate::error::hints;
+
+const HELP: &str = "↑↓ move  d download again  u upload again  o open  p preview  q quit";
+
+pub async fn run(mut browser: Browser, config: &Config) -> Result<()> {
+    loop {
+        let mut terminal = ratatui::init();
+        let action = pick(&mut terminal, &mut browser);
+        // Leave the alternate screen before anything else can print, even on errors
+        ratatui::restore();
+        let action = match action? {
+            Action::Quit => return Ok(()),
+            action => action,
+        };
+
+        // Actions print their progress like the other commands, so run them on the normal screen
+        browser.message = Some(match perform(&action, config).await {
+            Ok(message) => message,
+            Err(e) => hints::describe(&e).replace('\n', " "),
+        });
+        println!("{}", browser.message.as_deref().unwrap_or_default());
+        print!("Press Enter to go back to the archive");
+        io::stdout().flush()?;
+        io::stdin().lock().read_line(&mut String::new())?;
+        browser.reload(config)?;
+    }
+}
+
+/// Draws the browser and handles keys until one stands for an action
+fn pick(terminal: &mut DefaultTerminal, browser: &mut Browser) -> Result<Action> {
+    loop {
+        terminal.draw(|frame| draw(frame, browser))?;
+        // A terminal UI owns the thread anyway; let the runtime move other tasks off it
+        let event = tokio::task::block_in_place(event::read)?;
+        let Event::Key(key) = event else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let key = match key.code {
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Esc => Key::Esc,
+            KeyCode::Char(c) => Key::Char(c),
+            _ => continue,
+        };
+        if let Some(action) = browser.key(key) {
+            return Ok(action);
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, browser: &Browser) {
+    let [main, status] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let [list, details] = Layout::horizontal([Constraint::Length(30), Constraint::Min(20)]).areas(main);
+
+    let items: Vec<ListItem> = browser
+        .entries
+        .iter()
+        .map(|entry| ListItem::new(format!("{}  {}", entry.manifest.date, entry.status)))
+        .collect();
+    let title = format!(" Archive ({} dates) ", browser.entries.len());
+    let list_widget = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected((!browser.entries.is_empty()).then_some(browser.selected));
+    frame.render_stateful_widget(list_widget, list, &mut state);
+
+    let lines: Vec<Line> = match browser.details() {
+        details if details.is_empty() => vec![Line::from("Nothing archived in the output directory yet")],
+        details => details
+            .into_iter()
+            .map(|(label, value)| {
+                Line::from(vec![
+                    Span::styled(format!("{:<12}", label), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(value),
+                ])
+            })
+            .collect(),
+    };
+    let details_widget = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(" Details "))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(details_widget, details);
+
+    frame.render_widget(Paragraph::new(browser.message.as_deref().unwrap_or(HELP)), status);
+}
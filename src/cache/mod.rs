@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+use crate::types::PuzzleInfo;
+
+pub mod scans;
+
+/// Directory in the output directory holding the cache
+const CACHE_DIR: &str = ".cache";
+
+/// Images stored by their SHA-256, so a puzzle reprinted on another date is kept once
+const OBJECTS_DIR: &str = "objects";
+
+const INDEX_FILE: &str = "index.json";
+
+/// One downloaded image, as recorded in the index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedImage {
+    pub sha256: String,
+    pub mime_type: String,
+    /// ID of the uploaded copy on each sink it reached
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub uploads: BTreeMap<String, String>,
+    /// SHA-256 of the file behind each upload, where it isn't the image itself but a
+    /// processed variant, the original or a packet
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// The puzzle number and setter read off the image, with `[ocr]`
+    #[serde(default, skip_serializing_if = "PuzzleInfo::is_empty")]
+    pub puzzle: PuzzleInfo,
+}
+
+impl CachedImage {
+    /// The SHA-256 the copy uploaded to `sink` should have
+    pub fn uploaded_sha256(&self, sink: &str) -> &str {
+        self.checksums.get(sink).unwrap_or(&self.sha256)
+    }
+}
+
+/// Every image ever downloaded into an output directory, addressed by content, with an
+/// index from date and region to the content. Copies in the output directory can be renamed
+/// or deleted; they are restored from here instead of fetched from the site again.
+#[derive(Debug)]
+pub struct ImageCache {
+    root: PathBuf,
+    index: BTreeMap<NaiveDate, BTreeMap<String, CachedImage>>,
+}
+
+impl ImageCache {
+    pub fn open(output_dir: &Path) -> Result<Self> {
+        let root = output_dir.join(CACHE_DIR);
+        let index_path = root.join(INDEX_FILE);
+        let index = match fs::read_to_string(&index_path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse image cache index {}", index_path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read image cache index {}", index_path.display()))
+            }
+        };
+        Ok(Self { root, index })
+    }
+
+    /// Adds the image `region` had on `date`, writing its content only if it is new
+    pub fn store(&mut self, region: &str, date: NaiveDate, data: &[u8], mime_type: &str) -> Result<()> {
+        let sha256 = format!("{:x}", Sha256::digest(data));
+        let path = self.object_path(&sha256);
+        if !path.is_file() {
+            let dir = path.parent().context("Cache object has no directory")?;
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            // Written under a temporary name so an interrupted write never looks complete
+            storage::write_atomic(&path, data)?;
+        }
+
+        let image = CachedImage {
+            sha256,
+            mime_type: mime_type.to_string(),
+            uploads: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+            puzzle: PuzzleInfo::default(),
+        };
+        self.index.entry(date).or_default().insert(region.to_string(), image);
+        self.save()
+    }
+
+    /// Notes where the image `region` had on `date` was uploaded, if it is cached
+    pub fn record_upload(&mut self, region: &str, date: NaiveDate, sink: &str, id: &str) -> Result<()> {
+        let Some(image) = self.index.get_mut(&date).and_then(|images| images.get_mut(region)) else {
+            return Ok(());
+        };
+        image.uploads.insert(sink.to_string(), id.to_string());
+        image.checksums.remove(sink);
+        self.save()
+    }
+
+    /// Like [`Self::record_upload`], also noting the SHA-256 of the file that was uploaded,
+    /// so an audit checks the copy against what was sent rather than the image
+    pub fn record_delivery(&mut self, region: &str, date: NaiveDate, sink: &str, id: &str, sha256: &str) -> Result<()> {
+        let Some(image) = self.index.get_mut(&date).and_then(|images| images.get_mut(region)) else {
+            return Ok(());
+        };
+        image.uploads.insert(sink.to_string(), id.to_string());
+        match image.sha256 == sha256 {
+            true => image.checksums.remove(sink),
+            false => image.checksums.insert(sink.to_string(), sha256.to_string()),
+        };
+        self.save()
+    }
+
+    /// Notes the puzzle number and setter read off the image `region` had on `date`, if it is
+    /// cached
+    pub fn record_puzzle(&mut self, region: &str, date: NaiveDate, puzzle: &PuzzleInfo) -> Result<()> {
+        let Some(image) = self.index.get_mut(&date).and_then(|images| images.get_mut(region)) else {
+            return Ok(());
+        };
+        image.puzzle = puzzle.clone();
+        self.save()
+    }
+
+    /// The cache entry of `region` on `date`, whether or not its content is still there
+    pub fn entry(&self, region: &str, date: NaiveDate) -> Option<&CachedImage> {
+        self.index.get(&date)?.get(region)
+    }
+
+    /// Every date with an entry for `region`, oldest first
+    pub fn entries<'a>(&'a self, region: &'a str) -> impl Iterator<Item = (NaiveDate, &'a CachedImage)> + 'a {
+        self.index
+            .iter()
+            .filter_map(move |(date, images)| Some((*date, images.get(region)?)))
+    }
+
+    /// The cached image `region` had on `date`, if its content is still there
+    pub fn get(&self, region: &str, date: NaiveDate) -> Option<(PathBuf, &CachedImage)> {
+        let image = self.index.get(&date)?.get(region)?;
+        let path = self.object_path(&image.sha256);
+        path.is_file().then_some((path, image))
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.root.join(OBJECTS_DIR).join(&sha256[..2]).join(sha256)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = self.root.join(INDEX_FILE);
+        fs::create_dir_all(&self.root).with_context(|| format!("Failed to create {}", self.root.display()))?;
+        storage::write_atomic(&path, serde_json::to_string_pretty(&self.index)?.as_bytes())
+            .with_context(|| format!("Failed to write image cache index {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_store_and_get() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        assert!(cache.get("crossword", date(20)).is_none());
+
+        cache.store("crossword", date(20), b"puzzle", "image/jpeg").unwrap();
+
+        // The index survives reopening
+        let cache = ImageCache::open(dir.path()).unwrap();
+        let (path, image) = cache.get("crossword", date(20)).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"puzzle");
+        assert_eq!(image.mime_type, "image/jpeg");
+        assert!(cache.get("cartoon", date(20)).is_none());
+    }
+
+    #[test]
+    fn test_duplicates_are_stored_once() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), b"reprinted puzzle", "image/jpeg").unwrap();
+        cache.store("crossword", date(27), b"reprinted puzzle", "image/jpeg").unwrap();
+        cache.store("crossword", date(21), b"another puzzle", "image/png").unwrap();
+
+        let (first, _) = cache.get("crossword", date(20)).unwrap();
+        let (second, _) = cache.get("crossword", date(27)).unwrap();
+        assert_eq!(first, second);
+
+        let objects: usize = fs::read_dir(dir.path().join(CACHE_DIR).join(OBJECTS_DIR))
+            .unwrap()
+            .map(|shard| fs::read_dir(shard.unwrap().path()).unwrap().count())
+            .sum();
+        assert_eq!(objects, 2);
+    }
+
+    #[test]
+    fn test_record_upload() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), b"puzzle", "image/jpeg").unwrap();
+        cache.record_upload("crossword", date(20), "drive", "file-id").unwrap();
+        // Nothing to attach the upload of an uncached image to
+        cache.record_upload("crossword", date(21), "drive", "other-id").unwrap();
+
+        let cache = ImageCache::open(dir.path()).unwrap();
+        assert_eq!(cache.entry("crossword", date(20)).unwrap().uploads["drive"], "file-id");
+        assert!(cache.entry("crossword", date(21)).is_none());
+    }
+
+    #[test]
+    fn test_missing_content_is_a_miss() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), b"puzzle", "image/jpeg").unwrap();
+        let (path, _) = cache.get("crossword", date(20)).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(cache.get("crossword", date(20)).is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+use crate::types::TargetProfile;
+
+use super::CACHE_DIR;
+
+const SCANS_FILE: &str = "scans.json";
+
+/// The `[scan_cache]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanCacheConfig {
+    /// How long a page whose map has articles but not the region is skipped; 0 re-scans every time
+    pub miss_ttl_secs: u64,
+}
+
+impl Default for ScanCacheConfig {
+    fn default() -> Self {
+        Self { miss_ttl_secs: 3600 }
+    }
+}
+
+/// A page map that listed articles, none of them the region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Miss {
+    region: String,
+    page: u32,
+    /// What was looked for; a scan with another profile doesn't count
+    profile: TargetProfile,
+    checked_at: DateTime<Utc>,
+}
+
+/// Pages recently confirmed to lack a region, by date, so that polling for a late crossword
+/// doesn't fetch them again. Pages that failed or had an empty map aren't recorded, so they
+/// are always checked again.
+#[derive(Debug)]
+pub struct ScanCache {
+    path: PathBuf,
+    ttl: Duration,
+    misses: BTreeMap<NaiveDate, Vec<Miss>>,
+}
+
+impl ScanCache {
+    pub fn open(output_dir: &Path, config: &ScanCacheConfig) -> Result<Self> {
+        let path = output_dir.join(CACHE_DIR).join(SCANS_FILE);
+        let misses = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse scan cache {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read scan cache {}", path.display())),
+        };
+        let ttl = Duration::seconds(i64::try_from(config.miss_ttl_secs).unwrap_or(i64::MAX / 1000));
+        Ok(Self { path, ttl, misses })
+    }
+
+    /// Whether `page` was found without the region on `date` less than the TTL before `now`
+    pub fn is_miss(&self, date: NaiveDate, region: &str, page: u32, profile: &TargetProfile, now: DateTime<Utc>) -> bool {
+        self.misses.get(&date).is_some_and(|misses| {
+            misses.iter().any(|miss| {
+                miss.region == region && miss.page == page && miss.profile == *profile && now - miss.checked_at < self.ttl
+            })
+        })
+    }
+
+    /// Records that `page` lacked the region on `date`, dropping expired entries
+    pub fn record_miss(
+        &mut self,
+        date: NaiveDate,
+        region: &str,
+        page: u32,
+        profile: &TargetProfile,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        if self.ttl <= Duration::zero() {
+            return Ok(());
+        }
+        let misses = self.misses.entry(date).or_default();
+        misses.retain(|miss| !(miss.region == region && miss.page == page));
+        misses.push(Miss {
+            region: region.to_string(),
+            page,
+            profile: profile.clone(),
+            checked_at: now,
+        });
+
+        let ttl = self.ttl;
+        for misses in self.misses.values_mut() {
+            misses.retain(|miss| now - miss.checked_at < ttl);
+        }
+        self.misses.retain(|_, misses| !misses.is_empty());
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let dir = self.path.parent().context("Scan cache has no directory")?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        storage::write_atomic(&self.path, serde_json::to_string_pretty(&self.misses)?.as_bytes())
+            .with_context(|| format!("Failed to write scan cache {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_misses_expire() {
+        let dir = TempDir::new().unwrap();
+        let config = ScanCacheConfig { miss_ttl_secs: 600 };
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let profile = TargetProfile::default();
+        let now: DateTime<Utc> = "2024-03-20T05:00:00Z".parse().unwrap();
+
+        let mut cache = ScanCache::open(dir.path(), &config).unwrap();
+        cache.record_miss(date, "crossword", 1, &profile, now).unwrap();
+
+        let cache = ScanCache::open(dir.path(), &config).unwrap();
+        assert!(cache.is_miss(date, "crossword", 1, &profile, now + Duration::minutes(5)));
+        assert!(!cache.is_miss(date, "crossword", 1, &profile, now + Duration::minutes(10)));
+        assert!(!cache.is_miss(date, "crossword", 2, &profile, now));
+        assert!(!cache.is_miss(date, "cartoon", 1, &profile, now));
+        let other = TargetProfile {
+            expected: crate::types::Rect { x1: 0, y1: 0, x2: 500, y2: 500 },
+            ..TargetProfile::default()
+        };
+        assert!(!cache.is_miss(date, "crossword", 1, &other, now));
+
+        // Disabled, nothing is kept
+        let off = ScanCacheConfig { miss_ttl_secs: 0 };
+        let mut cache = ScanCache::open(dir.path(), &off).unwrap();
+        cache.record_miss(date, "crossword", 3, &profile, now).unwrap();
+        assert!(!cache.is_miss(date, "crossword", 3, &profile, now));
+    }
+}
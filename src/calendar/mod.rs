@@ -0,0 +1,128 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt::Write;
+
+use crate::archive;
+use crate::cache::ImageCache;
+use crate::config::Config;
+use crate::report;
+
+pub mod publication;
+
+/// Longest line, in bytes, before RFC 5545 wants it folded
+const MAX_LINE: usize = 75;
+
+/// An iCalendar file with an all-day event for every date from `from` to `to`: the saved
+/// crossword with a link to its uploaded copy, or a flag that the date is missing. Reading
+/// the output directory only; nothing is restored from the cache or fetched.
+pub fn render(config: &Config, from: NaiveDate, to: NaiveDate, now: DateTime<Utc>) -> Result<String> {
+    let cache = ImageCache::open(&config.output_dir)?;
+    let mut ics = String::new();
+    line(&mut ics, "BEGIN:VCALENDAR");
+    line(&mut ics, "VERSION:2.0");
+    line(&mut ics, &format!("PRODID:-//{}//{}//EN", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+    line(&mut ics, "CALSCALE:GREGORIAN");
+    line(&mut ics, "X-WR-CALNAME:Hitavada crosswords");
+
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        let entry = cache.entry("crossword", date);
+        let saved = archive::archived_file(config, date)?;
+        let cached = cache.get("crossword", date).is_some();
+        let (summary, description) = match (&saved, cached) {
+            (None, false) => (
+                "Missing crossword".to_string(),
+                format!("Not in the archive; run `backfill --from {date} --to {date}` to fetch it"),
+            ),
+            _ => {
+                let mut description = match &saved {
+                    Some(path) => format!("Saved as {}", path.display()),
+                    None => "Only in the image cache".to_string(),
+                };
+                let links = entry.into_iter().flat_map(|image| &image.uploads);
+                for (sink, url) in links.filter_map(|(sink, id)| Some((sink, report::file_url(sink, id)?))) {
+                    write!(description, "\nCopy on {}: {}", sink, url)?;
+                }
+                ("Crossword".to_string(), description)
+            }
+        };
+
+        line(&mut ics, "BEGIN:VEVENT");
+        line(&mut ics, &format!("UID:crossword-{}@{}", date, env!("CARGO_PKG_NAME")));
+        line(&mut ics, &format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+        line(&mut ics, &format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+        line(&mut ics, &format!("DTEND;VALUE=DATE:{}", date.succ_opt().unwrap_or(date).format("%Y%m%d")));
+        line(&mut ics, &format!("SUMMARY:{}", escape(&summary)));
+        line(&mut ics, &format!("DESCRIPTION:{}", escape(&description)));
+        line(&mut ics, "TRANSP:TRANSPARENT");
+        line(&mut ics, "END:VEVENT");
+    }
+
+    line(&mut ics, "END:VCALENDAR");
+    Ok(ics)
+}
+
+/// Appends a content line, folded to [`MAX_LINE`] bytes with CRLF endings as the format requires
+fn line(ics: &mut String, content: &str) {
+    let mut width = 0;
+    for c in content.chars() {
+        if width + c.len_utf8() > MAX_LINE {
+            ics.push_str("\r\n ");
+            // The leading space of a continuation counts towards its length
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// Escapes a TEXT value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_render_flags_missing_days() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        fs::write(dir.path().join("crossword_2024-03-20.jpg"), b"puzzle").unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), b"puzzle", "image/jpeg").unwrap();
+        cache.record_upload("crossword", date(20), "drive", "file-id").unwrap();
+
+        let now = DateTime::parse_from_rfc3339("2024-03-22T06:00:00Z").unwrap().with_timezone(&Utc);
+        let ics = render(&config, date(20), date(21), now).unwrap();
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240320\r\nDTEND;VALUE=DATE:20240321\r\nSUMMARY:Crossword\r\n"));
+        assert!(ics.contains("DTSTAMP:20240322T060000Z"));
+        // Folded lines are joined back up by dropping each CRLF and the space after it
+        let unfolded = ics.replace("\r\n ", "");
+        assert!(unfolded.contains("\\nCopy on drive: https://drive.google.com/file/d/file-id/view"));
+        assert!(unfolded.contains("DTSTART;VALUE=DATE:20240321\r\nDTEND;VALUE=DATE:20240322\r\nSUMMARY:Missing crossword"));
+        assert!(ics.split("\r\n").all(|line| line.len() <= MAX_LINE));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a, b; c\\d\ne"), "a\\, b\\; c\\\\d\\ne");
+    }
+}
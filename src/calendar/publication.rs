@@ -0,0 +1,92 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Puzzle;
+
+/// The `[publication]` section of the config: the days the paper carries no daily crossword,
+/// which range and backfill runs pass over instead of scanning every page for it. Holidays
+/// without a paper count for the Sunday cryptic too.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublicationConfig {
+    /// Days of the week without the daily crossword, e.g. `["Mon"]`
+    pub no_crossword_on: Vec<Weekday>,
+    /// Holidays falling on the same day every year, as `MM-DD`, e.g. `"08-16"`
+    pub every_year: Vec<String>,
+    /// One-off dates without a crossword, e.g. the day after Diwali
+    pub dates: Vec<NaiveDate>,
+}
+
+impl PublicationConfig {
+    pub fn validate(&self) -> Vec<String> {
+        self.every_year
+            .iter()
+            .filter(|day| parse_day(day).is_none())
+            .map(|day| format!("[publication] every_year: '{}' is not a day of the year as MM-DD", day))
+            .collect()
+    }
+
+    /// Why there is no `puzzle` on `date`, or `None` when there should be one
+    pub fn skipped(&self, date: NaiveDate, puzzle: Puzzle) -> Option<String> {
+        match puzzle {
+            Puzzle::Crossword if self.no_crossword_on.contains(&date.weekday()) => {
+                return Some(format!("no crossword on {}s", date.format("%A")));
+            }
+            Puzzle::SundayCryptic if date.weekday() != Weekday::Sun => {
+                return Some("the cryptic is only in the Sunday supplement".to_string());
+            }
+            _ => {}
+        }
+        let day = (date.month(), date.day());
+        if self.dates.contains(&date) || self.every_year.iter().any(|holiday| parse_day(holiday) == Some(day)) {
+            return Some("no crossword on this holiday".to_string());
+        }
+        None
+    }
+}
+
+/// Month and day of an `MM-DD` day of the year, checked against a leap year so `02-29` counts
+fn parse_day(day: &str) -> Option<(u32, u32)> {
+    let date = NaiveDate::parse_from_str(&format!("2024-{}", day), "%Y-%m-%d").ok()?;
+    Some((date.month(), date.day()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skipped_dates() {
+        let publication: PublicationConfig = toml::from_str(
+            r#"
+            no_crossword_on = ["Mon", "Sun"]
+            every_year = ["08-16", "01-27"]
+            dates = ["2024-11-02"]
+            "#,
+        )
+        .unwrap();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        let daily = |day| publication.skipped(day, Puzzle::Crossword);
+        assert_eq!(daily(date(2024, 3, 25)).as_deref(), Some("no crossword on Mondays"));
+        assert!(daily(date(2023, 8, 16)).is_some());
+        assert!(daily(date(2024, 11, 2)).is_some());
+        assert!(daily(date(2025, 11, 4)).is_none());
+        assert!(daily(date(2024, 3, 20)).is_none());
+
+        // The Sunday cryptic doesn't go by the daily crossword's days off
+        let cryptic = |day| publication.skipped(day, Puzzle::SundayCryptic);
+        assert!(daily(date(2024, 3, 24)).is_some());
+        assert!(cryptic(date(2024, 3, 24)).is_none());
+        assert!(cryptic(date(2024, 3, 20)).is_some());
+        // But there's none without a paper
+        assert!(cryptic(date(2030, 1, 27)).is_some());
+        assert!(publication.validate().is_empty());
+
+        let invalid = PublicationConfig {
+            every_year: vec!["16-08".to_string(), "02-29".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate().len(), 1);
+    }
+}
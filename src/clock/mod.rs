@@ -0,0 +1,136 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+use std::sync::Arc;
+
+/// Offset of Indian Standard Time, which the newspaper publishes and the backfill window go by
+const IST_OFFSET_SECS: i32 = 5 * 3600 + 30 * 60;
+
+/// Source of the current time, so "today" can be simulated in tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<FixedOffset>;
+
+    /// The calendar date in the clock's own time zone
+    fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+}
+
+/// The machine's local clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Local::now().fixed_offset()
+    }
+}
+
+/// The clock a run goes by, carried in its config so the steps deep inside it read the one
+/// the caller chose; the system clock unless set
+#[derive(Clone)]
+pub struct RunClock(pub Arc<dyn Clock>);
+
+impl Default for RunClock {
+    fn default() -> Self {
+        Self(Arc::new(SystemClock))
+    }
+}
+
+impl std::fmt::Debug for RunClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RunClock({})", self.0.now().to_rfc3339())
+    }
+}
+
+impl Clock for RunClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        self.0.now()
+    }
+}
+
+/// A clock frozen at a given instant
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<FixedOffset>);
+
+#[cfg(test)]
+impl FixedClock {
+    /// Parses an RFC 3339 timestamp such as `2024-03-20T23:59:59+05:30`
+    pub fn at(timestamp: &str) -> Self {
+        Self(DateTime::parse_from_rfc3339(timestamp).unwrap())
+    }
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        self.0
+    }
+}
+
+/// Indian Standard Time as a fixed offset
+pub fn ist() -> FixedOffset {
+    FixedOffset::east_opt(IST_OFFSET_SECS).unwrap()
+}
+
+/// The date of the edition out now: today in India, wherever the clock itself runs
+pub fn publication_today(clock: &dyn Clock) -> NaiveDate {
+    clock.now().with_timezone(&ist()).date_naive()
+}
+
+/// Uses the requested date, or today according to `clock`
+pub fn resolve_date(date: Option<NaiveDate>, clock: &dyn Clock) -> NaiveDate {
+    date.unwrap_or_else(|| clock.today())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_fixed_clock_today() {
+        let clock = FixedClock::at("2024-03-20T06:00:00+00:00");
+        assert_eq!(clock.today(), date(2024, 3, 20));
+    }
+
+    #[test]
+    fn test_today_around_midnight() {
+        assert_eq!(FixedClock::at("2024-03-20T23:59:59+05:30").today(), date(2024, 3, 20));
+        assert_eq!(FixedClock::at("2024-03-21T00:00:00+05:30").today(), date(2024, 3, 21));
+    }
+
+    #[test]
+    fn test_today_depends_on_time_zone() {
+        // 01:30 in India is still the previous evening in UTC, where Lambda runs
+        let ist = FixedClock::at("2024-03-21T01:30:00+05:30");
+        let utc = FixedClock(ist.now().with_timezone(&FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(ist.today(), date(2024, 3, 21));
+        assert_eq!(utc.today(), date(2024, 3, 20));
+    }
+
+    #[test]
+    fn test_publication_today() {
+        // Early morning in India, when the day's edition is already out, is the evening before in UTC
+        let utc = FixedClock::at("2024-03-20T20:00:00+00:00");
+        assert_eq!(utc.today(), date(2024, 3, 20));
+        assert_eq!(publication_today(&utc), date(2024, 3, 21));
+    }
+
+    #[test]
+    fn test_resolve_date() {
+        let clock = FixedClock::at("2024-03-20T06:00:00+00:00");
+        assert_eq!(resolve_date(None, &clock), date(2024, 3, 20));
+        assert_eq!(resolve_date(Some(date(2024, 1, 1)), &clock), date(2024, 1, 1));
+    }
+
+    #[test]
+    fn test_system_clock_matches_local() {
+        let before = Local::now().date_naive();
+        let today = SystemClock.today();
+        let after = Local::now().date_naive();
+        assert!(today == before || today == after);
+    }
+}
@@ -0,0 +1,54 @@
+//! Progress lines meant for a person watching the run, logged at info level so `-q` can
+//! silence them. Log output goes to standard output, except when that carries JSON for
+//! another program (`--stdin`, `--format json`), in which case it moves to standard error.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends log output to standard error from now on, leaving standard output to the caller
+pub fn keep_stdout_clean() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn print(line: fmt::Arguments) {
+    tracing::info!("{}", line);
+}
+
+/// Where the log subscriber writes: standard output, or standard error after
+/// [`keep_stdout_clean`]
+pub struct Output;
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if TO_STDERR.load(Ordering::Relaxed) {
+            io::stderr().write(buf)
+        } else {
+            io::stdout().write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()?;
+        io::stderr().flush()
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Output {
+    type Writer = Output;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Output
+    }
+}
+
+/// Like `println!`, but logged, so it honours `-q` and [`keep_stdout_clean`]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        $crate::console::print(format_args!($($arg)*))
+    };
+}
+
+pub(crate) use progress;
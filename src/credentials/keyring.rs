@@ -0,0 +1,77 @@
+//! Secrets kept in the desktop's OS keyring (macOS Keychain, Windows Credential Manager or
+//! the Secret Service on Linux) instead of plaintext files. Needs the `keyring` feature.
+
+use anyhow::Result;
+
+/// Keyring service every secret of the tool is stored under
+#[cfg_attr(not(feature = "keyring"), allow(dead_code))]
+const SERVICE: &str = "hitavada-crossword-downloader";
+
+/// Keyring entry holding the Google service account key JSON
+pub const GOOGLE_SERVICE_ACCOUNT: &str = "google-service-account";
+
+/// Keyring entry holding the Telegram bot token
+pub const TELEGRAM_BOT_TOKEN: &str = "telegram-bot-token";
+
+/// Keyring entry holding the SMTP password
+pub const SMTP_PASSWORD: &str = "smtp-password";
+
+#[cfg(feature = "keyring")]
+pub fn load(name: &str) -> Result<String> {
+    use anyhow::Context;
+
+    ::keyring::Entry::new(SERVICE, name)?
+        .get_password()
+        .with_context(|| format!("Failed to read '{}' from the OS keyring", name))
+}
+
+#[cfg(feature = "keyring")]
+pub fn store(name: &str, secret: &str) -> Result<()> {
+    use anyhow::Context;
+
+    ::keyring::Entry::new(SERVICE, name)?
+        .set_password(secret)
+        .with_context(|| format!("Failed to store '{}' in the OS keyring", name))
+}
+
+/// Whether the keyring already holds a secret under `name`
+#[cfg(feature = "keyring")]
+pub fn exists(name: &str) -> Result<bool> {
+    match ::keyring::Entry::new(SERVICE, name)?.get_password() {
+        Ok(_) => Ok(true),
+        Err(::keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("Failed to look up '{}' in the OS keyring", name))),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn load(_name: &str) -> Result<String> {
+    anyhow::bail!("{}", UNSUPPORTED)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store(_name: &str, _secret: &str) -> Result<()> {
+    anyhow::bail!("{}", UNSUPPORTED)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn exists(_name: &str) -> Result<bool> {
+    anyhow::bail!("{}", UNSUPPORTED)
+}
+
+#[cfg(not(feature = "keyring"))]
+const UNSUPPORTED: &str = "This build has no OS keyring support; rebuild with `--features keyring`";
+
+/// Whether this build can use the OS keyring at all
+pub fn supported() -> bool {
+    cfg!(feature = "keyring")
+}
+
+/// The secret under `name`, or `None` when the keyring has none or this build can't use it;
+/// for secrets the keyring is one of several places for
+pub fn find(name: &str) -> Result<Option<String>> {
+    match supported() && exists(name)? {
+        true => load(name).map(Some),
+        false => Ok(None),
+    }
+}
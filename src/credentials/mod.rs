@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_ssm::Client as SsmClient;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+pub mod keyring;
+
+/// SSM parameter holding the service account key in the default Lambda deployment
+pub const SSM_PARAMETER: &str = "/hitavada-crossword/google-service-account";
+
+/// SSM serves Secrets Manager secrets under this prefix, so both share one client
+const SECRETS_MANAGER_PREFIX: &str = "/aws/reference/secretsmanager/";
+
+/// Where the Google service account key is read from, set with `[credential_source]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// A local key file, for development
+    File { path: PathBuf },
+    /// An SSM Parameter Store SecureString
+    Ssm {
+        #[serde(default = "default_parameter")]
+        parameter: String,
+    },
+    /// A Secrets Manager secret, by name or ARN
+    SecretsManager { secret_id: String },
+    /// A field of a HashiCorp Vault KV v2 secret; the token comes from `VAULT_TOKEN`
+    Vault {
+        /// Server URL, defaults to `VAULT_ADDR`
+        address: Option<String>,
+        #[serde(default = "default_mount")]
+        mount: String,
+        path: String,
+        #[serde(default = "default_field")]
+        field: String,
+    },
+    /// The desktop's OS keyring, filled with `store-credentials`
+    Keyring {
+        #[serde(default = "default_entry")]
+        entry: String,
+    },
+}
+
+fn default_parameter() -> String {
+    SSM_PARAMETER.to_string()
+}
+
+fn default_mount() -> String {
+    "secret".to_string()
+}
+
+fn default_field() -> String {
+    "service_account".to_string()
+}
+
+fn default_entry() -> String {
+    keyring::GOOGLE_SERVICE_ACCOUNT.to_string()
+}
+
+impl CredentialSource {
+    /// The configured source; without one, `service_account_path` when set and SSM otherwise
+    pub fn from_config(config: &Config) -> Self {
+        if let Some(source) = &config.credential_source {
+            return source.clone();
+        }
+        match &config.service_account_path {
+            Some(path) => Self::File { path: path.clone() },
+            None => Self::Ssm {
+                parameter: default_parameter(),
+            },
+        }
+    }
+
+    /// Problems that can be spotted without fetching anything
+    pub fn validate(&self) -> Vec<String> {
+        match self {
+            Self::SecretsManager { secret_id } if secret_id.is_empty() => {
+                vec!["credential_source: secret_id is empty".to_string()]
+            }
+            Self::Vault { path, .. } if path.is_empty() => vec!["credential_source: Vault path is empty".to_string()],
+            Self::Keyring { .. } if !keyring::supported() => {
+                vec!["credential_source: the keyring needs a build with `--features keyring`".to_string()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn provider(&self) -> Box<dyn CredentialProvider> {
+        match self {
+            Self::File { path } => Box::new(FileProvider { path: path.clone() }),
+            Self::Ssm { parameter } => Box::new(SsmProvider {
+                parameter: parameter.clone(),
+            }),
+            Self::SecretsManager { secret_id } => Box::new(SsmProvider {
+                parameter: format!("{}{}", SECRETS_MANAGER_PREFIX, secret_id),
+            }),
+            Self::Vault {
+                address,
+                mount,
+                path,
+                field,
+            } => Box::new(VaultProvider {
+                address: address.clone(),
+                mount: mount.clone(),
+                path: path.clone(),
+                field: field.clone(),
+            }),
+            Self::Keyring { entry } => Box::new(KeyringProvider { entry: entry.clone() }),
+        }
+    }
+}
+
+/// Somewhere a secret can be fetched from
+#[async_trait]
+pub trait CredentialProvider: Send + Sync {
+    /// Where the secret is read from, for messages
+    fn describe(&self) -> String;
+
+    async fn fetch(&self) -> Result<String>;
+}
+
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl CredentialProvider for FileProvider {
+    fn describe(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    async fn fetch(&self) -> Result<String> {
+        fs::read_to_string(&self.path).context("Failed to read Google service account file")
+    }
+}
+
+/// Reads an SSM parameter, which also covers Secrets Manager secrets through their reference path
+pub struct SsmProvider {
+    parameter: String,
+}
+
+#[async_trait]
+impl CredentialProvider for SsmProvider {
+    fn describe(&self) -> String {
+        match self.parameter.strip_prefix(SECRETS_MANAGER_PREFIX) {
+            Some(secret_id) => format!("Secrets Manager secret {}", secret_id),
+            None => format!("SSM parameter {}", self.parameter),
+        }
+    }
+
+    async fn fetch(&self) -> Result<String> {
+        let config = aws_config::defaults(BehaviorVersion::latest()).load().await;
+        let client = SsmClient::new(&config);
+
+        let parameter = client
+            .get_parameter()
+            .name(&self.parameter)
+            .with_decryption(true)
+            .send()
+            .await?;
+
+        let value = parameter
+            .parameter()
+            .and_then(|p| p.value())
+            .context("Parameter value is empty")?;
+
+        Ok(value.to_string())
+    }
+}
+
+pub struct VaultProvider {
+    address: Option<String>,
+    mount: String,
+    path: String,
+    field: String,
+}
+
+#[async_trait]
+impl CredentialProvider for VaultProvider {
+    fn describe(&self) -> String {
+        format!("Vault secret {}/{} ({})", self.mount, self.path, self.field)
+    }
+
+    async fn fetch(&self) -> Result<String> {
+        let address = match &self.address {
+            Some(address) => address.clone(),
+            None => std::env::var("VAULT_ADDR").context("VAULT_ADDR is not set and no Vault address is configured")?,
+        };
+        let token = std::env::var("VAULT_TOKEN").context("VAULT_TOKEN is not set")?;
+        let url = format!("{}/v1/{}/data/{}", address.trim_end_matches('/'), self.mount, self.path);
+
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("X-Vault-Token", token)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Vault at {}", address))?
+            .error_for_status()
+            .context("Vault refused to read the secret")?;
+        vault_field(&response.text().await?, &self.field)
+    }
+}
+
+/// Picks `field` out of a KV v2 read response; a JSON object is passed on serialized
+fn vault_field(body: &str, field: &str) -> Result<String> {
+    let response: serde_json::Value = serde_json::from_str(body).context("Vault response is not JSON")?;
+    match &response["data"]["data"][field] {
+        serde_json::Value::Null => anyhow::bail!("The Vault secret has no field '{}'", field),
+        serde_json::Value::String(value) => Ok(value.clone()),
+        value => Ok(value.to_string()),
+    }
+}
+
+pub struct KeyringProvider {
+    entry: String,
+}
+
+#[async_trait]
+impl CredentialProvider for KeyringProvider {
+    fn describe(&self) -> String {
+        format!("OS keyring entry {}", self.entry)
+    }
+
+    async fn fetch(&self) -> Result<String> {
+        keyring::load(&self.entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_parse_sources() {
+        let source: CredentialSource = toml::from_str(r#"kind = "secrets_manager"
+secret_id = "google-service-account""#)
+        .unwrap();
+        assert_eq!(source.provider().describe(), "Secrets Manager secret google-service-account");
+
+        let source: CredentialSource = toml::from_str(r#"kind = "vault"
+path = "crossword/google""#).unwrap();
+        assert_eq!(
+            source,
+            CredentialSource::Vault {
+                address: None,
+                mount: "secret".to_string(),
+                path: "crossword/google".to_string(),
+                field: "service_account".to_string(),
+            }
+        );
+
+        let source: CredentialSource = toml::from_str(r#"kind = "ssm""#).unwrap();
+        assert_eq!(source.provider().describe(), format!("SSM parameter {}", SSM_PARAMETER));
+    }
+
+    #[test]
+    fn test_source_from_config() {
+        let mut config = Config::default();
+        assert_eq!(CredentialSource::from_config(&config).provider().describe(), format!("SSM parameter {}", SSM_PARAMETER));
+
+        config.service_account_path = Some(PathBuf::from("key.json"));
+        assert_eq!(CredentialSource::from_config(&config).provider().describe(), "file key.json");
+
+        config.credential_source = Some(CredentialSource::Keyring { entry: default_entry() });
+        assert_eq!(
+            CredentialSource::from_config(&config).provider().describe(),
+            "OS keyring entry google-service-account"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_provider() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{"type": "service_account"}}"#).unwrap();
+
+        let provider = CredentialSource::File {
+            path: file.path().to_path_buf(),
+        }
+        .provider();
+        assert_eq!(provider.fetch().await.unwrap(), r#"{"type": "service_account"}"#);
+    }
+
+    #[test]
+    fn test_vault_field() {
+        let body = r#"{"data": {"data": {"service_account": {"type": "service_account"}, "note": "plain"}, "metadata": {}}}"#;
+        assert_eq!(vault_field(body, "service_account").unwrap(), r#"{"type":"service_account"}"#);
+        assert_eq!(vault_field(body, "note").unwrap(), "plain");
+        assert!(vault_field(body, "missing").unwrap_err().to_string().contains("no field 'missing'"));
+    }
+}
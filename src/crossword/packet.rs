@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::archive::pdf;
+use crate::config::Config;
+use crate::console;
+use crate::naming;
+use crate::pipeline::Artifact;
+use crate::storage;
+use crate::types::PuzzleInfo;
+
+/// The `[packet]` section of the config: everything downloaded for a date bound into one PDF,
+/// uploaded in place of the separate images
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PacketConfig {
+    pub enabled: bool,
+    /// Regions in the order their pages are bound, by name, with `crossword` for the
+    /// crossword; the crossword then every feature when empty
+    pub order: Vec<String>,
+    /// strftime pattern for the packet's file name
+    pub filename_template: String,
+}
+
+impl Default for PacketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            order: Vec::new(),
+            filename_template: "packet_%Y-%m-%d".to_string(),
+        }
+    }
+}
+
+impl PacketConfig {
+    /// `regions` are the names the packet can be bound from
+    pub fn validate(&self, regions: &[&str]) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .order
+            .iter()
+            .filter(|name| !regions.contains(&name.as_str()))
+            .map(|name| format!("[packet] order names '{}', which is neither the crossword nor a feature", name))
+            .collect();
+        if let Err(e) = naming::validate_template(&self.filename_template) {
+            problems.push(format!("[packet] filename_template: {}", e));
+        }
+        problems
+    }
+}
+
+/// Binds the images downloaded for `date`, given by region name, into the packet and saves
+/// it in the output directory, titled with the crossword's `puzzle` details. Regions that
+/// weren't downloaded are left out.
+pub fn write(config: &Config, date: NaiveDate, files: &[(&str, String)], puzzle: &PuzzleInfo) -> Result<Artifact> {
+    let mut files: Vec<_> = files.iter().collect();
+    if !config.packet.order.is_empty() {
+        files.retain(|(name, _)| config.packet.order.iter().any(|ordered| ordered == name));
+        files.sort_by_key(|(name, _)| config.packet.order.iter().position(|ordered| ordered == name));
+    }
+    let images = files
+        .iter()
+        .map(|(_, path)| fs::read(path).with_context(|| format!("Failed to read {}", path)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let locale = naming::parse_locale(&config.locale)?;
+    let name = naming::render_filename(&config.packet.filename_template, locale, date, "pdf")?;
+    let path = config.output_dir.join(name);
+    storage::save(&path, &pdf::with_puzzle(&images, puzzle)?, &config.permissions)?;
+    console::progress!("Bound {} pages into {}", images.len(), path.display());
+    Ok(Artifact::new(path.to_string_lossy(), "application/pdf").dated(date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn image(dir: &TempDir, name: &str) -> String {
+        let path = dir.path().join(name);
+        image::RgbImage::new(40, 30).save(&path).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_binds_regions_in_order() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.packet.order = vec!["crossword".to_string(), "sudoku".to_string()];
+        let files = [
+            ("quiz", image(&dir, "quiz.png")),
+            ("sudoku", image(&dir, "sudoku.png")),
+            ("crossword", image(&dir, "crossword.png")),
+        ];
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+
+        let puzzle = PuzzleInfo {
+            number: Some(10234),
+            setter: None,
+        };
+
+        let artifact = write(&config, date, &files, &puzzle).unwrap();
+
+        assert!(artifact.path.ends_with("packet_2024-03-20.pdf"));
+        let packet = String::from_utf8_lossy(&fs::read(&artifact.path).unwrap()).into_owned();
+        // The quiz isn't in the order, so only two pages are bound
+        assert_eq!(packet.matches("/Type /Page ").count(), 2);
+        // "Crossword No. 10234" in UTF-16
+        assert!(packet.contains("/Title <FEFF00430072006F007300730077006F0072006400"), "{}", packet);
+        assert!(packet.contains(" /Info 9 0 R"));
+        assert!(!packet.contains("/Author"));
+        assert!(config.packet.validate(&["crossword", "sudoku"]).is_empty());
+        assert_eq!(config.packet.validate(&["crossword"]).len(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::Download;
+use crate::imaging;
+use crate::pipeline::UploadFailure;
+use crate::storage;
+use crate::types::PuzzleInfo;
+
+/// File in the output directory listing dates whose uploads didn't all go through
+const PENDING_FILE: &str = ".pending-uploads.json";
+
+/// A date whose image was saved but didn't reach every sink
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingRun {
+    pub filename: String,
+    pub mime_type: String,
+    pub page: u32,
+    pub crossword_url: String,
+    pub image_url: String,
+    pub failures: Vec<UploadFailure>,
+    /// The IDs of the uploads that did go through, by sink, so a retry can report every
+    /// destination without repeating theirs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub delivered: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+    #[serde(default, skip_serializing_if = "PuzzleInfo::is_empty")]
+    pub puzzle: PuzzleInfo,
+    /// Whether the failed uploads are bound for the review folder
+    #[serde(default, skip_serializing_if = "crate::types::is_false")]
+    pub needs_review: bool,
+}
+
+impl PendingRun {
+    pub fn new(download: &Download, failures: Vec<UploadFailure>, delivered: BTreeMap<String, String>) -> Self {
+        Self {
+            filename: download.filename.clone(),
+            mime_type: download.mime_type.to_string(),
+            page: download.page,
+            crossword_url: download.crossword_url.clone(),
+            image_url: download.image_url.clone(),
+            failures,
+            delivered,
+            confidence: download.confidence,
+            puzzle: download.puzzle.clone(),
+            needs_review: download.needs_review,
+        }
+    }
+
+    /// The download as first saved, for reporting a successful retry
+    pub fn download(&self) -> Download {
+        let mime_type = imaging::EXTENSIONS
+            .iter()
+            .map(|(mime, _)| *mime)
+            .find(|mime| *mime == self.mime_type)
+            .unwrap_or("image/jpeg");
+        Download {
+            filename: self.filename.clone(),
+            mime_type,
+            page: self.page,
+            crossword_url: self.crossword_url.clone(),
+            image_url: self.image_url.clone(),
+            comparison: None,
+            timings: Vec::new(),
+            link: None,
+            uploads: self.delivered.clone(),
+            puzzle: self.puzzle.clone(),
+            confidence: self.confidence,
+            needs_review: self.needs_review,
+            storage: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Dates waiting for a re-upload-only retry, so a partial failure never leaves
+/// the output directory and the sinks silently out of step. Kept per puzzle, as the daily
+/// crossword and the Sunday cryptic share dates.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PendingUploads {
+    /// Runs by puzzle (the region they were saved as), then date
+    pub runs: BTreeMap<String, BTreeMap<NaiveDate, PendingRun>>,
+}
+
+impl PendingUploads {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(PENDING_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse pending uploads {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read pending uploads {}", path.display())),
+        }
+    }
+
+    pub fn get(&self, region: &str, date: NaiveDate) -> Option<&PendingRun> {
+        self.runs.get(region).and_then(|runs| runs.get(&date))
+    }
+
+    pub fn contains(&self, region: &str, date: NaiveDate) -> bool {
+        self.get(region, date).is_some()
+    }
+
+    pub fn insert(&mut self, region: &str, date: NaiveDate, run: PendingRun) {
+        self.runs.entry(region.to_string()).or_default().insert(date, run);
+    }
+
+    pub fn remove(&mut self, region: &str, date: NaiveDate) -> Option<PendingRun> {
+        let runs = self.runs.get_mut(region)?;
+        let run = runs.remove(&date);
+        if runs.is_empty() {
+            self.runs.remove(region);
+        }
+        run
+    }
+
+    /// Writes the ledger, removing the file once nothing is pending
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.runs.values().all(BTreeMap::is_empty) {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to remove pending uploads {}", path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+        storage::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("Failed to write pending uploads {}", path.display()))
+    }
+}
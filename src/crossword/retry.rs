@@ -0,0 +1,135 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+use crate::console;
+use crate::error;
+
+/// The `[retry]` section of the config: when to try again while the crossword isn't
+/// published yet, counted from the first attempt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub schedule_minutes: Vec<u64>,
+    /// Have the Lambda answer a not-yet-published edition with a `retry` hint rather than an
+    /// error, for a scheduler such as Step Functions that invokes it again. Off, the invocation
+    /// fails, so Lambda's own retries, the dead-letter queue and error alarms still apply.
+    pub reinvoke: bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            schedule_minutes: vec![30, 60, 120],
+            reinvoke: false,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if self.schedule_minutes.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return vec!["[retry] schedule_minutes must be in increasing order".to_string()];
+        }
+        Vec::new()
+    }
+
+    /// How long to wait after `attempts` attempts before the next one, or `None` once the
+    /// schedule is used up
+    pub fn wait_after(&self, attempts: u32) -> Option<Duration> {
+        let index = usize::try_from(attempts).ok()?.checked_sub(1)?;
+        let next = *self.schedule_minutes.get(index)?;
+        let previous = index.checked_sub(1).map_or(0, |previous| self.schedule_minutes[previous]);
+        Some(Duration::from_secs(next.saturating_sub(previous) * 60))
+    }
+}
+
+/// Runs `attempt` again on the schedule for as long as it finds the crossword not published
+/// yet. Any other outcome, or the last scheduled attempt, is returned as it is.
+pub async fn until_published<T, F, Fut>(config: &RetryConfig, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempts = 0;
+    loop {
+        let result = attempt().await;
+        attempts += 1;
+        match &result {
+            Err(e) if error::is_not_yet_published(e) => match config.wait_after(attempts) {
+                Some(wait) => {
+                    console::progress!("{:#}; trying again in {} minutes", e, wait.as_secs() / 60);
+                    tokio::time::sleep(wait).await;
+                }
+                None => return result,
+            },
+            _ => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CrosswordError;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_wait_after() {
+        let config = RetryConfig::default();
+        assert_eq!(config.wait_after(0), None);
+        assert_eq!(config.wait_after(1), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(config.wait_after(2), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(config.wait_after(3), Some(Duration::from_secs(60 * 60)));
+        assert_eq!(config.wait_after(4), None);
+
+        let config = RetryConfig {
+            schedule_minutes: vec![60, 30],
+            ..RetryConfig::default()
+        };
+        assert_eq!(config.validate(), vec!["[retry] schedule_minutes must be in increasing order"]);
+    }
+
+    #[tokio::test]
+    async fn test_until_published() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let config = RetryConfig {
+            schedule_minutes: vec![0, 0],
+            ..RetryConfig::default()
+        };
+
+        let mut attempts = 0;
+        let result = until_published(&config, || {
+            attempts += 1;
+            let result = if attempts < 3 {
+                Err(CrosswordError::EditionNotPublished { date }.into())
+            } else {
+                Ok(attempts)
+            };
+            async move { result }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        // Other failures aren't retried
+        let mut attempts = 0;
+        let result: Result<()> = until_published(&config, || {
+            attempts += 1;
+            async { Err(anyhow::anyhow!("Could not find crossword on any page")) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        // Nor is the last scheduled attempt
+        let mut attempts = 0;
+        let result: Result<()> = until_published(&config, || {
+            attempts += 1;
+            async move { Err(CrosswordError::ImageNotYetAvailable { date }.into()) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+}
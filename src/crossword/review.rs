@@ -0,0 +1,113 @@
+//! Crosswords held for review: a detection scoring below `[review]`'s threshold is saved in
+//! the review folder of the output directory and left out of the cache, the learnt page
+//! positions and the upload records until `approve` accepts it.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{learn, record_uploads, remember, Region};
+use crate::config::Config;
+use crate::learning::MatchRecord;
+use crate::pipeline::{self, Artifact, Sink};
+use crate::storage;
+use crate::types::PuzzleInfo;
+
+/// File in the output directory listing the crosswords held for review
+const HELD_FILE: &str = ".held-for-review.json";
+
+/// A crossword saved in the review folder, with what its approval records
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeldImage {
+    pub filename: String,
+    pub mime_type: String,
+    /// Where it was found, learnt from once approved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<MatchRecord>,
+    #[serde(default, skip_serializing_if = "PuzzleInfo::is_empty")]
+    pub puzzle: PuzzleInfo,
+}
+
+/// The crosswords held for review, by puzzle and then date
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HeldImages {
+    pub images: BTreeMap<String, BTreeMap<NaiveDate, HeldImage>>,
+}
+
+impl HeldImages {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(HELD_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse held crosswords {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read held crosswords {}", path.display())),
+        }
+    }
+
+    pub fn insert(&mut self, region: &str, date: NaiveDate, image: HeldImage) {
+        self.images.entry(region.to_string()).or_default().insert(date, image);
+    }
+
+    pub fn remove(&mut self, region: &str, date: NaiveDate) -> Option<HeldImage> {
+        let images = self.images.get_mut(region)?;
+        let image = images.remove(&date);
+        if images.is_empty() {
+            self.images.remove(region);
+        }
+        image
+    }
+
+    /// Writes the list, removing the file once nothing is held
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.images.is_empty() {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to remove held crosswords {}", path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+        storage::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("Failed to write held crosswords {}", path.display()))
+    }
+}
+
+/// Accepts the crossword held for `date`: moves it into the archive, caches and learns from
+/// it like any download, and uploads it, processed, to the usual folders. Returns where it
+/// was archived.
+pub async fn approve(date: NaiveDate, config: &Config, sinks: Vec<Arc<dyn Sink>>) -> Result<String> {
+    let region = Region::crossword(config);
+    let path = HeldImages::path(&config.output_dir);
+    let mut held = HeldImages::load(&path)?;
+    let image = held
+        .remove(region.name, date)
+        .with_context(|| format!("No {} is held for review on {}", region.name, date))?;
+    let name = Path::new(&image.filename).file_name().context("Invalid filename")?;
+    let archived = config.output_dir.join(name);
+    fs::rename(&image.filename, &archived)
+        .with_context(|| format!("Failed to move {} to {}", image.filename, archived.display()))?;
+    held.save(&path)?;
+
+    let data = fs::read(&archived).with_context(|| format!("Failed to read {}", archived.display()))?;
+    remember(config, &region, date, &data, &image.mime_type, &image.puzzle);
+    if let Some(record) = image.record {
+        learn(config, &region, record);
+    }
+    let filename = archived.to_string_lossy().into_owned();
+    tracing::info!("Approved {}", filename);
+    let artifact = Artifact::new(filename.as_str(), image.mime_type.as_str()).dated(date);
+    let processors = config.processing.processors();
+    let deliveries = pipeline::run_processed(artifact, processors, sinks, &config.processing, None)
+        .await
+        .context("The crossword is approved and archived, but uploading it failed; `repair` uploads it again")?;
+    record_uploads(config, region.name, date, &deliveries);
+    Ok(filename)
+}
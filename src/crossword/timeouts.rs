@@ -0,0 +1,173 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::error::CrosswordError;
+
+/// The `[timeouts]` section of the config, in seconds. A phase without a limit takes as
+/// long as it needs, so one slow phase can use up a Lambda or cron window unless set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    /// Looking through the page maps for a region, over all pages scanned
+    pub scan_secs: Option<u64>,
+    /// Opening the article and downloading the image of a region, every part of a split puzzle included
+    pub download_secs: Option<u64>,
+    /// Uploading one file to every sink
+    pub upload_secs: Option<u64>,
+    /// The whole run, features and report included
+    pub run_secs: Option<u64>,
+}
+
+/// A stretch of a run with its own time limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Scan,
+    Download,
+    Upload,
+    Run,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [Phase::Scan, Phase::Download, Phase::Upload, Phase::Run];
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Scan => "page scan",
+            Phase::Download => "image download",
+            Phase::Upload => "upload",
+            Phase::Run => "run",
+        })
+    }
+}
+
+impl TimeoutsConfig {
+    pub fn limit(&self, phase: Phase) -> Option<Duration> {
+        let secs = match phase {
+            Phase::Scan => self.scan_secs,
+            Phase::Download => self.download_secs,
+            Phase::Upload => self.upload_secs,
+            Phase::Run => self.run_secs,
+        };
+        secs.map(Duration::from_secs)
+    }
+
+    /// Starts timing `phase`
+    pub fn start(&self, phase: Phase) -> Budget {
+        Budget {
+            phase,
+            limit: self.limit(phase).map(|limit| (limit, Instant::now() + limit)),
+        }
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for phase in Phase::ALL {
+            if self.limit(phase) == Some(Duration::ZERO) {
+                problems.push(format!("[timeouts] the {} limit must be more than 0 seconds", phase));
+            }
+        }
+        if let Some(run) = self.limit(Phase::Run) {
+            for phase in [Phase::Scan, Phase::Download, Phase::Upload] {
+                if self.limit(phase).is_some_and(|limit| limit > run) {
+                    problems.push(format!("[timeouts] the {} limit is longer than the whole run's", phase));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// The time left for a phase, counted from when it started
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    phase: Phase,
+    limit: Option<(Duration, Instant)>,
+}
+
+impl Budget {
+    /// Runs `future`, giving up with [`CrosswordError::TimedOut`] once the phase is out of time
+    pub async fn run<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some(deadline) = self.deadline() else {
+            return future.await;
+        };
+        match tokio::time::timeout_at(deadline, future).await {
+            Ok(result) => result,
+            Err(_) => Err(self.timed_out().into()),
+        }
+    }
+
+    /// When the phase runs out of time, if it has a limit
+    pub fn deadline(&self) -> Option<Instant> {
+        self.limit.map(|(_, deadline)| deadline)
+    }
+
+    pub fn expired(&self) -> bool {
+        self.deadline().is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// The error for the phase going past its limit
+    pub fn timed_out(&self) -> CrosswordError {
+        CrosswordError::TimedOut {
+            phase: self.phase.to_string(),
+            limit: self.limit.map_or(Duration::ZERO, |(limit, _)| limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_budget_is_shared_by_the_phase() {
+        let limit = Duration::from_millis(100);
+        let scan = Budget {
+            phase: Phase::Scan,
+            limit: Some((limit, Instant::now() + limit)),
+        };
+        let page = || async {
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            Ok(())
+        };
+
+        scan.run(page()).await.unwrap();
+        scan.run(page()).await.unwrap();
+        // The third page would end past the limit of the scan as a whole
+        let error = scan.run(page()).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CrosswordError>(),
+            Some(CrosswordError::TimedOut { phase, .. }) if phase == "page scan"
+        ));
+        assert!(crate::error::is_retryable(&error));
+
+        // Phases without a limit aren't cut off
+        let upload = TimeoutsConfig::default().start(Phase::Upload);
+        assert!(upload.limit.is_none());
+        upload.run(page()).await.unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(TimeoutsConfig::default().validate().is_empty());
+
+        let timeouts = TimeoutsConfig {
+            scan_secs: Some(0),
+            upload_secs: Some(120),
+            run_secs: Some(60),
+            ..Default::default()
+        };
+        assert_eq!(
+            timeouts.validate(),
+            vec![
+                "[timeouts] the page scan limit must be more than 0 seconds",
+                "[timeouts] the upload limit is longer than the whole run's",
+            ]
+        );
+    }
+}
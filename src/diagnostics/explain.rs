@@ -0,0 +1,160 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::PageCandidates;
+use crate::parser::EdgeCheck;
+
+/// How the candidate ranking is printed
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExplainFormat {
+    Table,
+    Json,
+}
+
+/// What the scan made of one area
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// The area that was downloaded
+    Selected,
+    /// Within tolerance, but an earlier area was picked (or it has no link)
+    MatchIgnored,
+    Rejected,
+    InvalidCoords,
+}
+
+/// One row of the explanation
+#[derive(Debug, Serialize)]
+pub struct Explanation<'a> {
+    pub page: u32,
+    pub coords: &'a str,
+    pub href: Option<&'a str>,
+    pub score: u8,
+    pub checks: &'a [EdgeCheck],
+    pub decision: Decision,
+}
+
+/// Judges every area of every scanned page, marking the one the scan picks
+pub fn explain(pages: &[PageCandidates]) -> Vec<Explanation<'_>> {
+    let mut selected = false;
+    let mut rows = Vec::new();
+    for page in pages {
+        for candidate in &page.candidates {
+            let decision = if candidate.checks.is_empty() {
+                Decision::InvalidCoords
+            } else if !candidate.matches {
+                Decision::Rejected
+            } else if !selected && candidate.href.is_some() {
+                selected = true;
+                Decision::Selected
+            } else {
+                Decision::MatchIgnored
+            };
+            rows.push(Explanation {
+                page: page.page,
+                coords: &candidate.coords,
+                href: candidate.href.as_deref(),
+                score: candidate.score,
+                checks: &candidate.checks,
+                decision,
+            });
+        }
+    }
+    rows
+}
+
+pub fn render(pages: &[PageCandidates], format: ExplainFormat) -> Result<String> {
+    let rows = explain(pages);
+    match format {
+        ExplainFormat::Json => Ok(serde_json::to_string_pretty(&rows)?),
+        ExplainFormat::Table => Ok(table(&rows)),
+    }
+}
+
+fn table(rows: &[Explanation]) -> String {
+    let mut lines = vec![format!(
+        "{:>4}  {:<22}  {:<10}  {:<10}  {:<10}  {:<10}  {:>5}  {}",
+        "PAGE", "COORDS", "X1", "Y1", "X2", "Y2", "SCORE", "DECISION"
+    )];
+    for row in rows {
+        let mut checks: Vec<String> = row
+            .checks
+            .iter()
+            .map(|check| {
+                let mark = if check.passed { "ok" } else { "FAIL" };
+                format!("{} {:+}", mark, i64::from(check.value) - i64::from(check.expected))
+            })
+            .collect();
+        checks.resize(4, "-".to_string());
+        let decision = match row.decision {
+            Decision::Selected => "selected",
+            Decision::MatchIgnored => "match, not used",
+            Decision::Rejected => "rejected",
+            Decision::InvalidCoords => "invalid coords",
+        };
+        lines.push(format!(
+            "{:>4}  {:<22}  {:<10}  {:<10}  {:<10}  {:<10}  {:>5}  {}",
+            row.page, row.coords, checks[0], checks[1], checks[2], checks[3], row.score, decision
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::types::TargetProfile;
+
+    fn pages() -> Vec<PageCandidates> {
+        let profile = TargetProfile::default();
+        vec![
+            PageCandidates {
+                page: 1,
+                candidates: parser::list_candidates(
+                    r#"<area coords="0,89,1255,1683" href="a1"><area coords="garbage" href="a2">"#,
+                    &profile,
+                ),
+            },
+            PageCandidates {
+                page: 2,
+                candidates: parser::list_candidates(
+                    r#"<area coords="4,1672,997,2778" href="b2"><area coords="0,1625,1000,2775" href="b3">"#,
+                    &profile,
+                ),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_explain_decisions() {
+        let pages = pages();
+        let decisions: Vec<_> = explain(&pages).iter().map(|row| row.decision).collect();
+
+        assert_eq!(
+            decisions,
+            vec![Decision::Rejected, Decision::InvalidCoords, Decision::Selected, Decision::MatchIgnored]
+        );
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render(&pages(), ExplainFormat::Table).unwrap();
+        let lines: Vec<_> = table.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].contains("FAIL -1536"));
+        assert!(lines[3].contains("ok +4") && lines[3].ends_with("selected"));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json: serde_json::Value = serde_json::from_str(&render(&pages(), ExplainFormat::Json).unwrap()).unwrap();
+
+        assert_eq!(json[2]["decision"], "selected");
+        assert_eq!(json[2]["checks"][0]["edge"], "x1");
+        assert_eq!(json[2]["checks"][0]["passed"], true);
+    }
+}
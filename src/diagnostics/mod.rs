@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::archive;
+use crate::config::Config;
+use crate::crossword;
+use crate::http::vcr::{Cassette, Interaction, RecordedRequest, RecordedResponse};
+use crate::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::parser::{self, Candidate};
+use crate::types::TargetProfile;
+
+pub mod explain;
+pub mod preflight;
+pub mod probe;
+
+/// Form field of the page-map request that carries the page number
+const PAGE_FIELD: &str = "get_mapping_coords_page=";
+
+/// Shown instead of values that could identify the user or their Drive
+const REDACTED: &str = "<redacted>";
+
+/// Line of `version.txt` giving the date the bundle was made for
+const DATE_LINE: &str = "date: ";
+
+/// One request of the run and how long it took
+#[derive(Debug, Serialize)]
+struct Timing {
+    method: String,
+    url: String,
+    status: Option<u16>,
+    started_ms: u128,
+    elapsed_ms: u128,
+}
+
+/// The areas found on one page map
+#[derive(Debug, Serialize)]
+pub struct PageCandidates {
+    pub page: u32,
+    pub candidates: Vec<Candidate>,
+}
+
+/// Passes requests through while keeping every exchange and its timing for inspection
+pub struct Capture<C> {
+    inner: C,
+    start: Instant,
+    interactions: Mutex<Vec<Interaction>>,
+    timings: Mutex<Vec<Timing>>,
+}
+
+impl<C: HttpClient> Capture<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            interactions: Mutex::new(Vec::new()),
+            timings: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Candidate areas of every page map fetched so far, judged against `profile`
+    pub fn page_candidates(&self, profile: &TargetProfile) -> Vec<PageCandidates> {
+        candidates(&self.interactions.lock().unwrap(), profile)
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Capture<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let recorded_request = RecordedRequest::from(&request);
+        let started = Instant::now();
+        let response = self.inner.send(request).await;
+
+        self.timings.lock().unwrap().push(Timing {
+            method: recorded_request.method.clone(),
+            url: recorded_request.url.clone(),
+            status: response.as_ref().ok().map(|r| r.status),
+            started_ms: started.duration_since(self.start).as_millis(),
+            elapsed_ms: started.elapsed().as_millis(),
+        });
+        if let Ok(response) = &response {
+            self.interactions.lock().unwrap().push(Interaction {
+                request: recorded_request,
+                response: RecordedResponse::from(response),
+            });
+        }
+
+        response
+    }
+}
+
+/// Runs detection for `date` without uploading and zips up everything needed to
+/// debug it: sanitized config, candidate areas, captured HTML, timings and versions
+pub async fn create_bundle(client: Box<dyn HttpClient>, date: NaiveDate, config: &Config, path: &Path) -> Result<()> {
+    // Keep the archive untouched; the image ends up in the captured responses anyway
+    let scratch = tempfile::tempdir()?;
+    let run_config = Config {
+        output_dir: scratch.path().to_path_buf(),
+        ..config.clone()
+    };
+
+    let capture = Capture::new(client);
+    let outcome = match crossword::fetch_crossword(&capture, date, &run_config).await {
+        Ok(download) => format!(
+            "found on page {} at {} ({})",
+            download.page, download.image_url, download.mime_type
+        ),
+        Err(e) => format!("failed: {:#}", e),
+    };
+    let total_ms = capture.start.elapsed().as_millis();
+
+    let interactions = capture.interactions.into_inner().unwrap();
+    let timings = capture.timings.into_inner().unwrap();
+
+    let mut entries: Vec<(String, Vec<u8>)> = vec![
+        ("version.txt".to_string(), version_info(date, &outcome, total_ms).into_bytes()),
+        ("config.toml".to_string(), toml::to_string(&sanitize(config))?.into_bytes()),
+        (
+            "candidates.json".to_string(),
+            serde_json::to_vec_pretty(&candidates(&interactions, &config.target))?,
+        ),
+        ("timings.json".to_string(), serde_json::to_vec_pretty(&timings)?),
+    ];
+    for (index, interaction) in interactions.iter().enumerate() {
+        if !interaction.response.base64 {
+            entries.push((
+                format!("html/{:02}-{}.html", index + 1, page_label(&interaction.request)),
+                interaction.response.body.clone().into_bytes(),
+            ));
+        }
+    }
+    // Replayable with --replay-http
+    let cassette = Cassette { interactions };
+    entries.push(("http.json".to_string(), serde_json::to_vec_pretty(&cassette)?));
+
+    archive::write_zip(path, &entries)
+}
+
+/// A debug bundle read back for `--replay`: the date it was made for and every exchange
+/// with the site, so the run can be repeated without the network
+pub struct Bundle {
+    pub date: NaiveDate,
+    pub cassette: Cassette,
+}
+
+impl Bundle {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut archive =
+            zip::ZipArchive::new(file).with_context(|| format!("{} is not a debug bundle", path.display()))?;
+        let mut read = |name: &str| -> Result<String> {
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .with_context(|| format!("{} has no {}", path.display(), name))?
+                .read_to_string(&mut contents)?;
+            Ok(contents)
+        };
+
+        let version = read("version.txt")?;
+        let date = version
+            .lines()
+            .find_map(|line| line.strip_prefix(DATE_LINE))
+            .with_context(|| format!("{} doesn't say which date it was made for", path.display()))?
+            .parse()
+            .context("Invalid date in version.txt")?;
+        let cassette = serde_json::from_str(&read("http.json")?).context("Failed to parse http.json")?;
+        Ok(Self { date, cassette })
+    }
+}
+
+fn version_info(date: NaiveDate, outcome: &str, total_ms: u128) -> String {
+    format!(
+        "{} {}\nos: {} {}\n{}{}\ngenerated: {}\noutcome: {}\ntotal time: {} ms\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        DATE_LINE,
+        date,
+        chrono::Utc::now().to_rfc3339(),
+        outcome,
+        total_ms
+    )
+}
+
+/// Removes values that identify the user: the Drive folder and polite-mode contact details
+fn sanitize(config: &Config) -> Config {
+    let mut config = config.clone();
+    config.drive_folder_id = config.drive_folder_id.map(|_| REDACTED.to_string());
+    config.polite.from = config.polite.from.map(|_| REDACTED.to_string());
+    for value in config.polite.headers.values_mut() {
+        *value = REDACTED.to_string();
+    }
+    config
+}
+
+fn page_number(request: &RecordedRequest) -> Option<u32> {
+    let body = request.body.as_deref()?;
+    let start = body.find(PAGE_FIELD)? + PAGE_FIELD.len();
+    body[start..].split('&').next()?.parse().ok()
+}
+
+fn page_label(request: &RecordedRequest) -> String {
+    match page_number(request) {
+        Some(page) => format!("map-page-{}", page),
+        None => "article".to_string(),
+    }
+}
+
+/// Every area on every page map the run looked at, marked with whether it matched
+fn candidates(interactions: &[Interaction], profile: &TargetProfile) -> Vec<PageCandidates> {
+    interactions
+        .iter()
+        .filter_map(|interaction| {
+            Some(PageCandidates {
+                page: page_number(&interaction.request)?,
+                candidates: parser::list_candidates(&interaction.response.body, profile),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::vcr::Replayer;
+    use zip::ZipArchive;
+
+    fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> String {
+        let mut contents = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[tokio::test]
+    async fn test_create_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_slice_2024-03-20.json");
+        let client = Box::new(Replayer::load(&fixture).unwrap());
+        let mut config = Config {
+            drive_folder_id: Some("1AbCdEfGhIjKlMnOpQrStUvWxYz012345".to_string()),
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.polite.from = Some("reader@example.com".to_string());
+        config.features.insert("cartoon".to_string(), Default::default());
+        let bundle = dir.path().join("bundle.zip");
+
+        create_bundle(client, NaiveDate::from_ymd_opt(2024, 3, 20).unwrap(), &config, &bundle)
+            .await
+            .unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&bundle).unwrap()).unwrap();
+        assert!(read_entry(&mut archive, "version.txt").contains("found on page 2"));
+
+        let config_toml = read_entry(&mut archive, "config.toml");
+        assert!(!config_toml.contains("1AbCdEfGh"));
+        assert!(!config_toml.contains("reader@example.com"));
+        assert!(config_toml.contains("[features.cartoon]"));
+
+        let candidates: serde_json::Value = serde_json::from_str(&read_entry(&mut archive, "candidates.json")).unwrap();
+        assert_eq!(candidates.as_array().unwrap().len(), 2);
+        assert_eq!(candidates[1]["page"], 2);
+
+        // The captured exchanges replay like any other fixture
+        let cassette: Cassette = serde_json::from_str(&read_entry(&mut archive, "http.json")).unwrap();
+        assert_eq!(cassette.interactions.len(), 4);
+        assert!(archive.file_names().any(|name| name == "html/01-map-page-1.html"));
+
+        // Nothing was written to the real output directory
+        assert!(!dir.path().join("crossword_2024-03-20.jpg").exists());
+
+        // Read back for --replay, the run finds the same crossword
+        let bundle = Bundle::load(&bundle).unwrap();
+        assert_eq!(bundle.date, NaiveDate::from_ymd_opt(2024, 3, 20).unwrap());
+        let replayer = Replayer::new(bundle.cassette);
+        let download = crossword::fetch_crossword(&replayer, bundle.date, &config).await.unwrap();
+        assert_eq!(download.page, 2);
+    }
+
+    #[test]
+    fn test_page_number() {
+        let request = RecordedRequest {
+            method: "POST".to_string(),
+            url: "https://www.ehitavada.com/val.php".to_string(),
+            body: Some("get_mapping_coords_prefix=Mpage&get_mapping_coords_page=12".to_string()),
+        };
+        assert_eq!(page_number(&request), Some(12));
+
+        let article = RecordedRequest { body: None, ..request };
+        assert_eq!(page_number(&article), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! A quick look at the network before a run: every host the run is going to talk to is
+//! resolved and connected to, so a flaky connection fails in seconds with the host at fault
+//! instead of somewhere in the middle of the pipeline.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::task::JoinSet;
+
+use crate::config::Config;
+use crate::console;
+use crate::error::CrosswordError;
+use crate::pipeline::Sink;
+use crate::signed_url::SignedUrlMode;
+
+const HTTPS_PORT: u16 = 443;
+
+/// The `[preflight]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreflightConfig {
+    /// Check the network before every run; `--preflight` does it for one run
+    pub enabled: bool,
+    /// How long each host may take to resolve, and then to accept a connection
+    pub timeout_secs: u64,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: 5,
+        }
+    }
+}
+
+impl PreflightConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if self.timeout_secs == 0 {
+            return vec!["[preflight] timeout_secs must be above 0".to_string()];
+        }
+        Vec::new()
+    }
+}
+
+/// A host the run needs, and what for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub purpose: &'static str,
+    pub host: String,
+    /// 443, unless a self-hosted server listens elsewhere
+    pub port: u16,
+}
+
+/// How one host did
+#[derive(Debug)]
+pub struct HostCheck {
+    pub endpoint: Endpoint,
+    /// Time to resolve and time to connect, or what went wrong
+    pub outcome: Result<(Duration, Duration), String>,
+}
+
+/// The hosts a run talks to: the newspaper, then whatever `sinks` upload to and the
+/// services configured to hear about the run
+pub fn endpoints(config: &Config, sinks: &[&str]) -> Vec<Endpoint> {
+    let mut endpoints = Vec::new();
+    let mut add = |purpose, host: &str, port| {
+        if !endpoints.iter().any(|endpoint: &Endpoint| endpoint.host == host && endpoint.port == port) {
+            endpoints.push(Endpoint { purpose, host: host.to_string(), port });
+        }
+    };
+    if let Some(host) = reqwest::Url::parse(&config.site.mapping.url).ok().as_ref().and_then(|url| url.host_str()) {
+        add("newspaper", host, HTTPS_PORT);
+    }
+    if sinks.contains(&"drive") {
+        add("Google sign-in", "oauth2.googleapis.com", HTTPS_PORT);
+        add("Google Drive", "www.googleapis.com", HTTPS_PORT);
+    }
+    if sinks.contains(&"s3") && config.signed_url.mode == SignedUrlMode::S3 {
+        if let Some(bucket) = &config.signed_url.bucket {
+            add("S3", &format!("{}.s3.amazonaws.com", bucket), HTTPS_PORT);
+        }
+    }
+    if sinks.contains(&"dropbox") {
+        add("Dropbox", "api.dropboxapi.com", HTTPS_PORT);
+        add("Dropbox uploads", "content.dropboxapi.com", HTTPS_PORT);
+        if config.dropbox.as_ref().is_some_and(|dropbox| dropbox.app_key.is_some()) {
+            add("Dropbox sign-in", "api.dropbox.com", HTTPS_PORT);
+        }
+    }
+    if sinks.contains(&"onedrive") {
+        add("Microsoft sign-in", "login.microsoftonline.com", HTTPS_PORT);
+        add("Microsoft Graph", "graph.microsoft.com", HTTPS_PORT);
+    }
+    if sinks.contains(&"webdav") {
+        let url = config.webdav.as_ref().and_then(|webdav| reqwest::Url::parse(&webdav.url).ok());
+        if let Some(url) = url {
+            if let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) {
+                add("WebDAV", host, port);
+            }
+        }
+    }
+    if let Some(sftp) = config.sftp.as_ref().filter(|_| sinks.contains(&"sftp")) {
+        add("SFTP", &sftp.host, sftp.port);
+    }
+    if config.notion.is_some() {
+        add("Notion", "api.notion.com", HTTPS_PORT);
+    }
+    if config.airtable.is_some() {
+        add("Airtable", "api.airtable.com", HTTPS_PORT);
+    }
+    if config.telegram.is_some() {
+        add("Telegram", "api.telegram.org", HTTPS_PORT);
+    }
+    if let Some(email) = &config.email {
+        add("SMTP", &email.host, email.port);
+    }
+    endpoints
+}
+
+/// Resolves and connects to every endpoint at once, reporting each in the order given
+pub async fn check_all(endpoints: Vec<Endpoint>, timeout: Duration) -> Vec<HostCheck> {
+    let mut tasks = JoinSet::new();
+    for (index, endpoint) in endpoints.into_iter().enumerate() {
+        tasks.spawn(async move {
+            let outcome = check(&endpoint.host, endpoint.port, timeout).await.map_err(|e| format!("{:#}", e));
+            (index, HostCheck { endpoint, outcome })
+        });
+    }
+    let mut checks: Vec<(usize, HostCheck)> = tasks.join_all().await;
+    checks.sort_by_key(|(index, _)| *index);
+    checks.into_iter().map(|(_, check)| check).collect()
+}
+
+async fn check(host: &str, port: u16, timeout: Duration) -> Result<(Duration, Duration)> {
+    let start = Instant::now();
+    let address = tokio::time::timeout(timeout, lookup_host((host, port)))
+        .await
+        .context("timed out resolving")?
+        .context("could not resolve")?
+        .next()
+        .context("resolved to no address")?;
+    let resolved = start.elapsed();
+
+    let start = Instant::now();
+    tokio::time::timeout(timeout, TcpStream::connect(address))
+        .await
+        .with_context(|| format!("timed out connecting to {}", address))?
+        .with_context(|| format!("could not connect to {}", address))?;
+    Ok((resolved, start.elapsed()))
+}
+
+/// Checks the network when `[preflight]` asks for it, printing a line per host and failing
+/// with every unreachable one
+pub async fn run(config: &Config, sinks: &[Arc<dyn Sink>]) -> Result<()> {
+    if !config.preflight.enabled {
+        return Ok(());
+    }
+    let sinks: Vec<&str> = sinks.iter().map(|sink| sink.name()).collect();
+    let checks = check_all(endpoints(config, &sinks), Duration::from_secs(config.preflight.timeout_secs)).await;
+    let mut failed = Vec::new();
+    for check in checks {
+        let Endpoint { purpose, host, .. } = check.endpoint;
+        match check.outcome {
+            Ok((resolved, connected)) => console::progress!(
+                "✓ {} ({}): resolved in {} ms, connected in {} ms",
+                purpose,
+                host,
+                resolved.as_millis(),
+                connected.as_millis()
+            ),
+            Err(error) => {
+                console::progress!("✗ {} ({}): {}", purpose, host, error);
+                failed.push(format!("{} ({}): {}", purpose, host, error));
+            }
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(CrosswordError::Unreachable { hosts: failed }.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telegram::TelegramConfig;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_endpoints() {
+        let config = Config {
+            telegram: Some(TelegramConfig {
+                bot_token: None,
+                chat_id: "42".to_string(),
+                progress_percent: 50,
+                announce: false,
+            }),
+            ..Config::default()
+        };
+        let hosts: Vec<String> = endpoints(&config, &["drive"]).into_iter().map(|endpoint| endpoint.host).collect();
+        assert_eq!(
+            hosts,
+            vec!["www.ehitavada.com", "oauth2.googleapis.com", "www.googleapis.com", "api.telegram.org"]
+        );
+        assert_eq!(endpoints(&Config::default(), &[]).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(check("127.0.0.1", port, Duration::from_secs(5)).await.is_ok());
+
+        drop(listener);
+        let error = check("127.0.0.1", port, Duration::from_secs(5)).await.unwrap_err();
+        assert!(format!("{:#}", error).starts_with("could not connect to 127.0.0.1:"));
+    }
+}
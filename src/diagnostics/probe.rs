@@ -0,0 +1,158 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use scraper::{Html, Selector};
+
+use crate::config::Config;
+use crate::crossword::Region;
+use crate::http::{self, HttpClient, HttpRequest};
+use crate::imaging;
+use crate::parser;
+
+/// One step of the probe and what it found
+#[derive(Debug)]
+pub struct ProbeStep {
+    pub name: &'static str,
+    pub outcome: Result<String, String>,
+}
+
+/// Walks the path of a download for `date` against the live site without saving anything,
+/// checking that the page maps, the crossword profile and the article markup still fit this
+/// version. Stops at the first step that fails.
+pub async fn probe(client: &dyn HttpClient, date: NaiveDate, config: &Config) -> Vec<ProbeStep> {
+    let mut steps = Vec::new();
+    let mut record = |name, outcome: Result<String>| {
+        let ok = outcome.is_ok();
+        steps.push(ProbeStep {
+            name,
+            outcome: outcome.map_err(|e| format!("{:#}", e)),
+        });
+        ok
+    };
+
+    let region = Region::crossword(config);
+    let mut areas = 0;
+    let mut scanned = 0;
+    let mut found = None;
+    for &page in &region.pages {
+        scanned += 1;
+        let html = match page_map(client, date, config, page).await {
+            Ok(html) => html,
+            Err(e) => {
+                record("Fetch page maps", Err(e.context(format!("page {}", page))));
+                return steps;
+            }
+        };
+        areas += parser::list_candidates(&html, region.profile).len();
+        if let Some(target) = parser::find_target(&html, region.profile) {
+            found = Some((page, target));
+            break;
+        }
+    }
+    let maps = match areas {
+        0 => Err(anyhow::anyhow!(
+            "no page map of {} lists any area; the mapping request may have changed, or the edition isn't up yet",
+            date
+        )),
+        _ => Ok(format!("{} areas on {} pages", areas, scanned)),
+    };
+    if !record("Fetch page maps", maps) {
+        return steps;
+    }
+
+    let Some((page, target)) = found else {
+        record(
+            "Locate the crossword",
+            Err(anyhow::anyhow!("no area matches [target]; run with --explain to see how they scored")),
+        );
+        return steps;
+    };
+    let rect = &target.rect;
+    record(
+        "Locate the crossword",
+        Ok(format!("page {} at ({},{},{},{})", page, rect.x1, rect.y1, rect.x2, rect.y2)),
+    );
+
+    let image_url = match article_image(client, &target.hrefs[0]).await {
+        Ok(url) => url,
+        Err(e) => {
+            record("Read the article", Err(e));
+            return steps;
+        }
+    };
+    record("Read the article", Ok(image_url.clone()));
+
+    let image = async {
+        let response = client.send(HttpRequest::get(&image_url).headers(http::create_headers()?)).await?;
+        let mime_type = imaging::sniff_mime_type(&response.body)
+            .with_context(|| format!("{} is not an image (HTTP {})", image_url, response.status))?;
+        Ok(format!("{}, {} bytes", mime_type, response.body.len()))
+    };
+    record("Download the image", image.await);
+    steps
+}
+
+async fn page_map(client: &dyn HttpClient, date: NaiveDate, config: &Config, page: u32) -> Result<String> {
+    let request = config.site.mapping_request(date, page)?.headers(http::create_headers()?);
+    let url = request.url.clone();
+    let response = client.send(request).await?;
+    let html = response.text();
+    if let Some(kind) = parser::detect_interstitial(response.status, &html) {
+        return Err(anyhow::anyhow!("{} returned {} (HTTP {})", url, kind, response.status));
+    }
+    Ok(html)
+}
+
+/// URL of the image on the article page at `href`
+async fn article_image(client: &dyn HttpClient, href: &str) -> Result<String> {
+    let url = format!("https://www.ehitavada.com/{}", href);
+    let response = client.send(HttpRequest::get(&url).headers(http::create_headers()?)).await?;
+    let document = Html::parse_document(&response.text());
+    let selector = Selector::parse(parser::ARTICLE_IMAGE).unwrap();
+    let src = document
+        .select(&selector)
+        .next()
+        .with_context(|| format!("{} has no '{}'", url, parser::ARTICLE_IMAGE))?
+        .value()
+        .attr("src")
+        .with_context(|| format!("The image on {} has no src", url))?;
+    if parser::is_placeholder_image(src) {
+        return Err(anyhow::anyhow!("{} shows the placeholder image; probe an earlier date", url));
+    }
+    Ok(format!("https://www.ehitavada.com/{}", src))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::vcr::Replayer;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_probe_replayed_site() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_slice_2024-03-20.json");
+        let client = Replayer::load(&fixture).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+
+        let steps = probe(&client, date, &Config::default()).await;
+        let names: Vec<&str> = steps.iter().map(|step| step.name).collect();
+        assert_eq!(names, vec!["Fetch page maps", "Locate the crossword", "Read the article", "Download the image"]);
+        assert!(steps.iter().all(|step| step.outcome.is_ok()), "{:?}", steps);
+        assert!(steps[1].outcome.as_ref().unwrap().starts_with("page 2"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_stops_at_unknown_markup() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_slice_2024-03-20.json");
+        let client = Replayer::load(&fixture).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let mut config = Config {
+            pages: vec![1, 2],
+            ..Config::default()
+        };
+        config.target.expected = crate::types::Rect { x1: 2000, y1: 0, x2: 2500, y2: 500 };
+
+        let steps = probe(&client, date, &config).await;
+        assert_eq!(steps.len(), 2);
+        assert!(steps[1].outcome.as_ref().unwrap_err().contains("no area matches [target]"));
+    }
+}
@@ -1,52 +1,400 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use google_drive3::api::Permission;
+use google_drive3::client::ContentRange;
+use std::time::Duration;
 use std::fs;
-use std::env;
 use std::path::Path;
 use std::io::Cursor;
-use aws_sdk_ssm::Client as SsmClient;
-use aws_config::BehaviorVersion;
 use google_drive3::DriveHub;
 use yup_oauth2::ServiceAccountAuthenticator;
+use hyper::client::HttpConnector;
 use hyper::Client;
+use hyper_rustls::HttpsConnector;
+use serde::Deserialize;
+use tokio::sync::{mpsc, OnceCell};
+use yup_oauth2::error::AuthErrorCode;
 
-pub async fn get_google_credentials() -> Result<String> {
-    // In local development, read from file
-    if let Ok(path) = env::var("GOOGLE_SERVICE_ACCOUNT_PATH") {
-        return fs::read_to_string(path)
-            .context("Failed to read Google service account file");
+use crate::clock::{Clock, RunClock};
+use crate::config::Config;
+use crate::console;
+use crate::credentials::{CredentialProvider, CredentialSource};
+use crate::http::backoff::{self, BackoffConfig};
+use crate::pipeline::quota::StorageQuota;
+use crate::pipeline::{Artifact, RemoteCopy, Sink, DRY_RUN_ID};
+use crate::signed_url::SignedUrlMode;
+use crate::telegram::{TelegramConfig, UploadMessage};
+use crate::error::CrosswordError;
+use crate::telemetry::{self, phases};
+
+/// Uploads pipeline artifacts to the configured Google Drive folder
+pub struct DriveSink {
+    folder_id: String,
+    credential_provider: Box<dyn CredentialProvider>,
+    credentials: OnceCell<String>,
+    /// Account given read access to each upload for a while, in `[signed_url]` drive mode
+    expiring_share: Option<(String, Duration)>,
+    /// Tells when the expiring access ends
+    clock: RunClock,
+    /// Where to report uploads that pass a share of their bytes, from `[telegram]`
+    progress: Option<TelegramConfig>,
+    backoff: BackoffConfig,
+    dry_run: bool,
+}
+
+impl DriveSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let folder_id = config
+            .drive_folder_id
+            .clone()
+            .context("GOOGLE_DRIVE_FOLDER_ID environment variable not set")?;
+        Ok(Self {
+            folder_id,
+            credential_provider: CredentialSource::from_config(config).provider(),
+            credentials: OnceCell::new(),
+            expiring_share: match (&config.signed_url.mode, &config.signed_url.email) {
+                (SignedUrlMode::Drive, Some(email)) => Some((email.clone(), config.signed_url.expires_in())),
+                _ => None,
+            },
+            clock: config.clock.clone(),
+            progress: config.telegram.clone(),
+            backoff: config.backoff.clone(),
+            dry_run: false,
+        })
+    }
+
+    /// The account each upload is shared with, and until when, counting from now
+    fn share_until(&self) -> Option<(&str, DateTime<Utc>)> {
+        let (email, expires_in) = self.expiring_share.as_ref()?;
+        Some((email.as_str(), self.clock.now().with_timezone(&Utc) + *expires_in))
+    }
+
+    /// Prints what would be uploaded instead of creating anything in Drive
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    async fn credentials(&self) -> Result<&String> {
+        // Fetch the credentials once and share them across all artifacts of the run
+        self.credentials
+            .get_or_try_init(|| phases::time("credentials", self.credential_provider.fetch()))
+            .await
+    }
+
+    async fn print_plan(&self, artifact: &Artifact) -> Result<String> {
+        let file = file_metadata(&artifact.path, &self.folder_id)?;
+        let size = fs::metadata(&artifact.path)?.len();
+
+        // Resolving the folder needs working credentials; the plan is still useful without it
+        let folder = match self.credentials().await {
+            Ok(credentials) => match build_authenticator(credentials).await {
+                Ok(auth) => folder_name(&build_hub(auth), &self.folder_id).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        let folder = match folder {
+            Ok(name) => format!("'{}' ({})", name, self.folder_id),
+            Err(e) => format!("{} (could not resolve: {:#})", self.folder_id, e),
+        };
+
+        console::progress!("Dry run: would upload {} to Google Drive", artifact.path);
+        console::progress!("  File name: {}", file.name.as_deref().unwrap_or_default());
+        console::progress!("  Parent folder: {}", folder);
+        if let Some(subfolder) = &artifact.folder {
+            console::progress!("  Subfolder: {}", subfolder);
+        }
+        console::progress!("  MIME type: {}", artifact.detected_mime_type()?);
+        console::progress!("  Size: {} bytes", size);
+        // Only the fields we set; the API type serializes every unset field as null
+        let mut metadata = serde_json::to_value(&file)?;
+        if let Some(fields) = metadata.as_object_mut() {
+            fields.retain(|_, value| !value.is_null());
+        }
+        console::progress!("  Metadata: {}", metadata);
+        Ok(DRY_RUN_ID.to_string())
+    }
+
+    /// Every file directly in the upload folder, leaving out subfolders and the trash
+    pub async fn files(&self) -> Result<Vec<DriveFile>> {
+        let hub = build_hub(build_authenticator(self.credentials().await?).await?);
+        let query = format!(
+            "'{}' in parents and mimeType != '{}' and trashed = false",
+            self.folder_id, FOLDER_MIME_TYPE
+        );
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut call = hub
+                .files()
+                .list()
+                .q(&query)
+                .supports_all_drives(true)
+                .include_items_from_all_drives(true)
+                .page_size(1000)
+                .param("fields", "nextPageToken,files(id,name,sha256Checksum,createdTime)");
+            if let Some(token) = &page_token {
+                call = call.page_token(token);
+            }
+            let (_, list) = call.doit().await.context("Failed to list the Drive folder")?;
+            files.extend(list.files.into_iter().flatten().filter_map(|file| {
+                Some(DriveFile {
+                    id: file.id?,
+                    name: file.name.unwrap_or_default(),
+                    sha256: file.sha256_checksum,
+                    created: file.created_time,
+                })
+            }));
+            page_token = list.next_page_token;
+            if page_token.is_none() {
+                return Ok(files);
+            }
+        }
+    }
+
+    /// Moves a file to the trash, where Drive keeps it for 30 days before deleting it
+    pub async fn trash(&self, id: &str) -> Result<()> {
+        let hub = build_hub(build_authenticator(self.credentials().await?).await?);
+        let trashed = google_drive3::api::File {
+            trashed: Some(true),
+            ..Default::default()
+        };
+        hub.files()
+            .update(trashed, id)
+            .supports_all_drives(true)
+            .param("fields", "id")
+            .doit_without_upload()
+            .await
+            .with_context(|| format!("Failed to trash {} in Google Drive", id))?;
+        Ok(())
+    }
+}
+
+/// A file in the upload folder, as listed by [`DriveSink::files`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriveFile {
+    pub id: String,
+    pub name: String,
+    pub sha256: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+impl Sink for DriveSink {
+    fn name(&self) -> &str {
+        "drive"
     }
 
-    // In Lambda, get from SSM Parameter Store
-    let config = aws_config::defaults(BehaviorVersion::latest())
-        .load()
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        if self.dry_run {
+            return self.print_plan(artifact).await;
+        }
+
+        let credentials = self.credentials().await?;
+        let mime_type = artifact.detected_mime_type()?;
+        let parent = match &artifact.folder {
+            Some(name) => subfolder_id(&self.folder_id, name, credentials)
+                .await
+                .with_context(|| format!("Failed to find or create the '{}' folder", name))?,
+            None => self.folder_id.clone(),
+        };
+        let (path, mime_type) = (artifact.path.as_str(), mime_type.as_str());
+        let uploaded = backoff::retry(&self.backoff, "Drive upload", is_unsent, || async {
+            match &self.progress {
+                Some(telegram) => upload_reporting_progress(path, mime_type, &parent, credentials, telegram).await,
+                None => upload_to_drive(path, mime_type, &parent, credentials).await,
+            }
+        })
         .await;
-    
-    let client = SsmClient::new(&config);
-    
-    let parameter = client
-        .get_parameter()
-        .name("/hitavada-crossword/google-service-account")
-        .with_decryption(true)
-        .send()
-        .await?;
-    
-    let value = parameter.parameter()
-        .and_then(|p| p.value())
-        .context("Parameter value is empty")?;
-    
-    Ok(value.to_string())
+        let file_id = uploaded.map_err(|e| check_rejected(e, credentials)).map_err(explain_full)?;
+        console::progress!("File uploaded to Google Drive with ID: {}", file_id);
+        if let Some((email, expires_at)) = self.share_until() {
+            grant_expiring_access(&file_id, email, expires_at, credentials)
+                .await
+                .with_context(|| format!("Failed to share {} with {}", file_id, email))?;
+            console::progress!("Shared with {} until {}", email, expires_at.to_rfc3339());
+        }
+        Ok(file_id)
+    }
+
+    async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+        if id == DRY_RUN_ID {
+            return Ok(RemoteCopy::Missing);
+        }
+        let hub = build_hub(build_authenticator(self.credentials().await?).await?);
+        let found = hub
+            .files()
+            .get(id)
+            .supports_all_drives(true)
+            .param("fields", "sha256Checksum,trashed")
+            .doit()
+            .await;
+        match found {
+            Ok((_, file)) if file.trashed == Some(true) => Ok(RemoteCopy::Missing),
+            Ok((_, file)) => Ok(RemoteCopy::Present { sha256: file.sha256_checksum }),
+            Err(e) if is_not_found(&e) => Ok(RemoteCopy::Missing),
+            Err(e) => Err(e).with_context(|| format!("Failed to look up {} in Google Drive", id)),
+        }
+    }
+
+    /// Every upload to Drive adds a file, so the one replaced goes to the trash
+    async fn remove(&self, id: &str) -> Result<()> {
+        if id == DRY_RUN_ID {
+            return Ok(());
+        }
+        self.trash(id).await
+    }
+
+    async fn quota(&self) -> Result<Option<StorageQuota>> {
+        if self.dry_run {
+            return Ok(None);
+        }
+        let hub = build_hub(build_authenticator(self.credentials().await?).await?);
+        // Uploads to a shared drive belong to the drive, and count against the organisation's
+        // pooled storage, which Drive doesn't report
+        let (_, folder) = hub
+            .files()
+            .get(&self.folder_id)
+            .supports_all_drives(true)
+            .param("fields", "driveId")
+            .doit()
+            .await
+            .context("Failed to look up the Drive folder")?;
+        if let Some(drive_id) = folder.drive_id {
+            tracing::info!("The Drive folder is on shared drive {}, whose storage Drive doesn't report", drive_id);
+            return Ok(None);
+        }
+        // Elsewhere files the service account uploads are its own, so its quota is the one
+        // that runs out
+        let (_, about) = hub
+            .about()
+            .get()
+            .param("fields", "storageQuota,user(emailAddress)")
+            .doit()
+            .await
+            .context("Failed to read the Drive storage quota")?;
+        let storage = about.storage_quota.unwrap_or_default();
+        Ok(Some(StorageQuota {
+            sink: self.name().to_string(),
+            account: about.user.and_then(|user| user.email_address),
+            used_bytes: storage.usage.unwrap_or_default().max(0) as u64,
+            limit_bytes: storage.limit.map(|limit| limit.max(0) as u64),
+        }))
+    }
+}
+
+/// Adds a plain explanation to an upload Drive refused because the account's storage is full
+fn explain_full(error: anyhow::Error) -> anyhow::Error {
+    let full = error.chain().any(|cause| match cause.downcast_ref::<google_drive3::Error>() {
+        Some(google_drive3::Error::BadRequest(body)) => body["error"]["errors"]
+            .as_array()
+            .is_some_and(|errors| errors.iter().any(|e| e["reason"] == "storageQuotaExceeded")),
+        _ => false,
+    });
+    match full {
+        true => error.context("Drive storage is full; free up space or move the uploads to a shared drive"),
+        false => error,
+    }
+}
+
+/// Whether Drive answered that the file doesn't exist, or isn't visible to the account
+fn is_not_found(error: &google_drive3::Error) -> bool {
+    match error {
+        google_drive3::Error::BadRequest(body) => body["error"]["code"] == 404,
+        google_drive3::Error::Failure(response) => response.status() == hyper::StatusCode::NOT_FOUND,
+        _ => false,
+    }
+}
+
+/// Identifying fields of a service account key; Google's key files carry no expiry date
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KeyMetadata {
+    pub client_email: String,
+    pub private_key_id: String,
+}
+
+pub fn key_metadata(credentials: &str) -> Result<KeyMetadata> {
+    serde_json::from_str(credentials).context("The service account key lacks client_email or private_key_id")
 }
 
-pub async fn upload_to_drive(filename: &str, credentials: &str) -> Result<String> {
-    let folder_id = env::var("GOOGLE_DRIVE_FOLDER_ID")
-        .context("GOOGLE_DRIVE_FOLDER_ID environment variable not set")?;
+/// Whether a failed Drive upload can be made again without risking a second copy. Creating a
+/// file isn't idempotent, so once Drive may have taken the request (a 5xx, or the connection
+/// dropping mid-way) trying again could add the file twice; only a connection that never
+/// opened, or Drive turning the request away as too many, is safe to repeat.
+fn is_unsent(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<google_drive3::Error>() {
+        Some(google_drive3::Error::HttpError(e)) => e.is_connect(),
+        Some(google_drive3::Error::Failure(response)) => response.status() == hyper::StatusCode::TOO_MANY_REQUESTS,
+        _ => false,
+    })
+}
+
+/// The token endpoint's explanation when it refused the key itself, rather than the request
+/// failing for some other reason (network, quota, folder permissions, ...)
+fn rejection_reason(error: &anyhow::Error) -> Option<String> {
+    let token_error = error.chain().find_map(|cause| match cause.downcast_ref::<google_drive3::Error>() {
+        Some(google_drive3::Error::MissingToken(e)) => e.downcast_ref::<yup_oauth2::Error>(),
+        _ => cause.downcast_ref::<yup_oauth2::Error>(),
+    })?;
+    let yup_oauth2::Error::AuthError(auth) = token_error else {
+        return None;
+    };
+    match auth.error {
+        // invalid_grant covers deleted keys ("Invalid JWT Signature.") and deleted or disabled accounts
+        AuthErrorCode::InvalidGrant | AuthErrorCode::InvalidClient | AuthErrorCode::UnauthorizedClient => Some(
+            auth.error_description
+                .clone()
+                .unwrap_or_else(|| auth.error.as_str().to_string()),
+        ),
+        _ => None,
+    }
+}
+
+/// Turns a refused key into [`CrosswordError::CredentialsRejected`] and raises the alarm;
+/// other errors pass through unchanged
+fn check_rejected(error: anyhow::Error, credentials: &str) -> anyhow::Error {
+    let Some(reason) = rejection_reason(&error) else {
+        return error;
+    };
+    let key = key_metadata(credentials).unwrap_or_else(|_| KeyMetadata {
+        client_email: "unknown account".to_string(),
+        private_key_id: "unknown".to_string(),
+    });
+    tracing::error!(
+        account = %key.client_email,
+        key_id = %key.private_key_id,
+        "Service account key rejected ({}); rotate it today",
+        reason
+    );
+    telemetry::record_credentials_rejected(&key.client_email);
+    CrosswordError::CredentialsRejected {
+        account: key.client_email,
+        key_id: key.private_key_id,
+        reason,
+    }
+    .into()
+}
+
+type Hub = DriveHub<HttpsConnector<HttpConnector>>;
+type Authenticator = yup_oauth2::authenticator::Authenticator<google_drive3::hyper_rustls::HttpsConnector<HttpConnector>>;
+
+const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive";
 
-    // Create authenticator
+/// Link to an uploaded file in the Drive web UI; dry runs have nothing to link to
+pub fn file_url(id: &str) -> Option<String> {
+    (id != DRY_RUN_ID).then(|| format!("https://drive.google.com/file/d/{}/view", id))
+}
+
+async fn build_authenticator(credentials: &str) -> Result<Authenticator> {
     let sa_key = serde_json::from_str(credentials)?;
-    let auth = ServiceAccountAuthenticator::builder(sa_key)
+    Ok(ServiceAccountAuthenticator::builder(sa_key)
         .build()
-        .await?;
+        .await?)
+}
 
+fn build_hub(auth: Authenticator) -> Hub {
     // Create Drive client with hyper
     let https = hyper_rustls::HttpsConnectorBuilder::new()
         .with_native_roots()
@@ -57,37 +405,278 @@ pub async fn upload_to_drive(filename: &str, credentials: &str) -> Result<String
     let client = Client::builder()
         .build(https);
 
-    let hub = DriveHub::new(client, auth);
+    DriveHub::new(client, auth)
+}
 
-    // Read file
-    let file_content = fs::read(filename)?;
+/// One step of the credential check and what it found
+#[derive(Debug)]
+pub struct VerificationStep {
+    pub name: &'static str,
+    pub outcome: Result<String, String>,
+}
+
+/// Walks the whole authentication chain, stopping at the first step that fails
+pub async fn verify_credentials(config: &Config) -> Vec<VerificationStep> {
+    let mut steps = Vec::new();
+    let mut record = |name, outcome: Result<String>| {
+        let ok = outcome.is_ok();
+        steps.push(VerificationStep {
+            name,
+            outcome: outcome.map_err(|e| format!("{:#}", e)),
+        });
+        ok
+    };
+
+    let provider = CredentialSource::from_config(config).provider();
+    let source = provider.describe();
+    let credentials = provider.fetch().await;
+    let credentials = match credentials {
+        Ok(credentials) => {
+            record("Load service account", Ok(format!("read from {}", source)));
+            credentials
+        }
+        Err(e) => {
+            record("Load service account", Err(e.context(format!("reading {}", source))));
+            return steps;
+        }
+    };
+
+    let auth = match build_authenticator(&credentials).await {
+        Ok(auth) => {
+            let key = key_metadata(&credentials)
+                .map(|key| format!("{} (key {})", key.client_email, key.private_key_id))
+                .unwrap_or_default();
+            record("Parse service account key", Ok(key));
+            auth
+        }
+        Err(e) => {
+            record("Parse service account key", Err(e));
+            return steps;
+        }
+    };
+
+    let token = auth.token(&[DRIVE_SCOPE]).await;
+    let token = token
+        .map(|_| "granted".to_string())
+        .map_err(|e| check_rejected(e.into(), &credentials));
+    if !record("Obtain access token", token) {
+        return steps;
+    }
+
+    let hub = build_hub(auth);
+    let about = hub.about().get().param("fields", "user(emailAddress)").doit().await;
+    let about = about
+        .map(|(_, about)| {
+            let email = about.user.and_then(|u| u.email_address).unwrap_or_default();
+            format!("authenticated as {}", email)
+        })
+        .map_err(Into::into);
+    if !record("Call Drive about.get", about) {
+        return steps;
+    }
+
+    if let Some(folder_id) = &config.drive_folder_id {
+        let folder = folder_name(&hub, folder_id)
+            .await
+            .map(|name| format!("can see folder '{}'", name))
+            .map_err(|e| e.context("share the folder with the service account email"));
+        record("Access upload folder", folder);
+    }
+
+    steps
+}
+
+async fn folder_name(hub: &Hub, folder_id: &str) -> Result<String> {
+    let (_, folder) = hub
+        .files()
+        .get(folder_id)
+        .supports_all_drives(true)
+        .param("fields", "name")
+        .doit()
+        .await?;
+    Ok(folder.name.unwrap_or_default())
+}
+
+/// The Drive metadata a local file is created with
+fn file_metadata(filename: &str, folder_id: &str) -> Result<google_drive3::api::File> {
     let file_name = Path::new(filename)
         .file_name()
         .and_then(|n| n.to_str())
         .context("Invalid filename")?;
 
-    // Create file metadata
-    let file = google_drive3::api::File {
+    Ok(google_drive3::api::File {
         name: Some(file_name.to_string()),
-        parents: Some(vec![folder_id]),
+        parents: Some(vec![folder_id.to_string()]),
         ..Default::default()
-    };
+    })
+}
+
+pub async fn upload_to_drive(filename: &str, mime_type: &str, folder_id: &str, credentials: &str) -> Result<String> {
+    let auth = build_authenticator(credentials).await?;
+    let hub = build_hub(auth);
+
+    // Read file
+    let file_content = fs::read(filename)?;
+    let file = file_metadata(filename, folder_id)?;
 
     // Upload file using Cursor
     let cursor = Cursor::new(file_content);
     let (_, file) = hub
         .files()
         .create(file)
-        .upload(cursor, "image/jpeg".parse()?)
+        .upload(cursor, mime_type.parse()?)
         .await?;
 
     Ok(file.id.unwrap_or_default())
 }
 
+/// Chunk size of uploads whose progress is reported, the smallest Drive takes
+const PROGRESS_CHUNK: u64 = 1 << 18;
+
+/// Passes the share of bytes sent so far on before each chunk of a resumable upload
+struct ChunkProgress(mpsc::UnboundedSender<u8>);
+
+impl google_drive3::Delegate for ChunkProgress {
+    fn chunk_size(&mut self) -> u64 {
+        PROGRESS_CHUNK
+    }
+
+    fn cancel_chunk_upload(&mut self, chunk: &ContentRange) -> bool {
+        let percent = chunk.range.as_ref().and_then(|range| (range.first * 100).checked_div(chunk.total_length));
+        if let Some(percent) = percent {
+            // Nobody listening only means nobody wants to know
+            let _ = self.0.send(percent as u8);
+        }
+        false
+    }
+}
+
+/// Uploads like [`upload_to_drive`] but in chunks, with a Telegram message once the upload
+/// passes `[telegram] progress_percent`. Telegram failures only log a warning.
+async fn upload_reporting_progress(
+    filename: &str,
+    mime_type: &str,
+    folder_id: &str,
+    credentials: &str,
+    telegram: &TelegramConfig,
+) -> Result<String> {
+    let name = Path::new(filename).file_name().unwrap_or_default().to_string_lossy();
+    let mut message = UploadMessage::new(telegram.clone(), format!("{} to Google Drive", name));
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    let upload = async {
+        let hub = build_hub(build_authenticator(credentials).await?);
+        let file = file_metadata(filename, folder_id)?;
+        let mut progress = ChunkProgress(sender);
+        let (_, file) = hub
+            .files()
+            .create(file)
+            .delegate(&mut progress)
+            .upload_resumable(Cursor::new(fs::read(filename)?), mime_type.parse()?)
+            .await?;
+        Ok(file.id.unwrap_or_default())
+    };
+    // Ends when the upload drops its sender
+    let follow = async {
+        while let Some(percent) = receiver.recv().await {
+            if let Err(e) = message.progress(percent).await {
+                tracing::warn!("Could not report the upload to Telegram: {:#}", e);
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(upload, follow);
+
+    if let Err(e) = message.finish(&result).await {
+        tracing::warn!("Could not report the upload to Telegram: {:#}", e);
+    }
+    result
+}
+
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// Folders the service account can see, as ID and name, for picking the upload folder
+pub async fn list_folders(credentials: &str) -> Result<Vec<(String, String)>> {
+    let hub = build_hub(build_authenticator(credentials).await?);
+    let query = format!("mimeType = '{}' and trashed = false", FOLDER_MIME_TYPE);
+    let (_, list) = hub
+        .files()
+        .list()
+        .q(&query)
+        .supports_all_drives(true)
+        .include_items_from_all_drives(true)
+        .order_by("name")
+        .page_size(100)
+        .param("fields", "files(id,name)")
+        .doit()
+        .await?;
+    Ok(list
+        .files
+        .into_iter()
+        .flatten()
+        .filter_map(|folder| Some((folder.id?, folder.name.unwrap_or_default())))
+        .collect())
+}
+
+/// ID of the folder `name` inside `parent`, created on first use
+async fn subfolder_id(parent: &str, name: &str, credentials: &str) -> Result<String> {
+    let hub = build_hub(build_authenticator(credentials).await?);
+    let query = format!(
+        "name = '{}' and '{}' in parents and mimeType = '{}' and trashed = false",
+        name, parent, FOLDER_MIME_TYPE
+    );
+    let (_, list) = hub
+        .files()
+        .list()
+        .q(&query)
+        .supports_all_drives(true)
+        .include_items_from_all_drives(true)
+        .param("fields", "files(id)")
+        .doit()
+        .await?;
+    if let Some(id) = list.files.into_iter().flatten().find_map(|file| file.id) {
+        return Ok(id);
+    }
+
+    let folder = google_drive3::api::File {
+        name: Some(name.to_string()),
+        mime_type: Some(FOLDER_MIME_TYPE.to_string()),
+        parents: Some(vec![parent.to_string()]),
+        ..Default::default()
+    };
+    // The client only creates files by uploading; a folder is an empty upload of its type
+    let (_, folder) = hub
+        .files()
+        .create(folder)
+        .upload(Cursor::new(Vec::new()), FOLDER_MIME_TYPE.parse()?)
+        .await?;
+    folder.id.context("Drive created the folder without an ID")
+}
+
+/// Read access for one account that Drive revokes at `expires_at`. Drive doesn't let
+/// "anyone with the link" access expire, so the link only works for that account.
+fn expiring_permission(email: &str, expires_at: DateTime<Utc>) -> Permission {
+    Permission {
+        type_: Some("user".to_string()),
+        role: Some("reader".to_string()),
+        email_address: Some(email.to_string()),
+        expiration_time: Some(expires_at),
+        ..Default::default()
+    }
+}
+
+async fn grant_expiring_access(file_id: &str, email: &str, expires_at: DateTime<Utc>, credentials: &str) -> Result<()> {
+    let hub = build_hub(build_authenticator(credentials).await?);
+    hub.permissions()
+        .create(expiring_permission(email, expires_at), file_id)
+        .send_notification_email(false)
+        .doit()
+        .await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
     use tempfile::NamedTempFile;
 
     // Define a trait for SSM operations
@@ -137,22 +726,50 @@ mod tests {
         }
     }
 
-    #[tokio::test]
-    async fn test_get_google_credentials_from_file() {
-        // Create a temporary file with test credentials
-        let temp_file = NamedTempFile::new().unwrap();
-        let test_credentials = r#"{"type": "service_account", "project_id": "test"}"#;
-        fs::write(&temp_file, test_credentials).unwrap();
+    fn token_error(error: AuthErrorCode, description: &str) -> anyhow::Error {
+        let auth = yup_oauth2::error::AuthError {
+            error,
+            error_description: Some(description.to_string()),
+            error_uri: None,
+        };
+        google_drive3::Error::MissingToken(Box::new(yup_oauth2::Error::AuthError(auth))).into()
+    }
 
-        // Set environment variable to point to our temp file
-        env::set_var("GOOGLE_SERVICE_ACCOUNT_PATH", temp_file.path());
+    #[test]
+    fn test_rejected_key_is_reported_for_rotation() {
+        let credentials = r#"{"type": "service_account", "client_email": "bot@test.iam.gserviceaccount.com", "private_key_id": "abc123"}"#;
+        let error = check_rejected(token_error(AuthErrorCode::InvalidGrant, "Invalid JWT Signature."), credentials);
 
-        let result = get_google_credentials().await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), test_credentials);
+        match error.downcast_ref::<CrosswordError>() {
+            Some(CrosswordError::CredentialsRejected { account, key_id, reason }) => {
+                assert_eq!(account, "bot@test.iam.gserviceaccount.com");
+                assert_eq!(key_id, "abc123");
+                assert_eq!(reason, "Invalid JWT Signature.");
+            }
+            other => panic!("expected a rejected key, got {:?}", other),
+        }
+        assert!(!crate::error::is_retryable(&error));
+    }
+
+    #[test]
+    fn test_other_auth_failures_pass_through() {
+        let error = check_rejected(token_error(AuthErrorCode::InvalidScope, "Bad scope"), "{}");
+        assert!(error.downcast_ref::<CrosswordError>().is_none());
 
-        // Cleanup
-        env::remove_var("GOOGLE_SERVICE_ACCOUNT_PATH");
+        let error = check_rejected(anyhow::anyhow!("connection reset"), "{}");
+        assert_eq!(error.to_string(), "connection reset");
+    }
+
+    #[test]
+    fn test_only_unsent_uploads_are_retried() {
+        let failure = |status: u16| -> anyhow::Error {
+            let response = hyper::Response::builder().status(status).body(hyper::Body::empty()).unwrap();
+            google_drive3::Error::Failure(response).into()
+        };
+        assert!(is_unsent(&failure(429)));
+        // Drive may have created the file before failing
+        assert!(!is_unsent(&failure(503)));
+        assert!(!is_unsent(&anyhow::anyhow!("connection reset")));
     }
 
     #[tokio::test]
@@ -184,9 +801,6 @@ mod tests {
         let test_content = b"test image content";
         fs::write(&temp_file, test_content).unwrap();
 
-        // Set required environment variable
-        env::set_var("GOOGLE_DRIVE_FOLDER_ID", "test-folder-id");
-
         // Test credentials
         let test_credentials = r#"{
             "type": "service_account",
@@ -201,13 +815,116 @@ mod tests {
             "client_x509_cert_url": "https://www.googleapis.com/robot/v1/metadata/x509/test"
         }"#;
 
-        let result = upload_to_drive(temp_file.path().to_str().unwrap(), test_credentials).await;
-        
-        // Cleanup
-        env::remove_var("GOOGLE_DRIVE_FOLDER_ID");
+        let result = upload_to_drive(temp_file.path().to_str().unwrap(), "image/jpeg", "test-folder-id", test_credentials).await;
 
         // Note: This test will fail in practice because we can't easily mock the Google Drive API
         // In a real test environment, we would use a mock for the DriveHub
         assert!(result.is_err());
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_verify_credentials_stops_at_missing_file() {
+        let config = Config {
+            service_account_path: Some(std::path::PathBuf::from("/nonexistent/key.json")),
+            ..Config::default()
+        };
+
+        let steps = verify_credentials(&config).await;
+
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].name, "Load service account");
+        assert!(steps[0].outcome.as_ref().unwrap_err().contains("/nonexistent/key.json"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_credentials_stops_at_invalid_key() {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(&temp_file, r#"{"type": "service_account"}"#).unwrap();
+        let config = Config {
+            service_account_path: Some(temp_file.path().to_path_buf()),
+            ..Config::default()
+        };
+
+        let steps = verify_credentials(&config).await;
+
+        assert_eq!(steps.len(), 2);
+        assert!(steps[0].outcome.is_ok());
+        assert_eq!(steps[1].name, "Parse service account key");
+        assert!(steps[1].outcome.is_err());
+    }
+
+    #[test]
+    fn test_chunk_progress() {
+        use google_drive3::client::Chunk;
+        use google_drive3::Delegate;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut progress = ChunkProgress(sender);
+        assert_eq!(progress.chunk_size(), PROGRESS_CHUNK);
+        for first in [0, PROGRESS_CHUNK, 2 * PROGRESS_CHUNK] {
+            let chunk = ContentRange {
+                range: Some(Chunk { first, last: first + PROGRESS_CHUNK - 1 }),
+                total_length: 3 * PROGRESS_CHUNK,
+            };
+            assert!(!progress.cancel_chunk_upload(&chunk));
+        }
+        drop(progress);
+        let mut sent = Vec::new();
+        while let Ok(percent) = receiver.try_recv() {
+            sent.push(percent);
+        }
+        assert_eq!(sent, vec![0, 33, 66]);
+    }
+
+    #[test]
+    fn test_expiring_permission() {
+        let expires_at = DateTime::parse_from_rfc3339("2024-03-21T06:00:00Z").unwrap().with_timezone(&Utc);
+        let permission = expiring_permission("solver@example.com", expires_at);
+        assert_eq!(permission.type_.as_deref(), Some("user"));
+        assert_eq!(permission.role.as_deref(), Some("reader"));
+        assert_eq!(permission.email_address.as_deref(), Some("solver@example.com"));
+        assert_eq!(permission.expiration_time, Some(expires_at));
+
+        // The access ends the configured time after the upload, by the run's clock
+        let config = Config {
+            drive_folder_id: Some("folder123".to_string()),
+            signed_url: crate::signed_url::SignedUrlConfig {
+                mode: SignedUrlMode::Drive,
+                email: Some("solver@example.com".to_string()),
+                ..Default::default()
+            },
+            clock: RunClock(std::sync::Arc::new(crate::clock::FixedClock::at("2024-03-20T11:30:00+05:30"))),
+            ..Config::default()
+        };
+        let sink = DriveSink::new(&config).unwrap();
+        assert_eq!(sink.share_until(), Some(("solver@example.com", expires_at)));
+    }
+
+    #[test]
+    fn test_file_metadata() {
+        let file = file_metadata("/tmp/crossword_2024-03-20.jpg", "folder123").unwrap();
+
+        assert_eq!(file.name.as_deref(), Some("crossword_2024-03-20.jpg"));
+        assert_eq!(file.parents, Some(vec!["folder123".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_upload() {
+        let image = NamedTempFile::new().unwrap();
+        fs::write(&image, b"image").unwrap();
+        let config = Config {
+            drive_folder_id: Some("folder123".to_string()),
+            service_account_path: Some(std::path::PathBuf::from("/nonexistent/key.json")),
+            ..Config::default()
+        };
+        let sink = DriveSink::new(&config).unwrap().dry_run(true);
+
+        // Unusable credentials only prevent resolving the folder name
+        let id = sink
+            .upload(&Artifact::new(image.path().to_string_lossy(), "image/jpeg"))
+            .await
+            .unwrap();
+
+        assert_eq!(id, DRY_RUN_ID);
+    }
+}
@@ -0,0 +1,287 @@
+//! Saving the crossword to a Dropbox folder instead of Google Drive, for households that
+//! already share one there. The token comes from `DROPBOX_TOKEN` or an SSM parameter; with
+//! an `app_key` it is a refresh token, traded for a short-lived access token on each run.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use tokio::sync::OnceCell;
+
+use crate::config::Config;
+use crate::console;
+use crate::credentials::CredentialSource;
+use crate::http::backoff::{self, BackoffConfig};
+use crate::pipeline::service::{self, is_transient};
+use crate::pipeline::{Artifact, RemoteCopy, Sink, DRY_RUN_ID};
+
+const API_URL: &str = "https://api.dropboxapi.com";
+const CONTENT_URL: &str = "https://content.dropboxapi.com";
+const TOKEN_URL: &str = "https://api.dropbox.com/oauth2/token";
+
+/// Environment variable holding the token, read before `token_parameter`
+pub const TOKEN_ENV: &str = "DROPBOX_TOKEN";
+
+/// Environment variable holding the app secret that goes with `app_key`
+pub const APP_SECRET_ENV: &str = "DROPBOX_APP_SECRET";
+
+/// The `[dropbox]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DropboxConfig {
+    /// Folder the crosswords are saved in, e.g. `/Family/Crosswords`; empty for the root
+    pub folder: String,
+    /// SSM parameter holding the token, when `DROPBOX_TOKEN` isn't set
+    pub token_parameter: Option<String>,
+    /// Key of the Dropbox app, making the token a refresh token; the secret comes from
+    /// `DROPBOX_APP_SECRET`
+    pub app_key: Option<String>,
+}
+
+impl Default for DropboxConfig {
+    fn default() -> Self {
+        Self {
+            folder: "/Crosswords".to_string(),
+            token_parameter: None,
+            app_key: None,
+        }
+    }
+}
+
+impl DropboxConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.folder.is_empty() && (!self.folder.starts_with('/') || self.folder.ends_with('/')) {
+            problems.push(format!(
+                "[dropbox] folder must start with / and not end with one, like /Crosswords, not '{}'",
+                self.folder
+            ));
+        }
+        if self.token_parameter.is_none() && std::env::var(TOKEN_ENV).is_err() {
+            problems.push(format!("[dropbox] needs a token_parameter or {}", TOKEN_ENV));
+        }
+        if self.app_key.is_some() && std::env::var(APP_SECRET_ENV).is_err() {
+            problems.push(format!("[dropbox] app_key needs the app secret in {}", APP_SECRET_ENV));
+        }
+        problems
+    }
+}
+
+/// Uploads pipeline artifacts to the configured Dropbox folder
+pub struct DropboxSink {
+    config: DropboxConfig,
+    token: OnceCell<String>,
+    client: reqwest::Client,
+    backoff: BackoffConfig,
+    dry_run: bool,
+}
+
+impl DropboxSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let dropbox = config.dropbox.clone().context("[dropbox] is not configured")?;
+        Ok(Self {
+            config: dropbox,
+            token: OnceCell::new(),
+            client: reqwest::Client::new(),
+            backoff: config.backoff.clone(),
+            dry_run: false,
+        })
+    }
+
+    /// Prints what would be uploaded instead of writing to Dropbox
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    fn path(&self, artifact: &Artifact) -> Result<String> {
+        let name = Path::new(&artifact.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Invalid filename")?;
+        match &artifact.folder {
+            Some(folder) => Ok(format!("{}/{}/{}", self.config.folder, folder, name)),
+            None => Ok(format!("{}/{}", self.config.folder, name)),
+        }
+    }
+
+    /// The access token, fetched (and refreshed) once per run
+    async fn token(&self) -> Result<&str> {
+        let token = self
+            .token
+            .get_or_try_init(|| async {
+                let secret = match std::env::var(TOKEN_ENV) {
+                    Ok(token) => token,
+                    Err(_) => {
+                        let parameter = self
+                            .config
+                            .token_parameter
+                            .clone()
+                            .with_context(|| format!("{} is not set and [dropbox] has no token_parameter", TOKEN_ENV))?;
+                        CredentialSource::Ssm { parameter }.provider().fetch().await?
+                    }
+                };
+                match &self.config.app_key {
+                    Some(app_key) => self.refresh(app_key, secret.trim()).await,
+                    None => Ok(secret.trim().to_string()),
+                }
+            })
+            .await?;
+        Ok(token)
+    }
+
+    /// Trades the refresh token for an access token
+    async fn refresh(&self, app_key: &str, refresh_token: &str) -> Result<String> {
+        let app_secret = std::env::var(APP_SECRET_ENV).with_context(|| format!("{} is not set", APP_SECRET_ENV))?;
+        let form = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", app_key),
+            ("client_secret", &app_secret),
+        ];
+        let response = self.client.post(TOKEN_URL).form(&form).send().await?;
+        let body = checked(response).await.context("Dropbox refused the refresh token")?;
+        body["access_token"]
+            .as_str()
+            .map(str::to_string)
+            .context("Dropbox's token answer has no access_token")
+    }
+}
+
+#[async_trait]
+impl Sink for DropboxSink {
+    fn name(&self) -> &str {
+        "dropbox"
+    }
+
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        let path = self.path(artifact)?;
+        if self.dry_run {
+            console::progress!("Dry run: would upload {} to Dropbox at {}", artifact.path, path);
+            return Ok(DRY_RUN_ID.to_string());
+        }
+
+        let token = self.token().await?;
+        let body = fs::read(&artifact.path).with_context(|| format!("Failed to read {}", artifact.path))?;
+        // A run done again replaces the file rather than adding a "(1)" copy next to it
+        let argument = header_json(&json!({ "path": path, "mode": "overwrite", "mute": true }));
+        let uploaded = backoff::retry(&self.backoff, "Dropbox upload", is_transient, || async {
+            let response = self
+                .client
+                .post(format!("{}/2/files/upload", CONTENT_URL))
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .header("Dropbox-API-Arg", &argument)
+                .body(body.clone())
+                .send()
+                .await?;
+            checked(response).await
+        })
+        .await
+        .with_context(|| format!("Failed to upload to Dropbox at {}", path))?;
+        let id = uploaded["id"].as_str().context("Dropbox's upload answer has no file ID")?;
+        console::progress!("File uploaded to Dropbox at {}", path);
+        Ok(id.to_string())
+    }
+
+    async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+        if id == DRY_RUN_ID {
+            return Ok(RemoteCopy::Missing);
+        }
+        let response = self
+            .client
+            .post(format!("{}/2/files/get_metadata", API_URL))
+            .header(AUTHORIZATION, format!("Bearer {}", self.token().await?))
+            .header(CONTENT_TYPE, "application/json")
+            .body(json!({ "path": id }).to_string())
+            .send()
+            .await?;
+        // Dropbox keeps its own block hash rather than a SHA-256 of the file
+        match checked(response).await {
+            Ok(_) => Ok(RemoteCopy::Present { sha256: None }),
+            Err(e) if is_not_found(&e) => Ok(RemoteCopy::Missing),
+            Err(e) => Err(e).with_context(|| format!("Failed to look up {} in Dropbox", id)),
+        }
+    }
+}
+
+/// The JSON body of a successful answer, or a [`service::ServiceError`] with Dropbox's summary
+async fn checked(response: reqwest::Response) -> Result<Value> {
+    service::json_answer("Dropbox", response, |body| body["error_summary"].as_str()).await
+}
+
+/// `value` as JSON fit for an HTTP header, which Dropbox wants in ASCII with everything
+/// else escaped, e.g. a folder named in Devanagari
+fn header_json(value: &Value) -> String {
+    let mut escaped = String::new();
+    for c in value.to_string().chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                escaped.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+/// Whether the lookup found nothing at the path or ID
+fn is_not_found(error: &anyhow::Error) -> bool {
+    let not_found = |e: &service::ServiceError| e.status == 409 && e.message.contains("not_found");
+    error.downcast_ref::<service::ServiceError>().is_some_and(not_found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = DropboxConfig {
+            token_parameter: Some("/hitavada-crossword/dropbox-token".to_string()),
+            ..DropboxConfig::default()
+        };
+        assert!(config.validate().is_empty());
+        config.folder = String::new();
+        assert!(config.validate().is_empty());
+        config.folder = "Crosswords/".to_string();
+        assert_eq!(config.validate().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_paths() {
+        let config = Config {
+            dropbox: Some(DropboxConfig {
+                folder: "/Family/शब्दकोडे".to_string(),
+                ..DropboxConfig::default()
+            }),
+            ..Config::default()
+        };
+        let sink = DropboxSink::new(&config).unwrap().dry_run(true);
+        assert_eq!(sink.upload(&service::test_artifact(None)).await.unwrap(), DRY_RUN_ID);
+
+        let path = sink.path(&service::test_artifact(Some("originals"))).unwrap();
+        assert_eq!(path, "/Family/शब्दकोडे/originals/crossword_2024-03-20.jpg");
+        let header = header_json(&json!({ "path": path }));
+        assert!(header.is_ascii());
+        assert!(header.starts_with(r#"{"path":"/Family/\u0936\u092c"#), "{}", header);
+    }
+
+    #[test]
+    fn test_not_found() {
+        let missing = |summary: &str| -> anyhow::Error {
+            service::ServiceError {
+                service: "Dropbox",
+                status: 409,
+                message: summary.to_string(),
+            }
+            .into()
+        };
+        assert!(is_not_found(&missing("path/not_found/..")));
+        assert!(!is_not_found(&missing("path/conflict/file/..")));
+    }
+}
@@ -0,0 +1,247 @@
+//! Emailing the downloaded crossword as an attachment, for family who read email but don't
+//! use Drive. The mail goes out through any SMTP server, with the password from
+//! `SMTP_PASSWORD`, the OS keyring or an SSM parameter, and its body is the run's message.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::credentials::{keyring, CredentialSource};
+use crate::crossword::Download;
+use crate::message::{self, Announcement, MessageConfig};
+
+/// Environment variable holding the SMTP password, read before `password_parameter`
+pub const PASSWORD_ENV: &str = "SMTP_PASSWORD";
+
+/// How long connecting and each SMTP command may take
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgraded with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// Unencrypted, only for a relay on your own network
+    None,
+}
+
+/// The `[email]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailConfig {
+    /// SMTP server, e.g. `smtp.gmail.com`
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    /// Account to sign in as; servers that take mail without signing in need none
+    pub username: Option<String>,
+    /// SSM parameter holding the password, when neither `SMTP_PASSWORD` nor the OS keyring has it
+    pub password_parameter: Option<String>,
+    /// Sender, e.g. `Crossword <crossword@example.com>`
+    pub from: String,
+    /// Recipients, each getting the same mail
+    pub to: Vec<String>,
+    /// Subject line, a template taking the same variables as `[message]`, with `{date}` as
+    /// `YYYY-MM-DD`
+    pub subject: String,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            security: SmtpSecurity::default(),
+            username: None,
+            password_parameter: None,
+            from: String::new(),
+            to: Vec::new(),
+            subject: "Hitavada Crossword – {date}".to_string(),
+        }
+    }
+}
+
+impl EmailConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.host.trim().is_empty() {
+            problems.push("[email] needs the host of an SMTP server".to_string());
+        }
+        if self.from.parse::<Mailbox>().is_err() {
+            problems.push(format!("[email] from '{}' is not an email address", self.from));
+        }
+        if self.to.is_empty() {
+            problems.push("[email] needs at least one address in to".to_string());
+        }
+        for to in self.to.iter().filter(|to| to.parse::<Mailbox>().is_err()) {
+            problems.push(format!("[email] to '{}' is not an email address", to));
+        }
+        let subject = self.subject_template().validate();
+        problems.extend(subject.into_iter().map(|problem| problem.replace("[message] template", "[email] subject")));
+        if self.username.is_some() && self.password_parameter.is_none() && std::env::var(PASSWORD_ENV).is_err() {
+            match keyring::find(keyring::SMTP_PASSWORD) {
+                Ok(Some(_)) => {}
+                Ok(None) => problems.push(format!(
+                    "[email] needs a password_parameter, {} or a password in the OS keyring to sign in",
+                    PASSWORD_ENV
+                )),
+                Err(e) => problems.push(format!("[email] has no password_parameter or {}, and {:#}", PASSWORD_ENV, e)),
+            }
+        }
+        problems
+    }
+
+    /// The subject as a `[message]` template, so it fills in variables the same way
+    fn subject_template(&self) -> MessageConfig {
+        MessageConfig {
+            template: Some(self.subject.clone()),
+            date_format: "%Y-%m-%d".to_string(),
+            ..MessageConfig::default()
+        }
+    }
+
+    async fn password(&self) -> Result<String> {
+        if let Ok(password) = std::env::var(PASSWORD_ENV) {
+            return Ok(password);
+        }
+        if let Some(password) = keyring::find(keyring::SMTP_PASSWORD)? {
+            return Ok(password);
+        }
+        let parameter = self.password_parameter.clone().with_context(|| {
+            format!(
+                "{} is not set, the OS keyring holds no password and [email] has no password_parameter",
+                PASSWORD_ENV
+            )
+        })?;
+        let password = CredentialSource::Ssm { parameter }.provider().fetch().await?;
+        Ok(password.trim().to_string())
+    }
+
+    async fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let mut builder = match self.security {
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+        };
+        builder = builder.port(self.port).timeout(Some(TIMEOUT));
+        if let Some(username) = &self.username {
+            builder = builder.credentials(Credentials::new(username.clone(), self.password().await?));
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Emails the crossword downloaded for `date` to every recipient
+pub async fn send(config: &Config, email: &EmailConfig, date: NaiveDate, download: &Download) -> Result<()> {
+    let data = std::fs::read(&download.filename).with_context(|| format!("Failed to read {}", download.filename))?;
+    let mail = compose(config, email, date, download, data)?;
+    email
+        .transport()
+        .await?
+        .send(mail)
+        .await
+        .with_context(|| format!("{} did not take the mail", email.host))?;
+    Ok(())
+}
+
+/// The mail for `download`, with the run's message as its text and `data` attached
+fn compose(
+    config: &Config,
+    email: &EmailConfig,
+    date: NaiveDate,
+    download: &Download,
+    data: Vec<u8>,
+) -> Result<Message> {
+    let name = Path::new(&download.filename)
+        .file_name()
+        .context("Invalid filename")?
+        .to_string_lossy()
+        .into_owned();
+    let announcement = Announcement {
+        date,
+        filename: &name,
+        link: download.link.as_deref(),
+        solver: config.rotation.as_ref().and_then(|rotation| rotation.solver(date)),
+    };
+    let subject = message::render(&email.subject_template(), &announcement)?;
+    let mut builder = Message::builder()
+        .from(email.from.parse().with_context(|| format!("Invalid sender '{}'", email.from))?)
+        .subject(subject);
+    for to in &email.to {
+        builder = builder.to(to.parse().with_context(|| format!("Invalid recipient '{}'", to))?);
+    }
+    let attachment = Attachment::new(name).body(data, ContentType::parse(download.mime_type)?);
+    let parts = MultiPart::mixed()
+        .singlepart(SinglePart::plain(message::downloaded(config, date, download)))
+        .singlepart(attachment);
+    Ok(builder.multipart(parts)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn email() -> EmailConfig {
+        EmailConfig {
+            host: "smtp.example.com".to_string(),
+            username: Some("crossword@example.com".to_string()),
+            password_parameter: Some("/hitavada-crossword/smtp-password".to_string()),
+            from: "Crossword <crossword@example.com>".to_string(),
+            to: vec!["aai@example.com".to_string(), "baba@example.com".to_string()],
+            ..EmailConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(email().validate().is_empty());
+        let broken = EmailConfig {
+            to: vec!["aai".to_string()],
+            subject: "Crossword {day}".to_string(),
+            ..email()
+        };
+        let problems = broken.validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[1].starts_with("[email] subject uses {day}"), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_compose() {
+        let download = Download {
+            filename: "/tmp/crossword_2024-03-20.jpg".to_string(),
+            mime_type: "image/jpeg",
+            page: 8,
+            crossword_url: "https://www.ehitavada.com/article.php?mid=Mpage_8&a=b".to_string(),
+            image_url: "https://www.ehitavada.com/encyc/6/20240320/xword.jpg".to_string(),
+            comparison: None,
+            timings: Vec::new(),
+            link: None,
+            uploads: BTreeMap::new(),
+            puzzle: Default::default(),
+            confidence: None,
+            needs_review: false,
+            storage: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let mail = compose(&Config::default(), &email(), date, &download, b"jpeg".to_vec()).unwrap();
+        assert_eq!(mail.headers().get_raw("Subject"), Some("Hitavada Crossword – 2024-03-20"));
+        assert_eq!(mail.envelope().to().len(), 2);
+        let formatted = String::from_utf8(mail.formatted()).unwrap();
+        assert!(formatted.contains("filename=\"crossword_2024-03-20.jpg\""), "{}", formatted);
+        assert!(formatted.contains("Content-Type: image/jpeg"), "{}", formatted);
+        assert!(formatted.contains("Crossword downloaded successfully"), "{}", formatted);
+    }
+}
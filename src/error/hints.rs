@@ -0,0 +1,148 @@
+use super::CrosswordError;
+use crate::types::Interstitial;
+
+/// A kind of failure with a known remedy, recognised wherever it sits in an error chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    DriveFolderMissing,
+    DriveFolderMalformed,
+    ServiceAccountKeyInvalid,
+    CredentialsRejected,
+    OutputDirUnusable,
+    NotYetPublished,
+    CrosswordNotFound,
+    SiteMaintenance,
+    SiteBlocked,
+    UploadIncomplete,
+    OutOfTime,
+    TimedOut,
+    UnusualDimensions,
+    Unreachable,
+    NewerState,
+}
+
+impl ErrorKind {
+    /// What to do about it
+    pub fn hint(self) -> &'static str {
+        match self {
+            ErrorKind::DriveFolderMissing => "Create a folder in Google Drive, share it with the service account's client_email as an Editor, and set GOOGLE_DRIVE_FOLDER_ID to the last segment of its URL",
+            ErrorKind::DriveFolderMalformed => "Open the folder in Drive and copy the part of the URL after /folders/, without any ?usp= suffix",
+            ErrorKind::ServiceAccountKeyInvalid => "Download a JSON key under IAM & Admin > Service Accounts > Keys in the Cloud console and point GOOGLE_SERVICE_ACCOUNT_PATH at it",
+            ErrorKind::CredentialsRejected => "Create a new key for the service account in the Cloud console, update the credential source, and check it with `verify-credentials`",
+            ErrorKind::OutputDirUnusable => "Create the directory, or set OUTPUT_DIR to one this user can write to",
+            ErrorKind::NotYetPublished => "The e-paper is usually up by early morning IST; run again later, or pass --wait-for-publication",
+            ErrorKind::CrosswordNotFound => "The page layout may have changed; run with --explain table to see how each area scored, and `debug-bundle` to report it. --article-url downloads the crossword by hand meanwhile",
+            ErrorKind::SiteMaintenance => "The site is down for maintenance; try again in an hour",
+            ErrorKind::SiteBlocked => "The site wants a human; open ehitavada.com in a browser from the same network, or enable the [browser] fallback",
+            ErrorKind::UploadIncomplete => "The crossword is saved locally; run `repair` for the date to repeat only the failed uploads",
+            ErrorKind::OutOfTime => "Invoke again to finish, or raise the Lambda timeout",
+            ErrorKind::TimedOut => "If the site or Drive is only slow today, raise that phase's limit in [timeouts]",
+            ErrorKind::Unreachable => "Check the network connection and DNS of this machine; a host that only times out may be blocked by a firewall or VPN",
+            ErrorKind::NewerState => "Upgrade to the version that last wrote the output directory, or point output_dir somewhere else; older versions could lose what it recorded",
+            ErrorKind::UnusualDimensions => "Run with --explain table to see which area was picked. If the layout really changed, set action = \"warn\" in [dimensions] until the new size becomes the usual one",
+        }
+    }
+}
+
+impl CrosswordError {
+    /// The kinds of failure it stands for; several for a configuration with several problems
+    pub fn kinds(&self) -> Vec<ErrorKind> {
+        let kind = match self {
+            CrosswordError::ImageNotYetAvailable { .. } | CrosswordError::EditionNotPublished { .. } => {
+                ErrorKind::NotYetPublished
+            }
+            CrosswordError::NotFound { .. } => ErrorKind::CrosswordNotFound,
+            CrosswordError::SiteInterstitial { kind: Interstitial::Maintenance, .. } => ErrorKind::SiteMaintenance,
+            CrosswordError::SiteInterstitial { .. } => ErrorKind::SiteBlocked,
+            CrosswordError::UploadIncomplete { .. } => ErrorKind::UploadIncomplete,
+            CrosswordError::CredentialsRejected { .. } => ErrorKind::CredentialsRejected,
+            CrosswordError::OutOfTime { .. } => ErrorKind::OutOfTime,
+            CrosswordError::TimedOut { .. } => ErrorKind::TimedOut,
+            CrosswordError::UnusualDimensions { .. } => ErrorKind::UnusualDimensions,
+            CrosswordError::Unreachable { .. } => ErrorKind::Unreachable,
+            CrosswordError::NewerState { .. } => ErrorKind::NewerState,
+            CrosswordError::InvalidConfig { kinds, .. } => return kinds.clone(),
+        };
+        vec![kind]
+    }
+}
+
+/// Every recognised kind of failure in the chain, once each, outermost first. Only
+/// [`CrosswordError`]s are recognised, never the wording of a message.
+pub fn kinds(error: &anyhow::Error) -> Vec<ErrorKind> {
+    let mut kinds = Vec::new();
+    for cause in error.chain() {
+        let Some(error) = cause.downcast_ref::<CrosswordError>() else {
+            continue;
+        };
+        for kind in error.kinds() {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+    }
+    kinds
+}
+
+pub fn hints(error: &anyhow::Error) -> Vec<&'static str> {
+    kinds(error).into_iter().map(ErrorKind::hint).collect()
+}
+
+/// The error with its causes on one line, then a `Hint:` line per remedy, for messages that
+/// leave the process such as the Lambda's error response
+pub fn describe(error: &anyhow::Error) -> String {
+    let mut message = format!("{:#}", error);
+    for hint in hints(error) {
+        message.push_str("\nHint: ");
+        message.push_str(hint);
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_kinds_through_context() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let error = Err::<(), _>(CrosswordError::EditionNotPublished { date })
+            .context("Download failed")
+            .unwrap_err();
+        assert_eq!(kinds(&error), vec![ErrorKind::NotYetPublished]);
+        assert_eq!(
+            describe(&error),
+            format!(
+                "Download failed: The 2024-03-20 edition is not on the site yet (its page maps have no articles)\nHint: {}",
+                ErrorKind::NotYetPublished.hint()
+            )
+        );
+
+        let blocked = CrosswordError::SiteInterstitial {
+            kind: Interstitial::Captcha,
+            url: "https://www.ehitavada.com/".to_string(),
+            status: 403,
+        };
+        assert_eq!(kinds(&blocked.into()), vec![ErrorKind::SiteBlocked]);
+    }
+
+    #[test]
+    fn test_every_config_problem_gets_a_hint() {
+        let error: anyhow::Error = CrosswordError::InvalidConfig {
+            problems: vec!["GOOGLE_DRIVE_FOLDER_ID is not set".to_string(), "Unknown locale".to_string()],
+            kinds: vec![ErrorKind::DriveFolderMissing, ErrorKind::OutputDirUnusable],
+        }
+        .into();
+        assert_eq!(kinds(&error), vec![ErrorKind::DriveFolderMissing, ErrorKind::OutputDirUnusable]);
+        assert!(hints(&error)[0].contains("share it with the service account"));
+
+        assert!(hints(&anyhow::anyhow!("connection reset")).is_empty());
+        assert_eq!(describe(&anyhow::anyhow!("connection reset")), "connection reset");
+        // Messages that merely read like a known failure aren't taken for one
+        assert!(kinds(&anyhow::anyhow!("Could not find crossword on any page")).is_empty());
+        let missing = CrosswordError::NotFound { region: "crossword".to_string() };
+        assert_eq!(kinds(&missing.into()), vec![ErrorKind::CrosswordNotFound]);
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::NaiveDate;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::pipeline::{Delivery, UploadFailure};
+use crate::types::Interstitial;
+
+pub mod hints;
+
+use hints::ErrorKind;
+
+/// Failures that callers need to tell apart from generic errors
+#[derive(Debug, Error)]
+pub enum CrosswordError {
+    /// The article exists but the site still serves its `not_found.png` placeholder
+    #[error("Crossword image for {date} is not yet available (the site returned its placeholder image)")]
+    ImageNotYetAvailable { date: NaiveDate },
+
+    /// None of the scanned page maps has any article on it: the edition isn't uploaded yet
+    #[error("The {date} edition is not on the site yet (its page maps have no articles)")]
+    EditionNotPublished { date: NaiveDate },
+
+    /// The edition is out, but none of the scanned pages has the region on it
+    #[error("Could not find {region} on any page")]
+    NotFound { region: String },
+
+    /// The site answered with a maintenance notice, CAPTCHA or login wall instead of content
+    #[error("{url} returned {kind} (HTTP {status}) instead of the expected page")]
+    SiteInterstitial {
+        kind: Interstitial,
+        url: String,
+        status: u16,
+    },
+
+    /// Some uploads failed after the image was saved locally; only those need repeating, as
+    /// the `delivered` ones went through
+    #[error("Upload failed: {}", .failures.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    UploadIncomplete {
+        failures: Vec<UploadFailure>,
+        delivered: Vec<Delivery>,
+    },
+
+    /// Google refused the service account key itself, e.g. because it was deleted or the
+    /// account disabled; nothing works again until the key is rotated
+    #[error("Google rejected the service account key {key_id} of {account} ({reason}); it has probably been revoked or deleted, so create a new key and update the credential source")]
+    CredentialsRejected {
+        account: String,
+        key_id: String,
+        reason: String,
+    },
+
+    /// The run stopped starting new requests because its time budget was nearly used up
+    #[error("Stopped after {requests} requests with only {}s left before the deadline", .remaining.as_secs())]
+    OutOfTime { remaining: Duration, requests: usize },
+
+    /// The detected image is far from the region's usual size, so the wrong area was
+    /// probably picked; raised instead of saving it when `[dimensions]` says to fail
+    #[error("The {region} image is {}x{}, far from its usual {}x{}; the wrong area was probably detected", .size.0, .size.1, .typical.0, .typical.1)]
+    UnusualDimensions {
+        region: String,
+        size: (u32, u32),
+        typical: (u32, u32),
+    },
+
+    /// A phase of the run went past its limit in `[timeouts]`
+    #[error("The {phase} took longer than its {}s limit", .limit.as_secs())]
+    TimedOut { phase: String, limit: Duration },
+
+    /// The pre-flight check couldn't reach hosts the run needs, so it didn't start
+    #[error("Pre-flight check failed, so nothing was attempted: {}", .hosts.join("; "))]
+    Unreachable { hosts: Vec<String> },
+
+    /// Everything wrong with the configuration at once, with the kinds of problem among them
+    /// that have a known remedy
+    #[error("Invalid configuration:\n  - {}", .problems.join("\n  - "))]
+    InvalidConfig { problems: Vec<String>, kinds: Vec<ErrorKind> },
+
+    /// The output directory's state files are laid out for a newer version of the tool
+    #[error("The state in {dir} has schema version {found}, written by version {written_by}, but this version only understands up to {supported}")]
+    NewerState {
+        dir: String,
+        found: u32,
+        supported: u32,
+        written_by: String,
+    },
+}
+
+impl CrosswordError {
+    /// Whether trying again later could succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CrosswordError::ImageNotYetAvailable { .. } => true,
+            CrosswordError::EditionNotPublished { .. } => true,
+            // The layout changed, or the profile no longer fits it
+            CrosswordError::NotFound { .. } => false,
+            // Maintenance ends on its own; CAPTCHAs and login walls need a human
+            CrosswordError::SiteInterstitial { kind, .. } => *kind == Interstitial::Maintenance,
+            // The next run re-uploads from the saved copy
+            CrosswordError::UploadIncomplete { .. } => true,
+            CrosswordError::OutOfTime { .. } => true,
+            // A slow site or Drive is usually quick again later
+            CrosswordError::TimedOut { .. } => true,
+            CrosswordError::CredentialsRejected { .. } => false,
+            // The same page map gives the same answer
+            CrosswordError::UnusualDimensions { .. } => false,
+            // Home connections and DNS usually come back by themselves
+            CrosswordError::Unreachable { .. } => true,
+            // Only an upgrade helps
+            CrosswordError::NewerState { .. } => false,
+            CrosswordError::InvalidConfig { .. } => false,
+        }
+    }
+}
+
+/// Whether an error anywhere in the chain says the crossword isn't published yet, which the
+/// `[retry]` schedule is for
+pub fn is_not_yet_published(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<CrosswordError>(),
+            Some(CrosswordError::ImageNotYetAvailable { .. } | CrosswordError::EditionNotPublished { .. })
+        )
+    })
+}
+
+/// Whether an error anywhere in the chain is a retryable [`CrosswordError`]
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<CrosswordError>())
+        .any(CrosswordError::is_retryable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 20).unwrap()
+    }
+
+    #[test]
+    fn test_image_not_yet_available_is_retryable() {
+        let error = CrosswordError::ImageNotYetAvailable { date: date() };
+        assert!(error.is_retryable());
+        assert!(error.to_string().contains("2024-03-20"));
+    }
+
+    #[test]
+    fn test_is_retryable_through_context() {
+        let error = Err::<(), _>(CrosswordError::ImageNotYetAvailable { date: date() })
+            .context("Download failed")
+            .unwrap_err();
+        assert!(is_retryable(&error));
+    }
+
+    #[test]
+    fn test_site_interstitial_retryability() {
+        let interstitial = |kind| CrosswordError::SiteInterstitial {
+            kind,
+            url: "https://www.ehitavada.com/val.php".to_string(),
+            status: 503,
+        };
+        assert!(interstitial(Interstitial::Maintenance).is_retryable());
+        assert!(!interstitial(Interstitial::Captcha).is_retryable());
+        assert!(!interstitial(Interstitial::LoginWall).is_retryable());
+        assert!(interstitial(Interstitial::Captcha).to_string().contains("a CAPTCHA challenge"));
+    }
+
+    #[test]
+    fn test_not_yet_published() {
+        assert!(is_not_yet_published(&CrosswordError::EditionNotPublished { date: date() }.into()));
+        assert!(is_not_yet_published(&CrosswordError::ImageNotYetAvailable { date: date() }.into()));
+        let timed_out = CrosswordError::TimedOut {
+            phase: "upload".to_string(),
+            limit: Duration::from_secs(60),
+        };
+        assert!(!is_not_yet_published(&timed_out.into()));
+    }
+
+    #[test]
+    fn test_other_errors_are_not_retryable() {
+        assert!(!is_not_yet_published(&anyhow::anyhow!("Could not find crossword on any page")));
+        assert!(!is_retryable(&anyhow::anyhow!("Could not find crossword on any page")));
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+use std::path::Path;
+
+/// Pixels per cell once both grids are resampled onto the same size
+const CELL: u32 = 32;
+
+/// Pixels darker than this count as ink
+const INK_THRESHOLD: u8 = 128;
+
+/// Average per-block ink difference above which a cell is flagged
+const DIFFERENCE_THRESHOLD: f32 = 0.08;
+
+/// Above this ink share a cell is a black square
+const BLOCK_THRESHOLD: f32 = 0.85;
+
+/// Cells of the solved grid that don't match the solution
+pub struct Grade {
+    /// (row, column) of each differing cell, both from 1
+    pub differences: Vec<(u32, u32)>,
+    /// The solved grid, aligned to the solution, with differing cells highlighted
+    pub overlay: RgbImage,
+}
+
+/// Compares a scanned or photographed solved grid with the official solution.
+///
+/// Both images are cropped to the grid's outline and resampled onto a `size` x `size`
+/// grid, then each cell's handwriting is compared by where its ink falls. This expects a
+/// roughly straight-on picture on a light background; it does no perspective correction.
+pub fn grade(mine: &DynamicImage, solution: &DynamicImage, size: u32) -> Result<Grade> {
+    let mine = align(mine, size).context("Could not find the grid in the solved image")?;
+    let solution = align(solution, size).context("Could not find the grid in the solution image")?;
+
+    let mut differences = Vec::new();
+    for row in 0..size {
+        for column in 0..size {
+            let (a, b) = (ink(&mine, row, column), ink(&solution, row, column));
+            let both_blocks = is_block(&a) && is_block(&b);
+            if !both_blocks && difference(&a, &b) > DIFFERENCE_THRESHOLD {
+                differences.push((row + 1, column + 1));
+            }
+        }
+    }
+
+    let mut overlay = DynamicImage::ImageLuma8(mine).to_rgb8();
+    for &(row, column) in &differences {
+        highlight(&mut overlay, row - 1, column - 1);
+    }
+
+    Ok(Grade { differences, overlay })
+}
+
+/// Loads both images, grades them and writes the highlighted grid to `output`
+pub fn check_files(mine: &Path, solution: &Path, size: u32, output: &Path) -> Result<Vec<(u32, u32)>> {
+    let open = |path: &Path| image::open(path).with_context(|| format!("Failed to open {}", path.display()));
+    let grade = grade(&open(mine)?, &open(solution)?, size)?;
+    grade
+        .overlay
+        .save(output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(grade.differences)
+}
+
+/// Crops to the bounding box of the ink and resamples to `size` cells a side
+fn align(image: &DynamicImage, size: u32) -> Option<GrayImage> {
+    let gray = image.to_luma8();
+    let (x1, y1, x2, y2) = ink_bounds(&gray)?;
+    let cropped = imageops::crop_imm(&gray, x1, y1, x2 - x1 + 1, y2 - y1 + 1).to_image();
+    Some(imageops::resize(&cropped, size * CELL, size * CELL, FilterType::Triangle))
+}
+
+/// The outermost rows and columns with a meaningful amount of ink; stray specks are ignored
+fn ink_bounds(gray: &GrayImage) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = gray.dimensions();
+    let is_ink = |x, y| gray.get_pixel(x, y)[0] < INK_THRESHOLD;
+    let rows: Vec<u32> = (0..height)
+        .filter(|&y| (0..width).filter(|&x| is_ink(x, y)).count() as u32 > width / 100)
+        .collect();
+    let columns: Vec<u32> = (0..width)
+        .filter(|&x| (0..height).filter(|&y| is_ink(x, y)).count() as u32 > height / 100)
+        .collect();
+
+    Some((*columns.first()?, *rows.first()?, *columns.last()?, *rows.last()?))
+}
+
+/// Ink share of each block of a 3x3 split of a cell's centre, away from the grid lines
+/// and clue numbers; coarse enough to forgive handwriting, fine enough to tell letters apart
+fn ink(grid: &GrayImage, row: u32, column: u32) -> [f32; 9] {
+    let margin = CELL / 5;
+    let block = (CELL - 2 * margin) / 3;
+    let mut shares = [0.0; 9];
+    for (index, share) in shares.iter_mut().enumerate() {
+        let x0 = column * CELL + margin + (index as u32 % 3) * block;
+        let y0 = row * CELL + margin + (index as u32 / 3) * block;
+        let inked = (y0..y0 + block)
+            .flat_map(|y| (x0..x0 + block).map(move |x| (x, y)))
+            .filter(|&(x, y)| grid.get_pixel(x, y)[0] < INK_THRESHOLD)
+            .count();
+        *share = inked as f32 / (block * block) as f32;
+    }
+    shares
+}
+
+/// Average difference in ink between matching blocks of two cells
+fn difference(a: &[f32; 9], b: &[f32; 9]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum::<f32>() / 9.0
+}
+
+fn is_block(shares: &[f32; 9]) -> bool {
+    shares.iter().all(|&share| share > BLOCK_THRESHOLD)
+}
+
+fn highlight(image: &mut RgbImage, row: u32, column: u32) {
+    for y in row * CELL..(row + 1) * CELL {
+        for x in column * CELL..(column + 1) * CELL {
+            let Rgb([r, g, b]) = *image.get_pixel(x, y);
+            image.put_pixel(x, y, Rgb([r / 2 + 127, g / 2, b / 2]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// What to draw in a cell of a synthetic grid
+    #[derive(Clone, Copy)]
+    enum Cell {
+        Empty,
+        Block,
+        /// A letter-ish stroke; different values draw different shapes
+        Letter(u8),
+    }
+
+    /// Draws a `cells.len()` square grid of `cell` pixels, `margin` pixels from the edge
+    fn draw(cells: &[Vec<Cell>], cell: u32, margin: u32) -> DynamicImage {
+        let size = cells.len() as u32;
+        let side = size * cell + 2 * margin;
+        let mut image = GrayImage::from_pixel(side, side, Luma([255]));
+        let mut fill = |x0: u32, y0: u32, w: u32, h: u32| {
+            for y in y0..y0 + h {
+                for x in x0..x0 + w {
+                    image.put_pixel(x, y, Luma([0]));
+                }
+            }
+        };
+
+        for (row, cells) in cells.iter().enumerate() {
+            for (column, content) in cells.iter().enumerate() {
+                let (x, y) = (margin + column as u32 * cell, margin + row as u32 * cell);
+                fill(x, y, cell, 1);
+                fill(x, y, 1, cell);
+                match content {
+                    Cell::Empty => {}
+                    Cell::Block => fill(x, y, cell, cell),
+                    Cell::Letter(0) => fill(x + cell / 4, y + cell / 2 - cell / 10, cell / 2, cell / 5),
+                    Cell::Letter(_) => fill(x + cell / 2 - cell / 10, y + cell / 4, cell / 5, cell / 2),
+                }
+            }
+        }
+        fill(margin, margin + size * cell - 1, size * cell, 1);
+        fill(margin + size * cell - 1, margin, 1, size * cell);
+        DynamicImage::ImageLuma8(image)
+    }
+
+    fn solution() -> Vec<Vec<Cell>> {
+        let mut cells = vec![vec![Cell::Letter(0); 5]; 5];
+        cells[0][4] = Cell::Block;
+        cells[2][2] = Cell::Block;
+        cells[4][0] = Cell::Letter(1);
+        cells
+    }
+
+    #[test]
+    fn test_identical_grids_match() {
+        let grade = grade(&draw(&solution(), 40, 10), &draw(&solution(), 40, 10), 5).unwrap();
+        assert!(grade.differences.is_empty());
+    }
+
+    #[test]
+    fn test_finds_wrong_and_missing_answers_despite_scale_and_margin() {
+        let mut mine = solution();
+        mine[1][3] = Cell::Letter(1);
+        mine[3][1] = Cell::Empty;
+
+        // A bigger scan with a wider border than the published solution
+        let grade = grade(&draw(&mine, 60, 45), &draw(&solution(), 40, 10), 5).unwrap();
+
+        assert_eq!(grade.differences, vec![(2, 4), (4, 2)]);
+        assert_eq!(grade.overlay.dimensions(), (5 * CELL, 5 * CELL));
+        assert_eq!(*grade.overlay.get_pixel(3 * CELL + 2, CELL + 2), Rgb([254, 127, 127]));
+    }
+
+    #[test]
+    fn test_blank_image_has_no_grid() {
+        let blank = DynamicImage::ImageLuma8(GrayImage::from_pixel(50, 50, Luma([255])));
+        let error = grade(&blank, &draw(&solution(), 40, 10), 5).err().unwrap();
+        assert!(error.to_string().contains("solved image"));
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+use super::{deadline, HttpClient, HttpRequest, HttpResponse};
+use crate::console;
+
+/// The `[backoff]` section of the config: how often a request that failed in passing, such
+/// as a 502 from the site or a dropped connection, is tried again before the run gives up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackoffConfig {
+    /// Tries in all, the first included; 1 turns retrying off
+    pub attempts: u32,
+    /// Wait before the second try, doubled before each one after it
+    pub initial_delay_ms: u64,
+    /// Longest wait between two tries
+    pub max_delay_ms: u64,
+    /// Wait a random amount between half and all of the delay, so runs that failed together
+    /// don't all come back at once
+    pub jitter: bool,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_delay_ms: 1000,
+            max_delay_ms: 10_000,
+            jitter: true,
+        }
+    }
+}
+
+impl BackoffConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.attempts == 0 {
+            problems.push("[backoff] attempts must be at least 1".to_string());
+        }
+        if self.initial_delay_ms > self.max_delay_ms {
+            problems.push("[backoff] initial_delay_ms is longer than max_delay_ms".to_string());
+        }
+        problems
+    }
+
+    /// How long to wait after the `failed`th try failed
+    fn delay(&self, failed: u32) -> Duration {
+        let delay = self
+            .initial_delay_ms
+            .saturating_mul(1u64 << failed.saturating_sub(1).min(32))
+            .min(self.max_delay_ms);
+        let delay = match self.jitter {
+            true => delay / 2 + fastrand::u64(0..=delay - delay / 2),
+            false => delay,
+        };
+        Duration::from_millis(delay)
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails with an error `transient` doesn't accept, or the
+/// tries run out, waiting longer before each new one
+pub async fn retry<T, F, Fut>(
+    config: &BackoffConfig,
+    what: &str,
+    transient: impl Fn(&anyhow::Error) -> bool,
+    mut attempt: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut failed = 0;
+    loop {
+        match attempt().await {
+            Err(e) if transient(&e) && failed + 1 < config.attempts => {
+                failed += 1;
+                let delay = config.delay(failed);
+                console::progress!("{} failed ({:#}); trying again in {} ms", what, e, delay.as_millis());
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Statuses the site answers with while overloaded or restarting, which are worth another try
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 429 | 500 | 502 | 503 | 504)
+}
+
+/// Connection failures and timeouts; an error from anywhere else, like polite mode's daily
+/// cap, would only happen again
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout() || e.is_request())
+}
+
+/// Wraps a client to send a request again when it fails in passing. Once the tries run out,
+/// the last response (or error) is passed on as it is.
+pub struct Backoff<C> {
+    inner: C,
+    config: BackoffConfig,
+}
+
+impl<C: HttpClient> Backoff<C> {
+    pub fn new(inner: C, config: BackoffConfig) -> Self {
+        Self { inner, config }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Backoff<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let mut failed = 0;
+        loop {
+            let result = self.inner.send(request.clone()).await;
+            let reason = match &result {
+                Ok(response) if is_transient_status(response.status) => format!("HTTP {}", response.status),
+                Err(e) if is_transient_error(e) => format!("{:#}", e),
+                _ => return result,
+            };
+            failed += 1;
+            if failed >= self.config.attempts {
+                return result;
+            }
+            let delay = self.config.delay(failed);
+            // The deadline wrapping this client only saw the first try
+            deadline::check(delay)?;
+            console::progress!("{} {} failed ({}); trying again in {} ms", request.method, request.url, reason, delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers with the given statuses in turn, then 200
+    struct Flaky(Mutex<Vec<u16>>);
+
+    #[async_trait]
+    impl HttpClient for Flaky {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            let mut statuses = self.0.lock().unwrap();
+            let status = if statuses.is_empty() { 200 } else { statuses.remove(0) };
+            Ok(HttpResponse {
+                status,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn config(attempts: u32) -> BackoffConfig {
+        BackoffConfig {
+            attempts,
+            initial_delay_ms: 0,
+            ..BackoffConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_statuses() {
+        let client = Backoff::new(Flaky(Mutex::new(vec![502, 503])), config(3));
+        let response = client.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+        assert_eq!(response.status, 200);
+
+        // Out of tries, the last answer is passed on
+        let client = Backoff::new(Flaky(Mutex::new(vec![502, 502, 502])), config(3));
+        assert_eq!(client.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap().status, 502);
+
+        // A 404 won't change by asking again
+        let client = Backoff::new(Flaky(Mutex::new(vec![404])), config(3));
+        assert_eq!(client.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap().status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_at_lasting_errors() {
+        let tries = Mutex::new(0);
+        let result: Result<()> = retry(&config(5), "Upload", |e| e.to_string() == "503", || async {
+            *tries.lock().unwrap() += 1;
+            match *tries.lock().unwrap() {
+                1 => Err(anyhow::anyhow!("503")),
+                _ => Err(anyhow::anyhow!("403")),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "403");
+        assert_eq!(*tries.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_delay_doubles_up_to_the_limit() {
+        let config = BackoffConfig {
+            jitter: false,
+            ..BackoffConfig::default()
+        };
+        let delays: Vec<u64> = (1..=6).map(|failed| config.delay(failed).as_millis() as u64).collect();
+        assert_eq!(delays, [1000, 2000, 4000, 8000, 10_000, 10_000]);
+
+        let jittered = BackoffConfig::default().delay(2).as_millis();
+        assert!((1000..=2000).contains(&jittered));
+    }
+}
@@ -0,0 +1,190 @@
+//! Fetching through a headless Chromium, for when the site's bot protection starts turning
+//! plain requests away. The browser opens the site like a reader would and then makes each
+//! request with `fetch()` from inside that page, so the responses go through the same
+//! parsing and pipeline code as usual. Needs the `browser` feature.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{HttpClient, HttpRequest, HttpResponse};
+use crate::parser;
+use crate::types::Interstitial;
+
+#[cfg(feature = "browser")]
+mod chromium;
+#[cfg(feature = "browser")]
+pub use chromium::Chromium;
+
+/// When requests go through the headless browser
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrowserMode {
+    /// Plain HTTP only
+    #[default]
+    Off,
+    /// Plain HTTP until the site blocks a request, the browser for the rest of the run
+    Fallback,
+    /// Every request through the browser
+    Always,
+}
+
+/// The `[browser]` section of the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserConfig {
+    pub mode: BrowserMode,
+    /// Chromium or Chrome binary; looked up in the usual places when unset
+    pub executable: Option<PathBuf>,
+    /// Run Chromium without its sandbox, which it refuses to start without when run as root,
+    /// e.g. in a container
+    pub no_sandbox: bool,
+}
+
+impl BrowserConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if self.mode != BrowserMode::Off && !cfg!(feature = "browser") {
+            return vec!["[browser] mode needs a build with `--features browser`".to_string()];
+        }
+        Vec::new()
+    }
+}
+
+/// Stands in for the browser in builds without the `browser` feature, which reject any
+/// other mode than `off` when validating the config
+#[cfg(not(feature = "browser"))]
+pub struct Chromium;
+
+#[cfg(not(feature = "browser"))]
+impl Chromium {
+    pub fn new(_config: BrowserConfig) -> Self {
+        Self
+    }
+}
+
+#[cfg(not(feature = "browser"))]
+#[async_trait]
+impl HttpClient for Chromium {
+    async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+        anyhow::bail!("This build has no headless browser support; rebuild with `--features browser`")
+    }
+}
+
+/// Whether the site turned a plain request away as a bot rather than answering it
+pub fn is_blocked(response: &HttpResponse) -> bool {
+    let is_html = response.content_type().is_some_and(|content_type| content_type.contains("html"));
+    matches!(response.status, 403 | 429)
+        || (is_html && parser::detect_interstitial(response.status, &response.text()) == Some(Interstitial::Captcha))
+}
+
+/// Sends requests through `inner` until the site blocks one, then repeats that request and
+/// sends every later one through `browser`
+pub struct Fallback<C, B> {
+    inner: C,
+    browser: B,
+    blocked: AtomicBool,
+}
+
+impl<C: HttpClient, B: HttpClient> Fallback<C, B> {
+    pub fn new(inner: C, browser: B) -> Self {
+        Self {
+            inner,
+            browser,
+            blocked: AtomicBool::new(false),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient, B: HttpClient> HttpClient for Fallback<C, B> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        if !self.blocked.load(Ordering::Relaxed) {
+            let response = self.inner.send(request.clone()).await?;
+            if !is_blocked(&response) {
+                return Ok(response);
+            }
+            tracing::warn!(
+                "{} was blocked (HTTP {}); switching to the headless browser",
+                request.url,
+                response.status
+            );
+            self.blocked.store(true, Ordering::Relaxed);
+        }
+        self.browser.send(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+    use std::sync::atomic::AtomicUsize;
+
+    /// Answers every request with the same response and counts them
+    struct FixedClient {
+        status: u16,
+        body: &'static str,
+        requests: AtomicUsize,
+    }
+
+    impl FixedClient {
+        fn new(status: u16, body: &'static str) -> Self {
+            Self {
+                status,
+                body,
+                requests: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HttpClient for FixedClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+            Ok(HttpResponse {
+                status: self.status,
+                headers,
+                body: self.body.as_bytes().to_vec(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_is_blocked() {
+        let response = |status, content_type: &'static str, body: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            HttpResponse {
+                status,
+                headers,
+                body: body.as_bytes().to_vec(),
+            }
+        };
+        assert!(is_blocked(&response(403, "text/html", "Forbidden")));
+        assert!(is_blocked(&response(429, "text/plain", "Slow down")));
+        assert!(is_blocked(&response(200, "text/html", "<div class=\"g-recaptcha\"></div>")));
+        assert!(!is_blocked(&response(200, "text/html", "<map></map>")));
+        assert!(!is_blocked(&response(503, "text/html", "Down for maintenance")));
+        assert!(!is_blocked(&response(200, "image/jpeg", "captcha")));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_switches_to_browser_once_blocked() {
+        let fallback = Fallback::new(FixedClient::new(200, "<map></map>"), FixedClient::new(200, "rendered"));
+        fallback.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+        assert_eq!(fallback.browser.requests.load(Ordering::Relaxed), 0);
+
+        let fallback = Fallback::new(FixedClient::new(403, "Forbidden"), FixedClient::new(200, "rendered"));
+        let response = fallback.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+        assert_eq!(response.text(), "rendered");
+        fallback.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+
+        // Once blocked, plain requests aren't tried again this run
+        assert_eq!(fallback.inner.requests.load(Ordering::Relaxed), 1);
+        assert_eq!(fallback.browser.requests.load(Ordering::Relaxed), 2);
+    }
+}
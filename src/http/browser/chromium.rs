@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chromiumoxide::{Browser, Page};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+
+use super::BrowserConfig;
+use crate::console;
+use crate::http::{HttpClient, HttpRequest, HttpResponse, USER_AGENT};
+
+/// A headless Chromium, launched on the first request and closed when dropped
+pub struct Chromium {
+    config: BrowserConfig,
+    session: OnceCell<Session>,
+}
+
+struct Session {
+    // Kills the Chromium process when dropped
+    _browser: Browser,
+    handler: JoinHandle<()>,
+    page: Page,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.handler.abort();
+    }
+}
+
+/// What the in-page `fetch()` hands back
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FetchResult {
+    status: u16,
+    content_type: Option<String>,
+    /// Base64, since the page can only return strings
+    body: String,
+}
+
+impl Chromium {
+    pub fn new(config: BrowserConfig) -> Self {
+        Self {
+            config,
+            session: OnceCell::new(),
+        }
+    }
+
+    /// Starts the browser and opens the site's front page, which lets any bot check run
+    /// and set its cookies before the first real request
+    async fn launch(&self, url: &str) -> Result<Session> {
+        let mut builder = chromiumoxide::BrowserConfig::builder().arg(format!("--user-agent={}", USER_AGENT));
+        if let Some(executable) = &self.config.executable {
+            builder = builder.chrome_executable(executable);
+        }
+        if self.config.no_sandbox {
+            builder = builder.no_sandbox();
+        }
+        let (browser, mut events) = Browser::launch(builder.build().map_err(anyhow::Error::msg)?)
+            .await
+            .context("Failed to launch the headless browser")?;
+        let handler = tokio::spawn(async move { while events.next().await.is_some() {} });
+
+        let origin = reqwest::Url::parse(url)?.join("/")?;
+        console::progress!("Opening {} in the headless browser", origin);
+        let page = browser.new_page(origin.as_str()).await?;
+        page.wait_for_navigation().await?;
+
+        Ok(Session {
+            _browser: browser,
+            handler,
+            page,
+        })
+    }
+}
+
+#[async_trait]
+impl HttpClient for Chromium {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let session = self.session.get_or_try_init(|| self.launch(&request.url)).await?;
+        let result: FetchResult = session
+            .page
+            .evaluate(fetch_script(&request)?)
+            .await
+            .with_context(|| format!("Headless browser failed to fetch {}", request.url))?
+            .into_value()?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = result.content_type {
+            headers.insert(CONTENT_TYPE, HeaderValue::try_from(content_type)?);
+        }
+        Ok(HttpResponse {
+            status: result.status,
+            headers,
+            body: STANDARD.decode(result.body)?,
+        })
+    }
+}
+
+/// JavaScript making `request` from inside the page, with the page's cookies
+fn fetch_script(request: &HttpRequest) -> Result<String> {
+    // The browser supplies its own user agent, origin and sec-* headers
+    let headers: BTreeMap<&str, &str> = request
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .collect();
+    Ok(format!(
+        r#"(async () => {{
+    const response = await fetch({url}, {{ method: {method}, headers: {headers}, body: {body}, credentials: "include" }});
+    const bytes = new Uint8Array(await response.arrayBuffer());
+    let binary = "";
+    for (let i = 0; i < bytes.length; i += 0x8000) {{
+        binary += String.fromCharCode.apply(null, bytes.subarray(i, i + 0x8000));
+    }}
+    return {{ status: response.status, contentType: response.headers.get("content-type"), body: btoa(binary) }};
+}})()"#,
+        url = serde_json::to_string(&request.url)?,
+        method = serde_json::to_string(request.method.as_str())?,
+        headers = serde_json::to_string(&headers)?,
+        body = serde_json::to_string(&request.body)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_script_escapes_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-requested-with", HeaderValue::from_static("XMLHttpRequest"));
+        let request = HttpRequest::post("https://www.ehitavada.com/val.php", "a=\"1\"&b=2").headers(headers);
+
+        let script = fetch_script(&request).unwrap();
+
+        assert!(script.contains(r#"fetch("https://www.ehitavada.com/val.php", { method: "POST""#));
+        assert!(script.contains(r#"headers: {"x-requested-with":"XMLHttpRequest"}"#));
+        assert!(script.contains(r#"body: "a=\"1\"&b=2""#));
+        assert!(fetch_script(&HttpRequest::get("https://www.ehitavada.com/")).unwrap().contains("body: null"));
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+use super::{HttpClient, HttpRequest, HttpResponse};
+
+/// Method, URL and body of a request
+type Key = (String, String, Option<String>);
+
+/// Remembers successful responses for the length of one run, so regions on the same
+/// page map don't fetch it again
+pub struct RunCache<C> {
+    inner: C,
+    responses: Mutex<HashMap<Key, HttpResponse>>,
+}
+
+impl<C: HttpClient> RunCache<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            responses: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for RunCache<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let key = (request.method.to_string(), request.url.clone(), request.body.clone());
+        if let Some(response) = self.responses.lock().await.get(&key) {
+            return Ok(response.clone());
+        }
+
+        let response = self.inner.send(request).await?;
+        // Errors might be transient, so only good responses are reused
+        if response.is_success() {
+            self.responses.lock().await.insert(key, response.clone());
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    /// Counts requests and answers with a configurable status
+    struct CountingClient {
+        calls: AtomicU16,
+        status: u16,
+    }
+
+    #[async_trait]
+    impl HttpClient for CountingClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(HttpResponse {
+                status: self.status,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_requests_are_served_from_cache() {
+        let cache = RunCache::new(CountingClient { calls: AtomicU16::new(0), status: 200 });
+
+        cache.send(HttpRequest::post("https://www.ehitavada.com/val.php", "page=1")).await.unwrap();
+        cache.send(HttpRequest::post("https://www.ehitavada.com/val.php", "page=1")).await.unwrap();
+        cache.send(HttpRequest::post("https://www.ehitavada.com/val.php", "page=2")).await.unwrap();
+
+        assert_eq!(cache.inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_not_cached() {
+        let cache = RunCache::new(CountingClient { calls: AtomicU16::new(0), status: 503 });
+
+        cache.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+        cache.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+
+        assert_eq!(cache.inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
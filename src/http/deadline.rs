@@ -0,0 +1,137 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use super::{HttpClient, HttpRequest, HttpResponse};
+use crate::error::CrosswordError;
+
+tokio::task_local! {
+    /// The deadline of the request being sent, for the clients it is passed through to
+    static CUTOFF: Cutoff;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cutoff {
+    deadline: SystemTime,
+    reserve: Duration,
+    requests: usize,
+}
+
+/// Fails with [`CrosswordError::OutOfTime`] when waiting `delay` before sending again would
+/// end inside the reserve of the deadline the request is sent under. A client retrying
+/// inside [`Deadline`] checks this before each wait, as `Deadline` itself only sees the
+/// first try.
+pub fn check(delay: Duration) -> Result<()> {
+    let Ok(cutoff) = CUTOFF.try_with(|cutoff| *cutoff) else {
+        return Ok(());
+    };
+    let remaining = cutoff.deadline.duration_since(SystemTime::now()).unwrap_or_default();
+    if remaining.saturating_sub(delay) < cutoff.reserve {
+        return Err(CrosswordError::OutOfTime {
+            remaining,
+            requests: cutoff.requests,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Wraps a client to refuse new requests once less than `reserve` is left before
+/// `deadline`, so a run stops scanning in time to finish what it started
+pub struct Deadline<C> {
+    inner: C,
+    deadline: SystemTime,
+    reserve: Duration,
+    sent: AtomicUsize,
+}
+
+impl<C: HttpClient> Deadline<C> {
+    pub fn new(inner: C, deadline: SystemTime, reserve: Duration) -> Self {
+        Self {
+            inner,
+            deadline,
+            reserve,
+            sent: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Deadline<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let remaining = self.deadline.duration_since(SystemTime::now()).unwrap_or_default();
+        if remaining < self.reserve {
+            return Err(CrosswordError::OutOfTime {
+                remaining,
+                requests: self.sent.load(Ordering::SeqCst),
+            }
+            .into());
+        }
+
+        let cutoff = Cutoff {
+            deadline: self.deadline,
+            reserve: self.reserve,
+            requests: self.sent.fetch_add(1, Ordering::SeqCst) + 1,
+        };
+        CUTOFF.scope(cutoff, self.inner.send(request)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct OkClient;
+
+    #[async_trait]
+    impl HttpClient for OkClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            Ok(HttpResponse { status: 200, ..Default::default() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refuses_requests_inside_the_reserve() {
+        let later = SystemTime::now() + Duration::from_secs(60);
+
+        let roomy = Deadline::new(OkClient, later, Duration::from_secs(10));
+        assert!(roomy.send(HttpRequest::get("https://www.ehitavada.com/")).await.is_ok());
+
+        let tight = Deadline::new(OkClient, later, Duration::from_secs(120));
+        let error = tight.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CrosswordError>(),
+            Some(CrosswordError::OutOfTime { requests: 0, .. })
+        ));
+        assert!(crate::error::is_retryable(&error));
+    }
+
+    /// Checks the deadline before a 30 s wait, as `Backoff` does before trying again
+    struct RetryingClient;
+
+    #[async_trait]
+    impl HttpClient for RetryingClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            check(Duration::from_secs(30))?;
+            Ok(HttpResponse { status: 200, ..Default::default() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_inside_stop_at_the_reserve() {
+        let later = SystemTime::now() + Duration::from_secs(60);
+        assert!(check(Duration::from_secs(3600)).is_ok(), "no deadline outside a Deadline");
+
+        let roomy = Deadline::new(RetryingClient, later, Duration::from_secs(10));
+        assert!(roomy.send(HttpRequest::get("https://www.ehitavada.com/")).await.is_ok());
+
+        // Sent with 60 s left, but a 30 s wait would leave less than the 40 s reserve
+        let tight = Deadline::new(RetryingClient, later, Duration::from_secs(40));
+        let error = tight.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CrosswordError>(),
+            Some(CrosswordError::OutOfTime { requests: 1, .. })
+        ));
+    }
+}
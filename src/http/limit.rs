@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Semaphore;
+
+use super::{HttpClient, HttpRequest, HttpResponse};
+
+/// Permits for requests to the newspaper, shared by every client the process builds, so
+/// regions and dates fetched side by side stay within one limit rather than one each
+static PERMITS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+/// Wraps a client to keep at most `[site] max_concurrent_requests` requests in flight at once,
+/// across the whole process
+pub struct Limited<C> {
+    inner: C,
+    permits: Arc<Semaphore>,
+}
+
+impl<C: HttpClient> Limited<C> {
+    /// The process-wide limit is set by the first client built; a config is loaded once per run
+    pub fn new(inner: C, max_concurrent_requests: usize) -> Self {
+        let permits = PERMITS.get_or_init(|| Arc::new(Semaphore::new(max_concurrent_requests)));
+        Self {
+            inner,
+            permits: permits.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Limited<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        // Held until the response is read; waiting for retries happens outside, without one
+        let _permit = self.permits.acquire().await.context("The request limit was closed")?;
+        self.inner.send(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Counts how many requests are in flight at once
+    #[derive(Default)]
+    struct SlowClient {
+        in_flight: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl HttpClient for SlowClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(HttpResponse { status: 200, ..Default::default() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clients_share_the_limit() {
+        let permits = Arc::new(Semaphore::new(2));
+        let inner = SlowClient::default();
+        let crossword = Limited { inner: &inner, permits: permits.clone() };
+        let sudoku = Limited { inner: &inner, permits };
+        let (a, b, c, d) = tokio::join!(
+            crossword.send(HttpRequest::get("https://www.ehitavada.com/a")),
+            crossword.send(HttpRequest::get("https://www.ehitavada.com/b")),
+            sudoku.send(HttpRequest::get("https://www.ehitavada.com/c")),
+            sudoku.send(HttpRequest::get("https://www.ehitavada.com/d")),
+        );
+        assert!(a.is_ok() && b.is_ok() && c.is_ok() && d.is_ok());
+        assert_eq!(inner.most.load(Ordering::SeqCst), 2);
+    }
+}
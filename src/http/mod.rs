@@ -1,8 +1,148 @@
 use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
 use reqwest::{
-    header::{HeaderMap, HeaderValue},
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    Method,
 };
 
+pub mod backoff;
+pub mod browser;
+pub mod cache;
+pub mod deadline;
+pub mod limit;
+pub mod polite;
+pub mod vcr;
+
+use crate::clock::Clock;
+use backoff::Backoff;
+use browser::{BrowserMode, Chromium, Fallback};
+use limit::Limited;
+use crate::config::Config;
+
+/// File in the output directory tracking polite mode's daily request count
+const REQUEST_BUDGET_FILE: &str = ".request-budget.json";
+
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Safari/537.36";
+
+/// An outgoing request to the newspaper site
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: Method::GET,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+
+    pub fn post(url: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            method: Method::POST,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: Some(body.into()),
+        }
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+/// A fully buffered response
+#[derive(Debug, Clone, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Sends requests to the site; implemented by the real client and by the record/replay layer
+#[async_trait]
+pub trait HttpClient: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+#[async_trait]
+impl<T: HttpClient + ?Sized> HttpClient for Box<T> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait]
+impl<T: HttpClient + ?Sized> HttpClient for &T {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait]
+impl HttpClient for reqwest::Client {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let mut builder = self.request(request.method, &request.url).headers(request.headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send().await?;
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?.to_vec();
+
+        Ok(HttpResponse { status, headers, body })
+    }
+}
+
+/// Builds the client used to talk to the site, throttled when polite mode is enabled and
+/// trying again on the `[backoff]` schedule when a request fails in passing
+pub fn build_client(config: &Config, clock: Arc<dyn Clock>) -> Result<Box<dyn HttpClient>> {
+    // Create a client with a user agent to mimic a browser
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()?;
+    let client: Box<dyn HttpClient> = match config.browser.mode {
+        BrowserMode::Off => Box::new(client),
+        BrowserMode::Fallback => Box::new(Fallback::new(client, Chromium::new(config.browser.clone()))),
+        BrowserMode::Always => Box::new(Chromium::new(config.browser.clone())),
+    };
+    // Innermost, so a permit covers the request itself and not the waits of polite mode or retries
+    let client: Box<dyn HttpClient> = Box::new(Limited::new(client, config.site.max_concurrent_requests));
+
+    // Retries go through polite mode too, so they are spaced out and counted like any request
+    let client: Box<dyn HttpClient> = if config.polite.enabled {
+        let budget_path = config.output_dir.join(REQUEST_BUDGET_FILE);
+        Box::new(polite::Polite::new(client, config.polite.clone(), budget_path, clock))
+    } else {
+        client
+    };
+    Ok(Box::new(Backoff::new(client, config.backoff.clone())))
+}
+
 pub fn create_headers() -> Result<HeaderMap> {
     let mut headers = HeaderMap::new();
     headers.insert("accept", HeaderValue::from_static("*/*"));
@@ -17,7 +157,7 @@ pub fn create_headers() -> Result<HeaderMap> {
     headers.insert("sec-fetch-dest", HeaderValue::from_static("empty"));
     headers.insert("sec-fetch-mode", HeaderValue::from_static("cors"));
     headers.insert("sec-fetch-site", HeaderValue::from_static("same-origin"));
-    headers.insert("user-agent", HeaderValue::from_static("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Safari/537.36"));
+    headers.insert("user-agent", HeaderValue::from_static(USER_AGENT));
     headers.insert("x-requested-with", HeaderValue::from_static("XMLHttpRequest"));
     Ok(headers)
 }
@@ -44,6 +184,34 @@ mod tests {
         assert_eq!(headers.get("content-type").unwrap(), "application/x-www-form-urlencoded; charset=UTF-8");
     }
 
+    #[test]
+    fn test_request_builders() {
+        let get = HttpRequest::get("https://www.ehitavada.com/article.php");
+        assert_eq!(get.method, Method::GET);
+        assert_eq!(get.body, None);
+
+        let post = HttpRequest::post("https://www.ehitavada.com/val.php", "a=b")
+            .headers(create_headers().unwrap());
+        assert_eq!(post.method, Method::POST);
+        assert_eq!(post.body.as_deref(), Some("a=b"));
+        assert!(post.headers.contains_key("origin"));
+    }
+
+    #[test]
+    fn test_response_helpers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        let response = HttpResponse {
+            status: 200,
+            headers,
+            body: b"<map></map>".to_vec(),
+        };
+        assert!(response.is_success());
+        assert_eq!(response.content_type(), Some("text/html"));
+        assert_eq!(response.text(), "<map></map>");
+        assert!(!HttpResponse { status: 502, ..Default::default() }.is_success());
+    }
+
     #[test]
     fn test_headers_are_valid() {
         let headers = create_headers().unwrap();
@@ -0,0 +1,253 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use reqwest::header::{HeaderName, HeaderValue, FROM, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use super::{HttpClient, HttpRequest, HttpResponse};
+use crate::clock::Clock;
+use crate::config::PoliteConfig;
+use crate::storage;
+
+/// Requests made so far today, persisted so the cap holds across runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Budget {
+    date: Option<NaiveDate>,
+    count: u32,
+}
+
+struct State {
+    last_request: Option<Instant>,
+    budget: Budget,
+}
+
+/// Wraps a client to space requests out, send them one at a time, and cap the daily total
+pub struct Polite<C> {
+    inner: C,
+    settings: PoliteConfig,
+    budget_path: PathBuf,
+    clock: Arc<dyn Clock>,
+    // Held for the whole request, which also limits concurrency to one
+    state: Mutex<State>,
+}
+
+impl<C: HttpClient> Polite<C> {
+    pub fn new(inner: C, settings: PoliteConfig, budget_path: impl Into<PathBuf>, clock: Arc<dyn Clock>) -> Self {
+        let budget_path = budget_path.into();
+        let budget = fs::read_to_string(&budget_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            inner,
+            settings,
+            budget_path,
+            clock,
+            state: Mutex::new(State {
+                last_request: None,
+                budget,
+            }),
+        }
+    }
+
+    fn identify(&self, request: &mut HttpRequest) -> Result<()> {
+        if self.settings.identify {
+            request.headers.insert(USER_AGENT, HeaderValue::try_from(user_agent(self.settings.from.as_deref()))?);
+        }
+        if let Some(from) = &self.settings.from {
+            request.headers.insert(FROM, HeaderValue::try_from(from.as_str())?);
+        }
+        for (name, value) in &self.settings.headers {
+            request.headers.insert(
+                HeaderName::try_from(name.as_str())?,
+                HeaderValue::try_from(value.as_str())?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The user agent of `identify`, e.g. `hitavada-crossword-downloader/0.1.0 (reader@example.com)`
+fn user_agent(contact: Option<&str>) -> String {
+    let tool = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    match contact {
+        Some(contact) => format!("{} ({})", tool, contact),
+        None => tool,
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Polite<C> {
+    async fn send(&self, mut request: HttpRequest) -> Result<HttpResponse> {
+        let mut state = self.state.lock().await;
+
+        let today = self.clock.today();
+        if state.budget.date != Some(today) {
+            state.budget = Budget {
+                date: Some(today),
+                count: 0,
+            };
+        }
+        if let Some(cap) = self.settings.max_requests_per_day {
+            if state.budget.count >= cap {
+                return Err(anyhow::anyhow!(
+                    "Polite mode daily cap of {} requests reached; try again tomorrow",
+                    cap
+                ));
+            }
+        }
+
+        if let Some(last) = state.last_request {
+            tokio::time::sleep_until(last + Duration::from_millis(self.settings.min_delay_ms)).await;
+        }
+
+        self.identify(&mut request)?;
+        state.budget.count += 1;
+        storage::write_atomic(&self.budget_path, serde_json::to_string(&state.budget)?.as_bytes())
+            .with_context(|| format!("Failed to write request budget {}", self.budget_path.display()))?;
+
+        let response = self.inner.send(request).await;
+        state.last_request = Some(Instant::now());
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex as StdMutex;
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct CapturingClient {
+        requests: StdMutex<Vec<(HttpRequest, std::time::Instant)>>,
+    }
+
+    #[async_trait]
+    impl HttpClient for CapturingClient {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            self.requests.lock().unwrap().push((request, std::time::Instant::now()));
+            Ok(HttpResponse {
+                status: 200,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn settings() -> PoliteConfig {
+        PoliteConfig {
+            enabled: true,
+            min_delay_ms: 0,
+            max_requests_per_day: None,
+            from: None,
+            headers: BTreeMap::new(),
+            identify: false,
+        }
+    }
+
+    fn clock(now: &str) -> Arc<dyn Clock> {
+        Arc::new(FixedClock::at(now))
+    }
+
+    #[tokio::test]
+    async fn test_spaces_out_requests() {
+        let dir = TempDir::new().unwrap();
+        let polite = Polite::new(
+            CapturingClient::default(),
+            PoliteConfig { min_delay_ms: 100, ..settings() },
+            dir.path().join("budget.json"),
+            clock("2024-03-20T06:00:00+00:00"),
+        );
+
+        for _ in 0..3 {
+            polite.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+        }
+
+        let requests = polite.inner.requests.lock().unwrap();
+        for pair in requests.windows(2) {
+            assert!(pair[1].1 - pair[0].1 >= Duration::from_millis(100));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_daily_cap_persists_across_runs() {
+        let dir = TempDir::new().unwrap();
+        let budget = dir.path().join("budget.json");
+        let capped = PoliteConfig { max_requests_per_day: Some(2), ..settings() };
+
+        let first_run = Polite::new(CapturingClient::default(), capped.clone(), &budget, clock("2024-03-20T06:00:00+00:00"));
+        first_run.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+
+        let second_run = Polite::new(CapturingClient::default(), capped.clone(), &budget, clock("2024-03-20T09:00:00+00:00"));
+        second_run.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+        let error = second_run.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap_err();
+        assert!(error.to_string().contains("daily cap of 2 requests"));
+
+        // The budget resets the next day
+        let next_day = Polite::new(CapturingClient::default(), capped, &budget, clock("2024-03-21T06:00:00+00:00"));
+        assert!(next_day.send(HttpRequest::get("https://www.ehitavada.com/")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_identifies_the_tool() {
+        let dir = TempDir::new().unwrap();
+        let mut headers = BTreeMap::new();
+        headers.insert("X-Crossword-Downloader".to_string(), "personal archive".to_string());
+        let polite = Polite::new(
+            CapturingClient::default(),
+            PoliteConfig {
+                from: Some("reader@example.com".to_string()),
+                headers,
+                ..settings()
+            },
+            dir.path().join("budget.json"),
+            clock("2024-03-20T06:00:00+00:00"),
+        );
+
+        polite.send(HttpRequest::get("https://www.ehitavada.com/")).await.unwrap();
+
+        let requests = polite.inner.requests.lock().unwrap();
+        let sent = &requests[0].0.headers;
+        assert_eq!(sent.get(FROM).unwrap(), "reader@example.com");
+        assert_eq!(sent.get("x-crossword-downloader").unwrap(), "personal archive");
+        assert!(sent.get(USER_AGENT).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_identify_replaces_the_browser_user_agent() {
+        let dir = TempDir::new().unwrap();
+        let settings = PoliteConfig {
+            from: Some("reader@example.com".to_string()),
+            identify: true,
+            ..settings()
+        };
+        assert!(crate::config::validate_polite(&settings).is_empty());
+        let polite = Polite::new(
+            CapturingClient::default(),
+            settings,
+            dir.path().join("budget.json"),
+            clock("2024-03-20T06:00:00+00:00"),
+        );
+
+        let mut request = HttpRequest::get("https://www.ehitavada.com/");
+        request.headers = crate::http::create_headers().unwrap();
+        polite.send(request).await.unwrap();
+
+        let requests = polite.inner.requests.lock().unwrap();
+        let user_agent = requests[0].0.headers.get(USER_AGENT).unwrap().to_str().unwrap();
+        let expected = format!("hitavada-crossword-downloader/{} (reader@example.com)", env!("CARGO_PKG_VERSION"));
+        assert_eq!(user_agent, expected);
+
+        let anonymous = PoliteConfig { identify: true, enabled: false, ..PoliteConfig::default() };
+        assert_eq!(crate::config::validate_polite(&anonymous).len(), 2);
+    }
+}
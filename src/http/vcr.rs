@@ -0,0 +1,296 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{HttpClient, HttpRequest, HttpResponse};
+
+/// A recorded request/response pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Text bodies are stored as-is, binary bodies (images) as base64
+    pub body: String,
+    #[serde(default, skip_serializing_if = "crate::types::is_false")]
+    pub base64: bool,
+}
+
+/// A fixture file holding the interactions of one run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read HTTP fixture {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse HTTP fixture {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content).with_context(|| format!("Failed to write HTTP fixture {}", path.display()))
+    }
+}
+
+impl From<&HttpRequest> for RecordedRequest {
+    fn from(request: &HttpRequest) -> Self {
+        Self {
+            method: request.method.to_string(),
+            url: request.url.clone(),
+            body: request.body.clone(),
+        }
+    }
+}
+
+impl From<&HttpResponse> for RecordedResponse {
+    fn from(response: &HttpResponse) -> Self {
+        let headers = response
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+
+        match std::str::from_utf8(&response.body) {
+            Ok(text) => Self {
+                status: response.status,
+                headers,
+                body: text.to_string(),
+                base64: false,
+            },
+            Err(_) => Self {
+                status: response.status,
+                headers,
+                body: STANDARD.encode(&response.body),
+                base64: true,
+            },
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<HttpResponse> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            headers.insert(HeaderName::try_from(name.as_str())?, HeaderValue::try_from(value.as_str())?);
+        }
+
+        let body = if self.base64 {
+            STANDARD.decode(&self.body).context("Recorded body is not valid base64")?
+        } else {
+            self.body.clone().into_bytes()
+        };
+
+        Ok(HttpResponse {
+            status: self.status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Passes requests through to a real client and writes every interaction to a fixture file
+pub struct Recorder<C> {
+    inner: C,
+    /// Where the fixture is written; unset to only keep the interactions in memory
+    path: Option<PathBuf>,
+    cassette: Mutex<Cassette>,
+}
+
+impl<C: HttpClient> Recorder<C> {
+    pub fn new(inner: C, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: Some(path.into()),
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    /// Keeps the interactions for [`Recorder::cassette`] without writing a fixture file
+    pub fn in_memory(inner: C) -> Self {
+        Self {
+            inner,
+            path: None,
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    /// The interactions recorded so far
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for Recorder<C> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let recorded_request = RecordedRequest::from(&request);
+        let response = self.inner.send(request).await?;
+
+        // Save after every interaction so a failed run still leaves a usable fixture
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.interactions.push(Interaction {
+            request: recorded_request,
+            response: RecordedResponse::from(&response),
+        });
+        if let Some(path) = &self.path {
+            cassette.save(path)?;
+        }
+
+        Ok(response)
+    }
+}
+
+/// Serves responses from a fixture file instead of the network
+pub struct Replayer {
+    interactions: Vec<Interaction>,
+    used: Mutex<Vec<bool>>,
+}
+
+impl Replayer {
+    pub fn new(cassette: Cassette) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        Self {
+            interactions: cassette.interactions,
+            used: Mutex::new(used),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+}
+
+#[async_trait]
+impl HttpClient for Replayer {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let wanted = RecordedRequest::from(&request);
+        let mut used = self.used.lock().unwrap();
+
+        // Identical requests are answered in the order they were recorded
+        let index = self
+            .interactions
+            .iter()
+            .enumerate()
+            .position(|(i, interaction)| !used[i] && interaction.request == wanted)
+            .with_context(|| format!("No recorded response for {} {}", wanted.method, wanted.url))?;
+
+        used[index] = true;
+        self.interactions[index].response.to_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::CONTENT_TYPE;
+    use tempfile::NamedTempFile;
+
+    /// Answers every request with a fixed response
+    struct StaticClient(HttpResponse);
+
+    #[async_trait]
+    impl HttpClient for StaticClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn image_response() -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/jpeg"));
+        HttpResponse {
+            status: 200,
+            headers,
+            body: vec![0xFF, 0xD8, 0xFF, 0xE0, 0x80],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let fixture = NamedTempFile::new().unwrap();
+        let recorder = Recorder::new(StaticClient(image_response()), fixture.path());
+
+        let request = HttpRequest::get("https://www.ehitavada.com/encyc/6/2024/03/20/Mpage_8.jpg");
+        recorder.send(request.clone()).await.unwrap();
+
+        let cassette = Cassette::load(fixture.path()).unwrap();
+        assert_eq!(cassette.interactions.len(), 1);
+        assert!(cassette.interactions[0].response.base64);
+
+        let replayer = Replayer::new(cassette);
+        let response = replayer.send(request).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type(), Some("image/jpeg"));
+        assert_eq!(response.body, image_response().body);
+    }
+
+    #[tokio::test]
+    async fn test_replay_matches_method_url_and_body() {
+        let cassette: Cassette = serde_json::from_str(
+            r#"{"interactions": [
+                {"request": {"method": "POST", "url": "https://www.ehitavada.com/val.php", "body": "page=1"},
+                 "response": {"status": 200, "body": "first"}},
+                {"request": {"method": "POST", "url": "https://www.ehitavada.com/val.php", "body": "page=2"},
+                 "response": {"status": 200, "body": "second"}}
+            ]}"#,
+        )
+        .unwrap();
+        let replayer = Replayer::new(cassette);
+
+        let page2 = replayer
+            .send(HttpRequest::post("https://www.ehitavada.com/val.php", "page=2"))
+            .await
+            .unwrap();
+        assert_eq!(page2.text(), "second");
+
+        let page1 = replayer
+            .send(HttpRequest::post("https://www.ehitavada.com/val.php", "page=1"))
+            .await
+            .unwrap();
+        assert_eq!(page1.text(), "first");
+    }
+
+    #[tokio::test]
+    async fn test_replay_consumes_interactions() {
+        let cassette: Cassette = serde_json::from_str(
+            r#"{"interactions": [
+                {"request": {"method": "GET", "url": "https://www.ehitavada.com/a"},
+                 "response": {"status": 502, "body": "Bad Gateway"}},
+                {"request": {"method": "GET", "url": "https://www.ehitavada.com/a"},
+                 "response": {"status": 200, "body": "ok"}}
+            ]}"#,
+        )
+        .unwrap();
+        let replayer = Replayer::new(cassette);
+        let request = HttpRequest::get("https://www.ehitavada.com/a");
+
+        assert_eq!(replayer.send(request.clone()).await.unwrap().status, 502);
+        assert_eq!(replayer.send(request.clone()).await.unwrap().status, 200);
+
+        let error = replayer.send(request).await.unwrap_err();
+        assert!(error.to_string().contains("No recorded response for GET"));
+    }
+}
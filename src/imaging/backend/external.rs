@@ -0,0 +1,235 @@
+//! Backends that run an installed command-line tool on each page, passing it through PNG
+//! files in a temporary directory
+
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageFormat};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::ImageProcessor;
+
+/// The first `program` in a directory on `PATH`
+fn on_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Runs `program` with the arguments `args` makes from the input and output files, and
+/// reads back what it wrote
+fn run(program: &Path, page: &DynamicImage, args: impl FnOnce(&Path, &Path) -> Vec<OsString>) -> Result<DynamicImage> {
+    let dir = tempfile::tempdir().context("Failed to create a directory for the image tool")?;
+    let (input, output) = (dir.path().join("in.png"), dir.path().join("out.png"));
+    page.save_with_format(&input, ImageFormat::Png)?;
+    let result = Command::new(program)
+        .args(args(&input, &output))
+        .output()
+        .with_context(|| format!("Failed to run {}", program.display()))?;
+    if !result.status.success() {
+        anyhow::bail!(
+            "{} failed ({}): {}",
+            program.display(),
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+    image::open(&output).with_context(|| format!("{} wrote no readable image", program.display()))
+}
+
+/// ImageMagick, as `magick` or the older `convert`
+pub struct ImageMagick {
+    program: PathBuf,
+}
+
+impl ImageMagick {
+    pub fn new(program: PathBuf) -> Self {
+        Self { program }
+    }
+
+    pub fn find() -> Option<Self> {
+        on_path("magick").or_else(|| on_path("convert")).map(Self::new)
+    }
+
+    fn convert(&self, page: &DynamicImage, operations: &[&str]) -> Result<DynamicImage> {
+        run(&self.program, page, |input, output| {
+            let mut args = vec![input.as_os_str().to_owned()];
+            args.extend(operations.iter().map(OsString::from));
+            args.push(output.as_os_str().to_owned());
+            args
+        })
+    }
+}
+
+impl ImageProcessor for ImageMagick {
+    fn name(&self) -> &'static str {
+        "imagemagick"
+    }
+
+    fn autocrop(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        // The white border makes the trim start from paper, whatever is in the corners
+        self.convert(page, &["-bordercolor", "white", "-border", "1", "-fuzz", "40%", "-trim", "+repage"])
+    }
+
+    fn deskew(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        self.convert(page, &["-background", "white", "-deskew", "40%", "+repage"])
+    }
+
+    fn grayscale(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        self.convert(page, &["-colorspace", "Gray"])
+    }
+
+    fn enhance(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        self.convert(page, &["-contrast-stretch", "1%x1%"])
+    }
+}
+
+/// libvips' `vips`. It has no trimming to the ink, so `autocrop` stays with the `image` crate,
+/// and `deskew` only has it turn the page by the angle found here.
+pub struct Vips {
+    program: PathBuf,
+}
+
+impl Vips {
+    pub fn new(program: PathBuf) -> Self {
+        Self { program }
+    }
+
+    pub fn find() -> Option<Self> {
+        on_path("vips").map(Self::new)
+    }
+
+    fn operation(&self, page: &DynamicImage, operation: &str, extra: &[&str]) -> Result<DynamicImage> {
+        run(&self.program, page, |input, output| {
+            let mut args = vec![OsString::from(operation), input.as_os_str().to_owned(), output.as_os_str().to_owned()];
+            args.extend(extra.iter().map(OsString::from));
+            args
+        })
+    }
+}
+
+impl ImageProcessor for Vips {
+    fn name(&self) -> &'static str {
+        "vips"
+    }
+
+    fn deskew(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        let angle = super::skew(&page.to_luma8());
+        if angle == 0.0 {
+            return Ok(page.clone());
+        }
+        let angle = format!("{}", -angle);
+        // vips reads an array from one argument, and wants a value for every band of the page
+        let white = vec!["255"; usize::from(page.color().channel_count())].join(" ");
+        self.operation(page, "similarity", &["--angle", &angle, "--background", &white])
+    }
+
+    fn grayscale(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        self.operation(page, "colourspace", &["b-w"])
+    }
+
+    /// Spreads the tones over the full range, then sharpens the print's edges
+    fn enhance(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        let equalised = self.operation(page, "hist_equal", &[])?;
+        self.operation(&equalised, "sharpen", &[])
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_tool_gets_the_page_and_its_result_is_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        // Stands in for `magick`: notes its arguments and copies the input to the output
+        let program = dir.path().join("magick");
+        let log = dir.path().join("args");
+        let script = format!("#!/bin/sh\necho \"$@\" > {}\nfor last; do :; done\ncp \"$1\" \"$last\"\n", log.display());
+        std::fs::write(&program, script).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let page = DynamicImage::ImageRgb8(image::RgbImage::new(30, 20));
+        let result = ImageMagick::new(program.clone()).grayscale(&page).unwrap();
+        assert_eq!(result.dimensions(), (30, 20));
+        let args = std::fs::read_to_string(&log).unwrap();
+        assert!(args.contains("in.png -colorspace Gray "), "{}", args);
+        assert!(args.trim_end().ends_with("out.png"));
+
+        std::fs::write(&program, "#!/bin/sh\necho 'no decode delegate' >&2\nexit 1\n").unwrap();
+        let error = ImageMagick::new(program).enhance(&page).unwrap_err();
+        assert!(error.to_string().contains("no decode delegate"));
+    }
+
+    /// A stand-in `vips` in `dir` that appends its arguments to `args` and copies its input
+    /// to its output
+    fn fake_vips(dir: &Path) -> (Vips, PathBuf) {
+        let program = dir.join("vips");
+        let log = dir.join("args");
+        let script = format!("#!/bin/sh\necho \"$@\" >> {}\ncp \"$2\" \"$3\"\n", log.display());
+        std::fs::write(&program, script).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (Vips::new(program), log)
+    }
+
+    #[test]
+    fn test_vips_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let (vips, log) = fake_vips(dir.path());
+        let page = DynamicImage::ImageRgb8(image::RgbImage::new(30, 20));
+        vips.enhance(&page).unwrap();
+        let lines = std::fs::read_to_string(&log).unwrap();
+        let operations: Vec<&str> = lines.lines().filter_map(|line| line.split(' ').next()).collect();
+        assert_eq!(operations, ["hist_equal", "sharpen"]);
+
+        std::fs::remove_file(&log).unwrap();
+        let lines = image::RgbaImage::from_fn(300, 300, |_, y| match y % 20 < 2 {
+            true => image::Rgba([0, 0, 0, 255]),
+            false => image::Rgba([255, 255, 255, 255]),
+        });
+        // Three bands of white for an RGB page, four with alpha
+        let tilted = DynamicImage::ImageRgba8(super::super::rotate(&lines, 3.0));
+        vips.deskew(&DynamicImage::ImageRgb8(tilted.to_rgb8())).unwrap();
+        vips.deskew(&tilted).unwrap();
+        let args = std::fs::read_to_string(&log).unwrap();
+        let backgrounds: Vec<&str> = args.lines().filter_map(|line| line.split("--background ").nth(1)).collect();
+        assert_eq!(backgrounds, ["255 255 255", "255 255 255 255"]);
+    }
+
+    /// The built-in backend and an installed libvips should agree on what the steps do to a
+    /// page, if not pixel for pixel
+    #[test]
+    fn test_vips_agrees_with_the_built_in_backend() {
+        let Some(vips) = Vips::find() else {
+            eprintln!("vips is not installed, skipping");
+            return;
+        };
+        let builtin = super::super::ImageCrate;
+        // Faint grey print on off-white paper
+        let page = DynamicImage::ImageRgb8(image::RgbImage::from_fn(120, 80, |x, y| match (x / 10 + y / 10) % 2 {
+            0 => image::Rgb([110, 110, 110]),
+            _ => image::Rgb([200, 200, 200]),
+        }));
+        let mean = |image: &DynamicImage| {
+            let luma = image.to_luma8();
+            let sum: u64 = luma.pixels().map(|pixel| u64::from(pixel[0])).sum();
+            sum / luma.len() as u64
+        };
+        let ink = |image: &DynamicImage| image.to_luma8().get_pixel(5, 5)[0];
+        let paper = |image: &DynamicImage| image.to_luma8().get_pixel(15, 5)[0];
+
+        for (step, ours, theirs) in [
+            ("grayscale", builtin.grayscale(&page).unwrap(), vips.grayscale(&page).unwrap()),
+            ("enhance", builtin.enhance(&page).unwrap(), vips.enhance(&page).unwrap()),
+        ] {
+            assert_eq!(ours.dimensions(), theirs.dimensions(), "{}", step);
+            assert!(mean(&ours).abs_diff(mean(&theirs)) < 40, "{}", step);
+        }
+        let (ours, theirs) = (builtin.enhance(&page).unwrap(), vips.enhance(&page).unwrap());
+        for enhanced in [&ours, &theirs] {
+            assert!(ink(enhanced) < ink(&page) && paper(enhanced) > paper(&page));
+        }
+    }
+}
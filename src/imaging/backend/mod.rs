@@ -0,0 +1,212 @@
+//! The image operations behind `[processing] pipeline`, done by the `image` crate by default
+//! or handed to ImageMagick or libvips where one is installed. The heavier steps, like
+//! `deskew`, are faster and often better done by those tools.
+
+use anyhow::Result;
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+mod external;
+
+pub use external::{ImageMagick, Vips};
+
+/// Luma below this counts as ink
+pub const INK: u8 = 128;
+
+/// Largest skew `deskew` looks for either way, and how finely, in degrees
+const MAX_SKEW: f32 = 5.0;
+const SKEW_STEP: f32 = 0.25;
+
+/// Share of darkest and brightest pixels `enhance` lets clip
+const CLIP: f64 = 0.01;
+
+/// Which library or tool does the image operations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The built-in `image` crate, which needs nothing installed
+    #[default]
+    Image,
+    /// ImageMagick's `magick` (or `convert`, before version 7)
+    ImageMagick,
+    /// libvips' `vips`; steps it has no operation for are done by the `image` crate
+    Vips,
+}
+
+/// The operations a pipeline step can ask for, on one page at a time
+pub trait ImageProcessor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Trims the margins around the ink
+    fn autocrop(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        Ok(autocrop(page))
+    }
+
+    /// Straightens a page scanned at a slight angle
+    fn deskew(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        Ok(deskew(page))
+    }
+
+    fn grayscale(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        Ok(page.grayscale())
+    }
+
+    /// Stretches the contrast so faint print comes out black on white
+    fn enhance(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        Ok(enhance(page))
+    }
+}
+
+/// Does everything in-process with the `image` crate
+pub struct ImageCrate;
+
+impl ImageProcessor for ImageCrate {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+}
+
+/// The processor for `backend`, falling back to the `image` crate with a warning when the
+/// tool isn't installed, so a config shared between machines still works on all of them
+pub fn processor(backend: Backend) -> Arc<dyn ImageProcessor> {
+    let found: Option<Arc<dyn ImageProcessor>> = match backend {
+        Backend::Image => return Arc::new(ImageCrate),
+        Backend::ImageMagick => ImageMagick::find().map(|tool| Arc::new(tool) as Arc<dyn ImageProcessor>),
+        Backend::Vips => Vips::find().map(|tool| Arc::new(tool) as Arc<dyn ImageProcessor>),
+    };
+    found.unwrap_or_else(|| {
+        tracing::warn!("{:?} is not installed, processing with the image crate instead", backend);
+        Arc::new(ImageCrate)
+    })
+}
+
+fn autocrop(page: &DynamicImage) -> DynamicImage {
+    let luma = page.to_luma8();
+    let inked = |x, y| luma.get_pixel(x, y)[0] < INK;
+    let (width, height) = luma.dimensions();
+    let rows: Vec<u32> = (0..height).filter(|&y| (0..width).any(|x| inked(x, y))).collect();
+    let columns: Vec<u32> = (0..width).filter(|&x| (0..height).any(|y| inked(x, y))).collect();
+    match (rows.first(), rows.last(), columns.first(), columns.last()) {
+        (Some(&top), Some(&bottom), Some(&left), Some(&right)) => {
+            page.crop_imm(left, top, right - left + 1, bottom - top + 1)
+        }
+        // A blank page has nothing to crop to
+        _ => page.clone(),
+    }
+}
+
+/// How far the page is turned, in degrees: the angle that lines the ink up into the
+/// sharpest rows
+fn skew(luma: &GrayImage) -> f32 {
+    let inked: Vec<(f32, f32)> = luma
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[0] < INK)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    let steps = (MAX_SKEW / SKEW_STEP) as i32;
+    let sharpness = |angle: f32| {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut rows = vec![0u64; (luma.width() + luma.height()) as usize * 2];
+        let offset = luma.width() as f32;
+        for (x, y) in &inked {
+            let row = (y * cos - x * sin + offset).max(0.0) as usize;
+            if let Some(count) = rows.get_mut(row) {
+                *count += 1;
+            }
+        }
+        rows.iter().map(|count| count * count).sum::<u64>()
+    };
+    (-steps..=steps)
+        .map(|step| step as f32 * SKEW_STEP)
+        .max_by_key(|angle| (sharpness(*angle), -(angle.abs() * 100.0) as i64))
+        .unwrap_or(0.0)
+}
+
+fn deskew(page: &DynamicImage) -> DynamicImage {
+    let angle = skew(&page.to_luma8());
+    if angle == 0.0 {
+        return page.clone();
+    }
+    DynamicImage::ImageRgba8(rotate(&page.to_rgba8(), -angle))
+}
+
+/// Turns the image `angle` degrees about its centre, keeping its size and filling the
+/// corners with white
+fn rotate(image: &RgbaImage, angle: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        let (sx, sy) = (dx * cos + dy * sin + cx, -dx * sin + dy * cos + cy);
+        if sx >= 0.0 && sy >= 0.0 && (sx as u32) < width && (sy as u32) < height {
+            *image.get_pixel(sx as u32, sy as u32)
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    })
+}
+
+fn enhance(page: &DynamicImage) -> DynamicImage {
+    let luma = page.to_luma8();
+    let mut histogram = [0u64; 256];
+    for pixel in luma.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let clip = (luma.len() as f64 * CLIP) as u64;
+    let level = |levels: Vec<usize>| {
+        let mut seen = 0;
+        levels
+            .into_iter()
+            .find(|&level| {
+                seen += histogram[level];
+                seen > clip
+            })
+            .unwrap_or(0) as f64
+    };
+    let (low, high) = (level((0..256).collect()), level((0..256).rev().collect()));
+    if high <= low {
+        return page.clone();
+    }
+    let stretch = |value: u8| ((f64::from(value) - low) * 255.0 / (high - low)).clamp(0.0, 255.0) as u8;
+    match page {
+        DynamicImage::ImageLuma8(gray) => {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+                Luma([stretch(gray.get_pixel(x, y)[0])])
+            }))
+        }
+        _ => {
+            let mut rgba = page.to_rgba8();
+            for pixel in rgba.pixels_mut() {
+                for channel in 0..3 {
+                    pixel[channel] = stretch(pixel[channel]);
+                }
+            }
+            DynamicImage::ImageRgba8(rgba)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deskew() {
+        let lines = RgbaImage::from_fn(300, 300, |_, y| {
+            if y % 20 < 2 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let tilted = rotate(&lines, 3.0);
+        let angle = skew(&DynamicImage::ImageRgba8(tilted.clone()).to_luma8());
+        assert!((angle - 3.0).abs() <= SKEW_STEP, "found {}", angle);
+        assert_eq!(skew(&DynamicImage::ImageRgba8(lines.clone()).to_luma8()), 0.0);
+
+        let straightened = ImageCrate.deskew(&DynamicImage::ImageRgba8(tilted)).unwrap();
+        assert!(skew(&straightened.to_luma8()).abs() <= SKEW_STEP);
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Side length of the difference hash grid; the hash has `HASH_SIZE * HASH_SIZE` bits
+const HASH_SIZE: u32 = 8;
+
+/// What we know about one copy of the crossword image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageSummary {
+    pub size: usize,
+    pub sha256: String,
+    /// Width and height, when the image could be decoded
+    pub dimensions: Option<(u32, u32)>,
+    /// Difference hash, robust to re-encoding and small edits
+    pub dhash: Option<u64>,
+}
+
+impl ImageSummary {
+    pub fn new(data: &[u8]) -> Self {
+        let decoded = image::load_from_memory(data).ok();
+        Self {
+            size: data.len(),
+            sha256: format!("{:x}", Sha256::digest(data)),
+            dimensions: decoded.as_ref().map(|image| (image.width(), image.height())),
+            dhash: decoded.as_ref().map(dhash),
+        }
+    }
+}
+
+/// How a fresh download differs from the copy already in the archive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub archived: ImageSummary,
+    pub new: ImageSummary,
+}
+
+impl Comparison {
+    pub fn new(archived: &[u8], new: &[u8]) -> Self {
+        Self {
+            archived: ImageSummary::new(archived),
+            new: ImageSummary::new(new),
+        }
+    }
+
+    pub fn identical(&self) -> bool {
+        self.archived.sha256 == self.new.sha256
+    }
+
+    /// Number of differing hash bits: 0 looks the same, above ~10 is a different picture
+    pub fn hash_distance(&self) -> Option<u32> {
+        Some((self.archived.dhash? ^ self.new.dhash?).count_ones())
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.identical() {
+            return write!(f, "identical to the archived copy ({} bytes)", self.new.size);
+        }
+
+        let dimensions = |summary: &ImageSummary| match summary.dimensions {
+            Some((width, height)) => format!("{}x{}", width, height),
+            None => "unknown".to_string(),
+        };
+        writeln!(f, "differs from the archived copy:")?;
+        writeln!(f, "  size: {} -> {} bytes", self.archived.size, self.new.size)?;
+        writeln!(f, "  sha256: {} -> {}", &self.archived.sha256[..12], &self.new.sha256[..12])?;
+        writeln!(f, "  dimensions: {} -> {}", dimensions(&self.archived), dimensions(&self.new))?;
+        match self.hash_distance() {
+            Some(distance) => write!(f, "  perceptual hash distance: {}/{}", distance, HASH_SIZE * HASH_SIZE),
+            None => write!(f, "  perceptual hash distance: unknown"),
+        }
+    }
+}
+
+/// Compares the brightness of horizontally adjacent pixels on a shrunken grayscale copy
+fn dhash(image: &image::DynamicImage) -> u64 {
+    let small = image
+        .grayscale()
+        .resize_exact(HASH_SIZE + 1, HASH_SIZE, image::imageops::FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..HASH_SIZE {
+        for x in 0..HASH_SIZE {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let v = pixel(x, y);
+            Rgb([v, v, v])
+        });
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_identical_copies() {
+        let data = png(64, 64, |x, _| (x * 4) as u8);
+        let comparison = Comparison::new(&data, &data);
+
+        assert!(comparison.identical());
+        assert_eq!(comparison.hash_distance(), Some(0));
+        assert!(comparison.to_string().starts_with("identical"));
+    }
+
+    #[test]
+    fn test_resized_copy_looks_the_same() {
+        let archived = png(64, 64, |x, _| (x * 4) as u8);
+        let new = png(128, 128, |x, _| (x * 2) as u8);
+        let comparison = Comparison::new(&archived, &new);
+
+        assert!(!comparison.identical());
+        assert_eq!(comparison.new.dimensions, Some((128, 128)));
+        assert!(comparison.hash_distance().unwrap() <= 2);
+        assert!(comparison.to_string().contains("64x64 -> 128x128"));
+    }
+
+    #[test]
+    fn test_different_picture() {
+        let archived = png(64, 64, |x, _| (x * 4) as u8);
+        let new = png(64, 64, |x, _| 255 - (x * 4) as u8);
+
+        assert!(Comparison::new(&archived, &new).hash_distance().unwrap() > 32);
+    }
+
+    #[test]
+    fn test_undecodable_copy() {
+        let archived = png(16, 16, |_, _| 0);
+        let comparison = Comparison::new(&archived, &[0xFF, 0xD8, 0xFF, 0xE0]);
+
+        assert_eq!(comparison.new.dimensions, None);
+        assert_eq!(comparison.hash_distance(), None);
+        assert!(comparison.to_string().contains("unknown"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Writing the puzzle number and setter read off the header into the saved image, so they
+//! travel with the file to Drive, a phone's gallery or a photo viewer. JPEG and PNG files get
+//! an XMP packet with the number as the title and the setter as the creator; GIF and WebP are
+//! saved as they came. Packets carry the same in their PDF info (see `archive::pdf`).
+
+use crate::types::PuzzleInfo;
+
+/// Namespace that starts a JPEG's XMP segment
+const JPEG_XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Keyword of a PNG's XMP text chunk
+const PNG_XMP: &[u8] = b"XML:com.adobe.xmp";
+
+/// The title the puzzle is filed under, e.g. `Crossword No. 10234`
+pub fn title(puzzle: &PuzzleInfo) -> Option<String> {
+    puzzle.number.map(|number| format!("Crossword No. {}", number))
+}
+
+/// `data` with the puzzle's details written into it, or unchanged when there are none or the
+/// format has no place for them
+pub fn tag(data: &[u8], mime_type: &str, puzzle: &PuzzleInfo) -> Vec<u8> {
+    if puzzle.is_empty() {
+        return data.to_vec();
+    }
+    let xmp = xmp(puzzle);
+    let tagged = match mime_type {
+        "image/jpeg" => tag_jpeg(data, &xmp),
+        "image/png" => tag_png(data, &xmp),
+        _ => None,
+    };
+    tagged.unwrap_or_else(|| data.to_vec())
+}
+
+fn xmp(puzzle: &PuzzleInfo) -> String {
+    let mut description = String::new();
+    if let Some(title) = title(puzzle) {
+        description.push_str(&format!(
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+            escape_xml(&title)
+        ));
+    }
+    if let Some(setter) = &puzzle.setter {
+        description.push_str(&format!(
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            escape_xml(setter)
+        ));
+    }
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+         <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">{}</rdf:Description>\
+         </rdf:RDF></x:xmpmeta><?xpacket end=\"w\"?>",
+        description
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Adds an APP1 segment after the start of the image, and after the JFIF header that has to
+/// come first when there is one
+fn tag_jpeg(data: &[u8], xmp: &str) -> Option<Vec<u8>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut at = 2;
+    if data.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let length = u16::from_be_bytes([*data.get(4)?, *data.get(5)?]) as usize;
+        at = 4 + length;
+    }
+    let length = u16::try_from(2 + JPEG_XMP.len() + xmp.len()).ok()?;
+    let mut tagged = Vec::with_capacity(data.len() + length as usize + 2);
+    tagged.extend_from_slice(data.get(..at)?);
+    tagged.extend_from_slice(&[0xFF, 0xE1]);
+    tagged.extend_from_slice(&length.to_be_bytes());
+    tagged.extend_from_slice(JPEG_XMP);
+    tagged.extend_from_slice(xmp.as_bytes());
+    tagged.extend_from_slice(&data[at..]);
+    Some(tagged)
+}
+
+/// Adds an uncompressed iTXt chunk right after the header chunk
+fn tag_png(data: &[u8], xmp: &str) -> Option<Vec<u8>> {
+    // Signature, then the header chunk: length, type, 13 bytes of data and the checksum
+    const HEADER_END: usize = 8 + 4 + 4 + 13 + 4;
+    if data.get(12..16) != Some(b"IHDR") || data.len() < HEADER_END {
+        return None;
+    }
+    let mut chunk = b"iTXt".to_vec();
+    chunk.extend_from_slice(PNG_XMP);
+    // End of the keyword, no compression, and empty language and translated keyword
+    chunk.extend_from_slice(&[0, 0, 0, 0, 0]);
+    chunk.extend_from_slice(xmp.as_bytes());
+
+    let mut tagged = Vec::with_capacity(data.len() + chunk.len() + 8);
+    tagged.extend_from_slice(&data[..HEADER_END]);
+    tagged.extend_from_slice(&u32::try_from(chunk.len() - 4).ok()?.to_be_bytes());
+    tagged.extend_from_slice(&chunk);
+    tagged.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+    tagged.extend_from_slice(&data[HEADER_END..]);
+    Some(tagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+    use std::io::Cursor;
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::new(40, 30).write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    fn contains(data: &[u8], text: &str) -> bool {
+        data.windows(text.len()).any(|window| window == text.as_bytes())
+    }
+
+    #[test]
+    fn test_tags_jpeg_and_png() {
+        let puzzle = PuzzleInfo {
+            number: Some(10234),
+            setter: Some("A. Kulkarni & co".to_string()),
+        };
+        for (format, mime_type) in [(ImageFormat::Jpeg, "image/jpeg"), (ImageFormat::Png, "image/png")] {
+            let tagged = tag(&encoded(format), mime_type, &puzzle);
+            assert!(contains(&tagged, "Crossword No. 10234"), "{}", mime_type);
+            assert!(contains(&tagged, "<rdf:li>A. Kulkarni &amp; co</rdf:li>"), "{}", mime_type);
+            // Still a valid image of the same size
+            let decoded = image::load_from_memory_with_format(&tagged, format).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (40, 30));
+        }
+    }
+
+    #[test]
+    fn test_leaves_untagged_files_alone() {
+        let png = encoded(ImageFormat::Png);
+        assert_eq!(tag(&png, "image/png", &PuzzleInfo::default()), png);
+        let puzzle = PuzzleInfo {
+            number: Some(10234),
+            setter: None,
+        };
+        assert_eq!(tag(b"GIF89a", "image/gif", &puzzle), b"GIF89a");
+        // Not what it claims to be
+        assert_eq!(tag(b"not a jpeg", "image/jpeg", &puzzle), b"not a jpeg");
+    }
+}
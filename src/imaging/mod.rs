@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use image::{imageops, Rgb, RgbImage};
+use std::io::Cursor;
+
+use crate::parser::Split;
+
+pub mod backend;
+pub mod compare;
+pub mod metadata;
+pub mod preview;
+
+/// Image formats the site is known to serve, identified by their leading magic bytes
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (&[0xFF, 0xD8, 0xFF], "image/jpeg"),
+    (&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A], "image/png"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+];
+
+/// File extension used when saving each image format
+pub const EXTENSIONS: &[(&str, &str)] = &[
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+];
+
+/// Extension to save an image of `mime_type` with, `jpg` for anything unknown
+pub fn extension(mime_type: &str) -> &'static str {
+    EXTENSIONS
+        .iter()
+        .find(|(mime, _)| *mime == mime_type)
+        .map_or("jpg", |(_, extension)| *extension)
+}
+
+/// Width and height from the image's header, without decoding it
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Joins the images of a target split across areas into one JPEG, padding the
+/// narrower or shorter parts with white
+pub fn stitch(parts: &[&[u8]], split: Split) -> Result<Vec<u8>> {
+    let images = parts
+        .iter()
+        .map(|data| image::load_from_memory(data).map(|image| image.to_rgb8()))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to decode part of a split image")?;
+
+    let widths = images.iter().map(RgbImage::width);
+    let heights = images.iter().map(RgbImage::height);
+    let (width, height) = match split {
+        Split::SideBySide => (widths.sum(), heights.max().unwrap_or_default()),
+        Split::Stacked => (widths.max().unwrap_or_default(), heights.sum()),
+    };
+    let mut canvas = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    let mut offset = 0;
+    for image in &images {
+        match split {
+            Split::SideBySide => {
+                imageops::replace(&mut canvas, image, i64::from(offset), 0);
+                offset += image.width();
+            }
+            Split::Stacked => {
+                imageops::replace(&mut canvas, image, 0, i64::from(offset));
+                offset += image.height();
+            }
+        }
+    }
+
+    let mut jpeg = Vec::new();
+    canvas.write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)?;
+    Ok(jpeg)
+}
+
+/// Returns the MIME type of the image in `data` based on its magic bytes
+pub fn sniff_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|(_, mime)| *mime)
+}
+
+/// Checks that a downloaded body really is an image, returning its MIME type.
+///
+/// Both the `Content-Type` header and the magic bytes must agree that this is an image,
+/// so an HTML error page served with a 200 status is rejected instead of saved as `.jpg`.
+pub fn validate_image(content_type: Option<&str>, data: &[u8]) -> Result<&'static str> {
+    if data.is_empty() {
+        return Err(anyhow::anyhow!("Downloaded image is empty"));
+    }
+
+    if let Some(content_type) = content_type {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        if !essence.to_ascii_lowercase().starts_with("image/") {
+            return Err(anyhow::anyhow!(
+                "Downloaded image has unexpected content type '{}' ({} bytes)",
+                content_type,
+                data.len()
+            ));
+        }
+    }
+
+    sniff_mime_type(data).ok_or_else(|| {
+        let preview: String = String::from_utf8_lossy(&data[..data.len().min(32)])
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect();
+        anyhow::anyhow!(
+            "Downloaded image is not a recognized image format ({} bytes, starts with {:?})",
+            data.len(),
+            preview
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0x00];
+
+    fn png(width: u32, height: u32, shade: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(width, height, Rgb([shade, shade, shade]))
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_stitch() {
+        let (grid, clues) = (png(40, 30, 0), png(20, 20, 0));
+
+        let side_by_side = image::load_from_memory(&stitch(&[&grid, &clues], Split::SideBySide).unwrap()).unwrap();
+        assert_eq!((side_by_side.width(), side_by_side.height()), (60, 30));
+        // Below the shorter part is white padding
+        assert!(side_by_side.to_rgb8().get_pixel(50, 27)[0] > 200);
+        assert!(side_by_side.to_rgb8().get_pixel(50, 5)[0] < 50);
+
+        let stacked = image::load_from_memory(&stitch(&[&grid, &clues], Split::Stacked).unwrap()).unwrap();
+        assert_eq!((stacked.width(), stacked.height()), (40, 50));
+
+        assert!(stitch(&[&grid, b"not an image"], Split::Stacked).is_err());
+    }
+
+    #[test]
+    fn test_sniff_mime_type() {
+        assert_eq!(sniff_mime_type(JPEG), Some("image/jpeg"));
+        assert_eq!(sniff_mime_type(PNG), Some("image/png"));
+        assert_eq!(sniff_mime_type(b"GIF89a...."), Some("image/gif"));
+        assert_eq!(sniff_mime_type(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_mime_type(b"<!DOCTYPE html>"), None);
+        assert_eq!(sniff_mime_type(&[]), None);
+    }
+
+    #[test]
+    fn test_validate_image_accepts_jpeg() {
+        assert_eq!(validate_image(Some("image/jpeg"), JPEG).unwrap(), "image/jpeg");
+        assert_eq!(validate_image(None, JPEG).unwrap(), "image/jpeg");
+    }
+
+    #[test]
+    fn test_validate_image_trusts_magic_bytes_over_image_content_type() {
+        assert_eq!(validate_image(Some("image/jpeg"), PNG).unwrap(), "image/png");
+    }
+
+    #[test]
+    fn test_validate_image_rejects_html_content_type() {
+        let result = validate_image(Some("text/html; charset=UTF-8"), JPEG);
+        assert!(result.unwrap_err().to_string().contains("text/html"));
+    }
+
+    #[test]
+    fn test_validate_image_rejects_html_body() {
+        let body = b"<html><body>Service Unavailable</body></html>";
+        let result = validate_image(Some("image/jpeg"), body);
+        assert!(result.unwrap_err().to_string().contains("not a recognized image format"));
+    }
+
+    #[test]
+    fn test_validate_image_rejects_empty_body() {
+        assert!(validate_image(Some("image/jpeg"), &[]).is_err());
+    }
+}
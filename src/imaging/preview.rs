@@ -0,0 +1,216 @@
+//! Showing an image inline in terminals that support a graphics protocol (kitty, iTerm2 and
+//! WezTerm, or sixel in foot, mlterm and xterm -ti vt340), to check the right area was
+//! downloaded without opening a file manager.
+
+use anyhow::{Context, Result};
+use base64::prelude::*;
+use clap::ValueEnum;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use std::io::Cursor;
+
+/// Widest preview sent to the terminal, in pixels; a crossword is legible well below this
+pub const MAX_WIDTH: u32 = 800;
+
+/// Largest piece of base64 the kitty protocol takes in one escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// Levels per channel of the sixel palette, a 6x6x6 colour cube
+const SIXEL_LEVELS: u32 = 6;
+
+/// How the image is sent to the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    /// Whichever the terminal is known to support
+    Auto,
+    Kitty,
+    /// iTerm2's inline images, also understood by WezTerm
+    Iterm,
+    Sixel,
+}
+
+impl Protocol {
+    /// The protocol of the terminal we run in, as far as its environment tells
+    pub fn detect() -> Option<Protocol> {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        detect_from(&var("TERM"), &var("TERM_PROGRAM"), std::env::var_os("KITTY_WINDOW_ID").is_some())
+    }
+
+    /// `Auto` resolved to the detected protocol, if any
+    pub fn resolve(self) -> Option<Protocol> {
+        match self {
+            Protocol::Auto => Protocol::detect(),
+            protocol => Some(protocol),
+        }
+    }
+}
+
+fn detect_from(term: &str, term_program: &str, kitty_window: bool) -> Option<Protocol> {
+    if kitty_window || term.contains("kitty") || term == "xterm-ghostty" {
+        Some(Protocol::Kitty)
+    } else if matches!(term_program, "iTerm.app" | "WezTerm") {
+        Some(Protocol::Iterm)
+    } else if term.starts_with("foot") || term.starts_with("mlterm") || term.contains("sixel") {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+/// The escape sequences that draw `data` at most `max_width` pixels wide, followed by a newline
+pub fn render(data: &[u8], protocol: Protocol, max_width: u32) -> Result<String> {
+    let protocol = protocol.resolve().context(
+        "This terminal doesn't seem to show images; pass the protocol it supports: kitty, iterm or sixel",
+    )?;
+    let image = image::load_from_memory(data).context("Failed to decode the image to preview")?;
+    let image = if image.width() > max_width {
+        image.thumbnail(max_width, u32::MAX)
+    } else {
+        image
+    };
+    Ok(match protocol {
+        Protocol::Kitty => kitty(&png(&image)?),
+        Protocol::Iterm => iterm(&png(&image)?),
+        Protocol::Sixel | Protocol::Auto => sixel(&image.to_rgb8()),
+    })
+}
+
+fn png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+fn kitty(png: &[u8]) -> String {
+    let encoded = BASE64_STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        // Only the first chunk carries the format (PNG) and action (transmit and display)
+        let keys = if index == 0 { format!("f=100,a=T,m={}", more) } else { format!("m={}", more) };
+        out.push_str(&format!("\x1b_G{};{}\x1b\\", keys, String::from_utf8_lossy(chunk)));
+    }
+    out.push('\n');
+    out
+}
+
+fn iterm(png: &[u8]) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07\n",
+        png.len(),
+        BASE64_STANDARD.encode(png)
+    )
+}
+
+/// Sixel data: the image in bands six pixels high, each band drawn once per colour in it
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let index = |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let level = |value: u8| (u32::from(value) * (SIXEL_LEVELS - 1) + 127) / 255;
+        (level(pixel[0]) * SIXEL_LEVELS + level(pixel[1])) * SIXEL_LEVELS + level(pixel[2])
+    };
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    let colours = SIXEL_LEVELS.pow(3);
+    for colour in 0..colours {
+        let percent = |level: u32| level * 100 / (SIXEL_LEVELS - 1);
+        let (r, g, b) = (colour / 36, colour / 6 % 6, colour % 6);
+        out.push_str(&format!("#{};2;{};{};{}", colour, percent(r), percent(g), percent(b)));
+    }
+
+    for top in (0..height).step_by(6) {
+        let rows = (height - top).min(6);
+        let mut used = vec![false; colours as usize];
+        for y in top..top + rows {
+            for x in 0..width {
+                used[index(x, y) as usize] = true;
+            }
+        }
+        let mut first = true;
+        for colour in (0..colours).filter(|colour| used[*colour as usize]) {
+            if !first {
+                // Back to the start of the band for the next colour
+                out.push('$');
+            }
+            first = false;
+            out.push_str(&format!("#{}", colour));
+            let bits = (0..width).map(|x| {
+                (0..rows).fold(0u8, |bits, row| bits | (u8::from(index(x, top + row) == colour) << row))
+            });
+            push_runs(&mut out, bits);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out
+}
+
+/// Sixel characters for the columns' bits, with repeats run-length encoded as `!<count><char>`
+fn push_runs(out: &mut String, bits: impl Iterator<Item = u8>) {
+    let mut run: Option<(u8, usize)> = None;
+    let flush = |out: &mut String, (bits, count): (u8, usize)| {
+        let c = char::from(63 + bits);
+        if count > 3 {
+            out.push_str(&format!("!{}{}", count, c));
+        } else {
+            out.extend(std::iter::repeat_n(c, count));
+        }
+    };
+    for bits in bits {
+        run = match run {
+            Some((previous, count)) if previous == bits => Some((bits, count + 1)),
+            Some(previous) => {
+                flush(out, previous);
+                Some((bits, 1))
+            }
+            None => Some((bits, 1)),
+        };
+    }
+    if let Some(last) = run {
+        flush(out, last);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect_from("xterm-kitty", "", false), Some(Protocol::Kitty));
+        assert_eq!(detect_from("xterm-256color", "", true), Some(Protocol::Kitty));
+        assert_eq!(detect_from("xterm-256color", "iTerm.app", false), Some(Protocol::Iterm));
+        assert_eq!(detect_from("foot", "", false), Some(Protocol::Sixel));
+        assert_eq!(detect_from("screen-256color", "tmux", false), None);
+    }
+
+    #[test]
+    fn test_render() {
+        // Black above white, 8 wide and 7 high: one full band and a band of one row
+        let image = RgbImage::from_fn(8, 7, |_, y| if y < 3 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) });
+        let sixel = sixel(&image);
+        assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;8;7#0;2;0;0;0#1;2;0;0;20"));
+        assert!(sixel.ends_with("#215;2;100;100;100#0!8F$#215!8w-#215!8@-\x1b\\\n"));
+
+        let data = png(&DynamicImage::ImageRgb8(image)).unwrap();
+        let kitty = render(&data, Protocol::Kitty, MAX_WIDTH).unwrap();
+        assert!(kitty.starts_with("\x1b_Gf=100,a=T,m=0;iVBOR"));
+        assert!(kitty.ends_with("\x1b\\\n"));
+        let iterm = render(&data, Protocol::Iterm, 4).unwrap();
+        assert!(iterm.starts_with("\x1b]1337;File=inline=1;size="));
+
+        // Long images are sent in pieces, each but the last saying more follows
+        let pieces = kitty_chunks(&"x".repeat(KITTY_CHUNK * 2 + 1));
+        assert_eq!(pieces, vec!["f=100,a=T,m=1", "m=1", "m=0"]);
+    }
+
+    fn kitty_chunks(png: &str) -> Vec<String> {
+        kitty(png.as_bytes())
+            .split("\x1b_G")
+            .skip(1)
+            .map(|sequence| sequence.split(';').next().unwrap().to_string())
+            .collect()
+    }
+}
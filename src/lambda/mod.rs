@@ -0,0 +1,627 @@
+use anyhow::{Context as _, Result};
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::clock::{self, Clock, RunClock, SystemClock};
+use crate::config::{self, Config};
+use crate::console;
+use crate::crossword::{self, Download};
+use crate::diagnostics::{explain, preflight};
+use crate::diagnostics::Capture;
+use crate::error::{self, CrosswordError};
+use crate::http::deadline::Deadline;
+use crate::http::HttpClient;
+use crate::message;
+use crate::pipeline::Sink;
+use crate::signed_url;
+use crate::types::{self, DateOutput, LambdaInput, LambdaOutput, RetryHint};
+
+/// Time kept back before the invocation deadline for the upload of what was already found
+pub const TIME_RESERVE: Duration = Duration::from_secs(15);
+
+/// Everything the handler needs, injected so it can run without network or AWS access
+pub struct Context {
+    pub client: Box<dyn HttpClient>,
+    pub clock: Arc<dyn Clock>,
+    pub config: Config,
+    pub sinks: Vec<Arc<dyn Sink>>,
+}
+
+impl Context {
+    /// The production context: the real site, the system clock and Google Drive
+    pub fn new(client: Box<dyn HttpClient>, config: Config) -> Result<Self> {
+        let sinks = signed_url::sinks(&config, false)?;
+        Ok(Self {
+            client,
+            clock: Arc::new(SystemClock),
+            config,
+            sinks,
+        })
+    }
+}
+
+/// Parses the event's date, falling back to today according to the context's clock
+fn resolve_date(input: &LambdaInput, clock: &dyn Clock) -> Result<NaiveDate> {
+    let date = match &input.date {
+        Some(date_str) => Some(types::parse_date_on(date_str, clock.today()).map_err(anyhow::Error::msg)?),
+        None => None,
+    };
+    let date = clock::resolve_date(date, clock);
+
+    if let Some(warning) = types::validate_date(date, clock.today(), false).map_err(anyhow::Error::msg)? {
+        tracing::warn!("{}", warning);
+    }
+
+    Ok(date)
+}
+
+/// Parses the event's range, with `to` falling back to today according to the context's clock
+fn resolve_range(input: &LambdaInput, clock: &dyn Clock) -> Result<(NaiveDate, NaiveDate)> {
+    if input.date.is_some() {
+        anyhow::bail!("Give either a date or a range with from and to, not both");
+    }
+    let parse = |date: &str| types::parse_date_on(date, clock.today()).map_err(anyhow::Error::msg);
+    let from = parse(input.from.as_deref().context("A range needs a from date")?)?;
+    let to = match &input.to {
+        Some(to) => parse(to)?,
+        None => clock.today(),
+    };
+    if from > to {
+        anyhow::bail!("The range starts on {} but ends earlier, on {}", from, to);
+    }
+    Ok((from, to))
+}
+
+/// Handles one invocation. With a `deadline` (from the Lambda context), no new request is
+/// started once less than [`TIME_RESERVE`] is left, and a partial report is returned
+/// instead of the function being killed mid-upload.
+pub async fn handle(input: LambdaInput, context: &Context, deadline: Option<SystemTime>) -> Result<LambdaOutput> {
+    if input.from.is_some() || input.to.is_some() {
+        return handle_range(input, context, deadline).await;
+    }
+    handle_date(input, context, deadline).await
+}
+
+/// Handles each date of the input's range in turn. A failed date is reported in its place
+/// and doesn't stop the rest; running out of time does, leaving the output incomplete.
+async fn handle_range(input: LambdaInput, context: &Context, deadline: Option<SystemTime>) -> Result<LambdaOutput> {
+    let (from, to) = resolve_range(&input, context.clock.as_ref())?;
+    let mut dates = Vec::new();
+    let mut complete = true;
+    let mut skipped = 0;
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        if let Some(reason) = context.config.publication.skipped(date, context.config.puzzle) {
+            tracing::info!("Skipping {}: {}", date, reason);
+            skipped += 1;
+            continue;
+        }
+        let input = LambdaInput {
+            date: Some(date.to_string()),
+            from: None,
+            to: None,
+            ..input.clone()
+        };
+        match handle_date(input, context, deadline).await {
+            Ok(output) => {
+                let stopped = !output.complete && output.retry.is_none();
+                dates.push(DateOutput { date, output: Some(output), error: None });
+                if stopped {
+                    complete = false;
+                    break;
+                }
+            }
+            Err(e) => {
+                tracing::warn!("{}: {:#}", date, e);
+                dates.push(DateOutput { date, output: None, error: Some(error::hints::describe(&e)) });
+            }
+        }
+    }
+
+    let downloaded = dates.iter().filter(|date| date.output.as_ref().is_some_and(|output| output.complete)).count();
+    let failed = dates.iter().filter(|date| date.error.is_some()).count();
+    let days = (to - from).num_days() + 1 - skipped;
+    let mut message = format!("Downloaded {} of {} crosswords from {} to {}", downloaded, days, from, to);
+    if failed > 0 {
+        message.push_str(&format!("; {} failed", failed));
+    }
+    if skipped > 0 {
+        message.push_str(&format!("; {} days without a crossword skipped", skipped));
+    }
+    if !complete {
+        message.push_str("; stopped early, invoke again to finish");
+    }
+    Ok(LambdaOutput {
+        message,
+        date: None,
+        filename: String::new(),
+        page: None,
+        image_url: None,
+        uploads: BTreeMap::new(),
+        complete,
+        comparison: None,
+        timings: Vec::new(),
+        explanation: None,
+        link: None,
+        solver: None,
+        puzzle: None,
+        confidence: None,
+        needs_review: false,
+        storage: Vec::new(),
+        retry: None,
+        warnings: Vec::new(),
+        dates,
+    })
+}
+
+async fn handle_date(input: LambdaInput, context: &Context, deadline: Option<SystemTime>) -> Result<LambdaOutput> {
+    let date = resolve_date(&input, context.clock.as_ref())?;
+    let mut config = invocation_config(&input, &context.config)?;
+    config.clock = RunClock(context.clock.clone());
+    let sinks = select_sinks(&input, &context.sinks)?;
+    preflight::run(&config, &sinks).await?;
+
+    let mut client: Box<dyn HttpClient + '_> = Box::new(context.client.as_ref());
+    if let Some(deadline) = deadline {
+        client = Box::new(Deadline::new(client, deadline, TIME_RESERVE));
+    }
+    let client = Capture::new(client);
+    let result = crossword::download_crossword(&client, date, &config, sinks).await;
+
+    // Most useful when nothing matched, so produce it whatever the outcome
+    let explanation = match input.explain {
+        Some(format) => {
+            let explanation = explain::render(&client.page_candidates(&config.target), format)?;
+            if result.is_err() {
+                console::progress!("{}", explanation);
+            }
+            Some(explanation)
+        }
+        None => None,
+    };
+
+    let download = match result {
+        Ok(download) => download,
+        Err(e) => match e.downcast_ref::<CrosswordError>() {
+            Some(stopped @ CrosswordError::OutOfTime { .. }) => {
+                tracing::warn!("{}; invoke again to finish {}", stopped, date);
+                return Ok(LambdaOutput {
+                    message: format!("Stopped early for {}: {}", date, stopped),
+                    date: Some(date),
+                    filename: String::new(),
+                    page: None,
+                    image_url: None,
+                    uploads: BTreeMap::new(),
+                    complete: false,
+                    comparison: None,
+                    timings: Vec::new(),
+                    explanation,
+                    link: None,
+                    solver: None,
+                    puzzle: None,
+                    confidence: None,
+                    needs_review: false,
+                    storage: Vec::new(),
+                    retry: None,
+                    warnings: Vec::new(),
+                    dates: Vec::new(),
+                });
+            }
+            _ if config.retry.reinvoke && error::is_not_yet_published(&e) => {
+                let attempt = input.attempt + 1;
+                let Some(wait) = config.retry.wait_after(attempt) else {
+                    tracing::warn!("{:#}; giving up after {} attempts", e, attempt);
+                    return Err(e);
+                };
+                return Ok(LambdaOutput {
+                    message: format!("{:#}; invoke again in {} minutes", e, wait.as_secs() / 60),
+                    date: Some(date),
+                    filename: String::new(),
+                    page: None,
+                    image_url: None,
+                    uploads: BTreeMap::new(),
+                    complete: false,
+                    comparison: None,
+                    timings: Vec::new(),
+                    explanation,
+                    link: None,
+                    solver: None,
+                    puzzle: None,
+                    confidence: None,
+                    needs_review: false,
+                    storage: Vec::new(),
+                    retry: Some(RetryHint {
+                        attempt,
+                        after_secs: wait.as_secs(),
+                    }),
+                    warnings: Vec::new(),
+                    dates: Vec::new(),
+                });
+            }
+            _ => {
+                if error::is_retryable(&e) {
+                    tracing::warn!("{:#}; the run can be retried later", e);
+                }
+                return Err(e);
+            }
+        },
+    };
+
+    Ok(LambdaOutput {
+        explanation,
+        ..output(&config, date, download)
+    })
+}
+
+/// The configuration with the invocation's page range and layout applied
+fn invocation_config(input: &LambdaInput, config: &Config) -> Result<Config> {
+    let mut config = config.clone();
+    if let Some(range) = &input.page_range {
+        config.pages = config::parse_page_range(range).map_err(anyhow::Error::msg)?;
+    }
+    if let Some(name) = &input.layout {
+        config.target = config
+            .layouts
+            .get(name)
+            .cloned()
+            .with_context(|| format!("No layout named '{}'; add it as [layouts.{}] in the config", name, name))?;
+    }
+    Ok(config)
+}
+
+/// The sinks the invocation asked for: none with `no_upload`, otherwise those named in `storage`
+fn select_sinks(input: &LambdaInput, sinks: &[Arc<dyn Sink>]) -> Result<Vec<Arc<dyn Sink>>> {
+    if input.no_upload {
+        return Ok(Vec::new());
+    }
+    let Some(names) = &input.storage else {
+        return Ok(sinks.to_vec());
+    };
+
+    names
+        .iter()
+        .map(|name| {
+            sinks.iter().find(|sink| sink.name() == name).cloned().with_context(|| {
+                let available: Vec<&str> = sinks.iter().map(|sink| sink.name()).collect();
+                format!("Unknown storage target '{}'; available: {}", name, available.join(", "))
+            })
+        })
+        .collect()
+}
+
+/// Report of a successful run, also printed by the CLI with `--format json`
+pub fn output(config: &Config, date: NaiveDate, download: Download) -> LambdaOutput {
+    let solver = config.rotation.as_ref().and_then(|rotation| rotation.solver(date));
+    let message = message::downloaded(config, date, &download);
+    LambdaOutput {
+        message,
+        date: Some(date),
+        filename: download.filename,
+        page: Some(download.page).filter(|page| *page != crossword::MANUAL_PAGE),
+        image_url: Some(download.image_url).filter(|url| !url.is_empty()),
+        uploads: download.uploads,
+        complete: true,
+        comparison: download.comparison,
+        timings: download.timings,
+        explanation: None,
+        link: download.link,
+        solver: solver.map(str::to_string),
+        puzzle: Some(download.puzzle).filter(|puzzle| !puzzle.is_empty()),
+        confidence: download.confidence,
+        needs_review: download.needs_review,
+        storage: download.storage,
+        retry: None,
+        warnings: download.warnings,
+        dates: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::http::vcr::Replayer;
+    use crate::http::{HttpRequest, HttpResponse};
+    use crate::diagnostics::explain::ExplainFormat;
+    use crate::pipeline::Artifact;
+    use crate::rotation::RotationConfig;
+    use crate::types::{Rect, TargetProfile};
+    use async_trait::async_trait;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        uploaded: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Sink for RecordingSink {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn upload(&self, artifact: &Artifact) -> Result<String> {
+            self.uploaded.lock().unwrap().push(artifact.path.clone());
+            Ok("file-id".to_string())
+        }
+    }
+
+    fn context(dir: &TempDir, fixture: &str, now: &str, sink: Arc<RecordingSink>) -> Context {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(fixture);
+        Context {
+            client: Box::new(Replayer::load(&fixture).unwrap()),
+            clock: Arc::new(FixedClock::at(now)),
+            config: Config {
+                output_dir: dir.path().to_path_buf(),
+                ..Config::default()
+            },
+            sinks: vec![sink],
+        }
+    }
+
+    /// The site before the day's edition is up: every page map is empty
+    struct Unpublished;
+
+    #[async_trait]
+    impl HttpClient for Unpublished {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            Ok(HttpResponse {
+                status: 200,
+                body: b"<map></map>".to_vec(),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn input(date: Option<&str>) -> LambdaInput {
+        LambdaInput {
+            date: date.map(String::from),
+            ..LambdaInput::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_defaults_to_clock_date() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink.clone());
+
+        let output = handle(input(None), &context, None).await.unwrap();
+
+        assert!(output.filename.ends_with("crossword_2024-03-20.jpg"));
+        let phases: Vec<&str> = output.timings.iter().map(|timing| timing.phase.as_str()).collect();
+        assert_eq!(phases, vec!["page_scan", "page_scan", "article_fetch", "image_download", "upload:recording"]);
+        assert_eq!(*sink.uploaded.lock().unwrap(), vec![output.filename]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_explicit_date() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-06-01T06:00:00+00:00", sink);
+
+        let output = handle(input(Some("2024-03-20")), &context, None).await.unwrap();
+        assert_eq!(output.message, "Crossword downloaded successfully");
+        assert_eq!(output.date, NaiveDate::from_ymd_opt(2024, 3, 20));
+        assert_eq!(output.page, Some(2));
+        assert_eq!(
+            output.image_url.as_deref(),
+            Some("https://www.ehitavada.com/encyc/6/2024/03/20/Mpage_2_slice_1.jpg")
+        );
+        assert_eq!(output.uploads["recording"], "file-id");
+    }
+
+    #[tokio::test]
+    async fn test_handle_announces_whose_turn_it_is() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let mut context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink);
+        context.config.rotation = Some(RotationConfig {
+            solvers: vec!["Priya".to_string(), "Rahul".to_string()],
+            start: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+        });
+
+        let output = handle(input(None), &context, None).await.unwrap();
+        assert_eq!(output.message, "Crossword downloaded successfully; it's Rahul's turn!");
+        assert_eq!(output.solver.as_deref(), Some("Rahul"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_asks_to_be_invoked_again_until_published() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let mut context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink);
+        context.client = Box::new(Unpublished);
+        context.config.retry.reinvoke = true;
+
+        let output = handle(input(None), &context, None).await.unwrap();
+        assert!(!output.complete);
+        assert!(output.message.ends_with("invoke again in 30 minutes"));
+        assert_eq!(output.retry, Some(RetryHint { attempt: 1, after_secs: 30 * 60 }));
+
+        let output = handle(LambdaInput { attempt: 2, ..input(None) }, &context, None).await.unwrap();
+        assert_eq!(output.retry, Some(RetryHint { attempt: 3, after_secs: 60 * 60 }));
+
+        // The schedule is used up
+        let error = handle(LambdaInput { attempt: 3, ..input(None) }, &context, None).await.unwrap_err();
+        assert!(crate::error::is_not_yet_published(&error));
+    }
+
+    #[tokio::test]
+    async fn test_handle_fails_while_unpublished_by_default() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let mut context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink);
+        context.client = Box::new(Unpublished);
+
+        // Without a scheduler reading the hint, only a failure gets the function invoked again
+        let error = handle(input(None), &context, None).await.unwrap_err();
+        assert!(crate::error::is_not_yet_published(&error));
+    }
+
+    #[tokio::test]
+    async fn test_handle_stops_before_the_deadline() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink.clone());
+
+        let deadline = SystemTime::now() + TIME_RESERVE / 2;
+        let output = handle(input(None), &context, Some(deadline)).await.unwrap();
+
+        assert!(!output.complete);
+        assert!(output.message.contains("Stopped early for 2024-03-20"));
+        assert!(output.filename.is_empty());
+        assert!(sink.uploaded.lock().unwrap().is_empty());
+        assert!(!serde_json::to_string(&output).unwrap().contains("filename"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_with_time_to_spare() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink);
+
+        let deadline = SystemTime::now() + Duration::from_secs(300);
+        let output = handle(input(None), &context, Some(deadline)).await.unwrap();
+        assert!(output.complete);
+    }
+
+    #[tokio::test]
+    async fn test_handle_range() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink.clone());
+
+        // Only 2024-03-20 was recorded, so the day before fails without stopping the range
+        let range = LambdaInput {
+            from: Some("2024-03-19".to_string()),
+            ..input(None)
+        };
+        let output = handle(range, &context, None).await.unwrap();
+        assert!(output.complete);
+        assert_eq!(output.message, "Downloaded 1 of 2 crosswords from 2024-03-19 to 2024-03-20; 1 failed");
+        assert_eq!(output.dates.len(), 2);
+        assert!(output.dates[0].error.is_some());
+        let downloaded = output.dates[1].output.as_ref().unwrap();
+        assert!(downloaded.filename.ends_with("crossword_2024-03-20.jpg"));
+        assert_eq!(sink.uploaded.lock().unwrap().len(), 1);
+
+        let backwards = LambdaInput {
+            from: Some("2024-03-20".to_string()),
+            to: Some("2024-03-18".to_string()),
+            ..input(None)
+        };
+        assert!(handle(backwards, &context, None).await.is_err());
+        let both = LambdaInput {
+            from: Some("2024-03-18".to_string()),
+            ..input(Some("2024-03-20"))
+        };
+        assert!(handle(both, &context, None).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_input_options() {
+        let input: LambdaInput = serde_json::from_str(
+            r#"{"date": "2024-03-20", "no_upload": true, "storage": ["drive"], "page_range": "1-6", "layout": "cartoon", "explain": "json"}"#,
+        )
+        .unwrap();
+        assert!(input.no_upload);
+        assert_eq!(input.storage, Some(vec!["drive".to_string()]));
+        assert_eq!(input.page_range.as_deref(), Some("1-6"));
+        assert_eq!(input.layout.as_deref(), Some("cartoon"));
+        assert_eq!(input.explain, Some(ExplainFormat::Json));
+
+        // The scheduled event still sends only a date, or nothing at all
+        let input: LambdaInput = serde_json::from_str("{}").unwrap();
+        assert!(!input.no_upload && input.storage.is_none() && input.explain.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_without_upload_on_given_pages() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink.clone());
+
+        let output = handle(
+            LambdaInput {
+                no_upload: true,
+                page_range: Some("2".to_string()),
+                explain: Some(ExplainFormat::Json),
+                ..input(None)
+            },
+            &context,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(Path::new(&output.filename).is_file());
+        assert!(sink.uploaded.lock().unwrap().is_empty());
+        // Page 1 was never scanned
+        let explanation: serde_json::Value = serde_json::from_str(&output.explanation.unwrap()).unwrap();
+        assert!(explanation.as_array().unwrap().iter().all(|row| row["page"] == 2));
+    }
+
+    #[tokio::test]
+    async fn test_handle_picks_storage_and_layout_by_name() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let mut context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink.clone());
+
+        let unknown = LambdaInput { storage: Some(vec!["dropbox".to_string()]), ..input(None) };
+        let error = handle(unknown, &context, None).await.unwrap_err();
+        assert!(error.to_string().contains("Unknown storage target 'dropbox'; available: recording"));
+
+        let unknown = LambdaInput { layout: Some("cartoon".to_string()), ..input(None) };
+        let error = handle(unknown, &context, None).await.unwrap_err();
+        assert!(error.to_string().contains("No layout named 'cartoon'"));
+
+        // A layout that only matches the crossword's own area
+        let tight = TargetProfile {
+            expected: Rect { x1: 4, y1: 1672, x2: 997, y2: 2778 },
+            tolerance: Rect { x1: 0, y1: 0, x2: 0, y2: 0 },
+        };
+        context.config.layouts.insert("tight".to_string(), tight);
+        let named = LambdaInput {
+            storage: Some(vec!["recording".to_string()]),
+            layout: Some("tight".to_string()),
+            ..input(None)
+        };
+        let output = handle(named, &context, None).await.unwrap();
+        assert_eq!(*sink.uploaded.lock().unwrap(), vec![output.filename]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_rejects_invalid_date() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-20T06:00:00+00:00", sink);
+
+        let error = handle(input(Some("03/20/2024")), &context, None).await.unwrap_err();
+        assert!(error.to_string().contains("Invalid date format"));
+    }
+
+    #[tokio::test]
+    async fn test_handle_rejects_future_date() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "multi_slice_2024-03-20.json", "2024-03-19T23:00:00+00:00", sink.clone());
+
+        let error = handle(input(Some("2024-03-20")), &context, None).await.unwrap_err();
+        assert!(error.to_string().contains("is in the future"));
+        assert!(sink.uploaded.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handle_does_not_upload_failed_downloads() {
+        let dir = TempDir::new().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let context = context(&dir, "html_instead_of_image_2024-03-21.json", "2024-03-21T06:00:00+00:00", sink.clone());
+
+        assert!(handle(input(None), &context, None).await.is_err());
+        assert!(sink.uploaded.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+/// Points taken off when no single area matched and two were stitched together
+const STITCHED_PENALTY: u8 = 20;
+
+/// Points taken off when the image is far from the region's usual size
+const UNUSUAL_SIZE_PENALTY: u8 = 40;
+
+/// The `[review]` section of the config: detections scoring below `threshold` are held for
+/// someone to check instead of being archived as if nothing were wrong
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewConfig {
+    /// Lowest confidence, from 0 to 100, archived as usual
+    pub threshold: u8,
+    /// Subfolder of each storage target that crosswords needing review go to
+    pub folder: String,
+}
+
+impl Default for ReviewConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 50,
+            folder: "review".to_string(),
+        }
+    }
+}
+
+impl ReviewConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.threshold > 100 {
+            problems.push(format!("[review] threshold must be at most 100, not {}", self.threshold));
+        }
+        if self.folder.trim().is_empty() || self.folder.contains(['/', '\\']) {
+            problems.push(format!("[review] folder must be a single folder name, not '{}'", self.folder));
+        }
+        problems
+    }
+
+    pub fn needs_review(&self, confidence: u8) -> bool {
+        confidence < self.threshold
+    }
+}
+
+/// How sure detection is of an area, from 0 to 100: how close it sits to the profile
+/// (see [`crate::parser::placement_score`]), less a penalty for each sign of trouble
+pub fn score(placement: u8, stitched: bool, unusual_size: bool) -> u8 {
+    let mut score = placement.min(100);
+    if stitched {
+        score = score.saturating_sub(STITCHED_PENALTY);
+    }
+    if unusual_size {
+        score = score.saturating_sub(UNUSUAL_SIZE_PENALTY);
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_and_threshold() {
+        assert_eq!(score(100, false, false), 100);
+        assert_eq!(score(78, true, false), 58);
+        assert_eq!(score(30, true, true), 0);
+
+        let review = ReviewConfig::default();
+        assert!(review.validate().is_empty());
+        assert!(!review.needs_review(50));
+        assert!(review.needs_review(49));
+        let nested = ReviewConfig {
+            threshold: 101,
+            folder: "review/low".to_string(),
+        };
+        assert_eq!(nested.validate().len(), 2);
+    }
+}
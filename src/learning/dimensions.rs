@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+use super::History;
+
+/// What to do when a detected image is far from the region's usual size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DimensionAction {
+    Off,
+    /// Save it anyway and report a warning
+    Warn,
+    /// Refuse the image, leaving any archived copy in place
+    Fail,
+}
+
+/// The `[dimensions]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DimensionsConfig {
+    pub action: DimensionAction,
+    /// How far width or height may stray from the usual, as a fraction of it
+    pub max_deviation: f64,
+    /// Matches with a known size needed before anything counts as unusual
+    pub min_history: usize,
+}
+
+impl Default for DimensionsConfig {
+    fn default() -> Self {
+        Self {
+            action: DimensionAction::Warn,
+            max_deviation: 0.5,
+            min_history: 5,
+        }
+    }
+}
+
+impl DimensionsConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if self.max_deviation.is_nan() || self.max_deviation <= 0.0 {
+            return vec!["[dimensions] max_deviation must be above 0".to_string()];
+        }
+        Vec::new()
+    }
+
+    /// The region's usual size when `dimensions` strays too far from it
+    pub fn unusual(&self, history: &History, region: &str, dimensions: (u32, u32)) -> Option<(u32, u32)> {
+        if self.action == DimensionAction::Off {
+            return None;
+        }
+        let typical = typical(history, region, self.min_history)?;
+        let strays = |actual: u32, usual: u32| {
+            f64::from(actual.abs_diff(usual)) > f64::from(usual) * self.max_deviation
+        };
+        (strays(dimensions.0, typical.0) || strays(dimensions.1, typical.1)).then_some(typical)
+    }
+}
+
+/// Median width and height of the region's recent matches, once `min_history` have a size
+fn typical(history: &History, region: &str, min_history: usize) -> Option<(u32, u32)> {
+    let sizes: Vec<(u32, u32)> = history.regions.get(region)?.iter().filter_map(|record| record.dimensions).collect();
+    if sizes.is_empty() || sizes.len() < min_history {
+        return None;
+    }
+    let median = |mut values: Vec<u32>| {
+        values.sort_unstable();
+        values[values.len() / 2]
+    };
+    Some((
+        median(sizes.iter().map(|size| size.0).collect()),
+        median(sizes.iter().map(|size| size.1).collect()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::MatchRecord;
+    use crate::types::Rect;
+    use chrono::NaiveDate;
+
+    fn history(sizes: &[(u32, u32)]) -> History {
+        let mut history = History::default();
+        for (day, size) in sizes.iter().enumerate() {
+            let record = MatchRecord {
+                date: NaiveDate::from_ymd_opt(2024, 3, 1 + day as u32).unwrap(),
+                page: 2,
+                rect: Rect { x1: 0, y1: 1625, x2: 1000, y2: 2775 },
+                dimensions: Some(*size),
+            };
+            history.record("crossword", record);
+        }
+        history
+    }
+
+    #[test]
+    fn test_unusual_dimensions() {
+        let config = DimensionsConfig::default();
+        let history = history(&[(1000, 1150), (1010, 1140), (990, 1160), (1000, 1150), (3000, 400)]);
+
+        // One outlier in the history doesn't move the median
+        assert_eq!(config.unusual(&history, "crossword", (1020, 1100)), None);
+        assert_eq!(config.unusual(&history, "crossword", (1000, 300)), Some((1000, 1150)));
+        assert_eq!(config.unusual(&history, "crossword", (1600, 1150)), Some((1000, 1150)));
+        assert_eq!(config.unusual(&history, "cartoon", (1600, 1150)), None);
+
+        let off = DimensionsConfig { action: DimensionAction::Off, ..DimensionsConfig::default() };
+        assert_eq!(off.unusual(&history, "crossword", (1000, 300)), None);
+        let patient = DimensionsConfig { min_history: 6, ..DimensionsConfig::default() };
+        assert_eq!(patient.unusual(&history, "crossword", (1000, 300)), None);
+    }
+}
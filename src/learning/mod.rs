@@ -0,0 +1,390 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+use crate::types::{Rect, TargetProfile};
+
+pub mod confidence;
+pub mod dimensions;
+
+/// File in the output directory remembering where recent matches were found
+const HISTORY_FILE: &str = ".match-history.json";
+
+/// Matches kept per region; older ones no longer say much about the current layout
+const HISTORY_SIZE: usize = 30;
+
+/// Fewest matches a suggestion is based on
+const MIN_MATCHES: usize = 3;
+
+/// Default number of recent matches averaged into a suggestion
+pub const DEFAULT_WINDOW: usize = 7;
+
+/// Recent matches whose pages are scanned before the rest
+const PAGE_HINTS: usize = 5;
+
+/// Where a region was found on one date
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub date: NaiveDate,
+    pub page: u32,
+    pub rect: Rect,
+    /// Width and height of the downloaded image, when it could be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<(u32, u32)>,
+}
+
+/// How often a region turned up on one page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageStats {
+    pub hits: u32,
+    pub last_seen: NaiveDate,
+}
+
+/// Recent matches of every region, keyed by region name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub regions: BTreeMap<String, Vec<MatchRecord>>,
+    /// Matches per page of every region over all recorded runs; unlike `regions` never trimmed
+    #[serde(default)]
+    pub pages: BTreeMap<String, BTreeMap<u32, PageStats>>,
+}
+
+impl History {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(HISTORY_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse match history {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read match history {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("Failed to write match history {}", path.display()))
+    }
+
+    /// Adds a match, replacing an earlier one for the same date
+    pub fn record(&mut self, region: &str, record: MatchRecord) {
+        let matches = self.regions.entry(region.to_string()).or_default();
+        // Re-running a date shouldn't count its page twice
+        if !matches.iter().any(|existing| existing.date == record.date) {
+            let stats = self.pages.entry(region.to_string()).or_default();
+            let page = stats.entry(record.page).or_insert(PageStats {
+                hits: 0,
+                last_seen: record.date,
+            });
+            page.hits += 1;
+            page.last_seen = page.last_seen.max(record.date);
+        }
+        matches.retain(|existing| existing.date != record.date);
+        matches.push(record);
+        matches.sort_by_key(|existing| existing.date);
+        if matches.len() > HISTORY_SIZE {
+            matches.drain(..matches.len() - HISTORY_SIZE);
+        }
+    }
+
+    /// Counts the pages of the recorded matches of regions without page stats, as in
+    /// histories written before they were kept
+    pub fn rebuild_page_stats(&mut self) {
+        for (region, matches) in &self.regions {
+            let stats = self.pages.entry(region.clone()).or_default();
+            if !stats.is_empty() {
+                continue;
+            }
+            for record in matches {
+                let page = stats.entry(record.page).or_insert(PageStats {
+                    hits: 0,
+                    last_seen: record.date,
+                });
+                page.hits += 1;
+                page.last_seen = page.last_seen.max(record.date);
+            }
+        }
+    }
+
+    /// Distinct pages of the region's last `window` matches, most recent first
+    pub fn page_hints(&self, region: &str, window: usize) -> Vec<u32> {
+        let mut pages = Vec::new();
+        let matches = self.regions.get(region).map(Vec::as_slice).unwrap_or_default();
+        for record in matches.iter().rev().take(window) {
+            if !pages.contains(&record.page) {
+                pages.push(record.page);
+            }
+        }
+        pages
+    }
+
+    /// Matches of the region on `page` over all recorded runs
+    pub fn hits(&self, region: &str, page: u32) -> u32 {
+        self.pages
+            .get(region)
+            .and_then(|pages| pages.get(&page))
+            .map_or(0, |stats| stats.hits)
+    }
+
+    /// A table per region of the pages it was found on and how often
+    pub fn render_stats(&self) -> String {
+        let mut out = String::new();
+        for (region, pages) in &self.pages {
+            let total: u32 = pages.values().map(|stats| stats.hits).sum();
+            out.push_str(&format!("{} ({} matches)\n", region, total));
+            out.push_str("  page  matches  share  last seen\n");
+            let mut pages: Vec<_> = pages.iter().collect();
+            pages.sort_by_key(|(page, stats)| (Reverse(stats.hits), **page));
+            for (page, stats) in pages {
+                let share = f64::from(stats.hits) * 100.0 / f64::from(total.max(1));
+                out.push_str(&format!(
+                    "  {:>4}  {:>7}  {:>4.0}%  {}\n",
+                    page, stats.hits, share, stats.last_seen
+                ));
+            }
+        }
+        out
+    }
+
+    /// Average rect of the region's last `window` matches, once there are enough of them
+    pub fn suggest(&self, region: &str, window: usize) -> Option<Rect> {
+        let matches = self.regions.get(region)?;
+        let recent = &matches[matches.len().saturating_sub(window)..];
+        if recent.len() < MIN_MATCHES.min(window.max(1)) {
+            return None;
+        }
+
+        let average = |edge: fn(&Rect) -> i32| {
+            let sum: i64 = recent.iter().map(|record| i64::from(edge(&record.rect))).sum();
+            (sum as f64 / recent.len() as f64).round() as i32
+        };
+        Some(Rect {
+            x1: average(|r| r.x1),
+            y1: average(|r| r.y1),
+            x2: average(|r| r.x2),
+            y2: average(|r| r.y2),
+        })
+    }
+}
+
+/// Whether the layout has moved far enough that matching relies on the tolerance:
+/// any edge of `suggested` more than half its tolerance away from the profile
+pub fn has_drifted(profile: &TargetProfile, suggested: &Rect) -> bool {
+    let (expected, tolerance) = (&profile.expected, &profile.tolerance);
+    [
+        (expected.x1, suggested.x1, tolerance.x1),
+        (expected.y1, suggested.y1, tolerance.y1),
+        (expected.x2, suggested.x2, tolerance.x2),
+        (expected.y2, suggested.y2, tolerance.y2),
+    ]
+    .into_iter()
+    .any(|(expected, suggested, tolerance)| expected.abs_diff(suggested) * 2 > tolerance.unsigned_abs())
+}
+
+/// Records a match in the output directory's history, returning a suggested profile
+/// when the region has drifted from `profile`
+pub fn record_match(
+    output_dir: &Path,
+    region: &str,
+    profile: &TargetProfile,
+    record: MatchRecord,
+) -> Result<Option<Rect>> {
+    let path = History::path(output_dir);
+    let mut history = History::load(&path)?;
+    history.record(region, record);
+    history.save(&path)?;
+
+    Ok(history
+        .suggest(region, DEFAULT_WINDOW)
+        .filter(|suggested| has_drifted(profile, suggested)))
+}
+
+/// `pages` reordered so the ones the region turned up on lately are scanned first, then
+/// the rest by how often it was found on them. The crossword stays on one page for weeks,
+/// so a run usually needs one or two requests.
+pub fn prioritize_pages(output_dir: &Path, region: &str, pages: &[u32]) -> Vec<u32> {
+    let history = match History::load(&History::path(output_dir)) {
+        Ok(history) => history,
+        Err(e) => {
+            tracing::warn!("Scanning pages in order: {:#}", e);
+            History::default()
+        }
+    };
+    let hints = history.page_hints(region, PAGE_HINTS);
+    let mut ordered = pages.to_vec();
+    // Stable, so pages never seen keep their order
+    ordered.sort_by_key(|page| {
+        let recent = hints.iter().position(|hint| hint == page).unwrap_or(usize::MAX);
+        (recent, Reverse(history.hits(region, *page)))
+    });
+    ordered
+}
+
+/// Writes a new expected rect into the config file, keeping its comments and layout
+pub fn apply_to_config(path: &Path, region: &str, rect: &Rect) -> Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let expected = if region == "crossword" {
+        &mut document["target"]["expected"]
+    } else {
+        &mut document["features"][region]["expected"]
+    };
+    for (edge, value) in [("x1", rect.x1), ("y1", rect.y1), ("x2", rect.x2), ("y2", rect.y2)] {
+        expected[edge] = toml_edit::value(i64::from(value));
+    }
+
+    storage::write_atomic(path, document.to_string().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn record(day: u32, y1: i32) -> MatchRecord {
+        MatchRecord {
+            date: NaiveDate::from_ymd_opt(2024, 3, day).unwrap(),
+            page: 8,
+            rect: Rect { x1: 0, y1, x2: 1000, y2: y1 + 1150 },
+            dimensions: None,
+        }
+    }
+
+    #[test]
+    fn test_suggest_needs_enough_matches() {
+        let mut history = History::default();
+        history.record("crossword", record(1, 1625));
+        history.record("crossword", record(2, 1625));
+        assert_eq!(history.suggest("crossword", DEFAULT_WINDOW), None);
+
+        history.record("crossword", record(3, 1655));
+        assert_eq!(history.suggest("crossword", DEFAULT_WINDOW).unwrap().y1, 1635);
+        assert_eq!(history.suggest("cartoon", DEFAULT_WINDOW), None);
+    }
+
+    #[test]
+    fn test_suggest_uses_recent_window() {
+        let mut history = History::default();
+        for day in 1..=10 {
+            history.record("crossword", record(day, if day <= 7 { 1625 } else { 1665 }));
+        }
+        // A re-run of the same date replaces its earlier match
+        history.record("crossword", record(10, 1665));
+
+        assert_eq!(history.regions["crossword"].len(), 10);
+        assert_eq!(history.suggest("crossword", 3).unwrap().y1, 1665);
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut history = History::default();
+        for day in 1..=31 {
+            history.record("crossword", record(day, 1625));
+        }
+
+        let matches = &history.regions["crossword"];
+        assert_eq!(matches.len(), HISTORY_SIZE);
+        assert_eq!(matches[0].date, NaiveDate::from_ymd_opt(2024, 3, 2).unwrap());
+    }
+
+    #[test]
+    fn test_prioritize_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages: Vec<u32> = (1..=8).collect();
+        assert_eq!(prioritize_pages(dir.path(), "crossword", &pages), pages);
+
+        let mut history = History::default();
+        for (day, page) in [(1, 7), (2, 5), (3, 6), (4, 6)] {
+            history.record("crossword", MatchRecord { page, ..record(day, 1625) });
+        }
+        history.record("cartoon", MatchRecord { page: 1, ..record(4, 1625) });
+        history.save(&History::path(dir.path())).unwrap();
+
+        assert_eq!(prioritize_pages(dir.path(), "crossword", &pages), vec![6, 5, 7, 1, 2, 3, 4, 8]);
+        assert_eq!(prioritize_pages(dir.path(), "cartoon", &pages), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        // Pages outside the region's configured list are never added
+        assert_eq!(prioritize_pages(dir.path(), "crossword", &[1, 2, 5]), vec![5, 1, 2]);
+    }
+
+    #[test]
+    fn test_page_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = History::default();
+        // Mostly on page 2, and on page 4 for a while long ago
+        for day in 1..=40 {
+            let page = if day <= 10 { 4 } else { 2 };
+            let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap() + chrono::Days::new(day);
+            history.record("crossword", MatchRecord { date, page, ..record(1, 1625) });
+        }
+        history.record("crossword", MatchRecord { page: 3, ..record(20, 1625) });
+        // Re-running a date doesn't count it again
+        history.record("crossword", MatchRecord { page: 3, ..record(20, 1625) });
+        history.save(&History::path(dir.path())).unwrap();
+
+        // Page 4 has left the recent history, but its hits still put it ahead of unseen pages
+        assert_eq!(history.regions["crossword"].len(), HISTORY_SIZE);
+        assert_eq!(history.hits("crossword", 4), 10);
+        assert_eq!(prioritize_pages(dir.path(), "crossword", &[1, 2, 3, 4, 5]), vec![3, 2, 4, 1, 5]);
+
+        let stats = history.render_stats();
+        assert!(stats.starts_with("crossword (41 matches)\n"));
+        assert!(stats.contains("     2       30    73%  2024-02-10\n"));
+        assert!(stats.contains("     3        1     2%  2024-03-20\n"));
+    }
+
+    #[test]
+    fn test_has_drifted() {
+        let profile = TargetProfile::default();
+        assert!(!has_drifted(&profile, &Rect { x1: 0, y1: 1650, x2: 1000, y2: 2775 }));
+        assert!(has_drifted(&profile, &Rect { x1: 0, y1: 1660, x2: 1000, y2: 2775 }));
+    }
+
+    #[test]
+    fn test_record_match_suggests_after_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = TargetProfile::default();
+
+        let mut suggestion = None;
+        for day in 1..=3 {
+            suggestion = record_match(dir.path(), "crossword", &profile, record(day, 1665)).unwrap();
+        }
+
+        assert_eq!(suggestion.unwrap().y1, 1665);
+        assert_eq!(History::load(&History::path(dir.path())).unwrap().regions["crossword"].len(), 3);
+    }
+
+    #[test]
+    fn test_apply_to_config_keeps_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "# Upload folder\ndrive_folder_id = \"abc\"\n\n[features.cartoon]\nexpected = { x1 = 0, y1 = 0, x2 = 400, y2 = 300 }\n",
+        )
+        .unwrap();
+        let rect = Rect { x1: 2, y1: 1660, x2: 1001, y2: 2790 };
+
+        apply_to_config(&path, "crossword", &rect).unwrap();
+        apply_to_config(&path, "cartoon", &Rect { x1: 0, y1: 10, x2: 400, y2: 310 }).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# Upload folder\n"));
+        let config: Config = toml::from_str(&content).unwrap();
+        assert_eq!(config.target.expected, rect);
+        assert_eq!(config.features["cartoon"].profile.expected.y1, 10);
+    }
+}
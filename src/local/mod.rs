@@ -0,0 +1,203 @@
+//! Keeping the crosswords in a folder on disk rather than uploading them anywhere, for
+//! offline use. Each file is filed under its year and month, e.g.
+//! `archive/2024/03/crossword_2024-03-20.jpg`, and listed in `index.json` in the archive's
+//! root.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+use crate::config::{Config, PermissionsConfig};
+use crate::console;
+use crate::naming;
+use crate::pipeline::{Artifact, RemoteCopy, Sink, DRY_RUN_ID};
+use crate::storage;
+
+/// File in the archive's root listing everything filed in it
+pub const INDEX_FILE: &str = "index.json";
+
+
+/// The `[local]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalConfig {
+    /// Root of the archive; relative paths start from the working directory
+    pub dir: PathBuf,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("archive"),
+        }
+    }
+}
+
+impl LocalConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if self.dir.as_os_str().is_empty() {
+            return vec!["[local] needs a dir to keep the archive in".to_string()];
+        }
+        Vec::new()
+    }
+}
+
+/// One file in the archive, as listed in `index.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub date: NaiveDate,
+    /// Relative to the archive's root
+    pub path: String,
+    pub sha256: String,
+    pub bytes: u64,
+    pub mime_type: String,
+}
+
+/// Files pipeline artifacts into the local archive
+pub struct LocalSink {
+    dir: PathBuf,
+    permissions: PermissionsConfig,
+    /// Held while `index.json` is read and rewritten, as sinks upload side by side
+    index: Mutex<()>,
+    dry_run: bool,
+}
+
+impl LocalSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let local = config.local.clone().context("[local] is not configured")?;
+        Ok(Self {
+            dir: local.dir,
+            permissions: config.permissions.clone(),
+            index: Mutex::new(()),
+            dry_run: false,
+        })
+    }
+
+    /// Prints what would be archived instead of writing it
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Path of the artifact within the archive, e.g. `2024/03/originals/crossword_2024-03-20.jpg`
+    fn relative_path(artifact: &Artifact) -> Result<(NaiveDate, PathBuf)> {
+        let name = Path::new(&artifact.path).file_name().context("Invalid filename")?;
+        let date = match artifact.date {
+            Some(date) => date,
+            None => naming::date_in(&name.to_string_lossy())
+                .with_context(|| format!("No date to file {} under", artifact.path))?,
+        };
+        let mut path = PathBuf::from(date.format("%Y").to_string());
+        path.push(date.format("%m").to_string());
+        path.extend(&artifact.folder);
+        Ok((date, path.join(name)))
+    }
+
+    /// Adds `entry` to the index, replacing any earlier copy at the same path
+    async fn record(&self, entry: IndexEntry) -> Result<()> {
+        let _guard = self.index.lock().await;
+        let path = self.dir.join(INDEX_FILE);
+        let mut entries = read_index(&path)?;
+        entries.retain(|existing| existing.path != entry.path);
+        entries.push(entry);
+        entries.sort_by(|a, b| (a.date, &a.path).cmp(&(b.date, &b.path)));
+        storage::write_atomic(&path, serde_json::to_string_pretty(&entries)?.as_bytes())
+    }
+}
+
+/// The entries of the index at `path`, or none before the first file is archived
+pub fn read_index(path: &Path) -> Result<Vec<IndexEntry>> {
+    match fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[async_trait]
+impl Sink for LocalSink {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        let (date, relative) = Self::relative_path(artifact)?;
+        let path = self.dir.join(&relative);
+        if self.dry_run {
+            console::progress!("Dry run: would archive {} as {}", artifact.path, path.display());
+            return Ok(DRY_RUN_ID.to_string());
+        }
+
+        let data = fs::read(&artifact.path).with_context(|| format!("Failed to read {}", artifact.path))?;
+        let folder = path.parent().context("Archive path has no folder")?;
+        fs::create_dir_all(folder).with_context(|| format!("Failed to create {}", folder.display()))?;
+        storage::save(&path, &data, &self.permissions)?;
+        let id = relative.to_string_lossy().into_owned();
+        self.record(IndexEntry {
+            date,
+            path: id.clone(),
+            sha256: format!("{:x}", Sha256::digest(&data)),
+            bytes: data.len() as u64,
+            mime_type: artifact.mime_type.clone(),
+        })
+        .await?;
+        console::progress!("File archived as {}", path.display());
+        Ok(id)
+    }
+
+    async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+        if id == DRY_RUN_ID {
+            return Ok(RemoteCopy::Missing);
+        }
+        let path = self.dir.join(id);
+        match fs::read(&path) {
+            Ok(data) => Ok(RemoteCopy::Present {
+                sha256: Some(format!("{:x}", Sha256::digest(&data))),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RemoteCopy::Missing),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_files_by_year_and_month() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            local: Some(LocalConfig {
+                dir: dir.path().join("archive"),
+            }),
+            ..Config::default()
+        };
+        let sink = LocalSink::new(&config).unwrap();
+        let source = dir.path().join("crossword_2024-03-20.jpg");
+        fs::write(&source, b"crossword").unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let artifact = Artifact::new(source.to_string_lossy(), "image/jpeg").dated(date);
+
+        let id = sink.upload(&artifact).await.unwrap();
+        assert_eq!(id, "2024/03/crossword_2024-03-20.jpg");
+        assert_eq!(fs::read(dir.path().join("archive").join(&id)).unwrap(), b"crossword");
+        // Uploading again replaces the entry rather than adding another
+        sink.upload(&artifact).await.unwrap();
+        let original = sink.upload(&artifact.original_copy().unwrap()).await.unwrap();
+        assert_eq!(original, "2024/03/originals/crossword_2024-03-20_orig.jpg");
+
+        let entries = read_index(&dir.path().join("archive").join(INDEX_FILE)).unwrap();
+        let paths: Vec<_> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, [id.as_str(), original.as_str()]);
+        assert_eq!(entries[0].bytes, 9);
+        let expected = format!("{:x}", Sha256::digest(b"crossword"));
+        assert_eq!(sink.inspect(&id).await.unwrap(), RemoteCopy::Present { sha256: Some(expected) });
+        assert_eq!(sink.inspect("2024/03/missing.jpg").await.unwrap(), RemoteCopy::Missing);
+    }
+}
@@ -1,55 +1,1165 @@
-use anyhow::Result;
-use chrono::{Local, NaiveDate};
-use clap::Parser;
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, Utc};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
-use reqwest::Client;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
+mod airtable;
+mod archive;
+mod backfill;
+mod browse;
+mod cache;
+mod calendar;
+mod clock;
+mod config;
+mod console;
+mod credentials;
 mod drive;
+mod dropbox;
+mod email;
+mod error;
+mod grading;
 mod http;
+mod imaging;
+mod lambda;
+mod learning;
+mod local;
+mod manpage;
+mod message;
+mod naming;
+mod notion;
+mod ocr;
+mod onedrive;
 mod parser;
+mod pipeline;
+mod redetect;
+mod report;
+mod rotation;
+mod setup;
+mod sftp;
+mod signed_url;
+mod site;
+mod status;
+mod storage;
+mod telegram;
+mod telemetry;
 mod types;
+mod webdav;
 mod crossword;
+mod diagnostics;
 
-use types::{LambdaInput, LambdaOutput};
+use archive::ArchiveFormat;
+use clock::Clock;
+use config::{Config, Puzzle};
+use crossword::{retry, Download};
+use diagnostics::explain::{self, ExplainFormat};
+use diagnostics::preflight;
+use diagnostics::Capture;
+use imaging::preview::{self, Protocol};
+use http::vcr::{Recorder, Replayer};
+use http::HttpClient;
+use learning::History;
+use pipeline::Sink;
+use status::export::ExportFormat;
+use status::runs::RunLog;
+use status::OutputFormat;
+use types::{LambdaInput, ManualSource};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Date in YYYY-MM-DD format (defaults to today)
-    #[arg(short, long, value_parser = types::parse_date)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Date as YYYY-MM-DD, day first like 20-03-2024 or "20 Mar 2024", or relative like
+    /// yesterday, last-sunday or -3 (defaults to today)
+    #[arg(short, long, value_parser = types::parse_date, allow_negative_numbers = true)]
     date: Option<NaiveDate>,
+
+    /// First date of a range to download one after the other, instead of a single --date
+    #[arg(long, value_parser = types::parse_date, allow_negative_numbers = true, conflicts_with_all = ["date", "replay", "article_url", "image_url"])]
+    from: Option<NaiveDate>,
+
+    /// Last date of the --from range, inclusive (defaults to today)
+    #[arg(long, value_parser = types::parse_date, allow_negative_numbers = true, requires = "from")]
+    to: Option<NaiveDate>,
+
+    /// Puzzle to download in place of the daily crossword, e.g. `sunday-cryptic` as set up in
+    /// [sunday_cryptic]
+    #[arg(long, value_enum, value_name = "PUZZLE")]
+    puzzle: Option<Puzzle>,
+
+    /// Try dates in the future instead of rejecting them
+    #[arg(long)]
+    allow_future: bool,
+
+    /// Path to the config file (defaults to ./config.toml when present)
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Config profile to lay over the rest of the config file, e.g. `family` for
+    /// `[config_profiles.family]`
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Log more detail: -v for each page scanned and request made, -vv for everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Log less: -q for warnings and errors only, -qq for errors only, -qqq for nothing
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// Record every request to the site and its response into this fixture file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay_http")]
+    record_http: Option<PathBuf>,
+
+    /// Serve site responses from a recorded fixture file instead of the network
+    #[arg(long, value_name = "FILE")]
+    replay_http: Option<PathBuf>,
+
+    /// Repeat the run captured in a `debug-bundle` zip against its recorded responses, in a
+    /// fresh directory and without uploading anything, for reproducing an old failure
+    #[arg(long, value_name = "BUNDLE", conflicts_with_all = ["record_http", "replay_http", "article_url", "image_url", "wait_for_publication"])]
+    replay: Option<PathBuf>,
+
+    /// Download as usual but only print what would be uploaded to Drive
+    #[arg(long)]
+    dry_run_upload: bool,
+
+    /// Only find the crossword: print its page, article and image URLs without downloading,
+    /// saving or uploading it
+    #[arg(long, conflicts_with_all = ["from", "dry_run_upload", "wait_for_publication", "article_url", "image_url", "preview"])]
+    dry_run: bool,
+
+    /// Keep trying on the [retry] schedule while the crossword isn't published yet
+    #[arg(long)]
+    wait_for_publication: bool,
+
+    /// Check that the site and the storage can be reached before starting, like
+    /// [preflight] enabled = true does for every run
+    #[arg(long)]
+    preflight: bool,
+
+    /// Print how every area on the scanned pages scored against the crossword profile
+    /// (shown as a table with --dry-run-upload); as `json` it has standard output to itself
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    explain: Option<ExplainFormat>,
+
+    /// Download the crossword from this e-paper article, skipping the page scan; for when
+    /// detection breaks but the article can be found by hand
+    #[arg(long, value_name = "URL", value_parser = types::parse_url, conflicts_with = "image_url")]
+    article_url: Option<String>,
+
+    /// Download this image as the crossword, skipping the page scan and the article
+    #[arg(long, value_name = "URL", value_parser = types::parse_url)]
+    image_url: Option<String>,
+
+    /// How to print the outcome: `json` prints a result per date like the Lambda returns it,
+    /// with the page, image URL, saved file, upload IDs and how long each phase took, and
+    /// moves progress lines to standard error
+    #[arg(long, value_enum, default_value = "text")]
+    format: RunFormat,
+
+    /// Shorthand for `--format json`
+    #[arg(long)]
+    json: bool,
+
+    /// Show the downloaded crossword in the terminal, through the given graphics protocol or
+    /// the one the terminal is known to support
+    #[arg(long, value_enum, value_name = "PROTOCOL", num_args = 0..=1, default_missing_value = "auto", conflicts_with = "json")]
+    preview: Option<Protocol>,
+
+    /// Read the invocation from standard input as the Lambda's JSON event, or an array of them
+    /// to run in turn, and write only the JSON result to standard output
+    #[arg(long, conflicts_with_all = ["date", "from", "to", "puzzle", "allow_future", "dry_run", "dry_run_upload", "wait_for_publication", "explain", "article_url", "image_url", "format", "json", "preview", "preflight", "replay"])]
+    stdin: bool,
+}
+
+impl Args {
+    fn json(&self) -> bool {
+        self.json || self.format == RunFormat::Json
+    }
+
+    /// Whether standard output carries a JSON report, the run's or `--explain json`'s, that
+    /// nothing else may be mixed into
+    fn clean_stdout(&self) -> bool {
+        self.json() || self.explain == Some(ExplainFormat::Json)
+    }
+
+    /// Where the text outcome of a run goes: standard output, unless a JSON `--explain`
+    /// report has it to itself
+    fn text_output(&self) -> Box<dyn Write> {
+        match self.explain == Some(ExplainFormat::Json) {
+            true => Box::new(std::io::stderr()),
+            false => Box::new(std::io::stdout()),
+        }
+    }
+}
+
+impl Command {
+    /// How the command prints its report, for those that print one
+    fn output(&self) -> Option<OutputFormat> {
+        match self {
+            Command::Backfill { output, .. }
+            | Command::Status { output, .. }
+            | Command::Audit { output, .. }
+            | Command::DedupeDrive { output, .. }
+            | Command::Redetect { output, .. }
+            | Command::Queue {
+                action: QueueCommand::List { output },
+            } => Some(*output),
+            _ => None,
+        }
+    }
+}
+
+/// How a download run prints its outcome
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum RunFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write the config file by answering a few questions: where to upload, the Google key
+    /// and Drive folder, and notifications
+    Init,
+    /// Check the Google credentials end to end without downloading anything
+    VerifyCredentials,
+    /// Save the Google service account key in the OS keyring, for a `keyring` credential source,
+    /// or a notifier's secret read from standard input
+    StoreCredentials {
+        /// Service account key JSON downloaded from the Cloud console
+        #[arg(required_unless_present_any = ["telegram_token", "smtp_password"])]
+        file: Option<PathBuf>,
+        /// Store the Telegram bot token, read from standard input, instead
+        #[arg(long, conflicts_with_all = ["file", "smtp_password"])]
+        telegram_token: bool,
+        /// Store the SMTP password for `[email]`, read from standard input, instead
+        #[arg(long, conflicts_with = "file")]
+        smtp_password: bool,
+        /// Replace a secret already in the keyring
+        #[arg(long)]
+        force: bool,
+    },
+    /// Collect everything needed to report a detection problem into a zip file
+    DebugBundle {
+        /// Date of the edition to diagnose (YYYY-MM-DD), defaults to today in IST
+        #[arg(short, long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        date: Option<NaiveDate>,
+
+        /// Where to write the bundle, defaults to crossword-debug-<date>.zip
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Check read-only that this version still understands the live site, e.g. weekly from
+    /// cron; fails when the site's markup no longer fits before the daily runs do
+    Probe {
+        /// Edition to check (YYYY-MM-DD), defaults to today's in India, where it is published
+        #[arg(short, long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        date: Option<NaiveDate>,
+    },
+    /// Bundle a month of saved crosswords into one archive and upload it
+    CompileMonth {
+        /// Month to compile (YYYY-MM)
+        #[arg(value_parser = types::parse_month)]
+        month: NaiveDate,
+
+        #[arg(long, value_enum, default_value = "zip")]
+        format: ArchiveFormat,
+    },
+    /// Suggest a target profile from where recent matches were found
+    LearnProfile {
+        /// Feature to learn instead of the crossword
+        #[arg(long)]
+        feature: Option<String>,
+
+        /// Number of recent matches to average
+        #[arg(long, default_value_t = learning::DEFAULT_WINDOW)]
+        window: usize,
+
+        /// Write the suggestion into the config file
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Show which pages the crossword and features were found on, and how often, and how
+    /// recent runs went: success rate, time per phase and the most common failures
+    Stats,
+    /// Download every missing crossword in a date range, newest first and within the
+    /// [backfill] budget; a rerun resumes with the dates not downloaded yet
+    Backfill {
+        /// First date to download (YYYY-MM-DD)
+        #[arg(value_parser = types::parse_date, allow_negative_numbers = true)]
+        from: NaiveDate,
+
+        /// Last date to download (YYYY-MM-DD), defaults to today
+        #[arg(long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        to: Option<NaiveDate>,
+
+        /// How to print the result for each date
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// List every date in a range with whether its crossword is archived, and where
+    Status {
+        /// First date to list (YYYY-MM-DD)
+        #[arg(value_parser = types::parse_date, allow_negative_numbers = true)]
+        from: NaiveDate,
+
+        /// Last date to list (YYYY-MM-DD), defaults to today
+        #[arg(long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        to: Option<NaiveDate>,
+
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Write an iCalendar file with an event for every archived crossword and every missing day
+    Calendar {
+        /// First date to include (YYYY-MM-DD)
+        #[arg(value_parser = types::parse_date, allow_negative_numbers = true)]
+        from: NaiveDate,
+
+        /// Last date to include (YYYY-MM-DD), defaults to today
+        #[arg(long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        to: Option<NaiveDate>,
+
+        /// Where to write the calendar, defaults to crosswords.ics in the output directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Inspect what the output directory keeps track of
+    State {
+        #[command(subcommand)]
+        action: StateCommand,
+    },
+    /// List the dates waiting for another attempt, or cancel or requeue one
+    Queue {
+        #[command(subcommand)]
+        action: QueueCommand,
+    },
+    /// Upload an already downloaded crossword again without contacting the site, e.g. after
+    /// a Drive outage; only the failed uploads are repeated when the last run recorded any
+    Repair {
+        /// Date of the saved crossword (YYYY-MM-DD), defaults to today
+        #[arg(short, long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        date: Option<NaiveDate>,
+    },
+    /// Accept a crossword held for review: archive it, cache and learn from it, and upload it
+    /// to the usual folders
+    Approve {
+        /// Date of the held crossword (YYYY-MM-DD), defaults to today
+        #[arg(short, long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        date: Option<NaiveDate>,
+    },
+    /// Check that every archived crossword is still saved and uploaded unchanged, checksums
+    /// included; `--fix` puts back what is missing or damaged
+    Audit {
+        /// Restore saved files from the image cache, or the site as a last resort, and upload
+        /// missing or damaged copies again
+        #[arg(long)]
+        fix: bool,
+
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Trash the extra copies of files in the Drive folder left by runs done again, keeping
+    /// the copy that matches the archive, and point the manifest at it
+    DedupeDrive {
+        /// List what would be trashed without touching Drive or the manifest
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Run detection again over the pages kept in [raw_archive], without contacting the site,
+    /// and replace archived crosswords it now finds in a different area
+    Redetect {
+        /// First date to redetect (YYYY-MM-DD)
+        #[arg(value_parser = types::parse_date, allow_negative_numbers = true)]
+        from: NaiveDate,
+
+        /// Last date to redetect (YYYY-MM-DD), defaults to today
+        #[arg(long, value_parser = types::parse_date, allow_negative_numbers = true)]
+        to: Option<NaiveDate>,
+
+        /// Report what would be replaced without changing the archive or uploading
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Compare a scan or photo of your solved grid with the official solution
+    Check {
+        /// Image of your solved grid
+        mine: PathBuf,
+
+        /// Image of the official solution
+        solution: PathBuf,
+
+        /// Cells per side of the grid
+        #[arg(long, default_value_t = 15)]
+        size: u32,
+
+        /// Where to write your grid with wrong cells highlighted
+        #[arg(short, long, default_value = "check.png")]
+        output: PathBuf,
+    },
+    /// Browse the archived dates in a terminal UI, and download, upload or open them again;
+    /// needs a build with `--features tui`
+    Browse,
+    /// Write man pages for every command, shell completions and a JSON description of all
+    /// options, for packaging
+    GenerateManpage {
+        /// Directory to write the pages, completions and cli.json into
+        #[arg(long, default_value = "man")]
+        out_dir: PathBuf,
+    },
 }
 
-async fn handler(event: LambdaEvent<LambdaInput>) -> Result<LambdaOutput, Error> {
-    let date = match event.payload.date {
-        Some(date_str) => NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
-            .map_err(|e| anyhow::anyhow!("Invalid date format: {}", e))?,
-        None => Local::now().date_naive(),
+#[derive(Subcommand, Debug)]
+enum StateCommand {
+    /// Write every archived date with its file, checksum and links, e.g. for a spreadsheet
+    Export {
+        #[arg(long, value_enum, default_value = "csv")]
+        format: ExportFormat,
+
+        /// Where to write the export, defaults to standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum QueueCommand {
+    /// Show every date with failed uploads or a failed backfill download, why, and what retries it
+    List {
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+    },
+    /// Stop retrying a date: drop its failed uploads and have backfills leave it be
+    Cancel {
+        #[arg(value_parser = types::parse_date, allow_negative_numbers = true)]
+        date: NaiveDate,
+    },
+    /// Have the next backfill over a date download it again, even if cancelled or done before
+    Requeue {
+        #[arg(value_parser = types::parse_date, allow_negative_numbers = true)]
+        date: NaiveDate,
+    },
+}
+
+/// Rejects future dates and warns about dates the archive probably doesn't cover
+fn check_date(date: NaiveDate, allow_future: bool, clock: &dyn Clock) -> Result<()> {
+    let today = clock.today();
+    if let Some(warning) = types::validate_date(date, today, allow_future).map_err(anyhow::Error::msg)? {
+        tracing::warn!("{}", warning);
+    }
+    Ok(())
+}
+
+async fn verify_credentials(config: &Config) -> Result<()> {
+    let steps = drive::verify_credentials(config).await;
+    for step in &steps {
+        match &step.outcome {
+            Ok(detail) => println!("✓ {}: {}", step.name, detail),
+            Err(error) => println!("✗ {}: {}", step.name, error),
+        }
+    }
+
+    match steps.iter().find(|step| step.outcome.is_err()) {
+        Some(failed) => Err(anyhow::anyhow!("Credential check failed at '{}'", failed.name)),
+        None => Ok(()),
+    }
+}
+
+fn store_credentials(file: Option<&Path>, telegram_token: bool, force: bool) -> Result<()> {
+    use credentials::keyring;
+
+    let (name, what) = match (file, telegram_token) {
+        (Some(_), _) => (keyring::GOOGLE_SERVICE_ACCOUNT, "service account key"),
+        (None, true) => (keyring::TELEGRAM_BOT_TOKEN, "Telegram bot token"),
+        (None, false) => (keyring::SMTP_PASSWORD, "SMTP password"),
+    };
+    // Every run on this account would quietly switch to the new secret
+    if keyring::exists(name)? {
+        if !force {
+            return Err(anyhow::anyhow!("The OS keyring already holds a {}; pass --force to replace it", what));
+        }
+        tracing::warn!("Replacing the {} already in the OS keyring", what);
+    }
+    let secret = match file {
+        Some(file) => {
+            let key = std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+            serde_json::from_str::<serde_json::Value>(&key)
+                .with_context(|| format!("{} is not a service account key", file.display()))?;
+            key
+        }
+        None => {
+            // Read rather than passed as an argument, so it stays out of the shell history
+            eprintln!("Enter the {}:", what);
+            let mut secret = String::new();
+            std::io::stdin().read_line(&mut secret).context("Failed to read standard input")?;
+            let secret = secret.trim().to_string();
+            if secret.is_empty() {
+                return Err(anyhow::anyhow!("No {} was given", what));
+            }
+            secret
+        }
+    };
+    keyring::store(name, &secret)?;
+    match file {
+        Some(file) => {
+            println!(
+                "Stored the service account key in the OS keyring; \
+                 set [credential_source] kind = \"keyring\" in config.toml to use it"
+            );
+            println!("You can now delete {}", file.display());
+        }
+        None => println!(
+            "Stored the {} in the OS keyring; runs use it unless config.toml or the environment sets one",
+            what
+        ),
+    }
+    Ok(())
+}
+
+async fn run_cli(args: Args, config: &Config) -> Result<()> {
+    let clock = config.clock.0.clone();
+    let replay = args.replay.as_deref().map(diagnostics::Bundle::load).transpose()?;
+    let replaying = replay.is_some();
+    let date = clock::resolve_date(args.date.or(replay.as_ref().map(|bundle| bundle.date)), clock.as_ref());
+    let dates: Vec<NaiveDate> = match args.from {
+        Some(from) => {
+            let to = args.to.unwrap_or_else(|| clock.today());
+            if from > to {
+                return Err(anyhow::anyhow!("The range starts on {} but ends earlier, on {}", from, to));
+            }
+            from.iter_days().take_while(|date| *date <= to).collect()
+        }
+        None => vec![date],
     };
+    for date in [dates[0], dates[dates.len() - 1]] {
+        check_date(date, args.allow_future, clock.as_ref())?;
+    }
 
-    // Create a client with a user agent to mimic a browser
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Safari/537.36")
-        .build()?;
+    let client: Box<dyn HttpClient> = match (replay, &args.record_http, &args.replay_http) {
+        (Some(bundle), _, _) => Box::new(Replayer::new(bundle.cassette)),
+        (_, Some(path), _) => Box::new(Recorder::new(http::build_client(config, clock.clone())?, path)),
+        (_, _, Some(path)) => Box::new(Replayer::load(path)?),
+        _ => http::build_client(config, clock.clone())?,
+    };
 
-    let filename = crossword::download_crossword(&client, date).await?;
-    
-    Ok(LambdaOutput {
-        message: "Crossword downloaded successfully".to_string(),
-        filename,
-    })
+    let mut config = config.clone();
+    if let Some(puzzle) = args.puzzle {
+        if puzzle == Puzzle::SundayCryptic && config.sunday_cryptic.is_none() {
+            return Err(anyhow::anyhow!("--puzzle sunday-cryptic needs a [sunday_cryptic] section in the config"));
+        }
+        config.puzzle = puzzle;
+    }
+    config.manual_source = match (args.article_url.clone(), args.image_url.clone()) {
+        (Some(url), _) => Some(ManualSource::Article(url)),
+        (_, Some(url)) => Some(ManualSource::Image(url)),
+        _ => None,
+    };
+    config.preflight.enabled |= args.preflight;
+    if replaying {
+        // Nothing learned or cached by earlier runs may change the outcome, and there is no
+        // network to check or report to
+        config.output_dir = tempfile::Builder::new().prefix("crossword-replay-").tempdir()?.into_path();
+        config.preflight.enabled = false;
+        config.telegram = None;
+        console::progress!("Replaying the run of {} in {}", date, config.output_dir.display());
+    }
+    // A dry run leaves no trace outside the output directory
+    if args.dry_run_upload || replaying {
+        config.notion = None;
+        config.airtable = None;
+        config.email = None;
+    }
+    let config = &config;
+    if args.dry_run {
+        return resolve_date(&args, client.as_ref(), date, config).await;
+    }
+
+    // A replay stops short of the uploads; even a dry run looks the Drive folder up
+    let sinks = match replaying {
+        true => Vec::new(),
+        false => signed_url::sinks(config, args.dry_run_upload)?,
+    };
+    preflight::run(config, &sinks).await?;
+
+    if args.from.is_none() {
+        let download = download_date(&args, client.as_ref(), date, config, &sinks).await?;
+        return print_download(&args, date, download, config);
+    }
+
+    // A failed date doesn't stop the rest of the range; it is reported in its place instead
+    let mut outputs = Vec::new();
+    let mut failed = 0;
+    for &date in &dates {
+        if let Some(reason) = config.publication.skipped(date, config.puzzle) {
+            match args.json() {
+                true => outputs.push(serde_json::json!({ "date": date, "skipped": reason })),
+                false => writeln!(args.text_output(), "{}: skipped, {}", date, reason)?,
+            }
+            continue;
+        }
+        match download_date(&args, client.as_ref(), date, config, &sinks).await {
+            Ok(download) if args.json() => outputs.push(serde_json::to_value(lambda::output(config, date, download))?),
+            Ok(download) => print_download(&args, date, download, config)?,
+            Err(e) => {
+                failed += 1;
+                let error = error::hints::describe(&e);
+                match args.json() {
+                    true => outputs.push(serde_json::json!({ "date": date, "error": error })),
+                    false => eprintln!("{}: {}", date, error),
+                }
+            }
+        }
+    }
+    if args.json() {
+        println!("{}", serde_json::to_string_pretty(&outputs)?);
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!("{} of the {} dates failed", failed, dates.len())),
+    }
+}
+
+/// Downloads one date, printing the candidate ranking when asked to
+async fn download_date(
+    args: &Args,
+    client: &dyn HttpClient,
+    date: NaiveDate,
+    config: &Config,
+    sinks: &[Arc<dyn Sink>],
+) -> Result<Download> {
+    let explain = args.explain.or(args.dry_run_upload.then_some(ExplainFormat::Table));
+    let client = Capture::new(client);
+    let result = if args.wait_for_publication {
+        retry::until_published(&config.retry, || {
+            crossword::download_crossword(&client, date, config, sinks.to_vec())
+        })
+        .await
+    } else {
+        crossword::download_crossword(&client, date, config, sinks.to_vec()).await
+    };
+
+    // Most useful when nothing matched, so print it whatever the outcome
+    if let Some(format) = explain {
+        print_explanation(args, &explain::render(&client.page_candidates(&config.target), format)?);
+    }
+    result
+}
+
+/// Prints the `--explain` report on standard output, or on standard error when that carries
+/// the run's JSON
+fn print_explanation(args: &Args, report: &str) {
+    match args.json() {
+        true => eprintln!("{}", report),
+        false => println!("{}", report),
+    }
+}
+
+/// Finds the crossword for `--dry-run` and prints where it is
+async fn resolve_date(args: &Args, client: &dyn HttpClient, date: NaiveDate, config: &Config) -> Result<()> {
+    let client = Capture::new(client);
+    let result = crossword::resolve_crossword(&client, date, config).await;
+    if let Some(format) = args.explain {
+        print_explanation(args, &explain::render(&client.page_candidates(&config.target), format)?);
+    }
+    let resolved = result?;
+
+    if args.json() {
+        println!("{}", serde_json::to_string_pretty(&resolved)?);
+    } else {
+        let mut out = args.text_output();
+        writeln!(out, "Crossword found on page {} (nothing was downloaded)", resolved.page)?;
+        for (crossword_url, image_url) in resolved.crossword_urls.iter().zip(&resolved.image_urls) {
+            writeln!(out, "Crossword URL: {}", crossword_url)?;
+            writeln!(out, "Image URL: {}", image_url)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_download(args: &Args, date: NaiveDate, download: Download, config: &Config) -> Result<()> {
+    if args.json() {
+        println!("{}", serde_json::to_string_pretty(&lambda::output(config, date, download))?);
+    } else {
+        let mut out = args.text_output();
+        writeln!(out, "Crossword downloaded successfully: {}", download.filename)?;
+        for warning in &download.warnings {
+            writeln!(out, "Warning: {}", warning)?;
+        }
+        if let Some(link) = &download.link {
+            writeln!(out, "Link, valid for {}s: {}", config.signed_url.expires_secs, link)?;
+        }
+        if config.message.is_some() {
+            writeln!(out, "{}", message::downloaded(config, date, &download))?;
+        } else if let Some(solver) = config.rotation.as_ref().and_then(|rotation| rotation.solver(date)) {
+            writeln!(out, "It's {}'s turn!", solver)?;
+        }
+        if let Some(protocol) = args.preview {
+            // The crossword is saved either way, so a terminal without graphics is no failure
+            let shown = std::fs::read(&download.filename)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| preview::render(&data, protocol, preview::MAX_WIDTH));
+            match shown {
+                Ok(escapes) => write!(out, "{}", escapes)?,
+                Err(e) => eprintln!("Could not show a preview: {:#}", e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One invocation, or a batch of them, as read by `--stdin`
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StdinInput {
+    One(LambdaInput),
+    Batch(Vec<LambdaInput>),
+}
+
+async fn run_stdin(args: &Args, config: Config) -> Result<()> {
+    console::keep_stdout_clean();
+    let mut input = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut input).context("Failed to read standard input")?;
+    let input: StdinInput = serde_json::from_str(&input).context("Standard input is not a Lambda event")?;
+
+    let client: Box<dyn HttpClient> = match (&args.record_http, &args.replay_http) {
+        (Some(path), _) => Box::new(Recorder::new(http::build_client(&config, config.clock.0.clone())?, path)),
+        (_, Some(path)) => Box::new(Replayer::load(path)?),
+        _ => http::build_client(&config, config.clock.0.clone())?,
+    };
+    let context = lambda::Context::new(client, config)?;
+
+    let mut failed = 0;
+    let output = match input {
+        StdinInput::One(input) => serde_json::to_value(lambda::handle(input, &context, None).await?)?,
+        StdinInput::Batch(inputs) => {
+            // A failed date doesn't stop the rest; it is reported in its place instead
+            let mut outputs = Vec::new();
+            for input in inputs {
+                let date = input.date.clone();
+                outputs.push(match lambda::handle(input, &context, None).await {
+                    Ok(output) => serde_json::to_value(output)?,
+                    Err(e) => {
+                        failed += 1;
+                        serde_json::json!({ "date": date, "error": error::hints::describe(&e) })
+                    }
+                });
+            }
+            serde_json::Value::Array(outputs)
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!("{} of the invocations failed", failed)),
+    }
+}
+
+async fn debug_bundle(date: Option<NaiveDate>, output: Option<PathBuf>, config: &Config) -> Result<()> {
+    let date = clock::resolve_date(date, &config.clock);
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("crossword-debug-{}.zip", date)));
+
+    let client = http::build_client(config, config.clock.0.clone())?;
+    diagnostics::create_bundle(client, date, config, &output).await?;
+    println!("Debug bundle written to {}", output.display());
+    Ok(())
+}
+
+async fn probe(date: Option<NaiveDate>, config: &Config) -> Result<()> {
+    let date = date.unwrap_or_else(|| clock::publication_today(&config.clock));
+    let client = http::build_client(config, config.clock.0.clone())?;
+
+    let steps = diagnostics::probe::probe(client.as_ref(), date, config).await;
+    for step in &steps {
+        match &step.outcome {
+            Ok(detail) => println!("✓ {}: {}", step.name, detail),
+            Err(error) => println!("✗ {}: {}", step.name, error),
+        }
+    }
+
+    let version = env!("CARGO_PKG_VERSION");
+    match steps.iter().find(|step| step.outcome.is_err()) {
+        Some(failed) => Err(anyhow::anyhow!(
+            "Could not confirm that version {} works with the site's edition of {}: '{}' failed",
+            version,
+            date,
+            failed.name
+        )),
+        None => {
+            println!("Version {} is compatible with the site's edition of {}", version, date);
+            Ok(())
+        }
+    }
+}
+
+fn generate_manpage(out_dir: &Path) -> Result<()> {
+    for path in manpage::write_all(&Args::command(), out_dir)? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+async fn compile_month(month: NaiveDate, format: ArchiveFormat, config: &Config) -> Result<()> {
+    let sinks = signed_url::sinks(config, false)?;
+    let path = archive::compile_month(config, month, format, sinks).await?;
+    println!("Monthly archive uploaded: {}", path.display());
+    Ok(())
+}
+
+fn stats(config: &Config) -> Result<()> {
+    let history = History::load(&History::path(&config.output_dir))?;
+    if history.pages.is_empty() {
+        println!("No matches recorded in {} yet", config.output_dir.display());
+    } else {
+        print!("{}", history.render_stats());
+    }
+    let runs = RunLog::load(&RunLog::path(&config.output_dir))?;
+    if !runs.runs.is_empty() {
+        print!("\n{}", runs.render_stats());
+    }
+    Ok(())
+}
+
+fn learn_profile(feature: Option<String>, window: usize, apply: bool, config: &Config, path: &Path) -> Result<()> {
+    let (region, profile) = match &feature {
+        Some(name) => {
+            let feature = config
+                .features
+                .get(name)
+                .with_context(|| format!("No feature named '{}' in the config", name))?;
+            (name.as_str(), &feature.profile)
+        }
+        None => ("crossword", &config.target),
+    };
+
+    let history = History::load(&History::path(&config.output_dir))?;
+    let Some(suggested) = history.suggest(region, window) else {
+        println!("Not enough recorded matches for the {} yet", region);
+        return Ok(());
+    };
+
+    let current = &profile.expected;
+    println!("Current {} profile:   ({},{},{},{})", region, current.x1, current.y1, current.x2, current.y2);
+    println!("Suggested {} profile: ({},{},{},{})", region, suggested.x1, suggested.y1, suggested.x2, suggested.y2);
+    if suggested == *current {
+        println!("The profile already matches recent pages");
+    } else if apply {
+        learning::apply_to_config(path, region, &suggested)?;
+        println!("Updated {}", path.display());
+    } else {
+        println!("Run again with --apply to write it to {}", path.display());
+    }
+    Ok(())
+}
+
+fn calendar(from: NaiveDate, to: Option<NaiveDate>, output: Option<PathBuf>, config: &Config) -> Result<()> {
+    let to = clock::resolve_date(to, &config.clock);
+    if from > to {
+        return Err(anyhow::anyhow!("The calendar starts on {} but ends earlier, on {}", from, to));
+    }
+    let output = output.unwrap_or_else(|| config.output_dir.join("crosswords.ics"));
+    std::fs::write(&output, calendar::render(config, from, to, config.clock.now().with_timezone(&Utc))?)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    println!("Calendar written to {}", output.display());
+    Ok(())
+}
+
+async fn backfill(from: NaiveDate, to: Option<NaiveDate>, output: OutputFormat, config: &Config) -> Result<()> {
+    let clock = config.clock.0.clone();
+    let to = clock::resolve_date(to, clock.as_ref());
+    check_date(to, false, clock.as_ref())?;
+    if from > to {
+        return Err(anyhow::anyhow!("Backfill starts on {} but ends earlier, on {}", from, to));
+    }
+
+    let client = backfill::Throttle::new(http::build_client(config, clock.clone())?, config.backfill.clone(), clock);
+    let sinks = signed_url::sinks(config, false)?;
+    preflight::run(config, &sinks).await?;
+    let summary = backfill::run(&client, from, to, config, sinks).await?;
+
+    println!("{}", status::render(&summary.rows, output)?);
+    if output == OutputFormat::Table {
+        println!(
+            "\nBackfill finished: {} downloaded, {} already archived, {} failed",
+            summary.downloaded,
+            summary.skipped,
+            summary.failed.len()
+        );
+        for (date, error) in &summary.failed {
+            println!("  {}: {}", date, error);
+        }
+    }
+    if summary.failed.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!("{} dates could not be backfilled; run again to retry them", summary.failed.len()))
+}
+
+fn status(from: NaiveDate, to: Option<NaiveDate>, output: OutputFormat, config: &Config) -> Result<()> {
+    let to = clock::resolve_date(to, &config.clock);
+    println!("{}", status::render(&status::rows(config, from, to)?, output)?);
+    Ok(())
+}
+
+fn export_state(format: ExportFormat, output: Option<PathBuf>, config: &Config) -> Result<()> {
+    let entries = status::export::manifest(config)?;
+    let rendered = status::export::render(&entries, format)?;
+    match output {
+        Some(output) => {
+            std::fs::write(&output, rendered).with_context(|| format!("Failed to write {}", output.display()))?;
+            println!("Exported {} dates to {}", entries.len(), output.display());
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn queue(action: QueueCommand, config: &Config) -> Result<()> {
+    match action {
+        QueueCommand::List { output } => {
+            println!("{}", status::queue::render(&status::queue::entries(config)?, output)?);
+        }
+        QueueCommand::Cancel { date } => {
+            let cancelled = status::queue::cancel(config, date)?;
+            if cancelled.is_empty() {
+                println!("Nothing is queued for {}", date);
+            }
+            for cancelled in cancelled {
+                println!("Cancelled {}", cancelled);
+            }
+        }
+        QueueCommand::Requeue { date } => match status::queue::requeue(config, date)? {
+            true => println!("The next backfill over {} will download it", date),
+            false => println!("{} is already left to the next backfill over it", date),
+        },
+    }
+    Ok(())
+}
+
+async fn repair(date: Option<NaiveDate>, config: &Config) -> Result<()> {
+    let date = clock::resolve_date(date, &config.clock);
+    let sinks = signed_url::sinks(config, false)?;
+    let filename = crossword::repair(date, config, sinks).await?;
+    println!("Crossword re-uploaded: {}", filename);
+    Ok(())
+}
+
+async fn approve(date: Option<NaiveDate>, config: &Config) -> Result<()> {
+    let date = clock::resolve_date(date, &config.clock);
+    let sinks = signed_url::sinks(config, false)?;
+    let filename = crossword::review::approve(date, config, sinks).await?;
+    println!("Crossword approved: {}", filename);
+    Ok(())
+}
+
+async fn audit(fix: bool, output: OutputFormat, config: &Config) -> Result<()> {
+    let client = http::build_client(config, config.clock.0.clone())?;
+    let sinks = signed_url::sinks(config, false)?;
+    let findings = status::audit::audit(client.as_ref(), config, &sinks, fix).await?;
+    println!("{}", status::audit::render(&findings, output)?);
+    match findings.iter().filter(|finding| finding.broken()).count() {
+        0 => Ok(()),
+        broken => Err(anyhow::anyhow!(
+            "{} copies are missing or damaged{}",
+            broken,
+            if fix { "" } else { "; run `audit --fix` to put them back" }
+        )),
+    }
+}
+
+async fn dedupe_drive(dry_run: bool, output: OutputFormat, config: &Config) -> Result<()> {
+    if !config.uploads_to_drive() {
+        return Err(anyhow::anyhow!(
+            "Nothing is uploaded to Google Drive with this config, so there is nothing to dedupe"
+        ));
+    }
+    let drive = drive::DriveSink::new(config)?;
+    let rows = status::dedupe::dedupe(config, &drive, dry_run).await?;
+    println!("{}", status::dedupe::render(&rows, output)?);
+    match rows.iter().filter(|row| row.action == "failed").count() {
+        0 => Ok(()),
+        failed => Err(anyhow::anyhow!("{} duplicates could not be trashed", failed)),
+    }
+}
+
+async fn redetect(
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+    dry_run: bool,
+    output: OutputFormat,
+    config: &Config,
+) -> Result<()> {
+    let raw = config
+        .raw_archive
+        .as_ref()
+        .context("redetect needs the pages kept by [raw_archive]; add the section to config.toml")?;
+    let to = clock::resolve_date(to, &config.clock);
+    if from > to {
+        return Err(anyhow::anyhow!("The range starts on {} but ends earlier, on {}", from, to));
+    }
+    let sinks = signed_url::sinks(config, dry_run)?;
+    let rows = redetect::run(config, raw, from, to, sinks, dry_run).await?;
+    println!("{}", redetect::render(&rows, output)?);
+    match rows.iter().filter(|row| row.outcome == "failed").count() {
+        0 => Ok(()),
+        failed => Err(anyhow::anyhow!("{} dates could not be redetected", failed)),
+    }
+}
+
+fn check(mine: &Path, solution: &Path, size: u32, output: &Path) -> Result<()> {
+    let differences = grading::check_files(mine, solution, size, output)?;
+    if differences.is_empty() {
+        println!("Every cell matches the solution");
+    } else {
+        println!("{} cells differ from the solution:", differences.len());
+        for (row, column) in &differences {
+            println!("  row {}, column {}", row, column);
+        }
+    }
+    println!("Marked grid written to {}", output.display());
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
-    
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .with_target(false)
-        .without_time()
-        .init();
-
-    run(service_fn(handler)).await
+
+    // The Lambda runtime sets this; anywhere else we run once from the command line
+    if std::env::var("AWS_LAMBDA_RUNTIME_API").is_ok() {
+        telemetry::init(telemetry::lambda_level(|key| std::env::var(key).ok()));
+        // Report every configuration problem before any network calls
+        let profile = std::env::var(config::PROFILE_ENV).ok();
+        let config = Config::load(None, profile.as_deref())?;
+        config.validate().map_err(|e| error::hints::describe(&e))?;
+        storage::migrations::run(&config.output_dir).map_err(|e| error::hints::describe(&e))?;
+        let client = http::build_client(&config, config.clock.0.clone())?;
+        let context = lambda::Context::new(client, config)?;
+        let context = &context;
+        run(service_fn(move |event: LambdaEvent<LambdaInput>| async move {
+            let deadline = UNIX_EPOCH + Duration::from_millis(event.context.deadline);
+            let result = lambda::handle(event.payload, context, Some(deadline)).await;
+            // The execution environment may be frozen as soon as we return
+            telemetry::flush();
+            result.map_err(|e| Error::from(error::hints::describe(&e)))
+        }))
+        .await
+    } else {
+        let args = Args::parse();
+        // A JSON or CSV report is for other tools, so progress goes to standard error
+        let output = args.command.as_ref().and_then(Command::output);
+        let report = output.is_some_and(|output| output != OutputFormat::Table);
+        let run_report = args.command.is_none() && (args.stdin || args.clean_stdout());
+        if report || run_report {
+            console::keep_stdout_clean();
+        }
+        telemetry::init(telemetry::cli_level(args.verbose, args.quiet));
+        // There may be no config to load yet
+        if let Some(Command::Init) = args.command {
+            let path = args.config.unwrap_or_else(|| PathBuf::from(config::DEFAULT_CONFIG_FILE));
+            let mut prompt = setup::Prompt::new(std::io::stdin().lock(), std::io::stdout());
+            setup::run(&mut prompt, &setup::DriveFolders, &path).await?;
+            return Ok(());
+        }
+        let config = Config::load(args.config.as_deref(), args.profile.as_deref())?;
+        // Upgrade the state files before anything reads them
+        let uses_state = !matches!(
+            args.command,
+            Some(Command::StoreCredentials { .. } | Command::Check { .. } | Command::GenerateManpage { .. })
+        );
+        let migrated = match uses_state {
+            true => storage::migrations::run(&config.output_dir),
+            false => Ok(()),
+        };
+        let result = match args.command {
+            _ if migrated.is_err() => migrated,
+            Some(Command::Init) => unreachable!("init runs before any config is loaded"),
+            Some(Command::VerifyCredentials) => {
+                // Unrelated config problems shouldn't hide which credential step fails
+                if let Err(e) = config.validate() {
+                    tracing::warn!("{}", e);
+                }
+                verify_credentials(&config).await
+            }
+            Some(Command::StoreCredentials { file, telegram_token, smtp_password: _, force }) => {
+                store_credentials(file.as_deref(), telegram_token, force)
+            }
+            Some(Command::DebugBundle { date, output }) => debug_bundle(date, output, &config).await,
+            Some(Command::Probe { date }) => probe(date, &config).await,
+            Some(Command::CompileMonth { month, format }) => match config.validate() {
+                Ok(()) => compile_month(month, format, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::LearnProfile { feature, window, apply }) => {
+                let path = args.config.clone().unwrap_or_else(|| PathBuf::from(config::DEFAULT_CONFIG_FILE));
+                learn_profile(feature, window, apply, &config, &path)
+            }
+            Some(Command::Stats) => stats(&config),
+            Some(Command::Calendar { from, to, output }) => calendar(from, to, output, &config),
+            Some(Command::Backfill { from, to, output }) => match config.validate() {
+                Ok(()) => backfill(from, to, output, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::Status { from, to, output }) => status(from, to, output, &config),
+            Some(Command::State { action: StateCommand::Export { format, output } }) => {
+                export_state(format, output, &config)
+            }
+            Some(Command::Queue { action }) => queue(action, &config),
+            Some(Command::Repair { date }) => match config.validate() {
+                Ok(()) => repair(date, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::Approve { date }) => match config.validate() {
+                Ok(()) => approve(date, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::Audit { fix, output }) => match config.validate() {
+                Ok(()) => audit(fix, output, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::DedupeDrive { dry_run, output }) => match config.validate() {
+                Ok(()) => dedupe_drive(dry_run, output, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::Redetect { from, to, dry_run, output }) => match config.validate() {
+                Ok(()) => redetect(from, to, dry_run, output, &config).await,
+                Err(e) => Err(e),
+            },
+            Some(Command::Check { mine, solution, size, output }) => {
+                check(&mine, &solution, size, &output)
+            }
+            Some(Command::GenerateManpage { out_dir }) => generate_manpage(&out_dir),
+            Some(Command::Browse) => browse::run(&config).await,
+            None if args.stdin => match config.validate() {
+                Ok(()) => run_stdin(&args, config).await,
+                Err(e) => Err(e),
+            },
+            None => match config.validate() {
+                Ok(()) => run_cli(args, &config).await,
+                Err(e) => Err(e),
+            },
+        };
+        telemetry::shutdown();
+        if let Err(e) = result {
+            eprintln!("Error: {:?}", e);
+            for hint in error::hints::hints(&e) {
+                eprintln!("Hint: {}", hint);
+            }
+            std::process::exit(1);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,166 @@
+//! Man pages, shell completions and a JSON description of the command line, generated from the
+//! clap definition so packages (Homebrew, deb) can ship documentation that never drifts from
+//! the binary.
+
+use anyhow::{Context, Result};
+use clap::{Arg, Command};
+use clap_complete::Shell;
+use clap_mangen::Man;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File the JSON description is written to, next to the man pages
+pub const METADATA_FILE: &str = "cli.json";
+
+/// Writes a man page for the command and every subcommand, named like git's
+/// (`<name>-<subcommand>.1`), bash, zsh and fish completions, and [`METADATA_FILE`], into `dir`
+pub fn write_all(command: &Command, dir: &Path) -> Result<Vec<PathBuf>> {
+    // No page or completions for clap's own `help` subcommand
+    let mut command = command.clone().disable_help_subcommand(true);
+    command.build();
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let mut written = Vec::new();
+    write_pages(&command, dir, &mut written)?;
+    let name = command.get_name().to_string();
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        let path = clap_complete::generate_to(shell, &mut command, &name, dir)
+            .with_context(|| format!("Failed to write the {} completions into {}", shell, dir.display()))?;
+        written.push(path);
+    }
+    let path = dir.join(METADATA_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&metadata(&command))?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    written.push(path);
+    Ok(written)
+}
+
+/// Writes the pages of `command` and its subcommands, the command's own first
+fn write_pages(command: &Command, dir: &Path, written: &mut Vec<PathBuf>) -> Result<()> {
+    let path = Man::new(command.clone())
+        .generate_to(dir)
+        .with_context(|| format!("Failed to write the man page of {} into {}", command.get_name(), dir.display()))?;
+    written.push(path);
+    for subcommand in visible_subcommands(command) {
+        write_pages(subcommand, dir, written)?;
+    }
+    Ok(())
+}
+
+/// Every subcommand, option and positional argument with its help, values and defaults
+pub fn metadata(command: &Command) -> Value {
+    let arguments: Vec<Value> = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .map(|arg| {
+            json!({
+                "id": arg.get_id().as_str(),
+                "short": arg.get_short().map(String::from),
+                "long": arg.get_long(),
+                "positional": arg.is_positional(),
+                "required": arg.is_required_set(),
+                "takes_value": arg.get_action().takes_values(),
+                "value_name": arg.get_action().takes_values().then(|| value_name(arg)),
+                "possible_values": arg
+                    .get_possible_values()
+                    .iter()
+                    .filter(|value| !value.is_hide_set())
+                    .map(|value| value.get_name().to_string())
+                    .collect::<Vec<_>>(),
+                "default": arg.get_default_values().first().map(|value| value.to_string_lossy().into_owned()),
+                "help": help(arg),
+            })
+        })
+        .collect();
+    json!({
+        "name": command.get_name(),
+        "version": command.get_version(),
+        "about": about(command),
+        "arguments": arguments,
+        "subcommands": visible_subcommands(command).map(metadata).collect::<Vec<_>>(),
+    })
+}
+
+fn visible_subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+    // `help` is clap's own, and documented by every page anyway
+    command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+fn about(command: &Command) -> String {
+    command.get_about().map(|about| about.to_string()).unwrap_or_default()
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_long_help()
+        .or(arg.get_help())
+        .map(|help| help.to_string())
+        .unwrap_or_default()
+}
+
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some([name, ..]) => name.to_string(),
+        _ => arg.get_id().as_str().to_uppercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{value_parser, Arg, ArgAction};
+
+    fn command() -> Command {
+        Command::new("crossword")
+            .version("1.2.3")
+            .about("Downloads the crossword")
+            .arg(Arg::new("date").short('d').long("date").help("Date of the edition"))
+            .arg(Arg::new("dry-run").long("dry-run").action(ArgAction::SetTrue).help("Upload nothing"))
+            .subcommand(
+                Command::new("backfill")
+                    .about("Download a range of dates")
+                    .arg(Arg::new("from").required(true).help("First date"))
+                    .arg(
+                        Arg::new("output")
+                            .long("output")
+                            .value_parser(["table", "json"])
+                            .default_value("table")
+                            .help("How to print results"),
+                    )
+                    .arg(Arg::new("limit").long("limit").value_parser(value_parser!(u32)).hide(true)),
+            )
+    }
+
+    #[test]
+    fn test_write_all() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let written = write_all(&command(), dir.path()).unwrap();
+        let names: Vec<String> = written
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["crossword.1", "crossword-backfill.1", "crossword.bash", "_crossword", "crossword.fish", "cli.json"]
+        );
+
+        let page = fs::read_to_string(dir.path().join("crossword.1")).unwrap();
+        assert!(page.contains("crossword\\-backfill(1)"));
+        let page = fs::read_to_string(dir.path().join("crossword-backfill.1")).unwrap();
+        assert!(page.contains("crossword\\-backfill \\- Download a range of dates"));
+        assert!(page.contains("\\fIPossible values:\\fR"));
+        assert!(!page.contains("limit"));
+        let bash = fs::read_to_string(dir.path().join("crossword.bash")).unwrap();
+        assert!(bash.contains("--dry-run"));
+
+        let metadata: Value = serde_json::from_str(&fs::read_to_string(dir.path().join(METADATA_FILE)).unwrap()).unwrap();
+        assert_eq!(metadata["version"], "1.2.3");
+        let backfill = &metadata["subcommands"][0];
+        assert_eq!(backfill["name"], "backfill");
+        let output = backfill["arguments"].as_array().unwrap().iter().find(|arg| arg["id"] == "output").unwrap();
+        assert_eq!(output["possible_values"], json!(["table", "json"]));
+        assert_eq!(output["default"], "table");
+    }
+}
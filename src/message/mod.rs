@@ -0,0 +1,286 @@
+//! The message announcing a downloaded crossword, as the run's `message` and on Telegram,
+//! rendered from a template the household can edit or have in Hindi or Marathi.
+//!
+//! Templates fill in `{date}`, `{weekday}`, `{link}`, `{solver}` and `{filename}`; `{{` and
+//! `}}` stand for braces. A line naming a variable the run has no value for, like `{solver}`
+//! without a `[rotation]`, is left out, so one template fits runs with and without it.
+
+use anyhow::Result;
+use chrono::{Locale, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::crossword::Download;
+use crate::report;
+
+/// Variables templates can use
+pub const VARIABLES: [&str; 5] = ["date", "weekday", "link", "solver", "filename"];
+
+/// Language of a built-in template, which also names the days and months
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Hi,
+    Mr,
+}
+
+impl Language {
+    fn template(self) -> &'static str {
+        match self {
+            Language::En => "The crossword of {weekday}, {date} is ready\n{link}\nIt's {solver}'s turn!",
+            Language::Hi => "{weekday}, {date} की वर्ग पहेली तैयार है\n{link}\nआज {solver} की बारी है!",
+            Language::Mr => "{weekday}, {date} चे शब्दकोडे तयार आहे\n{link}\nआज {solver} ची पाळी आहे!",
+        }
+    }
+
+    fn locale(self) -> Locale {
+        match self {
+            Language::En => Locale::en_IN,
+            Language::Hi => Locale::hi_IN,
+            Language::Mr => Locale::mr_IN,
+        }
+    }
+}
+
+/// The `[message]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageConfig {
+    /// `en`, `hi` or `mr`: the built-in template, and the language of `{date}` and `{weekday}`
+    pub language: Language,
+    /// A template of your own instead of the built-in one
+    pub template: Option<String>,
+    /// strftime pattern for `{date}`
+    pub date_format: String,
+}
+
+impl Default for MessageConfig {
+    fn default() -> Self {
+        Self {
+            language: Language::default(),
+            template: None,
+            date_format: "%-d %B %Y".to_string(),
+        }
+    }
+}
+
+impl MessageConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let template = self.template();
+        let mut problems: Vec<String> = match template.lines().map(placeholders).collect::<Result<Vec<_>>>() {
+            Ok(lines) => lines
+                .into_iter()
+                .flatten()
+                .filter(|name| !VARIABLES.contains(&name.as_str()))
+                .map(|name| format!("[message] template uses {{{}}}, which is not one of {}", name, VARIABLES.join(", ")))
+                .collect(),
+            Err(e) => vec![format!("[message] template: {}", e)],
+        };
+        if template.trim().is_empty() {
+            problems.push("[message] template is empty".to_string());
+        }
+        problems
+    }
+
+    fn template(&self) -> &str {
+        self.template.as_deref().unwrap_or(self.language.template())
+    }
+}
+
+/// What a message is about; unset values drop the lines that use them
+pub struct Announcement<'a> {
+    pub date: NaiveDate,
+    pub filename: &'a str,
+    pub link: Option<&'a str>,
+    pub solver: Option<&'a str>,
+}
+
+/// The message for `announcement`, from `config`'s template
+pub fn render(config: &MessageConfig, announcement: &Announcement) -> Result<String> {
+    let locale = config.language.locale();
+    let value = |name: &str| -> Option<String> {
+        match name {
+            "date" => Some(announcement.date.format_localized(&config.date_format, locale).to_string()),
+            "weekday" => Some(announcement.date.format_localized("%A", locale).to_string()),
+            "link" => announcement.link.map(str::to_string),
+            "solver" => announcement.solver.map(str::to_string),
+            "filename" => Some(announcement.filename.to_string()),
+            _ => None,
+        }
+    };
+
+    let mut lines = Vec::new();
+    'lines: for line in config.template().lines() {
+        let mut rendered = String::new();
+        for part in parse(line)? {
+            match part {
+                Part::Text(text) => rendered.push_str(&text),
+                Part::Variable(name) => match value(&name) {
+                    Some(value) => rendered.push_str(&value),
+                    None => continue 'lines,
+                },
+            }
+        }
+        lines.push(rendered);
+    }
+    Ok(lines.join("\n"))
+}
+
+/// The run's message for the crossword downloaded for `date`: from the `[message]` template
+/// when there is one, a plain English line otherwise. A crossword held for review says so
+/// on a line of its own, whatever the template.
+pub fn downloaded(config: &Config, date: NaiveDate, download: &Download) -> String {
+    let text = announcement(config, date, download);
+    match download.confidence.filter(|_| download.needs_review) {
+        Some(score) => format!(
+            "{}\nNeeds review: detection is only {}% sure this is the crossword, so it went to the review folder",
+            text, score
+        ),
+        None => text,
+    }
+}
+
+fn announcement(config: &Config, date: NaiveDate, download: &Download) -> String {
+    let solver = config.rotation.as_ref().and_then(|rotation| rotation.solver(date));
+    let plain = match solver {
+        Some(solver) => format!("Crossword downloaded successfully; it's {}'s turn!", solver),
+        None => "Crossword downloaded successfully".to_string(),
+    };
+    let Some(message) = &config.message else {
+        return plain;
+    };
+    let link = download
+        .link
+        .clone()
+        .or_else(|| download.uploads.iter().find_map(|(sink, id)| report::file_url(sink, id)));
+    let announcement = Announcement {
+        date,
+        filename: &download.filename,
+        link: link.as_deref(),
+        solver,
+    };
+    render(message, &announcement).unwrap_or_else(|e| {
+        tracing::warn!("Falling back to the plain message: {:#}", e);
+        plain
+    })
+}
+
+enum Part {
+    Text(String),
+    Variable(String),
+}
+
+/// Splits a template line into text and `{variable}`s
+fn parse(line: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if !closed || name.trim().is_empty() || name.contains('{') {
+                    return Err(anyhow::anyhow!("'{}' has an unclosed or empty {{}}", line));
+                }
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Variable(name.trim().to_string()));
+            }
+            '}' => return Err(anyhow::anyhow!("'{}' has a }} without a {{; write }}}} for a brace", line)),
+            c => text.push(c),
+        }
+    }
+    parts.push(Part::Text(text));
+    Ok(parts)
+}
+
+fn placeholders(line: &str) -> Result<Vec<String>> {
+    Ok(parse(line)?
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Variable(name) => Some(name),
+            Part::Text(_) => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announcement(solver: Option<&'static str>) -> Announcement<'static> {
+        Announcement {
+            date: NaiveDate::from_ymd_opt(2024, 3, 20).unwrap(),
+            filename: "crossword_2024-03-20.jpg",
+            link: Some("https://drive.google.com/file/d/file-id/view"),
+            solver,
+        }
+    }
+
+    #[test]
+    fn test_built_in_templates() {
+        let english = MessageConfig::default();
+        assert_eq!(
+            render(&english, &announcement(Some("Priya"))).unwrap(),
+            "The crossword of Wednesday, 20 March 2024 is ready\n\
+             https://drive.google.com/file/d/file-id/view\n\
+             It's Priya's turn!"
+        );
+        // Without a rotation, the line about whose turn it is goes
+        assert_eq!(render(&english, &announcement(None)).unwrap().lines().count(), 2);
+
+        let marathi = MessageConfig {
+            language: Language::Mr,
+            ..MessageConfig::default()
+        };
+        let message = render(&marathi, &announcement(Some("Aai"))).unwrap();
+        assert!(message.starts_with("बुधवार, 20 मार्च 2024 चे शब्दकोडे"), "{}", message);
+        assert!(message.ends_with("आज Aai ची पाळी आहे!"));
+        for language in [Language::En, Language::Hi, Language::Mr] {
+            assert!(MessageConfig { language, ..MessageConfig::default() }.validate().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_own_template() {
+        let config: MessageConfig = toml::from_str(
+            r#"
+            template = """
+            {{{weekday}}} {filename}
+            Solved by {solver}"""
+            date_format = "%d/%m"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_empty());
+        assert_eq!(render(&config, &announcement(None)).unwrap().trim(), "{Wednesday} crossword_2024-03-20.jpg");
+
+        let typo = MessageConfig {
+            template: Some("Ready: {lnk}".to_string()),
+            ..MessageConfig::default()
+        };
+        assert_eq!(typo.validate().len(), 1);
+        let unclosed = MessageConfig {
+            template: Some("Ready: {link".to_string()),
+            ..MessageConfig::default()
+        };
+        assert!(unclosed.validate()[0].contains("unclosed"));
+    }
+}
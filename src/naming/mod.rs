@@ -0,0 +1,184 @@
+use anyhow::Result;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Locale, NaiveDate};
+
+use crate::types::PuzzleInfo;
+
+/// Template reproducing the original `crossword_2024-03-20` names
+pub const DEFAULT_TEMPLATE: &str = "crossword_%Y-%m-%d";
+
+/// Puzzle details a template can name besides the date, read off the image with `[ocr]`
+pub const PUZZLE_PLACEHOLDERS: [&str; 2] = ["number", "setter"];
+
+/// Characters dropped along with a placeholder that has no value, like the `_#` of
+/// `crossword_#{number}`
+const SEPARATORS: [char; 5] = ['_', '-', '#', ' ', '.'];
+
+/// Parses a locale name such as `en_US`, `hi_IN` or `mr_IN`
+pub fn parse_locale(name: &str) -> Result<Locale> {
+    Locale::try_from(name).map_err(|_| anyhow::anyhow!("Unknown locale '{}'", name))
+}
+
+/// Checks that `template` is a valid strftime pattern producing a plain file name
+pub fn validate_template(template: &str) -> Result<()> {
+    if template.trim().is_empty() {
+        return Err(anyhow::anyhow!("Filename template is empty"));
+    }
+    if StrftimeItems::new(template).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow::anyhow!("Filename template '{}' has an invalid % specifier", template));
+    }
+    if template.contains(['/', '\\']) {
+        return Err(anyhow::anyhow!("Filename template '{}' must not contain path separators", template));
+    }
+    for part in template.split('{').skip(1) {
+        let name = part.split_once('}').map(|(name, _)| name);
+        if !name.is_some_and(|name| PUZZLE_PLACEHOLDERS.contains(&name)) {
+            return Err(anyhow::anyhow!(
+                "Filename template '{}' can only name {{number}} and {{setter}} in braces",
+                template
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Renders the file name for `date`, with weekday and month names in `locale`.
+///
+/// The template uses strftime specifiers, so `Crossword_%A_%d-%b-%Y` becomes
+/// `Crossword_Wednesday_20-Mar-2024.jpg` in `en_US`.
+pub fn render_filename(template: &str, locale: Locale, date: NaiveDate, extension: &str) -> Result<String> {
+    render_puzzle_filename(template, locale, date, extension, &PuzzleInfo::default())
+}
+
+/// Like [`render_filename`], also filling in `{number}` and `{setter}` from `puzzle`. One
+/// that wasn't read is left out with the separators before it, so `crossword_#{number}_%Y`
+/// still makes a tidy `crossword_2024`.
+pub fn render_puzzle_filename(
+    template: &str,
+    locale: Locale,
+    date: NaiveDate,
+    extension: &str,
+    puzzle: &PuzzleInfo,
+) -> Result<String> {
+    validate_template(template)?;
+    // Filled in after the date, so a `%` in a setter's name is kept as it is
+    let stem = date.format_localized(template, locale).to_string();
+    let mut filled = String::new();
+    let mut rest = stem.as_str();
+    while let Some((before, after)) = rest.split_once('{') {
+        let (name, after) = after.split_once('}').unwrap_or((after, ""));
+        filled.push_str(before);
+        rest = after;
+        let value = match name {
+            "number" => puzzle.number.map(|number| number.to_string()),
+            _ => puzzle.setter.as_deref().map(file_safe),
+        };
+        match value {
+            Some(value) => filled.push_str(&value),
+            None => {
+                filled.truncate(filled.trim_end_matches(SEPARATORS).len());
+                // At the start, the separators after it go instead
+                if filled.is_empty() {
+                    rest = rest.trim_start_matches(SEPARATORS);
+                }
+            }
+        }
+    }
+    filled.push_str(rest);
+    Ok(format!("{}.{}", filled.trim(), extension))
+}
+
+/// The first `YYYY-MM-DD` date in a file name, as the default template writes it
+pub fn date_in(name: &str) -> Option<NaiveDate> {
+    // Checked digit by digit first, as chrono would also take ` 2024-03-2` for the 2nd
+    let shaped = |candidate: &str| {
+        let dash = |i: usize| i == 4 || i == 7;
+        candidate.bytes().enumerate().all(|(i, b)| if dash(i) { b == b'-' } else { b.is_ascii_digit() })
+    };
+    (0..name.len().saturating_sub(9))
+        .filter_map(|start| name.get(start..start + 10))
+        .filter(|candidate| shaped(candidate))
+        .find_map(|candidate| NaiveDate::parse_from_str(candidate, "%Y-%m-%d").ok())
+}
+
+/// A setter's name as part of a file name, without characters file systems refuse
+fn file_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '-' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 21).unwrap()
+    }
+
+    #[test]
+    fn test_default_template_matches_original_names() {
+        let name = render_filename(DEFAULT_TEMPLATE, Locale::en_US, date(), "jpg").unwrap();
+        assert_eq!(name, "crossword_2024-03-21.jpg");
+    }
+
+    #[test]
+    fn test_weekday_and_month_names() {
+        let name = render_filename("Crossword_%A_%d-%b-%Y", Locale::en_US, date(), "jpg").unwrap();
+        assert_eq!(name, "Crossword_Thursday_21-Mar-2024.jpg");
+    }
+
+    #[test]
+    fn test_localized_names() {
+        let hindi = render_filename("%A_%B", parse_locale("hi_IN").unwrap(), date(), "jpg").unwrap();
+        assert_eq!(hindi, "गुरुवार_मार्च.jpg");
+
+        let marathi = render_filename("%A", parse_locale("mr_IN").unwrap(), date(), "png").unwrap();
+        assert_eq!(marathi, "गुरुवार.png");
+    }
+
+    #[test]
+    fn test_puzzle_placeholders() {
+        let template = "crossword_#{number}_%Y-%m-%d";
+        let puzzle = PuzzleInfo {
+            number: Some(10234),
+            setter: Some("A/B Singh".to_string()),
+        };
+        let name = render_puzzle_filename(template, Locale::en_US, date(), "jpg", &puzzle).unwrap();
+        assert_eq!(name, "crossword_#10234_2024-03-21.jpg");
+        let name = render_puzzle_filename("{setter} {number}", Locale::en_US, date(), "jpg", &puzzle).unwrap();
+        assert_eq!(name, "A-B Singh 10234.jpg");
+
+        // What wasn't read is left out, with its separators
+        let unread = PuzzleInfo::default();
+        let name = render_puzzle_filename(template, Locale::en_US, date(), "jpg", &unread).unwrap();
+        assert_eq!(name, "crossword_2024-03-21.jpg");
+        let name = render_puzzle_filename("#{number}_%Y", Locale::en_US, date(), "jpg", &unread).unwrap();
+        assert_eq!(name, "2024.jpg");
+    }
+
+    #[test]
+    fn test_date_in_name() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20);
+        assert_eq!(date_in("crossword_2024-03-20.jpg"), date);
+        assert_eq!(date_in("2024-03-20.pdf"), date);
+        assert_eq!(date_in("Crossword 2024-03-20.jpg"), date);
+        assert_eq!(date_in("Crossword_Wednesday_20-Mar-2024.jpg"), None);
+    }
+
+    #[test]
+    fn test_parse_locale_rejects_unknown() {
+        assert!(parse_locale("xx_YY").is_err());
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("crossword_%Y-%m-%d").is_ok());
+        assert!(validate_template("").is_err());
+        assert!(validate_template("crossword_%Q").is_err());
+        assert!(validate_template("%Y/%m/crossword").is_err());
+        assert!(validate_template("crossword_{number}_{setter}").is_ok());
+        assert!(validate_template("crossword_{date}").is_err());
+        assert!(validate_template("crossword_{number").is_err());
+    }
+}
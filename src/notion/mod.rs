@@ -0,0 +1,163 @@
+//! Keeping a row per date in a Notion database, for people who track which puzzles they
+//! have solved there. Each run creates the date's page, or updates its status and link;
+//! the "solved" checkbox is only set when the page is created, so ticking it sticks.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::console;
+use crate::crossword::{self, Download};
+
+const API_URL: &str = "https://api.notion.com/v1";
+
+/// API version the request bodies are written for
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// Environment variable holding the integration's secret
+pub const TOKEN_ENV: &str = "NOTION_TOKEN";
+
+/// The `[notion]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotionConfig {
+    /// ID of the database, from its URL; the integration must be added to it
+    pub database_id: String,
+    #[serde(default)]
+    pub properties: NotionProperties,
+}
+
+/// Names of the database's columns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotionProperties {
+    pub title: String,
+    pub date: String,
+    /// A select column
+    pub status: String,
+    /// A URL column
+    pub link: String,
+    /// A checkbox column
+    pub solved: String,
+}
+
+impl Default for NotionProperties {
+    fn default() -> Self {
+        Self {
+            title: "Name".to_string(),
+            date: "Date".to_string(),
+            status: "Status".to_string(),
+            link: "Link".to_string(),
+            solved: "Solved".to_string(),
+        }
+    }
+}
+
+impl NotionConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.database_id.is_empty() {
+            problems.push("[notion] database_id is empty".to_string());
+        }
+        if std::env::var(TOKEN_ENV).is_err() {
+            problems.push(format!("[notion] needs the integration secret in {}", TOKEN_ENV));
+        }
+        problems
+    }
+}
+
+/// Properties of the date's page; `solved` is only included for a new page
+fn page_properties(notion: &NotionConfig, date: NaiveDate, status: &str, link: Option<&str>, new: bool) -> Value {
+    let names = &notion.properties;
+    let mut properties = json!({
+        names.title.as_str(): { "title": [{ "text": { "content": format!("Crossword {}", date.format("%-d %B %Y")) } }] },
+        names.date.as_str(): { "date": { "start": date.to_string() } },
+        names.status.as_str(): { "select": { "name": status } },
+        names.link.as_str(): { "url": link },
+    });
+    if new {
+        properties[names.solved.as_str()] = json!({ "checkbox": false });
+    }
+    properties
+}
+
+/// Creates or updates the page for `date` with how its run ended
+pub async fn record(config: &Config, notion: &NotionConfig, date: NaiveDate, result: &Result<Download>) -> Result<()> {
+    let token = std::env::var(TOKEN_ENV).with_context(|| format!("{} is not set", TOKEN_ENV))?;
+    let client = reqwest::Client::new();
+    let request = |method: reqwest::Method, path: String, body: &Value| {
+        client
+            .request(method, format!("{}/{}", API_URL, path))
+            .bearer_auth(&token)
+            .header("Notion-Version", NOTION_VERSION)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+    };
+
+    let query = json!({ "filter": { "property": notion.properties.date, "date": { "equals": date.to_string() } } });
+    let found = request(reqwest::Method::POST, format!("databases/{}/query", notion.database_id), &query)
+        .await?
+        .error_for_status()
+        .context("Notion refused to query the database")?
+        .text()
+        .await?;
+    let found: Value = serde_json::from_str(&found).context("Notion's response is not JSON")?;
+    let existing = found["results"][0]["id"].as_str();
+
+    let status = crossword::status(result);
+    let link = crossword::link(config, date, result);
+    let response = match existing {
+        Some(page_id) => {
+            let properties = page_properties(notion, date, status, link.as_deref(), false);
+            request(reqwest::Method::PATCH, format!("pages/{}", page_id), &json!({ "properties": properties })).await?
+        }
+        None => {
+            let properties = page_properties(notion, date, status, link.as_deref(), true);
+            // Shows the crossword right on the page
+            let children: Vec<Value> = link
+                .iter()
+                .map(|url| json!({ "object": "block", "type": "embed", "embed": { "url": url } }))
+                .collect();
+            let page = json!({
+                "parent": { "database_id": notion.database_id },
+                "properties": properties,
+                "children": children,
+            });
+            request(reqwest::Method::POST, "pages".to_string(), &page).await?
+        }
+    };
+    response.error_for_status().context("Notion refused to save the page")?;
+    console::progress!("Recorded {} as {} in Notion", date, status);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notion() -> NotionConfig {
+        toml::from_str(r#"database_id = "0123456789abcdef0123456789abcdef""#).unwrap()
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 20).unwrap()
+    }
+
+    #[test]
+    fn test_page_properties() {
+        let properties = page_properties(&notion(), date(), "Downloaded", Some("https://example.com/x"), true);
+        assert_eq!(properties["Name"]["title"][0]["text"]["content"], "Crossword 20 March 2024");
+        assert_eq!(properties["Date"]["date"]["start"], "2024-03-20");
+        assert_eq!(properties["Status"]["select"]["name"], "Downloaded");
+        assert_eq!(properties["Link"]["url"], "https://example.com/x");
+        assert_eq!(properties["Solved"]["checkbox"], false);
+
+        // Updating leaves the checkbox as the solver set it
+        let properties = page_properties(&notion(), date(), "Failed", None, false);
+        assert!(properties.get("Solved").is_none());
+        assert!(properties["Link"]["url"].is_null());
+    }
+}
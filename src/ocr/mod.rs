@@ -0,0 +1,154 @@
+//! Reads the crossword's header line, e.g. "CROSSWORD No. 10234 · Set by A. Kulkarni", for
+//! its running number and setter, so the archive can be searched by puzzle number. The text
+//! comes from an installed Tesseract, run on just the top strip of the image.
+
+use anyhow::{Context, Result};
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::types::PuzzleInfo;
+
+/// Words that come right before the puzzle number
+const NUMBER_LABELS: [&str; 4] = ["no", "number", "crossword", "puzzle"];
+
+/// Words that come right before the setter's name, lowercased
+const SETTER_LABELS: [&str; 4] = ["set by", "compiled by", "setter", "by"];
+
+/// The `[ocr]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcrConfig {
+    /// Share of the image's height, from the top, that holds the header line
+    pub header_height: f64,
+    /// Tesseract language(s), e.g. `eng` or `eng+hin`
+    pub language: String,
+    /// The `tesseract` command, when it isn't on `PATH`
+    pub program: PathBuf,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            header_height: 0.12,
+            language: "eng".to_string(),
+            program: PathBuf::from("tesseract"),
+        }
+    }
+}
+
+impl OcrConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !(self.header_height > 0.0 && self.header_height <= 1.0) {
+            problems.push(format!("[ocr] header_height must be above 0 and at most 1, not {}", self.header_height));
+        }
+        if self.language.trim().is_empty() {
+            problems.push("[ocr] language is empty".to_string());
+        }
+        problems
+    }
+}
+
+/// The number and setter on the header of the crossword image `data`
+pub fn read_header(config: &OcrConfig, data: &[u8]) -> Result<PuzzleInfo> {
+    let image = image::load_from_memory(data).context("Failed to decode the crossword image")?;
+    let height = ((f64::from(image.height()) * config.header_height).ceil() as u32).clamp(1, image.height());
+    let header = image.crop_imm(0, 0, image.width(), height);
+
+    let dir = tempfile::tempdir().context("Failed to create a directory for OCR")?;
+    let input = dir.path().join("header.png");
+    header.save_with_format(&input, ImageFormat::Png)?;
+    // A single block of text, which suits one or two header lines
+    let output = Command::new(&config.program)
+        .arg(&input)
+        .args(["stdout", "-l", &config.language, "--psm", "6"])
+        .output()
+        .with_context(|| format!("Failed to run {}; is Tesseract installed?", config.program.display()))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed ({}): {}",
+            config.program.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Picks the number and setter out of the header's text
+pub fn parse(text: &str) -> PuzzleInfo {
+    PuzzleInfo {
+        number: number(text),
+        setter: text.lines().find_map(setter),
+    }
+}
+
+/// A 3 to 6 digit number, preferring one labelled `No.`, `#` or the like over a bare one,
+/// which could be part of the date
+fn number(text: &str) -> Option<u32> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut bare = None;
+    for (index, word) in words.iter().enumerate() {
+        let hashed = word.starts_with('#');
+        let digits = word.trim_matches(|c: char| !c.is_ascii_digit());
+        if !(3..=6).contains(&digits.len()) || !digits.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let label = index
+            .checked_sub(1)
+            .map(|before| words[before].trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase());
+        if hashed || label.is_some_and(|label| NUMBER_LABELS.contains(&label.as_str())) {
+            return digits.parse().ok();
+        }
+        // `No.10234` has its label in the same word
+        let joined = word.to_lowercase();
+        if joined.starts_with("no") && !joined.starts_with("nov") {
+            return digits.parse().ok();
+        }
+        bare = bare.or(digits.parse().ok().filter(|_| digits.len() != 4));
+    }
+    bare
+}
+
+/// The name after `Set by`, `Setter:` or the like on `line`, up to the first character that
+/// can't be part of a name
+fn setter(line: &str) -> Option<String> {
+    // ASCII-only, so offsets into it are offsets into `line`
+    let lower = line.to_ascii_lowercase();
+    SETTER_LABELS.iter().find_map(|label| {
+        let start = lower.match_indices(label).map(|(at, _)| at).find(|&at| {
+            !lower[..at].ends_with(char::is_alphanumeric) && !lower[at + label.len()..].starts_with(char::is_alphanumeric)
+        })?;
+        let rest = line.get(start + label.len()..)?.trim_start_matches([':', ' ', '-', '\u{2013}']);
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_alphabetic() || " .'-".contains(*c))
+            .collect();
+        let name = name.trim().trim_end_matches(['-', '.']).trim();
+        (name.chars().filter(|c| c.is_alphabetic()).count() >= 2).then(|| name.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let parsed = parse("CROSSWORD No. 10234\nSet by A. Kulkarni");
+        assert_eq!(parsed.number, Some(10234));
+        assert_eq!(parsed.setter.as_deref(), Some("A. Kulkarni"));
+
+        let parsed = parse("The Hitavada Crossword #9876 - Compiled by: Meera Rao, 2024");
+        assert_eq!(parsed.number, Some(9876));
+        assert_eq!(parsed.setter.as_deref(), Some("Meera Rao"));
+
+        // A bare year isn't taken for the number
+        let parsed = parse("Wednesday 20 March 2024 10234");
+        assert_eq!(parsed.number, Some(10234));
+        assert_eq!(parsed.setter, None);
+        assert!(parse("ACROSS\n1 Bye, it's over (4)").is_empty());
+    }
+}
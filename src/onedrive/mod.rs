@@ -0,0 +1,332 @@
+//! Uploading to OneDrive or a SharePoint document library through Microsoft Graph, for
+//! households on Microsoft 365. The app signs in with its own client credentials, so the
+//! drive is named in the config (by ID, user or site) rather than being "my" drive.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use tokio::sync::OnceCell;
+
+use crate::config::Config;
+use crate::console;
+use crate::credentials::CredentialSource;
+use crate::http::backoff::{self, BackoffConfig};
+use crate::pipeline::service::{self, is_transient};
+use crate::pipeline::{Artifact, RemoteCopy, Sink, DRY_RUN_ID};
+
+const GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+
+/// Environment variable holding the app's client secret, read before `secret_parameter`
+pub const SECRET_ENV: &str = "ONEDRIVE_CLIENT_SECRET";
+
+/// Largest file Graph takes in a single request; bigger ones go through an upload session
+const SIMPLE_UPLOAD_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Bytes sent per request of an upload session, which Graph wants in multiples of 320 KiB
+const SESSION_CHUNK: usize = 16 * 320 * 1024;
+
+/// The `[onedrive]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OneDriveConfig {
+    /// Directory (tenant) ID of the Entra app registration
+    pub tenant_id: String,
+    /// Application (client) ID of the app registration
+    pub client_id: String,
+    /// SSM parameter holding the client secret, when `ONEDRIVE_CLIENT_SECRET` isn't set
+    pub secret_parameter: Option<String>,
+    /// ID of the drive to upload to, e.g. a document library's; or one of `user` and `site_id`
+    pub drive_id: Option<String>,
+    /// ID or user principal name of the user whose OneDrive to upload to
+    pub user: Option<String>,
+    /// ID of the SharePoint site whose default document library to upload to
+    pub site_id: Option<String>,
+    /// Folder in the drive, e.g. `Family/Crosswords`; empty for the top level
+    pub folder: String,
+}
+
+impl Default for OneDriveConfig {
+    fn default() -> Self {
+        Self {
+            tenant_id: String::new(),
+            client_id: String::new(),
+            secret_parameter: None,
+            drive_id: None,
+            user: None,
+            site_id: None,
+            folder: "Crosswords".to_string(),
+        }
+    }
+}
+
+impl OneDriveConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.tenant_id.trim().is_empty() || self.client_id.trim().is_empty() {
+            problems.push("[onedrive] needs the app's tenant_id and client_id".to_string());
+        }
+        if self.secret_parameter.is_none() && std::env::var(SECRET_ENV).is_err() {
+            problems.push(format!("[onedrive] needs a secret_parameter or {}", SECRET_ENV));
+        }
+        let drives = [&self.drive_id, &self.user, &self.site_id].iter().filter(|drive| drive.is_some()).count();
+        if drives != 1 {
+            problems.push("[onedrive] needs exactly one of drive_id, user and site_id".to_string());
+        }
+        if self.folder.starts_with('/') || self.folder.ends_with('/') || self.folder.contains("//") {
+            problems.push(format!(
+                "[onedrive] folder '{}' must be a relative path like Family/Crosswords",
+                self.folder
+            ));
+        }
+        problems
+    }
+
+    /// The Graph path of the drive, without a leading slash
+    fn drive_path(&self) -> Result<String> {
+        match (&self.drive_id, &self.user, &self.site_id) {
+            (Some(drive_id), _, _) => Ok(format!("drives/{}", drive_id)),
+            (None, Some(user), _) => Ok(format!("users/{}/drive", user)),
+            (None, None, Some(site_id)) => Ok(format!("sites/{}/drive", site_id)),
+            (None, None, None) => anyhow::bail!("[onedrive] needs one of drive_id, user and site_id"),
+        }
+    }
+}
+
+/// Uploads pipeline artifacts to a folder of a OneDrive or SharePoint drive
+pub struct OneDriveSink {
+    config: OneDriveConfig,
+    drive: String,
+    token: OnceCell<String>,
+    client: reqwest::Client,
+    backoff: BackoffConfig,
+    dry_run: bool,
+}
+
+impl OneDriveSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let onedrive = config.onedrive.clone().context("[onedrive] is not configured")?;
+        Ok(Self {
+            drive: onedrive.drive_path()?,
+            config: onedrive,
+            token: OnceCell::new(),
+            client: reqwest::Client::new(),
+            backoff: config.backoff.clone(),
+            dry_run: false,
+        })
+    }
+
+    /// Prints what would be uploaded instead of writing to the drive
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Path of the artifact in the drive, segment by segment
+    fn segments(&self, artifact: &Artifact) -> Result<Vec<String>> {
+        let name = Path::new(&artifact.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Invalid filename")?;
+        let mut segments: Vec<String> =
+            self.config.folder.split('/').filter(|segment| !segment.is_empty()).map(String::from).collect();
+        segments.extend(artifact.folder.clone());
+        segments.push(name.to_string());
+        Ok(segments)
+    }
+
+    /// `/{drive}/root:/{path}:/{action}`, each segment of the path escaped
+    fn item_url(&self, segments: &[String], action: &str) -> Result<Url> {
+        let mut url = Url::parse(&format!("{}/{}", GRAPH_URL, self.drive))?;
+        {
+            let mut path = url.path_segments_mut().map_err(|()| anyhow::anyhow!("Graph URL can't take a path"))?;
+            path.push("root:");
+            if let Some((last, folders)) = segments.split_last() {
+                path.extend(folders);
+                path.push(&format!("{}:", last));
+            }
+            path.push(action);
+        }
+        Ok(url)
+    }
+
+    /// An app-only access token, requested once per run
+    async fn token(&self) -> Result<&str> {
+        let token = self
+            .token
+            .get_or_try_init(|| async {
+                let secret = match std::env::var(SECRET_ENV) {
+                    Ok(secret) => secret,
+                    Err(_) => {
+                        let parameter = self.config.secret_parameter.clone().with_context(|| {
+                            format!("{} is not set and [onedrive] has no secret_parameter", SECRET_ENV)
+                        })?;
+                        CredentialSource::Ssm { parameter }.provider().fetch().await?
+                    }
+                };
+                let form = [
+                    ("grant_type", "client_credentials"),
+                    ("client_id", self.config.client_id.as_str()),
+                    ("client_secret", secret.trim()),
+                    ("scope", "https://graph.microsoft.com/.default"),
+                ];
+                let url = format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", self.config.tenant_id);
+                let response = self.client.post(url).form(&form).send().await?;
+                let body = answer(response).await.context("Microsoft sign-in refused the app's credentials")?;
+                body["access_token"]
+                    .as_str()
+                    .map(str::to_string)
+                    .context("The sign-in answer has no access_token")
+            })
+            .await?;
+        Ok(token)
+    }
+
+    /// Puts `body` at `segments` in one request, or through an upload session when it is too
+    /// big for one; either way an existing file of that name is replaced
+    async fn put(&self, segments: &[String], body: &[u8]) -> Result<Value> {
+        let token = self.token().await?;
+        if body.len() <= SIMPLE_UPLOAD_LIMIT {
+            let response = self
+                .client
+                .put(self.item_url(segments, "content")?)
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(body.to_vec())
+                .send()
+                .await?;
+            return answer(response).await;
+        }
+
+        let session = self
+            .client
+            .post(self.item_url(segments, "createUploadSession")?)
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .header(CONTENT_TYPE, "application/json")
+            .body(json!({ "item": { "@microsoft.graph.conflictBehavior": "replace" } }).to_string())
+            .send()
+            .await?;
+        let session = answer(session).await?;
+        let upload_url = session["uploadUrl"].as_str().context("Graph's upload session has no uploadUrl")?;
+        let mut item = Value::Null;
+        for (index, chunk) in body.chunks(SESSION_CHUNK).enumerate() {
+            let start = index * SESSION_CHUNK;
+            // The upload URL is pre-authenticated; Graph refuses it with a bearer token
+            let response = self
+                .client
+                .put(upload_url)
+                .header(CONTENT_LENGTH, chunk.len())
+                .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, start + chunk.len() - 1, body.len()))
+                .body(chunk.to_vec())
+                .send()
+                .await?;
+            item = answer(response).await?;
+        }
+        Ok(item)
+    }
+}
+
+#[async_trait]
+impl Sink for OneDriveSink {
+    fn name(&self) -> &str {
+        "onedrive"
+    }
+
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        let segments = self.segments(artifact)?;
+        let path = segments.join("/");
+        if self.dry_run {
+            console::progress!("Dry run: would upload {} to OneDrive at {}/{}", artifact.path, self.drive, path);
+            return Ok(DRY_RUN_ID.to_string());
+        }
+
+        let body = fs::read(&artifact.path).with_context(|| format!("Failed to read {}", artifact.path))?;
+        let item = backoff::retry(&self.backoff, "OneDrive upload", is_transient, || self.put(&segments, &body))
+            .await
+            .with_context(|| format!("Failed to upload to OneDrive at {}", path))?;
+        let id = item["id"].as_str().context("Graph's upload answer has no item ID")?;
+        console::progress!("File uploaded to OneDrive at {}", path);
+        Ok(id.to_string())
+    }
+
+    async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+        if id == DRY_RUN_ID {
+            return Ok(RemoteCopy::Missing);
+        }
+        let response = self
+            .client
+            .get(format!("{}/{}/items/{}?$select=file", GRAPH_URL, self.drive, id))
+            .header(AUTHORIZATION, format!("Bearer {}", self.token().await?))
+            .send()
+            .await?;
+        match answer(response).await {
+            // Personal OneDrive keeps a SHA-256; business drives and SharePoint only their own hash
+            Ok(item) => Ok(RemoteCopy::Present {
+                sha256: item["file"]["hashes"]["sha256Hash"].as_str().map(str::to_lowercase),
+            }),
+            Err(e) if service::is_status(&e, 404) => Ok(RemoteCopy::Missing),
+            Err(e) => Err(e).with_context(|| format!("Failed to look up {} in OneDrive", id)),
+        }
+    }
+}
+
+/// The JSON body of a successful answer, or a [`service::ServiceError`] with Graph's message.
+/// A finished chunk of an upload session answers 202 with a body, the others without.
+async fn answer(response: reqwest::Response) -> Result<Value> {
+    // Graph puts it under error.message, the sign-in endpoint under error_description
+    service::json_answer("Microsoft Graph", response, |body| {
+        body["error"]["message"].as_str().or(body["error_description"].as_str())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn onedrive() -> OneDriveConfig {
+        OneDriveConfig {
+            tenant_id: "00000000-0000-0000-0000-000000000001".to_string(),
+            client_id: "00000000-0000-0000-0000-000000000002".to_string(),
+            secret_parameter: Some("/hitavada-crossword/onedrive-secret".to_string()),
+            user: Some("family@example.onmicrosoft.com".to_string()),
+            folder: "Family/Crosswords".to_string(),
+            ..OneDriveConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(onedrive().validate().is_empty());
+        let both = OneDriveConfig {
+            drive_id: Some("b!abc".to_string()),
+            folder: "/Crosswords".to_string(),
+            ..onedrive()
+        };
+        assert_eq!(both.validate().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_item_urls() {
+        let config = Config {
+            onedrive: Some(onedrive()),
+            ..Config::default()
+        };
+        let sink = OneDriveSink::new(&config).unwrap().dry_run(true);
+        let artifact = Artifact {
+            path: "/tmp/crossword 2024-03-20.jpg".to_string(),
+            ..service::test_artifact(Some("originals"))
+        };
+        let segments = sink.segments(&artifact).unwrap();
+        assert_eq!(
+            sink.item_url(&segments, "content").unwrap().as_str(),
+            "https://graph.microsoft.com/v1.0/users/family@example.onmicrosoft.com/drive\
+             /root:/Family/Crosswords/originals/crossword%202024-03-20.jpg:/content"
+        );
+        assert_eq!(sink.upload(&artifact).await.unwrap(), DRY_RUN_ID);
+    }
+}
@@ -1,5 +1,6 @@
 use scraper::{Html, Selector};
-use crate::types::Rect;
+use serde::Serialize;
+use crate::types::{Interstitial, Rect, TargetProfile};
 
 /// Parses a single coords string into a Rect
 pub fn parse_coords(coords_str: &str) -> Option<Rect> {
@@ -20,43 +21,297 @@ pub fn parse_coords(coords_str: &str) -> Option<Rect> {
     }
 }
 
-/// Gets the target area's href from the HTML content with a tolerance of 50 for y1 and y2, and 10 for x2
-pub fn get_target_rect(html: &str) -> Option<String> {
+/// Selector of the image on an article page
+pub const ARTICLE_IMAGE: &str = ".slices_container img";
+
+/// Path of the placeholder the site shows while an article image has not been published yet
+const PLACEHOLDER_IMAGE: &str = "images/not_found.png";
+
+/// Checks whether an image `src` points at the site's "not found" placeholder
+pub fn is_placeholder_image(src: &str) -> bool {
+    let path = src.split(['?', '#']).next().unwrap_or_default();
+    path.trim_start_matches('/').ends_with(PLACEHOLDER_IMAGE)
+}
+
+const MAINTENANCE_MARKERS: &[&str] = &[
+    "under maintenance",
+    "maintenance mode",
+    "scheduled maintenance",
+    "temporarily unavailable",
+    "be back soon",
+];
+
+const CAPTCHA_MARKERS: &[&str] = &[
+    "captcha",
+    "cf-challenge",
+    "challenge-platform",
+    "verify you are human",
+    "are you a robot",
+];
+
+const LOGIN_MARKERS: &[&str] = &[
+    "type=\"password\"",
+    "type='password'",
+    "please login",
+    "please log in",
+    "sign in to continue",
+    "login to continue",
+    "subscribe to continue",
+];
+
+/// Recognizes maintenance notices, CAPTCHAs and login walls from the status code and page content.
+///
+/// Only call this once the expected content is known to be missing, since regular pages
+/// may mention words like "login" in their navigation.
+pub fn detect_interstitial(status: u16, html: &str) -> Option<Interstitial> {
+    let content = html.to_ascii_lowercase();
+    let contains_any = |markers: &[&str]| markers.iter().any(|m| content.contains(m));
+
+    if contains_any(CAPTCHA_MARKERS) {
+        Some(Interstitial::Captcha)
+    } else if contains_any(LOGIN_MARKERS) || status == 401 {
+        Some(Interstitial::LoginWall)
+    } else if contains_any(MAINTENANCE_MARKERS) || status == 503 {
+        Some(Interstitial::Maintenance)
+    } else {
+        None
+    }
+}
+
+/// Checks `value` is at most `tolerance` away from `expected` without overflowing on extreme coords
+fn within(value: i32, expected: i32, tolerance: i32) -> bool {
+    u32::try_from(tolerance).is_ok_and(|tolerance| value.abs_diff(expected) <= tolerance)
+}
+
+/// Largest gap, in page map units, between two areas that together make up one target
+const ADJACENT_GAP: i32 = 20;
+
+/// How the two parts of a target split across areas sit next to each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Split {
+    SideBySide,
+    Stacked,
+}
+
+/// The area picked for a profile: where it links to and where it actually sits
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetMatch {
+    /// Article links in reading order; two when the target is split across areas
+    pub hrefs: Vec<String>,
+    pub rect: Rect,
+    pub split: Option<Split>,
+}
+
+/// Finds the target area in the HTML content, allowing each edge to drift by the
+/// profile's tolerance (by default 5 for x1, 50 for y1 and y2, and 10 for x2).
+///
+/// When no single area matches, the puzzle may have been mapped as two adjacent areas
+/// (grid and clues, say), so a pair that together covers the expected region is accepted.
+pub fn find_target(html: &str, profile: &TargetProfile) -> Option<TargetMatch> {
+    let candidates = list_candidates(html, profile);
+    candidates
+        .iter()
+        .filter(|candidate| candidate.matches)
+        .find_map(|candidate| {
+            Some(TargetMatch {
+                rect: parse_coords(&candidate.coords)?,
+                hrefs: vec![candidate.href.clone()?],
+                split: None,
+            })
+        })
+        .or_else(|| find_split_target(&candidates, profile))
+}
+
+fn find_split_target(candidates: &[Candidate], profile: &TargetProfile) -> Option<TargetMatch> {
+    let areas: Vec<(Rect, &str)> = candidates
+        .iter()
+        .filter_map(|candidate| Some((parse_coords(&candidate.coords)?, candidate.href.as_deref()?)))
+        .collect();
+
+    for (index, a) in areas.iter().enumerate() {
+        for b in &areas[index + 1..] {
+            let (split, first, second) = match (adjacency(&a.0, &b.0), adjacency(&b.0, &a.0)) {
+                (Some(split), _) => (split, a, b),
+                (None, Some(split)) => (split, b, a),
+                (None, None) => continue,
+            };
+            let rect = Rect {
+                x1: first.0.x1.min(second.0.x1),
+                y1: first.0.y1.min(second.0.y1),
+                x2: first.0.x2.max(second.0.x2),
+                y2: first.0.y2.max(second.0.y2),
+            };
+            if edge_checks(&rect, profile).iter().all(|check| check.passed) {
+                return Some(TargetMatch {
+                    hrefs: vec![first.1.to_string(), second.1.to_string()],
+                    rect,
+                    split: Some(split),
+                });
+            }
+        }
+    }
+    None
+}
+
+/// How `second` continues `first`: directly to its right or directly below it
+fn adjacency(first: &Rect, second: &Rect) -> Option<Split> {
+    if within(second.x1, first.x2, ADJACENT_GAP) && overlaps((first.y1, first.y2), (second.y1, second.y2)) {
+        Some(Split::SideBySide)
+    } else if within(second.y1, first.y2, ADJACENT_GAP) && overlaps((first.x1, first.x2), (second.x1, second.x2)) {
+        Some(Split::Stacked)
+    } else {
+        None
+    }
+}
+
+/// Whether two spans share more than half of the shorter one
+fn overlaps(a: (i32, i32), b: (i32, i32)) -> bool {
+    let overlap = i64::from(a.1.min(b.1)) - i64::from(a.0.max(b.0));
+    let shorter = (i64::from(a.1) - i64::from(a.0)).min(i64::from(b.1) - i64::from(b.0));
+    shorter > 0 && overlap * 2 > shorter
+}
+
+/// The target area's href, which is all most tests care about
+#[cfg(test)]
+fn get_target_rect(html: &str, profile: &TargetProfile) -> Option<String> {
+    find_target(html, profile).and_then(|target| target.hrefs.into_iter().next())
+}
+
+/// An `<area>` on a page map and whether the target profile accepts it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candidate {
+    pub coords: String,
+    pub href: Option<String>,
+    pub matches: bool,
+    /// How close the area is to the profile, from 0 (outside every tolerance) to 100 (exact)
+    pub score: u8,
+    /// One check per edge; empty when the coordinates don't parse
+    pub checks: Vec<EdgeCheck>,
+}
+
+/// Whether one edge of an area is within the profile's tolerance
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EdgeCheck {
+    pub edge: &'static str,
+    pub value: i32,
+    pub expected: i32,
+    pub tolerance: i32,
+    pub passed: bool,
+}
+
+impl EdgeCheck {
+    /// 1.0 on the expected value, falling to 0.0 at the tolerance and beyond
+    fn closeness(&self) -> f64 {
+        let distance = f64::from(self.value.abs_diff(self.expected));
+        if self.tolerance <= 0 {
+            return if distance == 0.0 { 1.0 } else { 0.0 };
+        }
+        (1.0 - distance / f64::from(self.tolerance)).max(0.0)
+    }
+}
+
+/// Every area on a page map, in document order
+pub fn list_candidates(html: &str, profile: &TargetProfile) -> Vec<Candidate> {
     let document = Html::parse_document(html);
     let area_selector = Selector::parse("area").unwrap();
-    let tolerance_x1 = 5;
-    let tolerance_y1 = 50;
-    let tolerance_x2 = 10;
-    let tolerance_y2 = 50;
-
-    document.select(&area_selector)
-        .find_map(|area| {
-            if let Some(coords) = area.value().attr("coords") {
-                if let Some(rect) = parse_coords(coords) {
-                    // Check if coordinates are within tolerance
-                    let x1_in_range = (rect.x1 - 0).abs() <= tolerance_x1;
-                    let y1_in_range = (rect.y1 - 1625).abs() <= tolerance_y1;
-                    let x2_in_range = (rect.x2 - 1000).abs() <= tolerance_x2;
-                    let y2_in_range = (rect.y2 - 2775).abs() <= tolerance_y2;
-                    
-                    if x1_in_range && y1_in_range && x2_in_range && y2_in_range {
-                        area.value().attr("href").map(String::from)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
+
+    document
+        .select(&area_selector)
+        .filter_map(|area| {
+            let coords = area.value().attr("coords")?;
+            let checks = parse_coords(coords)
+                .map(|rect| edge_checks(&rect, profile))
+                .unwrap_or_default();
+            let score = closeness_score(&checks);
+            Some(Candidate {
+                coords: coords.to_string(),
+                href: area.value().attr("href").map(String::from),
+                matches: !checks.is_empty() && checks.iter().all(|check| check.passed),
+                score,
+                checks,
+            })
         })
+        .collect()
+}
+
+/// How close `rect` is to the profile, scored like a candidate from 0 to 100
+pub fn placement_score(rect: &Rect, profile: &TargetProfile) -> u8 {
+    closeness_score(&edge_checks(rect, profile))
+}
+
+fn closeness_score(checks: &[EdgeCheck]) -> u8 {
+    if checks.is_empty() {
+        return 0;
+    }
+    let total: f64 = checks.iter().map(EdgeCheck::closeness).sum();
+    (total / checks.len() as f64 * 100.0).round() as u8
+}
+
+fn edge_checks(rect: &Rect, profile: &TargetProfile) -> Vec<EdgeCheck> {
+    let expected = &profile.expected;
+    let tolerance = &profile.tolerance;
+
+    [
+        ("x1", rect.x1, expected.x1, tolerance.x1),
+        ("y1", rect.y1, expected.y1, tolerance.y1),
+        ("x2", rect.x2, expected.x2, tolerance.x2),
+        ("y2", rect.y2, expected.y2, tolerance.y2),
+    ]
+    .into_iter()
+    .map(|(edge, value, expected, tolerance)| EdgeCheck {
+        edge,
+        value,
+        expected,
+        tolerance,
+        passed: within(value, expected, tolerance),
+    })
+    .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_placeholder_image() {
+        assert!(is_placeholder_image("images/not_found.png"));
+        assert!(is_placeholder_image("/images/not_found.png"));
+        assert!(is_placeholder_image("images/not_found.png?v=1710892800"));
+        assert!(is_placeholder_image("https://www.ehitavada.com/images/not_found.png"));
+        assert!(!is_placeholder_image("encyc/6/20240320/Mpage_8_slice_1.jpg"));
+        assert!(!is_placeholder_image("images/not_found.png.jpg"));
+    }
+
+    #[test]
+    fn test_detect_interstitial_maintenance() {
+        let html = "<html><body><h1>Site Under Maintenance</h1><p>We'll be back soon.</p></body></html>";
+        assert_eq!(detect_interstitial(200, html), Some(Interstitial::Maintenance));
+        assert_eq!(detect_interstitial(503, ""), Some(Interstitial::Maintenance));
+    }
+
+    #[test]
+    fn test_detect_interstitial_captcha() {
+        let html = r#"<div class="g-recaptcha" data-sitekey="abc"></div>"#;
+        assert_eq!(detect_interstitial(200, html), Some(Interstitial::Captcha));
+        let html = r#"<script src="/cdn-cgi/challenge-platform/h/b/orchestrate"></script>"#;
+        assert_eq!(detect_interstitial(403, html), Some(Interstitial::Captcha));
+    }
+
+    #[test]
+    fn test_detect_interstitial_login_wall() {
+        let html = r#"<form action="login.php"><input type="password" name="pass"/></form>"#;
+        assert_eq!(detect_interstitial(200, html), Some(Interstitial::LoginWall));
+        assert_eq!(detect_interstitial(401, ""), Some(Interstitial::LoginWall));
+    }
+
+    #[test]
+    fn test_detect_interstitial_regular_pages() {
+        let html = r#"<map><area shape="rect" coords="0,1625,1000,2775" href="test1"/></map>"#;
+        assert_eq!(detect_interstitial(200, html), None);
+        assert_eq!(detect_interstitial(200, ""), None);
+        assert_eq!(detect_interstitial(404, "Not Found"), None);
+    }
+
     #[test]
     fn test_get_target_rect_exact_match() {
         let html = r#"
@@ -64,7 +319,7 @@ mod tests {
                 <area shape="rect" coords="0,1625,1000,2775" href="test1"/>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), Some("test1".to_string()));
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), Some("test1".to_string()));
     }
 
     #[test]
@@ -74,7 +329,7 @@ mod tests {
                 <area shape="rect" coords="0,1670,1001,2764" href="test2"/>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), Some("test2".to_string()));
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), Some("test2".to_string()));
     }
 
     #[test]
@@ -84,7 +339,7 @@ mod tests {
                 <area shape="rect" coords="0,1627,242,2286" href="test3"/>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), None);
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), None);
     }
 
     #[test]
@@ -94,7 +349,7 @@ mod tests {
                 <area shape="rect" coords="10,1625,1000,2775" href="test4"/>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), None);
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), None);
     }
 
     #[test]
@@ -106,7 +361,7 @@ mod tests {
                 <area shape="rect" coords="0,2000,500,3000" href="test7"/>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), Some("test6".to_string()));
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), Some("test6".to_string()));
     }
 
     #[test]
@@ -117,7 +372,7 @@ mod tests {
                 <area shape="rect" coords="0,1625,1000" href="test9"/>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), None);
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), None);
     }
 
     #[test]
@@ -130,13 +385,109 @@ mod tests {
                 <area shape="rect" coords="995,1664,1749,2778" href="test13">
             </map>
         "#;
-        assert_eq!(get_target_rect(html), Some("test12".to_string()));
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), Some("test12".to_string()));
+    }
+
+    #[test]
+    fn test_get_target_rect_with_custom_profile() {
+        let html = r#"
+            <map>
+                <area shape="rect" coords="0,1625,1000,2775" href="default"/>
+                <area shape="rect" coords="1000,100,1750,900" href="custom"/>
+            </map>
+        "#;
+        let profile = TargetProfile {
+            expected: Rect { x1: 1000, y1: 100, x2: 1750, y2: 900 },
+            tolerance: Rect { x1: 0, y1: 0, x2: 0, y2: 0 },
+        };
+        assert_eq!(get_target_rect(html, &profile), Some("custom".to_string()));
+    }
+
+    #[test]
+    fn test_find_target_split_side_by_side() {
+        // Clues to the right of the grid, listed first on the map
+        let html = r#"<map>
+            <area coords="4,100,1255,1600" href="headline"/>
+            <area coords="502,1630,998,2770" href="clues"/>
+            <area coords="2,1628,500,2772" href="grid"/>
+        </map>"#;
+        let target = find_target(html, &TargetProfile::default()).unwrap();
+        assert_eq!(target.hrefs, vec!["grid", "clues"]);
+        assert_eq!(target.split, Some(Split::SideBySide));
+        assert_eq!(target.rect, Rect { x1: 2, y1: 1628, x2: 998, y2: 2772 });
+    }
+
+    #[test]
+    fn test_find_target_split_stacked() {
+        let html = r#"<map>
+            <area coords="0,1625,1000,2200" href="grid"/>
+            <area coords="2,2210,998,2775" href="clues"/>
+        </map>"#;
+        let target = find_target(html, &TargetProfile::default()).unwrap();
+        assert_eq!(target.hrefs, vec!["grid", "clues"]);
+        assert_eq!(target.split, Some(Split::Stacked));
+    }
+
+    #[test]
+    fn test_find_target_prefers_single_area_and_ignores_distant_pairs() {
+        let single = r#"<map>
+            <area coords="0,1625,500,2775" href="left"/>
+            <area coords="500,1625,1000,2775" href="right"/>
+            <area coords="0,1625,1000,2775" href="whole"/>
+        </map>"#;
+        let target = find_target(single, &TargetProfile::default()).unwrap();
+        assert_eq!(target.hrefs, vec!["whole"]);
+        assert_eq!(target.split, None);
+
+        // Together they span the region, but there's a column of other content between them
+        let apart = r#"<map>
+            <area coords="0,1625,300,2775" href="left"/>
+            <area coords="700,1625,1000,2775" href="right"/>
+        </map>"#;
+        assert_eq!(find_target(apart, &TargetProfile::default()), None);
+    }
+
+    #[test]
+    fn test_list_candidates() {
+        let html = r#"<map>
+            <area coords="0,0,500,800" href="article.php?mid=headline"/>
+            <area coords="not,numbers" href="article.php?mid=broken"/>
+            <area coords="0,1625,1000,2775" href="article.php?mid=crossword"/>
+        </map>"#;
+
+        let candidates = list_candidates(html, &TargetProfile::default());
+
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates.iter().filter(|c| c.matches).count(), 1);
+        assert!(candidates[2].matches);
+        assert_eq!(candidates[1].href.as_deref(), Some("article.php?mid=broken"));
+        assert!(candidates[1].checks.is_empty());
+    }
+
+    #[test]
+    fn test_candidate_checks_and_score() {
+        let html = r#"<map>
+            <area coords="0,1625,1000,2775" href="exact"/>
+            <area coords="2,1650,1000,2775" href="drifted"/>
+            <area coords="0,1625,1020,2775" href="too wide"/>
+        </map>"#;
+
+        let candidates = list_candidates(html, &TargetProfile::default());
+
+        assert_eq!(candidates[0].score, 100);
+        // x1 is 2/5 off and y1 25/50 off
+        assert_eq!(candidates[1].score, 78);
+        assert!(candidates[1].matches);
+        let failed: Vec<_> = candidates[2].checks.iter().filter(|c| !c.passed).map(|c| c.edge).collect();
+        assert_eq!(failed, vec!["x2"]);
+        assert!(!candidates[2].matches);
+        assert_eq!(candidates[2].score, 75);
     }
 
     #[test]
     fn test_get_target_rect_empty_html() {
         let html = "";
-        assert_eq!(get_target_rect(html), None);
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), None);
     }
 
     #[test]
@@ -145,6 +496,81 @@ mod tests {
             <map>
             </map>
         "#;
-        assert_eq!(get_target_rect(html), None);
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), None);
+    }
+
+    #[test]
+    fn test_get_target_rect_extreme_coords() {
+        let html = r#"
+            <map>
+                <area shape="rect" coords="-2147483648,2147483647,-2147483648,2147483647" href="extreme"/>
+            </map>
+        "#;
+        assert_eq!(get_target_rect(html, &TargetProfile::default()), None);
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn area_html(coords: &str, href: &str) -> String {
+        format!(r#"<map><area shape="rect" coords="{}" href="{}"/></map>"#, coords, href)
     }
-} 
\ No newline at end of file
+
+    proptest! {
+        #[test]
+        fn parse_coords_never_panics(input in any::<String>()) {
+            let _ = parse_coords(&input);
+        }
+
+        #[test]
+        fn parse_coords_round_trips(x1 in any::<i32>(), y1 in any::<i32>(), x2 in any::<i32>(), y2 in any::<i32>()) {
+            let coords = format!("{}, {},{} ,{}", x1, y1, x2, y2);
+            prop_assert_eq!(parse_coords(&coords), Some(Rect { x1, y1, x2, y2 }));
+        }
+
+        #[test]
+        fn parse_coords_rejects_wrong_arity(values in prop::collection::vec(any::<i32>(), 0..10)) {
+            prop_assume!(values.len() != 4);
+            let coords = values.iter().map(i32::to_string).collect::<Vec<_>>().join(",");
+            prop_assert_eq!(parse_coords(&coords), None);
+        }
+
+        #[test]
+        fn get_target_rect_never_panics_on_garbage(html in any::<String>()) {
+            let _ = get_target_rect(&html, &TargetProfile::default());
+        }
+
+        #[test]
+        fn get_target_rect_never_panics_on_garbage_coords(coords in "[-0-9, ]{0,40}") {
+            let _ = get_target_rect(&area_html(&coords, "x"), &TargetProfile::default());
+        }
+
+        #[test]
+        fn get_target_rect_matches_within_tolerance(
+            dx1 in -5i32..=5, dy1 in -50i32..=50, dx2 in -10i32..=10, dy2 in -50i32..=50,
+        ) {
+            let coords = format!("{},{},{},{}", dx1, 1625 + dy1, 1000 + dx2, 2775 + dy2);
+            prop_assert_eq!(
+                get_target_rect(&area_html(&coords, "match"), &TargetProfile::default()),
+                Some("match".to_string())
+            );
+        }
+
+        #[test]
+        fn get_target_rect_rejects_outside_tolerance(offset in 51i32..100_000, edge in 0usize..4, sign in prop::bool::ANY) {
+            let mut values = [0, 1625, 1000, 2775];
+            values[edge] += if sign { offset } else { -offset };
+            let coords = values.iter().map(i32::to_string).collect::<Vec<_>>().join(",");
+            prop_assert_eq!(get_target_rect(&area_html(&coords, "miss"), &TargetProfile::default()), None);
+        }
+
+        #[test]
+        fn get_target_rect_handles_extreme_values(x1 in any::<i32>(), y1 in any::<i32>(), x2 in any::<i32>(), y2 in any::<i32>()) {
+            let coords = format!("{},{},{},{}", x1, y1, x2, y2);
+            let _ = get_target_rect(&area_html(&coords, "x"), &TargetProfile::default());
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tracing::Instrument;
+
+use crate::console;
+use crate::error::CrosswordError;
+use crate::imaging::backend::{self, Backend};
+use crate::storage;
+use crate::telemetry::phases;
+
+pub mod quota;
+pub mod service;
+pub mod steps;
+
+use quota::StorageQuota;
+use steps::Step;
+
+/// Reported by a sink instead of an ID for an upload skipped by a dry run
+pub const DRY_RUN_ID: &str = "dry-run";
+
+/// Subfolder the untouched download is filed under when processed variants are uploaded too
+pub const ORIGINALS_FOLDER: &str = "originals";
+
+/// The `[processing]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingConfig {
+    /// Upload the original next to the processed variants, under `originals/`
+    pub keep_original: bool,
+    /// Steps applied in order to make the processed variant, e.g. `["autocrop", "pdf"]`
+    pub pipeline: Vec<Step>,
+    /// What does the image operations: `image` (built in), `imagemagick` or `vips`
+    pub backend: Backend,
+}
+
+impl Default for ProcessingConfig {
+    fn default() -> Self {
+        Self {
+            keep_original: true,
+            pipeline: Vec::new(),
+            backend: Backend::default(),
+        }
+    }
+}
+
+impl ProcessingConfig {
+    pub fn validate(&self) -> Vec<String> {
+        steps::validate(&self.pipeline)
+    }
+
+    /// The processors the pipeline runs, none when it is empty
+    pub fn processors(&self) -> Vec<Arc<dyn Processor>> {
+        if self.pipeline.is_empty() {
+            return Vec::new();
+        }
+        let processor = backend::processor(self.backend);
+        vec![Arc::new(steps::StepPipeline::new(self.pipeline.clone(), processor))]
+    }
+}
+
+/// A file produced by the run, either the original download or a processed variant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Artifact {
+    pub path: String,
+    pub mime_type: String,
+    /// Subfolder of each sink's destination to put the file in, rather than the top level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    /// Edition the file belongs to, for sinks that file by date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+}
+
+impl Artifact {
+    pub fn new(path: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            mime_type: mime_type.into(),
+            folder: None,
+            date: None,
+        }
+    }
+
+    /// The artifact, marked as belonging to the edition of `date`
+    pub fn dated(self, date: NaiveDate) -> Self {
+        Self {
+            date: Some(date),
+            ..self
+        }
+    }
+
+    /// A pristine copy of the download as `originals/<name>_orig.<ext>` next to it, set to be
+    /// uploaded into the sinks' `originals` subfolder
+    pub fn original_copy(&self) -> Result<Artifact> {
+        let path = Path::new(&self.path);
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).context("Invalid filename")?;
+        let mut name = format!("{}_orig", stem);
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            name = format!("{}.{}", name, extension);
+        }
+        let dir = path.parent().unwrap_or(Path::new("")).join(ORIGINALS_FOLDER);
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let copy = dir.join(name);
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", self.path))?;
+        storage::write_atomic(&copy, &data)?;
+        Ok(Artifact {
+            path: copy.to_string_lossy().into_owned(),
+            mime_type: self.mime_type.clone(),
+            folder: Some(ORIGINALS_FOLDER.to_string()),
+            date: self.date,
+        })
+    }
+
+    /// The MIME type the file's content actually has, going by its magic bytes. Formats
+    /// without any, such as HTML, keep the declared type.
+    pub fn detected_mime_type(&self) -> Result<String> {
+        let kind = infer::get_from_path(&self.path).with_context(|| format!("Failed to read {}", self.path))?;
+        let detected = kind.map_or(self.mime_type.as_str(), |kind| kind.mime_type());
+        if detected != self.mime_type {
+            tracing::warn!("{} was labelled {} but contains {}", self.path, self.mime_type, detected);
+        }
+        Ok(detected.to_string())
+    }
+}
+
+/// A post-processing step (crop, enhance, PDF, ...) deriving a variant from the original image
+pub trait Processor: Send + Sync {
+    fn name(&self) -> &str;
+    fn process(&self, original: &Artifact) -> Result<Artifact>;
+}
+
+/// A storage target that artifacts are uploaded to
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &str;
+    async fn upload(&self, artifact: &Artifact) -> Result<String>;
+
+    /// What the sink still holds under an ID `upload` returned, for audits
+    async fn inspect(&self, _id: &str) -> Result<RemoteCopy> {
+        Ok(RemoteCopy::Unchecked)
+    }
+
+    /// Takes down an earlier upload that a new one replaces. Sinks that upload to the same
+    /// path each time have already overwritten it, so by default there is nothing to do.
+    async fn remove(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// How much space the sink has left, when it can tell
+    async fn quota(&self) -> Result<Option<StorageQuota>> {
+        Ok(None)
+    }
+}
+
+/// An uploaded copy as a sink finds it when asked again
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCopy {
+    /// Still there, with its SHA-256 when the sink keeps one
+    Present { sha256: Option<String> },
+    Missing,
+    /// The sink can't look its uploads up
+    Unchecked,
+}
+
+/// The result of uploading one artifact to one sink
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub sink: String,
+    pub artifact: Artifact,
+    pub id: String,
+}
+
+/// An artifact that didn't reach one of the sinks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadFailure {
+    pub sink: String,
+    pub artifact: Artifact,
+    pub error: String,
+}
+
+impl fmt::Display for UploadFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.sink, self.artifact.path, self.error)
+    }
+}
+
+/// Runs post-processing and uploads as two stages connected by a channel.
+///
+/// The original is handed to the upload stage immediately, and each processed
+/// variant follows as soon as it is ready, so uploads of earlier artifacts overlap
+/// with processing of later ones. Every artifact is uploaded to all sinks concurrently.
+pub async fn run(
+    original: Artifact,
+    processors: Vec<Arc<dyn Processor>>,
+    sinks: Vec<Arc<dyn Sink>>,
+) -> Result<Vec<Delivery>> {
+    run_stages(Some(original.clone()), original, processors, sinks, None).await
+}
+
+/// Like [`run`], except that once there are processed variants the original is uploaded as
+/// [`Artifact::original_copy`], or not at all without `keep_original`. Uploads still going at
+/// `deadline` are cut off and reported as failed, next to those that finished in time.
+pub async fn run_processed(
+    original: Artifact,
+    processors: Vec<Arc<dyn Processor>>,
+    sinks: Vec<Arc<dyn Sink>>,
+    processing: &ProcessingConfig,
+    deadline: Option<Instant>,
+) -> Result<Vec<Delivery>> {
+    let upload = match (processors.is_empty(), processing.keep_original) {
+        (true, _) => Some(original.clone()),
+        (false, true) => Some(original.original_copy()?),
+        (false, false) => None,
+    };
+    run_stages(upload, original, processors, sinks, deadline).await
+}
+
+/// The two stages of [`run`]; `upload` is what goes out for the original, if anything
+async fn run_stages(
+    upload: Option<Artifact>,
+    original: Artifact,
+    processors: Vec<Arc<dyn Processor>>,
+    sinks: Vec<Arc<dyn Sink>>,
+    deadline: Option<Instant>,
+) -> Result<Vec<Delivery>> {
+    let (tx, mut rx) = mpsc::channel::<Artifact>(processors.len() + 1);
+    let unprocessed = original.clone();
+
+    // Processing stage: CPU-bound work runs on the blocking pool
+    let processing = tokio::spawn(phases::propagate(async move {
+        if let Some(upload) = upload {
+            tx.send(upload).await.ok();
+        }
+        for processor in processors {
+            let input = original.clone();
+            let name = processor.name().to_string();
+            let phase = format!("post_processing:{}", name);
+            let variant = phases::time(phase, tokio::task::spawn_blocking(move || processor.process(&input)))
+                .await?
+                .with_context(|| format!("Post-processing step '{}' failed", name))?;
+            console::progress!("Processed '{}' into {}", name, variant.path);
+            if tx.send(variant).await.is_err() {
+                break;
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    }));
+
+    // Upload stage: fan each artifact out to every sink as it arrives
+    let cutoff = async move {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(cutoff);
+    let mut uploads = JoinSet::new();
+    // Uploads started and not yet finished, by sink and path
+    let mut in_flight: Vec<(String, Artifact)> = Vec::new();
+    let mut receiving = true;
+    let mut deliveries = Vec::new();
+    let mut failures = Vec::new();
+    while receiving || !uploads.is_empty() {
+        tokio::select! {
+            artifact = rx.recv(), if receiving => {
+                let Some(artifact) = artifact else {
+                    receiving = false;
+                    continue;
+                };
+                for sink in &sinks {
+                    let sink = Arc::clone(sink);
+                    let artifact = artifact.clone();
+                    in_flight.push((sink.name().to_string(), artifact.clone()));
+                    let span = tracing::info_span!("upload", sink = sink.name(), path = %artifact.path);
+                    uploads.spawn(phases::propagate(
+                        async move {
+                            let result = phases::time(format!("upload:{}", sink.name()), sink.upload(&artifact)).await;
+                            (sink.name().to_string(), artifact, result)
+                        }
+                        .instrument(span),
+                    ));
+                }
+            }
+            Some(joined) = uploads.join_next() => {
+                let (sink, artifact, result) = joined?;
+                in_flight.retain(|(name, started)| *name != sink || started.path != artifact.path);
+                match result {
+                    Ok(id) => deliveries.push(Delivery { sink, artifact, id }),
+                    Err(e) => failures.push(UploadFailure {
+                        sink,
+                        artifact,
+                        error: format!("{:#}", e),
+                    }),
+                }
+            }
+            () = &mut cutoff => {
+                // What finished in time is kept; the rest may or may not have landed, so it is
+                // repeated, as are the variants already made and waiting for their turn
+                uploads.abort_all();
+                processing.abort();
+                while let Ok(artifact) = rx.try_recv() {
+                    in_flight.extend(sinks.iter().map(|sink| (sink.name().to_string(), artifact.clone())));
+                }
+                failures.extend(in_flight.into_iter().map(|(sink, artifact)| UploadFailure {
+                    sink,
+                    artifact,
+                    error: "cut off by the upload's time limit".to_string(),
+                }));
+                return Err(CrosswordError::UploadIncomplete {
+                    failures,
+                    delivered: deliveries,
+                }
+                .into());
+            }
+        }
+    }
+
+    // What went out before a step failed is reported with the rest, so it is still recorded
+    if let Err(e) = processing.await? {
+        if sinks.is_empty() {
+            return Err(e);
+        }
+        // The original stands in for the variant that couldn't be made, so a retry still
+        // delivers the crossword
+        failures.extend(sinks.iter().map(|sink| UploadFailure {
+            sink: sink.name().to_string(),
+            artifact: unprocessed.clone(),
+            error: format!("{:#}", e),
+        }));
+    }
+
+    if !failures.is_empty() {
+        // Typed so the caller can record exactly which uploads to repeat
+        return Err(CrosswordError::UploadIncomplete {
+            failures,
+            delivered: deliveries,
+        }
+        .into());
+    }
+
+    Ok(deliveries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_detected_mime_type() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let png = dir.path().join("crossword.jpg");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let artifact = Artifact::new(png.to_string_lossy(), "image/jpeg");
+        assert_eq!(artifact.detected_mime_type().unwrap(), "image/png");
+
+        let pdf = dir.path().join("crosswords.pdf");
+        std::fs::write(&pdf, b"%PDF-1.7\n").unwrap();
+        let artifact = Artifact::new(pdf.to_string_lossy(), "application/octet-stream");
+        assert_eq!(artifact.detected_mime_type().unwrap(), "application/pdf");
+
+        let html = dir.path().join("report.html");
+        std::fs::write(&html, "<p>Crossword</p>").unwrap();
+        let artifact = Artifact::new(html.to_string_lossy(), "text/html");
+        assert_eq!(artifact.detected_mime_type().unwrap(), "text/html");
+
+        assert!(Artifact::new("/nonexistent/file.jpg", "image/jpeg").detected_mime_type().is_err());
+    }
+
+    struct SlowProcessor {
+        suffix: &'static str,
+        delay: Duration,
+    }
+
+    impl Processor for SlowProcessor {
+        fn name(&self) -> &str {
+            self.suffix
+        }
+
+        fn process(&self, original: &Artifact) -> Result<Artifact> {
+            std::thread::sleep(self.delay);
+            Ok(Artifact::new(
+                format!("{}.{}", original.path, self.suffix),
+                "application/octet-stream",
+            ))
+        }
+    }
+
+    struct FailingProcessor;
+
+    impl Processor for FailingProcessor {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn process(&self, _original: &Artifact) -> Result<Artifact> {
+            Err(anyhow::anyhow!("boom"))
+        }
+    }
+
+    struct RecordingSink {
+        name: &'static str,
+        delay: Duration,
+        fail: bool,
+        uploaded: Mutex<Vec<String>>,
+    }
+
+    impl RecordingSink {
+        fn new(name: &'static str, delay: Duration) -> Self {
+            Self {
+                name,
+                delay,
+                fail: false,
+                uploaded: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Sink for RecordingSink {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn upload(&self, artifact: &Artifact) -> Result<String> {
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(anyhow::anyhow!("unavailable"));
+            }
+            self.uploaded.lock().unwrap().push(artifact.path.clone());
+            Ok(format!("{}:{}", self.name, artifact.path))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_without_processors_uploads_original() {
+        let sink = Arc::new(RecordingSink::new("drive", Duration::ZERO));
+        let original = Artifact::new("/tmp/crossword.jpg", "image/jpeg");
+
+        let deliveries = run(original.clone(), Vec::new(), vec![sink.clone()]).await.unwrap();
+
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].artifact, original);
+        assert_eq!(deliveries[0].id, "drive:/tmp/crossword.jpg");
+    }
+
+    #[tokio::test]
+    async fn test_run_uploads_every_variant_to_every_sink() {
+        let drive = Arc::new(RecordingSink::new("drive", Duration::ZERO));
+        let local = Arc::new(RecordingSink::new("local", Duration::ZERO));
+        let processors: Vec<Arc<dyn Processor>> = vec![
+            Arc::new(SlowProcessor { suffix: "crop", delay: Duration::ZERO }),
+            Arc::new(SlowProcessor { suffix: "pdf", delay: Duration::ZERO }),
+        ];
+
+        let deliveries = run(
+            Artifact::new("/tmp/crossword.jpg", "image/jpeg"),
+            processors,
+            vec![drive.clone(), local.clone()],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(deliveries.len(), 6);
+        for sink in [&drive, &local] {
+            let mut uploaded = sink.uploaded.lock().unwrap().clone();
+            uploaded.sort();
+            assert_eq!(
+                uploaded,
+                vec!["/tmp/crossword.jpg", "/tmp/crossword.jpg.crop", "/tmp/crossword.jpg.pdf"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_processed_files_the_original_separately() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("crossword_2024-03-20.jpg");
+        std::fs::write(&path, b"\xFF\xD8\xFF").unwrap();
+        let original = Artifact::new(path.to_string_lossy(), "image/jpeg");
+        let processors = || -> Vec<Arc<dyn Processor>> {
+            vec![Arc::new(SlowProcessor { suffix: "print.pdf", delay: Duration::ZERO })]
+        };
+
+        let sink = Arc::new(RecordingSink::new("drive", Duration::ZERO));
+        let processing = ProcessingConfig::default();
+        let deliveries = run_processed(original.clone(), processors(), vec![sink.clone()], &processing, None)
+            .await
+            .unwrap();
+        let copy = dir.path().join("originals/crossword_2024-03-20_orig.jpg");
+        assert_eq!(std::fs::read(&copy).unwrap(), b"\xFF\xD8\xFF");
+        let kept = deliveries.iter().find(|delivery| delivery.artifact.folder.is_some()).unwrap();
+        assert_eq!(kept.artifact.path, copy.to_string_lossy());
+        assert_eq!(kept.artifact.folder.as_deref(), Some(ORIGINALS_FOLDER));
+        assert_eq!(deliveries.len(), 2);
+
+        // Only the variants
+        let sink = Arc::new(RecordingSink::new("drive", Duration::ZERO));
+        let processing = ProcessingConfig {
+            keep_original: false,
+            ..ProcessingConfig::default()
+        };
+        run_processed(original.clone(), processors(), vec![sink.clone()], &processing, None).await.unwrap();
+        assert_eq!(*sink.uploaded.lock().unwrap(), vec![format!("{}.print.pdf", original.path)]);
+
+        // Nothing to tell apart without processing
+        let sink = Arc::new(RecordingSink::new("drive", Duration::ZERO));
+        run_processed(original.clone(), Vec::new(), vec![sink.clone()], &processing, None).await.unwrap();
+        assert_eq!(*sink.uploaded.lock().unwrap(), vec![original.path]);
+    }
+
+    #[tokio::test]
+    async fn test_run_overlaps_processing_and_uploads() {
+        let stage = Duration::from_millis(200);
+        let processors: Vec<Arc<dyn Processor>> = vec![
+            Arc::new(SlowProcessor { suffix: "crop", delay: stage }),
+            Arc::new(SlowProcessor { suffix: "pdf", delay: stage }),
+        ];
+        let sinks: Vec<Arc<dyn Sink>> = vec![
+            Arc::new(RecordingSink::new("drive", stage)),
+            Arc::new(RecordingSink::new("local", stage)),
+        ];
+
+        let start = Instant::now();
+        run(Artifact::new("/tmp/crossword.jpg", "image/jpeg"), processors, sinks)
+            .await
+            .unwrap();
+
+        // Sequential execution would take 2 processing + 6 upload stages
+        assert!(start.elapsed() < stage * 5);
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_failed_sink() {
+        let mut broken = RecordingSink::new("dropbox", Duration::ZERO);
+        broken.fail = true;
+        let sinks: Vec<Arc<dyn Sink>> = vec![
+            Arc::new(RecordingSink::new("drive", Duration::ZERO)),
+            Arc::new(broken),
+        ];
+
+        let result = run(Artifact::new("/tmp/crossword.jpg", "image/jpeg"), Vec::new(), sinks).await;
+
+        let error = result.unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("dropbox"));
+        assert!(message.contains("unavailable"));
+        match error.downcast_ref::<CrosswordError>() {
+            Some(CrosswordError::UploadIncomplete { failures, delivered }) => {
+                assert_eq!(delivered.len(), 1);
+                assert_eq!(delivered[0].sink, "drive");
+                assert_eq!(failures.len(), 1);
+                assert_eq!(failures[0].sink, "dropbox");
+                assert_eq!(failures[0].artifact.path, "/tmp/crossword.jpg");
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_failed_processor() {
+        let sink = Arc::new(RecordingSink::new("drive", Duration::ZERO));
+        let processors: Vec<Arc<dyn Processor>> = vec![Arc::new(FailingProcessor)];
+
+        let original = Artifact::new("/tmp/crossword.jpg", "image/jpeg");
+        let result = run(original.clone(), processors, vec![sink.clone()]).await;
+
+        // The original is still delivered before the failing step is reached, and reported as such
+        assert_eq!(sink.uploaded.lock().unwrap().len(), 1);
+        let error = result.unwrap_err().downcast::<CrosswordError>().unwrap();
+        let CrosswordError::UploadIncomplete { failures, delivered } = error else {
+            panic!("expected an incomplete upload, got {:?}", error);
+        };
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(failures.len(), 1);
+        assert_eq!((failures[0].sink.as_str(), &failures[0].artifact), ("drive", &original));
+        assert_eq!(failures[0].error, "Post-processing step 'broken' failed: boom");
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+use super::Sink;
+use crate::types::{Warning, WarningKind};
+
+/// The `[quota]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// Share of a storage target's space, in percent, past which each run warns
+    pub warn_percent: u8,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self { warn_percent: 90 }
+    }
+}
+
+impl QuotaConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if !(1..=100).contains(&self.warn_percent) {
+            return vec![format!("[quota] warn_percent must be from 1 to 100, not {}", self.warn_percent)];
+        }
+        Vec::new()
+    }
+}
+
+/// How much space a storage target has left, as it reports it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageQuota {
+    /// The sink reporting it, e.g. `drive`
+    pub sink: String,
+    /// Account the uploads count against, when the sink says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    pub used_bytes: u64,
+    /// Unset for unlimited storage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_bytes: Option<u64>,
+}
+
+impl StorageQuota {
+    pub fn remaining_bytes(&self) -> Option<u64> {
+        self.limit_bytes.map(|limit| limit.saturating_sub(self.used_bytes))
+    }
+
+    /// Share of the limit in use, rounded down; a zero limit counts as full
+    pub fn used_percent(&self) -> Option<u8> {
+        self.limit_bytes.map(|limit| match limit {
+            0 => 100,
+            limit => (self.used_bytes.saturating_mul(100) / limit).min(100) as u8,
+        })
+    }
+}
+
+impl fmt::Display for StorageQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.sink)?;
+        if let Some(account) = &self.account {
+            write!(f, " ({})", account)?;
+        }
+        match (self.limit_bytes, self.remaining_bytes()) {
+            (Some(limit), Some(remaining)) => write!(
+                f,
+                ": {} of {} used, {} left",
+                gigabytes(self.used_bytes),
+                gigabytes(limit),
+                gigabytes(remaining)
+            ),
+            _ => write!(f, ": {} used, no limit", gigabytes(self.used_bytes)),
+        }
+    }
+}
+
+fn gigabytes(bytes: u64) -> String {
+    format!("{:.2} GB", bytes as f64 / 1e9)
+}
+
+/// Asks every sink how full it is, with a warning for each past `warn_percent`. This is
+/// reporting only, so a sink that can't be asked is logged and left out.
+pub async fn check(config: &QuotaConfig, sinks: &[Arc<dyn Sink>]) -> (Vec<StorageQuota>, Vec<Warning>) {
+    let mut quotas = Vec::new();
+    let mut warnings = Vec::new();
+    for sink in sinks {
+        match sink.quota().await {
+            Ok(Some(quota)) => {
+                if let Some(warning) = warning(config, &quota) {
+                    warnings.push(warning);
+                }
+                quotas.push(quota);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Could not read the storage quota of {}: {:#}", sink.name(), e),
+        }
+    }
+    (quotas, warnings)
+}
+
+fn warning(config: &QuotaConfig, quota: &StorageQuota) -> Option<Warning> {
+    let percent = quota.used_percent().filter(|percent| *percent >= config.warn_percent)?;
+    let message = format!("Storage is {}% full on {}; uploads fail once it runs out", percent, quota);
+    tracing::warn!("{}", message);
+    Some(Warning::new(WarningKind::StorageLow, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warns_when_nearly_full() {
+        let mut quota = StorageQuota {
+            sink: "drive".to_string(),
+            account: Some("crosswords@project.iam.gserviceaccount.com".to_string()),
+            used_bytes: 13_950_000_000,
+            limit_bytes: Some(15_000_000_000),
+        };
+        assert_eq!(quota.remaining_bytes(), Some(1_050_000_000));
+        assert_eq!(quota.used_percent(), Some(93));
+        let config = QuotaConfig::default();
+        let low = warning(&config, &quota).unwrap();
+        assert_eq!(
+            low.message,
+            "Storage is 93% full on drive (crosswords@project.iam.gserviceaccount.com): 13.95 GB of 15.00 GB \
+             used, 1.05 GB left; uploads fail once it runs out"
+        );
+
+        quota.used_bytes = 1_000_000_000;
+        assert!(warning(&config, &quota).is_none());
+        quota.limit_bytes = None;
+        assert_eq!(quota.used_percent(), None);
+        assert!(warning(&config, &quota).is_none());
+        assert_eq!(QuotaConfig { warn_percent: 0 }.validate().len(), 1);
+    }
+}
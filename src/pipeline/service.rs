@@ -0,0 +1,99 @@
+//! What the sinks talking to a storage service's web API have in common: the error for an
+//! answer that isn't a success, reading an answer, and telling a busy service from a refusal.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::http::backoff;
+
+/// An error answer from a storage service, kept apart so busy servers can be told from refusals
+#[derive(Debug, thiserror::Error)]
+#[error("{service} answered HTTP {status}: {message}")]
+pub struct ServiceError {
+    /// The service as named in messages, e.g. `Dropbox`
+    pub service: &'static str,
+    pub status: u16,
+    pub message: String,
+}
+
+/// Whether the service answered with `status`
+pub fn is_status(error: &anyhow::Error, status: u16) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<ServiceError>())
+        .any(|e| e.status == status)
+}
+
+/// Throttling, a server error or a dropped connection, which another try may get past,
+/// rather than the request being refused
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    let busy = error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<ServiceError>())
+        .any(|e| e.status == 429 || e.status >= 500);
+    busy || backoff::is_transient_error(error)
+}
+
+/// The JSON body of a successful answer, `null` when it has none, or a [`ServiceError`] with
+/// the explanation `message` finds in the body, or else the body itself
+pub async fn json_answer(
+    service: &'static str,
+    response: reqwest::Response,
+    message: impl Fn(&Value) -> Option<&str>,
+) -> Result<Value> {
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        let message = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|body| message(&body).map(str::to_string))
+            .unwrap_or(text);
+        return Err(ServiceError {
+            service,
+            status: status.as_u16(),
+            message,
+        }
+        .into());
+    }
+    if text.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&text).with_context(|| format!("{} answered with something other than JSON", service))
+}
+
+/// The crossword as the sinks' tests upload it, in `folder` if given
+#[cfg(test)]
+pub fn test_artifact(folder: Option<&str>) -> super::Artifact {
+    super::Artifact {
+        folder: folder.map(str::to_string),
+        ..super::Artifact::new("/tmp/crossword_2024-03-20.jpg", "image/jpeg")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answered(status: u16) -> anyhow::Error {
+        ServiceError {
+            service: "Dropbox",
+            status,
+            message: "path/not_found/..".to_string(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_tells_busy_from_refused() {
+        let busy = answered(503).context("Failed to upload");
+        assert!(is_transient(&busy));
+        assert_eq!(format!("{:#}", busy), "Failed to upload: Dropbox answered HTTP 503: path/not_found/..");
+        assert!(is_transient(&answered(429)));
+
+        let missing = answered(409);
+        assert!(!is_transient(&missing));
+        assert!(is_status(&missing, 409));
+        assert!(!is_status(&missing, 404));
+        assert!(!is_transient(&anyhow::anyhow!("quota exceeded")));
+    }
+}
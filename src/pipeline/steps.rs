@@ -0,0 +1,202 @@
+//! The post-processing steps named in `[processing] pipeline`, applied in order to the
+//! downloaded crossword by one [`Processor`]. Each step works on a list of pages so that
+//! `split` can turn one image into two and `pdf` can bind them together.
+
+use anyhow::{Context, Result};
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{Artifact, Processor};
+use crate::archive::pdf;
+use crate::imaging::backend::{ImageProcessor, INK};
+use crate::storage;
+
+/// Suffix of the processed file's name, before the extension
+const SUFFIX: &str = "processed";
+
+/// One post-processing step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    /// Trims the margins around the ink
+    Autocrop,
+    /// Straightens a page scanned at a slight angle
+    Deskew,
+    Grayscale,
+    /// Stretches the contrast so faint print comes out black on white
+    Enhance,
+    /// Cuts each page in two at the emptiest line near its middle, e.g. grid and clues
+    Split,
+    /// Binds the pages into one PDF; only as the last step
+    Pdf,
+}
+
+/// Problems with the steps of `[processing] pipeline`
+pub fn validate(steps: &[Step]) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(index) = steps.iter().position(|step| *step == Step::Pdf) {
+        if index + 1 != steps.len() {
+            problems.push("[processing] pipeline can only end with \"pdf\"".to_string());
+        }
+    }
+    if steps.contains(&Step::Split) && steps.last() != Some(&Step::Pdf) {
+        problems.push("[processing] pipeline needs \"pdf\" at the end to keep both halves of \"split\"".to_string());
+    }
+    problems
+}
+
+/// Runs the steps one after the other with `processor`, writing the result as
+/// `<name>_processed.<png|pdf>`
+pub struct StepPipeline {
+    steps: Vec<Step>,
+    processor: Arc<dyn ImageProcessor>,
+}
+
+impl StepPipeline {
+    pub fn new(steps: Vec<Step>, processor: Arc<dyn ImageProcessor>) -> Self {
+        Self { steps, processor }
+    }
+}
+
+impl Processor for StepPipeline {
+    fn name(&self) -> &str {
+        "pipeline"
+    }
+
+    fn process(&self, original: &Artifact) -> Result<Artifact> {
+        let data = std::fs::read(&original.path).with_context(|| format!("Failed to read {}", original.path))?;
+        let mut pages = vec![image::load_from_memory(&data).context("Failed to decode the crossword image")?];
+        tracing::debug!(backend = self.processor.name(), "Processing {}", original.path);
+        for step in &self.steps {
+            let processor = self.processor.as_ref();
+            pages = match step {
+                Step::Autocrop => pages.iter().map(|page| processor.autocrop(page)).collect::<Result<_>>()?,
+                Step::Deskew => pages.iter().map(|page| processor.deskew(page)).collect::<Result<_>>()?,
+                Step::Grayscale => pages.iter().map(|page| processor.grayscale(page)).collect::<Result<_>>()?,
+                Step::Enhance => pages.iter().map(|page| processor.enhance(page)).collect::<Result<_>>()?,
+                Step::Split => pages.iter().flat_map(split).collect(),
+                Step::Pdf => break,
+            };
+        }
+
+        let (bytes, extension, mime_type) = if self.steps.last() == Some(&Step::Pdf) {
+            let images = pages
+                .iter()
+                .map(|page| encode(page, ImageFormat::Jpeg))
+                .collect::<Result<Vec<_>>>()?;
+            (pdf::from_images(&images)?, "pdf", "application/pdf")
+        } else {
+            (encode(&pages[0], ImageFormat::Png)?, "png", "image/png")
+        };
+
+        let path = Path::new(&original.path);
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("Invalid filename")?;
+        let output = path.with_file_name(format!("{}_{}.{}", stem, SUFFIX, extension));
+        storage::write_atomic(&output, &bytes)?;
+        Ok(Artifact {
+            date: original.date,
+            ..Artifact::new(output.to_string_lossy(), mime_type)
+        })
+    }
+}
+
+fn encode(page: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    // JPEG has no alpha channel
+    let page = match (format, page.color().has_alpha()) {
+        (ImageFormat::Jpeg, true) => DynamicImage::ImageRgb8(page.to_rgb8()),
+        _ => page.clone(),
+    };
+    let mut bytes = Vec::new();
+    page.write_to(&mut Cursor::new(&mut bytes), format)?;
+    Ok(bytes)
+}
+
+/// The page cut across its longer side where the fewest pixels are ink, between 30% and 70%
+fn split(page: &DynamicImage) -> Vec<DynamicImage> {
+    let luma = page.to_luma8();
+    let (width, height) = page.dimensions();
+    let across_rows = height >= width;
+    let (length, breadth) = if across_rows { (height, width) } else { (width, height) };
+    let inked = |at: u32| {
+        (0..breadth)
+            .filter(|&along| {
+                let (x, y) = if across_rows { (along, at) } else { (at, along) };
+                luma.get_pixel(x, y)[0] < INK
+            })
+            .count()
+    };
+    let middle = length / 2;
+    let Some(cut) = (length * 3 / 10..length * 7 / 10).min_by_key(|&at| (inked(at), at.abs_diff(middle))) else {
+        return vec![page.clone()];
+    };
+    if across_rows {
+        vec![
+            page.crop_imm(0, 0, width, cut),
+            page.crop_imm(0, cut, width, height - cut),
+        ]
+    } else {
+        vec![
+            page.crop_imm(0, 0, cut, height),
+            page.crop_imm(cut, 0, width - cut, height),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::backend::ImageCrate;
+    use image::{Rgb, RgbImage};
+
+    /// A white page with a dark grid block on top and a band of "clues" below, with margins
+    fn page() -> RgbImage {
+        RgbImage::from_fn(200, 300, |x, y| {
+            let grid = (40..160).contains(&x) && (20..140).contains(&y);
+            let clues = (30..170).contains(&x) && (200..260).contains(&y) && y % 4 == 0;
+            if grid || clues {
+                Rgb([60, 40, 40])
+            } else {
+                Rgb([230, 230, 220])
+            }
+        })
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("crossword_2024-03-20.jpg");
+        DynamicImage::ImageRgb8(page())
+            .save_with_format(&path, ImageFormat::Png)
+            .unwrap();
+        let original = Artifact::new(path.to_string_lossy(), "image/png");
+
+        let steps = vec![Step::Autocrop, Step::Grayscale, Step::Enhance];
+        let artifact = StepPipeline::new(steps, Arc::new(ImageCrate)).process(&original).unwrap();
+        assert!(artifact.path.ends_with("crossword_2024-03-20_processed.png"));
+        let processed = image::open(&artifact.path).unwrap();
+        assert_eq!(processed.dimensions(), (140, 237));
+        assert_eq!(processed.color(), image::ColorType::L8);
+        // Stretched to full black and white
+        assert_eq!(processed.to_luma8().get_pixel(10, 10)[0], 0);
+        assert_eq!(processed.to_luma8().get_pixel(0, 0)[0], 255);
+
+        let steps = vec![Step::Autocrop, Step::Split, Step::Pdf];
+        let artifact = StepPipeline::new(steps, Arc::new(ImageCrate)).process(&original).unwrap();
+        assert_eq!(artifact.mime_type, "application/pdf");
+        let pdf = std::fs::read(&artifact.path).unwrap();
+        assert!(String::from_utf8_lossy(&pdf).contains("/Count 2"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&[Step::Autocrop, Step::Pdf]).is_empty());
+        assert_eq!(validate(&[Step::Pdf, Step::Grayscale]).len(), 1);
+        assert_eq!(validate(&[Step::Split]).len(), 1);
+    }
+}
@@ -0,0 +1,359 @@
+//! Keeping the site traffic of each download in S3, and `redetect`, which runs the current
+//! detection over it again. When detection improves, dates archived with the wrong area can
+//! then be repaired from what the site served back then, without asking it again.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::Arc;
+
+use crate::archive;
+use crate::cache::ImageCache;
+use crate::cache::scans::ScanCacheConfig;
+use crate::config::Config;
+use crate::crossword;
+use crate::http::vcr::{Cassette, Replayer};
+use crate::http::{HttpClient, HttpRequest, HttpResponse};
+use crate::imaging::{self, compare::Comparison};
+use crate::pipeline::Sink;
+use crate::status::{csv_field, OutputFormat};
+use crate::storage;
+
+/// The `[raw_archive]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RawArchiveConfig {
+    /// Bucket the pages are kept in; the run needs `s3:PutObject` on it, `redetect` `s3:GetObject`
+    pub bucket: String,
+    /// Key prefix, followed by the date, e.g. `raw-pages/2024-03-20.json`
+    pub prefix: String,
+}
+
+impl Default for RawArchiveConfig {
+    fn default() -> Self {
+        Self {
+            bucket: String::new(),
+            prefix: "raw-pages/".to_string(),
+        }
+    }
+}
+
+impl RawArchiveConfig {
+    pub fn validate(&self) -> Vec<String> {
+        match self.bucket.trim().is_empty() {
+            true => vec!["[raw_archive] needs a bucket".to_string()],
+            false => Vec::new(),
+        }
+    }
+
+    fn key(&self, date: NaiveDate) -> String {
+        format!("{}{}.json", self.prefix, date)
+    }
+}
+
+async fn s3_client() -> S3Client {
+    S3Client::new(&aws_config::defaults(BehaviorVersion::latest()).load().await)
+}
+
+/// Uploads the page maps, articles and images a download of `date` fetched
+pub async fn store(raw: &RawArchiveConfig, date: NaiveDate, cassette: &Cassette) -> Result<()> {
+    let key = raw.key(date);
+    s3_client()
+        .await
+        .put_object()
+        .bucket(&raw.bucket)
+        .key(&key)
+        .content_type("application/json")
+        .body(ByteStream::from(serde_json::to_vec(cassette)?))
+        .send()
+        .await
+        .with_context(|| format!("Failed to upload the raw pages to s3://{}/{}", raw.bucket, key))?;
+    tracing::debug!("Raw pages kept at s3://{}/{}", raw.bucket, key);
+    Ok(())
+}
+
+/// The traffic kept for `date`, if any was
+async fn fetch(s3: &S3Client, raw: &RawArchiveConfig, date: NaiveDate) -> Result<Option<Cassette>> {
+    let key = raw.key(date);
+    let object = s3.get_object().bucket(&raw.bucket).key(&key).send().await;
+    let object = match object {
+        Ok(object) => object,
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read s3://{}/{}", raw.bucket, key)),
+    };
+    let body = object.body.collect().await.context("Failed to read the raw pages")?.into_bytes();
+    serde_json::from_slice(&body)
+        .map(Some)
+        .with_context(|| format!("s3://{}/{} is not a recording of the site", raw.bucket, key))
+}
+
+/// Answers from the kept traffic only. A page the original run skipped, say because the scan
+/// cache knew it lacked the crossword, answers 404 rather than being fetched from the site.
+struct Offline(Replayer);
+
+#[async_trait]
+impl HttpClient for Offline {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        match self.0.send(request).await {
+            Ok(response) => Ok(response),
+            Err(_) => Ok(HttpResponse {
+                status: 404,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// What re-detection made of one date
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Redetection {
+    pub date: NaiveDate,
+    /// `repaired`, `unchanged`, `no raw pages`, `not archived` or `failed`
+    pub outcome: &'static str,
+    pub detail: Option<String>,
+}
+
+impl Redetection {
+    fn new(date: NaiveDate, outcome: &'static str, detail: Option<String>) -> Self {
+        Self { date, outcome, detail }
+    }
+}
+
+/// Re-detects every date from `from` to `to` with the raw pages kept in `raw`
+pub async fn run(
+    config: &Config,
+    raw: &RawArchiveConfig,
+    from: NaiveDate,
+    to: NaiveDate,
+    sinks: Vec<Arc<dyn Sink>>,
+    dry_run: bool,
+) -> Result<Vec<Redetection>> {
+    let s3 = s3_client().await;
+    let mut rows = Vec::new();
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        let row = match fetch(&s3, raw, date).await? {
+            Some(cassette) => redetect(config, date, cassette, sinks.clone(), dry_run).await,
+            None => Redetection::new(date, "no raw pages", None),
+        };
+        tracing::info!("{}: {}", date, row.outcome);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Runs detection for `date` over `cassette` and, when it picks a different image than the
+/// one archived, puts the new one in its place and uploads it again
+pub async fn redetect(
+    config: &Config,
+    date: NaiveDate,
+    cassette: Cassette,
+    sinks: Vec<Arc<dyn Sink>>,
+    dry_run: bool,
+) -> Redetection {
+    let archived = match archive::archive(config, date) {
+        Ok(Some(path)) => path,
+        Ok(None) => return Redetection::new(date, "not archived", None),
+        Err(e) => return Redetection::new(date, "failed", Some(format!("{:#}", e))),
+    };
+    match replace(config, date, cassette, &archived, sinks, dry_run).await {
+        Ok(Some(detail)) => Redetection::new(date, "repaired", Some(detail)),
+        Ok(None) => Redetection::new(date, "unchanged", None),
+        Err(e) => Redetection::new(date, "failed", Some(format!("{:#}", e))),
+    }
+}
+
+/// How the re-detected image differs from the archived one, once it has replaced it
+async fn replace(
+    config: &Config,
+    date: NaiveDate,
+    cassette: Cassette,
+    archived: &std::path::Path,
+    sinks: Vec<Arc<dyn Sink>>,
+    dry_run: bool,
+) -> Result<Option<String>> {
+    // Detection saves and records as it goes, so it runs against a scratch directory; every
+    // page kept is scanned, whatever the scan cache remembers
+    let scratch = tempfile::tempdir().context("Failed to create a scratch directory")?;
+    let scratch_config = Config {
+        output_dir: scratch.path().to_path_buf(),
+        scan_cache: ScanCacheConfig { miss_ttl_secs: 0 },
+        manual_source: None,
+        ..config.clone()
+    };
+    let download = crossword::fetch_crossword(&Offline(Replayer::new(cassette)), date, &scratch_config).await?;
+    let new = fs::read(&download.filename).with_context(|| format!("Failed to read {}", download.filename))?;
+    let old = fs::read(archived).with_context(|| format!("Failed to read {}", archived.display()))?;
+    let comparison = Comparison::new(&old, &new);
+    if comparison.identical() {
+        return Ok(None);
+    }
+    let detail = format!("page {}, {}", download.page, comparison);
+    if dry_run {
+        return Ok(Some(format!("would replace {} ({})", archived.display(), detail)));
+    }
+
+    // Storing the new image forgets where the old one went, so that is read first
+    let uploads = |cache: &ImageCache| cache.entry("crossword", date).map(|image| image.uploads.clone());
+    let replaced = ImageCache::open(&config.output_dir).map(|cache| uploads(&cache).unwrap_or_default())?;
+    storage::save(archived, &new, &config.permissions)?;
+    let mime_type = imaging::sniff_mime_type(&new).unwrap_or(download.mime_type);
+    // Like a download, the cache is a convenience that shouldn't fail the repair
+    let cached = ImageCache::open(&config.output_dir).and_then(|mut cache| cache.store("crossword", date, &new, mime_type));
+    if let Err(e) = cached {
+        tracing::warn!("Could not add the image to the cache: {:#}", e);
+    }
+    crossword::repair(date, config, sinks.clone()).await?;
+
+    // Copies the new uploads didn't overwrite would still pass for the crossword
+    let uploaded = uploads(&ImageCache::open(&config.output_dir)?).unwrap_or_default();
+    for (name, id) in replaced.iter().filter(|(name, id)| uploaded.get(*name) != Some(*id)) {
+        let Some(sink) = sinks.iter().find(|sink| sink.name() == name) else {
+            continue;
+        };
+        if let Err(e) = sink.remove(id).await {
+            tracing::warn!("Could not remove the replaced upload {} from {}: {:#}", id, name, e);
+        }
+    }
+    Ok(Some(detail))
+}
+
+pub fn render(rows: &[Redetection], format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(rows)?),
+        OutputFormat::Csv => {
+            let mut lines = vec!["date,outcome,detail".to_string()];
+            for row in rows {
+                let detail = row.detail.clone().unwrap_or_default();
+                lines.push(format!("{},{},{}", row.date, csv_field(row.outcome), csv_field(&detail)));
+            }
+            Ok(lines.join("\n"))
+        }
+        OutputFormat::Table => {
+            let mut lines: Vec<String> = rows
+                .iter()
+                .map(|row| match &row.detail {
+                    Some(detail) => format!("{}: {}, {}", row.date, row.outcome, detail),
+                    None => format!("{}: {}", row.date, row.outcome),
+                })
+                .collect();
+            let count = |outcome| rows.iter().filter(|row| row.outcome == outcome).count();
+            lines.push(format!(
+                "{} dates: {} repaired, {} unchanged, {} without raw pages, {} failed",
+                rows.len(),
+                count("repaired"),
+                count("unchanged"),
+                count("no raw pages") + count("not archived"),
+                count("failed")
+            ));
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::vcr::Recorder;
+    use crate::pipeline::Artifact;
+    use crate::types::{Rect, TargetProfile};
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    /// Serves a page map with two areas, their articles and their images
+    struct Site;
+
+    #[async_trait]
+    impl HttpClient for Site {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            let mut headers = HeaderMap::new();
+            let body: Vec<u8> = if request.method == reqwest::Method::POST {
+                br#"<map>
+                    <area coords="0,100,1000,900" href="article.php?mid=cartoon"/>
+                    <area coords="0,1625,1000,2775" href="article.php?mid=crossword"/>
+                </map>"#
+                    .to_vec()
+            } else if let Some((_, mid)) = request.url.split_once("mid=") {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+                format!(r#"<div class="slices_container"><img src="encyc/{}.jpg"/></div>"#, mid).into_bytes()
+            } else {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/jpeg"));
+                let marker = if request.url.contains("crossword") { 0xC0 } else { 0xCA };
+                vec![0xFF, 0xD8, 0xFF, 0xE0, marker]
+            };
+            Ok(HttpResponse { status: 200, headers, body })
+        }
+    }
+
+    /// Adds a file on every upload, like Drive
+    #[derive(Default)]
+    struct Uploads {
+        uploaded: Mutex<Vec<String>>,
+        removed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Sink for Uploads {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn upload(&self, artifact: &Artifact) -> Result<String> {
+            let mut uploaded = self.uploaded.lock().unwrap();
+            uploaded.push(artifact.path.clone());
+            Ok(format!("file-{}", uploaded.len()))
+        }
+
+        async fn remove(&self, id: &str) -> Result<()> {
+            self.removed.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repairs_a_wrong_crop_from_the_kept_pages() {
+        let dir = TempDir::new().unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        // A profile loose enough to pick the cartoon above the crossword
+        let loose = Config {
+            output_dir: dir.path().to_path_buf(),
+            pages: vec![1],
+            target: TargetProfile {
+                expected: Rect { x1: 0, y1: 100, x2: 1000, y2: 900 },
+                ..TargetProfile::default()
+            },
+            ..Config::default()
+        };
+        let recorder = Recorder::in_memory(Site);
+        let wrong = crossword::fetch_crossword(&recorder, date, &loose).await.unwrap();
+        assert_eq!(fs::read(&wrong.filename).unwrap()[4], 0xCA);
+        crossword::keep_page(&recorder, date, &loose, wrong.page).await;
+
+        let fixed = Config {
+            target: TargetProfile::default(),
+            ..loose
+        };
+        ImageCache::open(dir.path()).unwrap().record_upload("crossword", date, "recording", "wrong-crop").unwrap();
+        let sink = Arc::new(Uploads::default());
+        let row = redetect(&fixed, date, recorder.cassette(), vec![sink.clone()], true).await;
+        assert_eq!(row.outcome, "repaired", "{:?}", row.detail);
+        assert!(row.detail.unwrap().starts_with("would replace"));
+        assert_eq!(fs::read(&wrong.filename).unwrap()[4], 0xCA);
+
+        let row = redetect(&fixed, date, recorder.cassette(), vec![sink.clone()], false).await;
+        assert_eq!(row.outcome, "repaired", "{:?}", row.detail);
+        assert_eq!(fs::read(&wrong.filename).unwrap()[4], 0xC0);
+        assert_eq!(*sink.uploaded.lock().unwrap(), vec![wrong.filename.clone()]);
+        assert_eq!(*sink.removed.lock().unwrap(), ["wrong-crop"]);
+        let cache = ImageCache::open(dir.path()).unwrap();
+        assert_eq!(cache.entry("crossword", date).unwrap().uploads["recording"], "file-1");
+
+        let row = redetect(&fixed, date, recorder.cassette(), vec![sink], false).await;
+        assert_eq!(row.outcome, "unchanged");
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::NaiveDate;
+use image::imageops::FilterType;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::console;
+use crate::crossword::{self, Download};
+use crate::drive;
+use crate::imaging::compare::ImageSummary;
+use crate::pipeline::{Artifact, Delivery};
+use crate::storage;
+use crate::telemetry::phases::PhaseTiming;
+
+/// Width the embedded preview is scaled down to; big enough to recognise the puzzle
+const THUMBNAIL_WIDTH: u32 = 480;
+
+/// Writes a self-contained HTML page describing the run next to the downloaded image.
+///
+/// The page embeds a thumbnail, so it can be shared on its own, and links back to the
+/// e-paper and to every uploaded copy.
+pub fn write(
+    config: &Config,
+    date: NaiveDate,
+    download: &Download,
+    deliveries: &[Delivery],
+    timings: &[PhaseTiming],
+) -> Result<Artifact> {
+    let image = fs::read(&download.filename)
+        .with_context(|| format!("Failed to read {} for the report", download.filename))?;
+    let html = render(date, download, &image, deliveries, timings)?;
+
+    let path = Path::new(&download.filename).with_extension("html");
+    storage::save(&path, html.as_bytes(), &config.permissions)?;
+    console::progress!("Report saved as: {}", path.display());
+    Ok(Artifact::new(path.to_string_lossy(), "text/html").dated(date))
+}
+
+fn render(
+    date: NaiveDate,
+    download: &Download,
+    image: &[u8],
+    deliveries: &[Delivery],
+    timings: &[PhaseTiming],
+) -> Result<String> {
+    let summary = ImageSummary::new(image);
+    let name = Path::new(&download.filename)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = format!("Hitavada crossword, {}", date.format("%A %-d %B %Y"));
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html lang=\"en\"><head><meta charset=\"utf-8\">")?;
+    writeln!(html, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">")?;
+    writeln!(html, "<title>{}</title>", escape(&title))?;
+    writeln!(
+        html,
+        "<style>body{{font-family:sans-serif;max-width:40em;margin:2em auto;padding:0 1em}}\
+         img{{max-width:100%;border:1px solid #ccc}}th{{text-align:left;padding-right:1em}}</style>"
+    )?;
+    writeln!(html, "</head><body>")?;
+    writeln!(html, "<h1>{}</h1>", escape(&title))?;
+    match thumbnail(image) {
+        Ok(uri) => writeln!(html, "<p><img src=\"{}\" alt=\"{}\"></p>", uri, escape(&name))?,
+        Err(e) => tracing::warn!("Report has no thumbnail: {:#}", e),
+    }
+
+    writeln!(html, "<h2>Details</h2><table>")?;
+    let dimensions = summary
+        .dimensions
+        .map(|(width, height)| format!("{}x{}", width, height))
+        .unwrap_or_else(|| "unknown".to_string());
+    let page = match download.page {
+        crossword::MANUAL_PAGE => "not scanned, URL given by hand".to_string(),
+        page => page.to_string(),
+    };
+    for (label, value) in [
+        ("File", name.as_str()),
+        ("Type", download.mime_type),
+        ("Size", &format!("{} bytes", summary.size)),
+        ("Dimensions", &dimensions),
+        ("Page", &page),
+        ("SHA-256", &summary.sha256),
+    ] {
+        writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, escape(value))?;
+    }
+    writeln!(html, "</table>")?;
+
+    if !download.storage.is_empty() {
+        writeln!(html, "<h2>Storage</h2><ul>")?;
+        for quota in &download.storage {
+            writeln!(html, "<li>{}</li>", escape(&quota.to_string()))?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+
+    if !download.warnings.is_empty() {
+        writeln!(html, "<h2>Warnings</h2><ul>")?;
+        for warning in &download.warnings {
+            writeln!(html, "<li>{}</li>", escape(&warning.message))?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+
+    writeln!(html, "<h2>Timings</h2><table>")?;
+    for timing in timings {
+        writeln!(html, "<tr><th>{}</th><td>{} ms</td></tr>", escape(&timing.phase), timing.elapsed_ms)?;
+    }
+    writeln!(html, "</table>")?;
+
+    writeln!(html, "<h2>Links</h2><ul>")?;
+    if !download.crossword_url.is_empty() {
+        writeln!(html, "<li><a href=\"{}\">Article on the e-paper</a></li>", escape(&download.crossword_url))?;
+    }
+    writeln!(html, "<li><a href=\"{}\">Original image</a></li>", escape(&download.image_url))?;
+    for delivery in deliveries {
+        if let Some(url) = file_url(&delivery.sink, &delivery.id) {
+            writeln!(html, "<li><a href=\"{}\">Copy on {}</a></li>", escape(&url), escape(&delivery.sink))?;
+        }
+    }
+    writeln!(html, "</ul>")?;
+
+    writeln!(
+        html,
+        "<p><small>Generated by {} {} at {}</small></p>",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        chrono::Utc::now().to_rfc3339()
+    )?;
+    writeln!(html, "</body></html>")?;
+    Ok(html)
+}
+
+/// A scaled-down JPEG of the image as a data URI
+fn thumbnail(image: &[u8]) -> Result<String> {
+    let decoded = image::load_from_memory(image).context("Failed to decode image")?;
+    let small = if decoded.width() > THUMBNAIL_WIDTH {
+        let height = decoded.height() * THUMBNAIL_WIDTH / decoded.width();
+        decoded.resize(THUMBNAIL_WIDTH, height.max(1), FilterType::Triangle)
+    } else {
+        decoded
+    };
+
+    let mut jpeg = Vec::new();
+    small.to_rgb8().write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)?;
+    Ok(format!("data:image/jpeg;base64,{}", STANDARD.encode(jpeg)))
+}
+
+/// Where an uploaded copy can be opened, for sinks that have a web view
+pub fn file_url(sink: &str, id: &str) -> Option<String> {
+    match sink {
+        "drive" => drive::file_url(id),
+        _ => None,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::quota::StorageQuota;
+    use crate::types::{Warning, WarningKind};
+    use image::{Rgb, RgbImage};
+    use std::collections::BTreeMap;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(width, height, Rgb([240, 240, 240]))
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn download(filename: String) -> Download {
+        Download {
+            filename,
+            mime_type: "image/png",
+            page: 2,
+            crossword_url: "https://www.ehitavada.com/article.php?mid=Mpage_2&a=b".to_string(),
+            image_url: "https://www.ehitavada.com/encyc/6/20240320/xword.png".to_string(),
+            comparison: None,
+            timings: Vec::new(),
+            link: None,
+            uploads: BTreeMap::new(),
+            puzzle: Default::default(),
+            confidence: None,
+            needs_review: false,
+            storage: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("crossword_2024-03-20.png");
+        fs::write(&image, png(1200, 900)).unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let deliveries = vec![
+            Delivery {
+                sink: "drive".to_string(),
+                artifact: Artifact::new(image.to_string_lossy(), "image/png"),
+                id: "1XyZ".to_string(),
+            },
+            Delivery {
+                sink: "s3".to_string(),
+                artifact: Artifact::new(image.to_string_lossy(), "image/png"),
+                id: "ignored".to_string(),
+            },
+        ];
+        let timings = [
+            PhaseTiming { phase: "page_scan".to_string(), elapsed_ms: 1500 },
+            PhaseTiming { phase: "upload:drive".to_string(), elapsed_ms: 250 },
+        ];
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+
+        let download = Download {
+            warnings: vec![Warning::new(WarningKind::Stitched, "Stitched the crossword from 2 areas")],
+            storage: vec![StorageQuota {
+                sink: "drive".to_string(),
+                account: None,
+                used_bytes: 2_500_000_000,
+                limit_bytes: Some(15_000_000_000),
+            }],
+            ..download(image.to_string_lossy().into_owned())
+        };
+        let artifact = write(&config, date, &download, &deliveries, &timings).unwrap();
+
+        assert_eq!(artifact.mime_type, "text/html");
+        assert!(artifact.path.ends_with("crossword_2024-03-20.html"));
+        let html = fs::read_to_string(&artifact.path).unwrap();
+        assert!(html.contains("Wednesday 20 March 2024"));
+        assert!(html.contains("data:image/jpeg;base64,"));
+        assert!(html.contains("<td>1200x900</td>"));
+        assert!(html.contains("<th>page_scan</th><td>1500 ms</td>"));
+        assert!(html.contains("mid=Mpage_2&amp;a=b"));
+        assert!(html.contains("https://drive.google.com/file/d/1XyZ/view"));
+        assert!(!html.contains("Copy on s3"));
+        assert!(html.contains("<li>drive: 2.50 GB of 15.00 GB used, 12.50 GB left</li>"));
+        assert!(html.contains("<h2>Warnings</h2><ul>\n<li>Stitched the crossword from 2 areas</li>"));
+    }
+
+    #[test]
+    fn test_thumbnail_is_scaled_down() {
+        let uri = thumbnail(&png(1200, 900)).unwrap();
+        let jpeg = STANDARD.decode(uri.trim_start_matches("data:image/jpeg;base64,")).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (THUMBNAIL_WIDTH, 360));
+    }
+}
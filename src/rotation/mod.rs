@@ -0,0 +1,50 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// The `[rotation]` section of the config: whose turn it is to solve, going round the list
+/// one name a day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationConfig {
+    pub solvers: Vec<String>,
+    /// A date on which the first name has their turn
+    pub start: NaiveDate,
+}
+
+impl RotationConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if self.solvers.iter().all(|name| name.trim().is_empty()) {
+            return vec!["[rotation] solvers is empty".to_string()];
+        }
+        Vec::new()
+    }
+
+    /// Who solves the crossword of `date`; dates before `start` count backwards round the list
+    pub fn solver(&self, date: NaiveDate) -> Option<&str> {
+        if self.solvers.is_empty() {
+            return None;
+        }
+        let days = (date - self.start).num_days();
+        let index = days.rem_euclid(self.solvers.len() as i64) as usize;
+        Some(&self.solvers[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solver_rotates_daily() {
+        let rotation: RotationConfig = toml::from_str(
+            r#"solvers = ["Priya", "Rahul", "Aai"]
+start = "2024-03-18""#,
+        )
+        .unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+
+        assert_eq!(rotation.solver(date(18)), Some("Priya"));
+        assert_eq!(rotation.solver(date(20)), Some("Aai"));
+        assert_eq!(rotation.solver(date(21)), Some("Priya"));
+        assert_eq!(rotation.solver(date(17)), Some("Aai"));
+    }
+}
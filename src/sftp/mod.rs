@@ -0,0 +1,268 @@
+//! Dropping the crossword onto an SFTP server, such as a NAS at home, straight from the
+//! Lambda or the command line. The server is reached with a private key, and its host key
+//! is pinned by fingerprint since a Lambda has no `known_hosts` to check it against. Needs
+//! the `sftp` feature.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::OnceCell;
+
+use crate::config::Config;
+use crate::console;
+use crate::credentials::CredentialSource;
+use crate::pipeline::{Artifact, RemoteCopy, Sink, DRY_RUN_ID};
+
+#[cfg(feature = "sftp")]
+mod session;
+
+/// Environment variable holding the private key itself, read before `key_path` and
+/// `key_parameter`
+pub const KEY_ENV: &str = "SFTP_PRIVATE_KEY";
+
+/// Environment variable holding the passphrase of an encrypted private key
+pub const PASSPHRASE_ENV: &str = "SFTP_KEY_PASSPHRASE";
+
+
+/// The `[sftp]` section of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SftpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Directory the crosswords are saved in, e.g. `/volume1/Family/Crosswords`; it must exist
+    pub folder: String,
+    /// SHA-256 fingerprint of the server's host key, as `ssh-keygen -l` prints it
+    pub host_key: String,
+    /// Private key file, e.g. `~/.ssh/id_ed25519` with `~` as `$HOME`, when `SFTP_PRIVATE_KEY` isn't set
+    pub key_path: Option<PathBuf>,
+    /// SSM parameter holding the private key, for the Lambda
+    pub key_parameter: Option<String>,
+}
+
+impl Default for SftpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 22,
+            username: String::new(),
+            folder: String::new(),
+            host_key: String::new(),
+            key_path: None,
+            key_parameter: None,
+        }
+    }
+}
+
+impl SftpConfig {
+    pub fn validate(&self) -> Vec<String> {
+        if !cfg!(feature = "sftp") {
+            return vec!["[sftp] needs a build with `--features sftp`".to_string()];
+        }
+        let mut problems = Vec::new();
+        if self.host.trim().is_empty() || self.username.trim().is_empty() {
+            problems.push("[sftp] needs a host and a username".to_string());
+        }
+        if !self.folder.starts_with('/') {
+            problems.push(format!("[sftp] folder must be an absolute path, not '{}'", self.folder));
+        }
+        if fingerprint(&self.host_key).is_none() {
+            problems.push(format!(
+                "[sftp] host_key must be the server's SHA256 fingerprint, e.g. from \
+                 `ssh-keyscan {} | ssh-keygen -lf -`",
+                self.host
+            ));
+        }
+        let key_sources = [self.key_path.is_some(), self.key_parameter.is_some()];
+        if std::env::var(KEY_ENV).is_err() && !key_sources.contains(&true) {
+            problems.push(format!("[sftp] needs a key_path, a key_parameter or {}", KEY_ENV));
+        }
+        problems
+    }
+}
+
+/// `path` with a leading `~` taken as the `home` directory, as a shell would
+fn expand_home(path: &Path, home: Option<&Path>) -> PathBuf {
+    match (path.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The base64 part of a `SHA256:...` fingerprint, without padding; none for anything else
+fn fingerprint(host_key: &str) -> Option<&str> {
+    let digest = host_key.trim().strip_prefix("SHA256:")?.trim_end_matches('=');
+    // 32 bytes of SHA-256 come out as 43 base64 characters once the padding is gone
+    let valid = digest.len() == 43 && digest.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/');
+    valid.then_some(digest)
+}
+
+/// What the SSH session needs to sign in
+struct Login {
+    key: String,
+    passphrase: Option<String>,
+}
+
+/// Uploads pipeline artifacts to the configured SFTP folder
+pub struct SftpSink {
+    config: SftpConfig,
+    login: OnceCell<Login>,
+    dry_run: bool,
+}
+
+impl SftpSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let sftp = config.sftp.clone().context("[sftp] is not configured")?;
+        Ok(Self {
+            config: sftp,
+            login: OnceCell::new(),
+            dry_run: false,
+        })
+    }
+
+    /// Prints what would be uploaded instead of writing to the server
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Remote path of the artifact, in a subfolder of `folder` for variants
+    fn path(&self, artifact: &Artifact) -> Result<PathBuf> {
+        let name = Path::new(&artifact.path).file_name().context("Invalid filename")?;
+        let mut path = PathBuf::from(&self.config.folder);
+        path.extend(&artifact.folder);
+        Ok(path.join(name))
+    }
+
+    /// The private key, read once per run
+    async fn login(&self) -> Result<&Login> {
+        self.login
+            .get_or_try_init(|| async {
+                let key = if let Ok(key) = std::env::var(KEY_ENV) {
+                    key
+                } else if let Some(path) = &self.config.key_path {
+                    let path = expand_home(path, std::env::var_os("HOME").as_deref().map(Path::new));
+                    std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?
+                } else {
+                    let parameter = self.config.key_parameter.clone().with_context(|| {
+                        format!("{} is not set and [sftp] has neither key_path nor key_parameter", KEY_ENV)
+                    })?;
+                    CredentialSource::Ssm { parameter }.provider().fetch().await?
+                };
+                anyhow::Ok(Login {
+                    key,
+                    passphrase: std::env::var(PASSPHRASE_ENV).ok(),
+                })
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl Sink for SftpSink {
+    fn name(&self) -> &str {
+        "sftp"
+    }
+
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        let path = self.path(artifact)?;
+        let location = format!("{}:{}", self.config.host, path.display());
+        if self.dry_run {
+            console::progress!("Dry run: would upload {} to {}", artifact.path, location);
+            return Ok(DRY_RUN_ID.to_string());
+        }
+
+        let body = std::fs::read(&artifact.path).with_context(|| format!("Failed to read {}", artifact.path))?;
+        let login = self.login().await?;
+        let (config, key, passphrase) = (self.config.clone(), login.key.clone(), login.passphrase.clone());
+        let remote = path.clone();
+        tokio::task::spawn_blocking(move || session::put(&config, &key, passphrase.as_deref(), &remote, &body))
+            .await?
+            .with_context(|| format!("Failed to upload to {}", location))?;
+        console::progress!("File uploaded to {}", location);
+        Ok(path.display().to_string())
+    }
+
+    async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+        if id == DRY_RUN_ID {
+            return Ok(RemoteCopy::Missing);
+        }
+        let login = self.login().await?;
+        let (config, key, passphrase) = (self.config.clone(), login.key.clone(), login.passphrase.clone());
+        let remote = PathBuf::from(id);
+        // The file is read back and hashed, as SFTP has no checksums of its own
+        let sha256 = tokio::task::spawn_blocking(move || session::sha256(&config, &key, passphrase.as_deref(), &remote))
+            .await?
+            .with_context(|| format!("Failed to look up {} on {}", id, self.config.host))?;
+        Ok(match sha256 {
+            Some(sha256) => RemoteCopy::Present { sha256: Some(sha256) },
+            None => RemoteCopy::Missing,
+        })
+    }
+}
+
+/// Stands in for the SSH session in builds without the `sftp` feature, which reject an
+/// `[sftp]` section when validating the config
+#[cfg(not(feature = "sftp"))]
+mod session {
+    use anyhow::Result;
+    use std::path::Path;
+
+    use super::SftpConfig;
+
+    pub fn put(_config: &SftpConfig, _key: &str, _passphrase: Option<&str>, _path: &Path, _body: &[u8]) -> Result<()> {
+        anyhow::bail!("This build has no SFTP support; rebuild with `--features sftp`")
+    }
+
+    pub fn sha256(_config: &SftpConfig, _key: &str, _passphrase: Option<&str>, _path: &Path) -> Result<Option<String>> {
+        anyhow::bail!("This build has no SFTP support; rebuild with `--features sftp`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::service;
+
+    #[test]
+    fn test_fingerprint() {
+        let key = "SHA256:nThbg6kXUpJWGl7E1IGOCspRomTxdCARLviKw6E5SY8";
+        assert_eq!(fingerprint(key), Some(&key[7..]));
+        assert_eq!(fingerprint(&format!("{}=", key)), Some(&key[7..]));
+        assert!(fingerprint("MD5:16:27:ac:a5:76:28:2d:36:63:1b:56:4d:eb:df:a6:48").is_none());
+        assert!(fingerprint("").is_none());
+    }
+
+    #[test]
+    fn test_key_path_under_home() {
+        let home = Some(Path::new("/home/crosswords"));
+        let expanded = expand_home(Path::new("~/.ssh/id_ed25519"), home);
+        assert_eq!(expanded, Path::new("/home/crosswords/.ssh/id_ed25519"));
+        assert_eq!(expand_home(Path::new("~"), home), Path::new("/home/crosswords/"));
+        assert_eq!(expand_home(Path::new("/etc/ssh/key"), home), Path::new("/etc/ssh/key"));
+        // Another user's home isn't looked up
+        assert_eq!(expand_home(Path::new("~admin/key"), home), Path::new("~admin/key"));
+        assert_eq!(expand_home(Path::new("~/key"), None), Path::new("~/key"));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_paths() {
+        let config = Config {
+            sftp: Some(SftpConfig {
+                host: "nas.local".to_string(),
+                username: "crosswords".to_string(),
+                folder: "/volume1/Crosswords".to_string(),
+                ..SftpConfig::default()
+            }),
+            ..Config::default()
+        };
+        let sink = SftpSink::new(&config).unwrap().dry_run(true);
+        let artifact = service::test_artifact(None);
+        assert_eq!(sink.upload(&artifact).await.unwrap(), DRY_RUN_ID);
+        assert_eq!(sink.path(&artifact).unwrap(), Path::new("/volume1/Crosswords/crossword_2024-03-20.jpg"));
+        let path = sink.path(&service::test_artifact(Some("originals"))).unwrap();
+        assert_eq!(path, Path::new("/volume1/Crosswords/originals/crossword_2024-03-20.jpg"));
+    }
+}
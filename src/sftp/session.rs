@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use ssh2::{ErrorCode, HashType, RenameFlags, Session, Sftp};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{fingerprint, SftpConfig};
+
+/// How long connecting and each SFTP operation may take before the upload is given up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// SFTP's status for a path that doesn't exist
+const NO_SUCH_FILE: i32 = 2;
+
+/// Signs in to the server, after checking its host key against the pinned fingerprint
+fn connect(config: &SftpConfig, key: &str, passphrase: Option<&str>) -> Result<Sftp> {
+    let address = (config.host.as_str(), config.port);
+    let tcp = std::net::ToSocketAddrs::to_socket_addrs(&address)
+        .with_context(|| format!("Could not resolve {}", config.host))?
+        .find_map(|address| TcpStream::connect_timeout(&address, TIMEOUT).ok())
+        .with_context(|| format!("Could not connect to {}:{}", config.host, config.port))?;
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(TIMEOUT.as_millis() as u32);
+    session.handshake().context("SSH handshake failed")?;
+
+    let presented = session.host_key_hash(HashType::Sha256).map(|hash| STANDARD_NO_PAD.encode(hash));
+    if presented.as_deref() != fingerprint(&config.host_key) {
+        anyhow::bail!(
+            "{}'s host key is SHA256:{}, not the pinned {}; check the server before updating host_key",
+            config.host,
+            presented.unwrap_or_default(),
+            config.host_key
+        );
+    }
+    session
+        .userauth_pubkey_memory(&config.username, None, key.trim(), passphrase)
+        .with_context(|| format!("{} refused the key for {}", config.host, config.username))?;
+    Ok(session.sftp()?)
+}
+
+fn is_missing(error: &ssh2::Error) -> bool {
+    error.code() == ErrorCode::SFTP(NO_SUCH_FILE)
+}
+
+/// Writes `body` to `path`, creating its folder if need be. The file is written under a
+/// temporary name and renamed over `path`, so a dropped connection never leaves half an
+/// image where the crossword should be.
+pub fn put(config: &SftpConfig, key: &str, passphrase: Option<&str>, path: &Path, body: &[u8]) -> Result<()> {
+    let sftp = connect(config, key, passphrase)?;
+    let folder = path.parent().context("Remote path has no folder")?;
+    if let Err(e) = sftp.stat(folder) {
+        if !is_missing(&e) {
+            return Err(e).with_context(|| format!("Failed to look up {}", folder.display()));
+        }
+        sftp.mkdir(folder, 0o755)
+            .with_context(|| format!("Failed to create {}", folder.display()))?;
+    }
+
+    let name = path.file_name().context("Remote path has no file name")?.to_string_lossy();
+    let partial = folder.join(format!(".{}.part", name));
+    let mut file = sftp.create(&partial).with_context(|| format!("Failed to create {}", partial.display()))?;
+    file.write_all(body)?;
+    drop(file);
+    // Not every server knows the overwrite flag, so the old file goes first when it is refused
+    let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+    if sftp.rename(&partial, path, Some(flags)).is_err() {
+        match sftp.unlink(path) {
+            Err(e) if !is_missing(&e) => return Err(e).with_context(|| format!("Failed to replace {}", path.display())),
+            _ => {}
+        }
+        sftp.rename(&partial, path, None)
+            .with_context(|| format!("Failed to move the upload to {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// SHA-256 of the file at `path`, or none when it is gone
+pub fn sha256(config: &SftpConfig, key: &str, passphrase: Option<&str>, path: &Path) -> Result<Option<String>> {
+    let sftp = connect(config, key, passphrase)?;
+    let mut file = match sftp.open(path) {
+        Ok(file) => file,
+        Err(e) if is_missing(&e) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(Some(format!("{:x}", Sha256::digest(&data))))
+}
@@ -0,0 +1,189 @@
+//! Handing out the crossword as a link that stops working after a while, for passing on in
+//! notifications without leaving a permanent share behind. In `s3` mode the crossword goes
+//! to an S3 bucket instead of Drive and the link is a presigned URL; in `drive` mode it
+//! still goes to Drive and one account is given read access that expires.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::drive::{self, DriveSink};
+use crate::dropbox::DropboxSink;
+use crate::local::LocalSink;
+use crate::onedrive::OneDriveSink;
+use crate::webdav::WebDavSink;
+use crate::pipeline::{Delivery, Sink};
+use crate::sftp::SftpSink;
+
+mod s3;
+
+pub use s3::S3Sink;
+
+/// The longest a presigned S3 URL can be valid for
+const MAX_S3_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Where the time-limited link points
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignedUrlMode {
+    /// Upload to Drive as usual, without a link
+    #[default]
+    Off,
+    /// Upload to S3 instead of Drive and return a presigned URL
+    S3,
+    /// Upload to Drive and give `email` read access until the link expires
+    Drive,
+}
+
+/// The `[signed_url]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignedUrlConfig {
+    pub mode: SignedUrlMode,
+    /// Bucket uploaded to in `s3` mode
+    pub bucket: Option<String>,
+    /// Prepended to the file name to make the object key
+    pub prefix: String,
+    /// Account given access in `drive` mode; Drive only lets access of a named user expire
+    pub email: Option<String>,
+    /// How long the link works
+    pub expires_secs: u64,
+}
+
+impl Default for SignedUrlConfig {
+    fn default() -> Self {
+        Self {
+            mode: SignedUrlMode::Off,
+            bucket: None,
+            prefix: String::new(),
+            email: None,
+            expires_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl SignedUrlConfig {
+    pub fn expires_in(&self) -> Duration {
+        Duration::from_secs(self.expires_secs)
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.mode == SignedUrlMode::Off {
+            return problems;
+        }
+        if self.expires_secs == 0 {
+            problems.push("[signed_url] expires_secs must be more than 0".to_string());
+        }
+        match self.mode {
+            SignedUrlMode::S3 if self.bucket.as_deref().unwrap_or_default().is_empty() => {
+                problems.push("[signed_url] s3 mode needs a bucket".to_string())
+            }
+            SignedUrlMode::S3 if self.expires_in() > MAX_S3_EXPIRY => {
+                problems.push("[signed_url] presigned S3 URLs can't be valid for more than 7 days".to_string())
+            }
+            SignedUrlMode::Drive if self.email.as_deref().unwrap_or_default().is_empty() => {
+                problems.push("[signed_url] drive mode needs the email of the account to share with".to_string())
+            }
+            _ => {}
+        }
+        problems
+    }
+}
+
+/// Where a run's files are uploaded: S3 in `s3` mode; otherwise Dropbox, OneDrive, WebDAV,
+/// SFTP and the local archive when configured, each getting every file, and Drive when none is
+pub fn sinks(config: &Config, dry_run: bool) -> Result<Vec<Arc<dyn Sink>>> {
+    if config.signed_url.mode == SignedUrlMode::S3 {
+        return Ok(vec![Arc::new(S3Sink::new(&config.signed_url)?.dry_run(dry_run))]);
+    }
+    let mut sinks: Vec<Arc<dyn Sink>> = Vec::new();
+    if config.dropbox.is_some() {
+        sinks.push(Arc::new(DropboxSink::new(config)?.dry_run(dry_run)));
+    }
+    if config.onedrive.is_some() {
+        sinks.push(Arc::new(OneDriveSink::new(config)?.dry_run(dry_run)));
+    }
+    if config.webdav.is_some() {
+        sinks.push(Arc::new(WebDavSink::new(config)?.dry_run(dry_run)));
+    }
+    if config.sftp.is_some() {
+        sinks.push(Arc::new(SftpSink::new(config)?.dry_run(dry_run)));
+    }
+    if config.local.is_some() {
+        sinks.push(Arc::new(LocalSink::new(config)?.dry_run(dry_run)));
+    }
+    if sinks.is_empty() {
+        sinks.push(Arc::new(DriveSink::new(config)?.dry_run(dry_run)));
+    }
+    Ok(sinks)
+}
+
+/// The time-limited link to the crossword among its deliveries, if the mode makes one. The
+/// crossword is already uploaded, so a link that can't be made is only worth a warning.
+pub async fn link(config: &SignedUrlConfig, deliveries: &[Delivery]) -> Option<String> {
+    for delivery in deliveries {
+        match (config.mode, delivery.sink.as_str()) {
+            (SignedUrlMode::S3, "s3") => match s3::presigned_url(config, &delivery.id).await {
+                Ok(url) => return url,
+                Err(e) => tracing::warn!("No link to the crossword: {:#}", e),
+            },
+            (SignedUrlMode::Drive, "drive") => return drive::file_url(&delivery.id),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Artifact;
+
+    #[test]
+    fn test_validate() {
+        let mut config = SignedUrlConfig::default();
+        assert!(config.validate().is_empty());
+
+        config.mode = SignedUrlMode::S3;
+        assert_eq!(config.validate(), vec!["[signed_url] s3 mode needs a bucket"]);
+        config.bucket = Some("crosswords".to_string());
+        config.expires_secs = 30 * 24 * 60 * 60;
+        assert_eq!(
+            config.validate(),
+            vec!["[signed_url] presigned S3 URLs can't be valid for more than 7 days"]
+        );
+
+        let config: SignedUrlConfig = toml::from_str(r#"mode = "drive""#).unwrap();
+        assert_eq!(config.expires_in(), Duration::from_secs(86400));
+        assert_eq!(
+            config.validate(),
+            vec!["[signed_url] drive mode needs the email of the account to share with"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_link() {
+        let delivery = |sink: &str, id: &str| Delivery {
+            sink: sink.to_string(),
+            artifact: Artifact::new("/tmp/crossword_2024-03-20.jpg", "image/jpeg"),
+            id: id.to_string(),
+        };
+        let deliveries = vec![delivery("drive", "file-id"), delivery("s3", "hitavada/crossword_2024-03-20.jpg")];
+
+        let mut config = SignedUrlConfig::default();
+        assert_eq!(link(&config, &deliveries).await, None);
+
+        config.mode = SignedUrlMode::S3;
+        config.bucket = Some("crosswords".to_string());
+        assert_eq!(link(&config, &[delivery("s3", crate::pipeline::DRY_RUN_ID)]).await, None);
+
+        config.mode = SignedUrlMode::Drive;
+        assert_eq!(
+            link(&config, &deliveries).await.as_deref(),
+            Some("https://drive.google.com/file/d/file-id/view")
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use super::SignedUrlConfig;
+use crate::console;
+use crate::pipeline::{Artifact, Sink, DRY_RUN_ID};
+
+
+/// Uploads pipeline artifacts to a private S3 bucket, reporting each by its object key; the
+/// presigned URL is made from the key when a link is wanted, so it is always fresh
+pub struct S3Sink {
+    bucket: String,
+    prefix: String,
+    expires_in: Duration,
+    dry_run: bool,
+}
+
+impl S3Sink {
+    pub fn new(config: &SignedUrlConfig) -> Result<Self> {
+        let bucket = config.bucket.clone().context("[signed_url] s3 mode needs a bucket")?;
+        Ok(Self {
+            bucket,
+            prefix: config.prefix.clone(),
+            expires_in: config.expires_in(),
+            dry_run: false,
+        })
+    }
+
+    /// Prints what would be uploaded instead of writing to the bucket
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    fn key(&self, artifact: &Artifact) -> Result<String> {
+        let name = Path::new(&artifact.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Invalid filename")?;
+        match &artifact.folder {
+            Some(folder) => Ok(format!("{}{}/{}", self.prefix, folder, name)),
+            None => Ok(format!("{}{}", self.prefix, name)),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for S3Sink {
+    fn name(&self) -> &str {
+        "s3"
+    }
+
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        let key = self.key(artifact)?;
+        let mime_type = artifact.detected_mime_type()?;
+        if self.dry_run {
+            console::progress!("Dry run: would upload {} to s3://{}/{}", artifact.path, self.bucket, key);
+            console::progress!("  MIME type: {}", mime_type);
+            console::progress!("  Link valid for: {}s", self.expires_in.as_secs());
+            return Ok(DRY_RUN_ID.to_string());
+        }
+
+        let config = aws_config::defaults(BehaviorVersion::latest()).load().await;
+        let client = S3Client::new(&config);
+        let body = fs::read(&artifact.path).with_context(|| format!("Failed to read {}", artifact.path))?;
+        client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .content_type(mime_type)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .with_context(|| format!("Failed to upload to s3://{}/{}", self.bucket, key))?;
+        console::progress!("File uploaded to s3://{}/{}", self.bucket, key);
+        Ok(key)
+    }
+}
+
+/// A presigned URL for the object an upload was reported with, valid for `expires_secs`
+/// from now; dry runs have none
+pub async fn presigned_url(config: &SignedUrlConfig, key: &str) -> Result<Option<String>> {
+    if key == DRY_RUN_ID {
+        return Ok(None);
+    }
+    let bucket = config.bucket.as_deref().context("[signed_url] s3 mode needs a bucket")?;
+    let aws = aws_config::defaults(BehaviorVersion::latest()).load().await;
+    presign(&S3Client::new(&aws), bucket, key, config.expires_in()).await.map(Some)
+}
+
+async fn presign(client: &S3Client, bucket: &str, key: &str, expires_in: Duration) -> Result<String> {
+    let presigned = client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .presigned(PresigningConfig::expires_in(expires_in)?)
+        .await
+        .with_context(|| format!("Failed to presign the download URL of s3://{}/{}", bucket, key))?;
+    Ok(presigned.uri().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_upload() {
+        let config = SignedUrlConfig {
+            bucket: Some("crosswords".to_string()),
+            prefix: "hitavada/".to_string(),
+            ..SignedUrlConfig::default()
+        };
+        let sink = S3Sink::new(&config).unwrap().dry_run(true);
+        let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        fs::write(file.path(), [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+        let path = file.path().to_string_lossy().to_string();
+
+        let artifact = Artifact::new(path, "image/png");
+        assert!(sink.key(&artifact).unwrap().starts_with("hitavada/"));
+        assert_eq!(sink.upload(&artifact).await.unwrap(), DRY_RUN_ID);
+
+        let original = Artifact {
+            path: "/tmp/originals/crossword_2024-03-20_orig.jpg".to_string(),
+            folder: Some("originals".to_string()),
+            ..artifact
+        };
+        assert_eq!(sink.key(&original).unwrap(), "hitavada/originals/crossword_2024-03-20_orig.jpg");
+    }
+
+    #[tokio::test]
+    async fn test_presigns_the_recorded_key() {
+        use aws_sdk_s3::config::{Credentials, Region};
+
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("ap-south-1"))
+            .credentials_provider(Credentials::new("AKIDEXAMPLE", "secret", None, None, "test"))
+            .build();
+        let client = S3Client::from_conf(config);
+        let key = "hitavada/crossword_2024-03-20.jpg";
+        let url = presign(&client, "crosswords", key, Duration::from_secs(3600)).await.unwrap();
+        let object = "https://crosswords.s3.ap-south-1.amazonaws.com/hitavada/crossword_2024-03-20.jpg?";
+        assert!(url.starts_with(object), "{}", url);
+        assert!(url.contains("X-Amz-Expires=3600"), "{}", url);
+        assert_eq!(presigned_url(&SignedUrlConfig::default(), DRY_RUN_ID).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,337 @@
+use anyhow::Result;
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::http::HttpRequest;
+
+/// Page image path under the site root for current editions
+pub const DEFAULT_PAGE_IMAGE: &str = "encyc/6/%Y%m%d/Mpage_{page}.jpg";
+
+/// Prefix of the main edition's pages, in their image paths and in the page map form
+pub const DEFAULT_PREFIX: &str = "Mpage";
+
+/// Placeholder for the page number in page image and mapping templates
+const PAGE_PLACEHOLDER: &str = "{page}";
+
+/// Placeholder for the rendered page image path in mapping templates
+const PAGE_IMAGE_PLACEHOLDER: &str = "{page_image}";
+
+/// Ways the archive has written the date in its `encyc` paths, tried in turn when a file isn't
+/// found where an article points
+const DATE_ENCODINGS: &[&str] = &["%Y/%m/%d", "%Y%m%d", "%d%m%Y", "%Y-%m-%d", "%Y/%-m/%-d", "%d/%m/%Y"];
+
+/// Root of the e-paper, which article links are relative to
+pub const DEFAULT_BASE_URL: &str = "https://www.ehitavada.com/";
+
+/// Endpoint answering with a page's image map
+pub const DEFAULT_MAPPING_URL: &str = "https://www.ehitavada.com/val.php";
+
+/// Where the e-paper keeps its files, which has changed over the years
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SiteConfig {
+    /// Root of the e-paper, which article links on the page maps are relative to
+    pub base_url: String,
+    /// strftime template of a page image's path, with `{page}` for the page number
+    pub page_image: String,
+    /// Layouts used by older editions, each up to and including its cutoff date
+    pub eras: Vec<UrlEra>,
+    /// The request asking for a page's image map
+    pub mapping: MappingConfig,
+    /// Most requests to the site in flight at once, over every region and date of the process
+    pub max_concurrent_requests: usize,
+}
+
+/// Form posted to fetch a page map. Field values are strftime templates that may
+/// also use `{page}` and `{page_image}`; they are URL-encoded when the body is built.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingConfig {
+    pub url: String,
+    /// Name and value template of each form field, in the order they are sent
+    pub fields: Vec<(String, String)>,
+}
+
+impl Default for MappingConfig {
+    fn default() -> Self {
+        let fields = [
+            ("get_mapping_coords", "https://ehitavada.com/{page_image}"),
+            ("get_mapping_coords_date", "%Y-%m-%d"),
+            ("get_mapping_coords_prefix", "Mpage"),
+            ("get_mapping_coords_page", "{page}"),
+        ];
+        Self {
+            url: DEFAULT_MAPPING_URL.to_string(),
+            fields: fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// A past layout of the archive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlEra {
+    /// Last edition published with this layout
+    pub until: NaiveDate,
+    pub page_image: String,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            page_image: DEFAULT_PAGE_IMAGE.to_string(),
+            eras: Vec::new(),
+            mapping: MappingConfig::default(),
+            max_concurrent_requests: 4,
+        }
+    }
+}
+
+impl SiteConfig {
+    /// The page image template in use on `date`: the earliest era still covering it,
+    /// or the current layout
+    pub fn page_image_template(&self, date: NaiveDate) -> &str {
+        self.eras
+            .iter()
+            .filter(|era| date <= era.until)
+            .min_by_key(|era| era.until)
+            .map_or(&self.page_image, |era| &era.page_image)
+    }
+
+    /// Full URL of a link on a page map, e.g. `article.php?mid=Mpage_2024-03-20_2b`
+    pub fn url(&self, href: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), href.trim_start_matches('/'))
+    }
+
+    /// Path of a page's image for `date`, e.g. `encyc/6/20240320/Mpage_8.jpg`
+    pub fn page_image_path(&self, date: NaiveDate, page: u32) -> String {
+        let template = self.page_image_template(date).replace(PAGE_PLACEHOLDER, &page.to_string());
+        date.format(&template).to_string()
+    }
+
+    /// The form request for one page's image map
+    pub fn mapping_request(&self, date: NaiveDate, page: u32) -> Result<HttpRequest> {
+        self.prefixed_mapping_request(date, page, DEFAULT_PREFIX)
+    }
+
+    /// The form request for one page's image map among the pages named with `prefix`, such as a
+    /// supplement's: the main edition's prefix is swapped for it in the page image and the form
+    pub fn prefixed_mapping_request(&self, date: NaiveDate, page: u32, prefix: &str) -> Result<HttpRequest> {
+        let page_image = self.page_image_path(date, page).replace(DEFAULT_PREFIX, prefix);
+        let fields: Vec<(&str, String)> = self
+            .mapping
+            .fields
+            .iter()
+            .map(|(name, template)| {
+                let value = date
+                    .format(template)
+                    .to_string()
+                    .replace(DEFAULT_PREFIX, prefix)
+                    .replace(PAGE_IMAGE_PLACEHOLDER, &page_image)
+                    .replace(PAGE_PLACEHOLDER, &page.to_string());
+                (name.as_str(), value)
+            })
+            .collect();
+
+        Ok(HttpRequest::post(&self.mapping.url, serde_urlencoded::to_string(fields)?))
+    }
+
+    /// `url` with its date, written one of the archive's ways, rewritten each of the other ways;
+    /// nothing when the date can't be found in it
+    pub fn date_variants(url: &str, date: NaiveDate) -> Vec<String> {
+        let encodings: Vec<String> = DATE_ENCODINGS.iter().map(|format| date.format(format).to_string()).collect();
+        let Some(found) = encodings.iter().find(|encoded| url.contains(encoded.as_str())) else {
+            return Vec::new();
+        };
+        let mut variants: Vec<String> = Vec::new();
+        for encoded in &encodings {
+            let variant = url.replacen(found.as_str(), encoded, 1);
+            if variant != url && !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+        variants
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let templates = std::iter::once(("site.page_image".to_string(), &self.page_image)).chain(
+            self.eras
+                .iter()
+                .map(|era| (format!("site era until {}", era.until), &era.page_image)),
+        );
+        for (name, template) in templates {
+            if let Err(e) = validate_template(template) {
+                problems.push(format!("{}: {}", name, e));
+            }
+        }
+
+        for (name, url) in [("site.base_url", &self.base_url), ("site.mapping.url", &self.mapping.url)] {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!("{} '{}' is not an http(s) URL", name, url));
+            }
+        }
+        if self.max_concurrent_requests == 0 {
+            problems.push("site.max_concurrent_requests must be at least 1".to_string());
+        }
+        if self.mapping.fields.is_empty() {
+            problems.push("site.mapping.fields is empty".to_string());
+        }
+        for (name, template) in &self.mapping.fields {
+            if StrftimeItems::new(template).any(|item| matches!(item, Item::Error)) {
+                problems.push(format!("site.mapping field '{}': '{}' has an invalid % specifier", name, template));
+            }
+        }
+        problems
+    }
+}
+
+fn validate_template(template: &str) -> Result<()> {
+    if !template.contains(PAGE_PLACEHOLDER) {
+        return Err(anyhow::anyhow!("'{}' has no {} placeholder", template, PAGE_PLACEHOLDER));
+    }
+    if StrftimeItems::new(template).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow::anyhow!("'{}' has an invalid % specifier", template));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn site() -> SiteConfig {
+        SiteConfig {
+            eras: vec![
+                UrlEra {
+                    until: date(2021, 6, 30),
+                    page_image: "encyc/5/%Y/%m/%d/Mpage_{page}.jpg".to_string(),
+                },
+                UrlEra {
+                    until: date(2019, 12, 31),
+                    page_image: "encyc/4/%d%m%Y/page{page}.jpg".to_string(),
+                },
+            ],
+            ..SiteConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_links_are_under_the_base_url() {
+        let site = SiteConfig {
+            base_url: "https://archive.example.com/hitavada".to_string(),
+            ..SiteConfig::default()
+        };
+        assert_eq!(site.url("article.php?mid=2b"), "https://archive.example.com/hitavada/article.php?mid=2b");
+        assert_eq!(SiteConfig::default().url("/article.php"), "https://www.ehitavada.com/article.php");
+    }
+
+    #[test]
+    fn test_default_page_image_path() {
+        let path = SiteConfig::default().page_image_path(date(2024, 3, 20), 8);
+        assert_eq!(path, "encyc/6/20240320/Mpage_8.jpg");
+    }
+
+    #[test]
+    fn test_eras_apply_up_to_their_cutoff() {
+        let site = site();
+
+        assert_eq!(site.page_image_path(date(2019, 5, 1), 2), "encyc/4/01052019/page2.jpg");
+        assert_eq!(site.page_image_path(date(2019, 12, 31), 2), "encyc/4/31122019/page2.jpg");
+        assert_eq!(site.page_image_path(date(2020, 1, 1), 2), "encyc/5/2020/01/01/Mpage_2.jpg");
+        assert_eq!(site.page_image_path(date(2021, 7, 1), 2), "encyc/6/20210701/Mpage_2.jpg");
+    }
+
+    #[test]
+    fn test_parse_eras() {
+        let site: SiteConfig = toml::from_str(
+            r#"
+            [[eras]]
+            until = "2021-06-30"
+            page_image = "encyc/5/%Y/%m/%d/Mpage_{page}.jpg"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(site.page_image, DEFAULT_PAGE_IMAGE);
+        assert_eq!(site.eras[0].until, date(2021, 6, 30));
+    }
+
+    #[test]
+    fn test_default_mapping_request() {
+        let request = SiteConfig::default().mapping_request(date(2024, 3, 20), 8).unwrap();
+
+        assert_eq!(request.url, DEFAULT_MAPPING_URL);
+        assert_eq!(
+            request.body.as_deref(),
+            Some(
+                "get_mapping_coords=https%3A%2F%2Fehitavada.com%2Fencyc%2F6%2F20240320%2FMpage_8.jpg\
+                 &get_mapping_coords_date=2024-03-20&get_mapping_coords_prefix=Mpage&get_mapping_coords_page=8"
+            )
+        );
+    }
+
+    #[test]
+    fn test_prefixed_mapping_request() {
+        let request = SiteConfig::default().prefixed_mapping_request(date(2024, 3, 24), 3, "Spage").unwrap();
+
+        assert_eq!(
+            request.body.as_deref(),
+            Some(
+                "get_mapping_coords=https%3A%2F%2Fehitavada.com%2Fencyc%2F6%2F20240324%2FSpage_3.jpg\
+                 &get_mapping_coords_date=2024-03-24&get_mapping_coords_prefix=Spage&get_mapping_coords_page=3"
+            )
+        );
+    }
+
+    #[test]
+    fn test_date_variants() {
+        let url = "https://www.ehitavada.com/encyc/6/2024/03/05/Mpage_2_slice_1.jpg";
+        let variants = SiteConfig::date_variants(url, date(2024, 3, 5));
+
+        assert_eq!(variants[0], "https://www.ehitavada.com/encyc/6/20240305/Mpage_2_slice_1.jpg");
+        assert!(variants.contains(&"https://www.ehitavada.com/encyc/6/2024/3/5/Mpage_2_slice_1.jpg".to_string()));
+        assert!(!variants.contains(&url.to_string()));
+        assert!(SiteConfig::date_variants(url, date(2024, 3, 6)).is_empty());
+    }
+
+    #[test]
+    fn test_mapping_override() {
+        let site: SiteConfig = toml::from_str(
+            r#"
+            [mapping]
+            url = "https://www.ehitavada.com/api/map.php"
+            fields = [["image", "{page_image}"], ["edition date", "%d/%m/%Y"], ["p", "{page}"]]
+            "#,
+        )
+        .unwrap();
+
+        let request = site.mapping_request(date(2024, 3, 20), 2).unwrap();
+
+        assert_eq!(request.url, "https://www.ehitavada.com/api/map.php");
+        assert_eq!(
+            request.body.as_deref(),
+            Some("image=encyc%2F6%2F20240320%2FMpage_2.jpg&edition+date=20%2F03%2F2024&p=2")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut site = site();
+        site.page_image = "encyc/6/%Y%m%d/Mpage.jpg".to_string();
+        site.eras[0].page_image = "encyc/%Q/{page}.jpg".to_string();
+
+        let problems = site.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("no {page} placeholder"));
+        assert!(problems[1].contains("until 2021-06-30"));
+    }
+}
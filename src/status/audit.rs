@@ -0,0 +1,341 @@
+//! Checks that every archived crossword is still where the manifest says it went, unchanged,
+//! and with `fix` puts back the copies that went missing or were damaged: from the saved file
+//! or the image cache where possible, from the site as a last resort.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{csv_field, OutputFormat};
+use crate::archive;
+use crate::cache::{CachedImage, ImageCache};
+use crate::config::Config;
+use crate::console;
+use crate::crossword;
+use crate::http::HttpClient;
+use crate::pipeline::{self, Artifact, RemoteCopy, Sink};
+
+/// Target of the copy in the output directory, next to the sinks' names
+pub const LOCAL: &str = "local";
+
+/// How one copy of one date's crossword was found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub date: NaiveDate,
+    /// `local` for the saved file, or the sink the crossword was uploaded to
+    pub target: String,
+    /// `ok`, `missing`, `mismatch` (the checksum differs) or `unchecked`
+    pub state: &'static str,
+    pub detail: Option<String>,
+    /// How the copy was put back, when it was
+    pub fixed: Option<String>,
+}
+
+impl Finding {
+    fn new(date: NaiveDate, target: &str, state: &'static str, detail: Option<String>) -> Self {
+        Self {
+            date,
+            target: target.to_string(),
+            state,
+            detail,
+            fixed: None,
+        }
+    }
+
+    /// Missing or damaged, and not put back
+    pub fn broken(&self) -> bool {
+        matches!(self.state, "missing" | "mismatch") && self.fixed.is_none()
+    }
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Audits every date in the manifest against the saved files and `sinks`. With `fix`, broken
+/// copies are restored; a copy that can't be restored is reported, not treated as an error.
+pub async fn audit(
+    client: &dyn HttpClient,
+    config: &Config,
+    sinks: &[Arc<dyn Sink>],
+    fix: bool,
+) -> Result<Vec<Finding>> {
+    let images: Vec<(NaiveDate, CachedImage)> = ImageCache::open(&config.output_dir)?
+        .entries("crossword")
+        .map(|(date, image)| (date, image.clone()))
+        .collect();
+
+    let mut findings = Vec::new();
+    for (date, image) in images {
+        let (mut local, mut good) = check_local(config, date, &image)?;
+        if fix && local.state != "ok" {
+            match restore_local(client, config, date, &image).await {
+                Ok((path, how)) => {
+                    local.fixed = Some(how);
+                    good = Some(path);
+                }
+                Err(e) => local.detail = Some(format!("{:#}", e)),
+            }
+        }
+        findings.push(local);
+
+        for (name, id) in &image.uploads {
+            let Some(sink) = sinks.iter().find(|sink| sink.name() == name) else {
+                findings.push(Finding::new(date, name, "unchecked", Some("no longer configured".to_string())));
+                continue;
+            };
+            let mut finding = match sink.inspect(id).await {
+                Ok(RemoteCopy::Present { sha256: Some(sha256) }) if sha256 != image.uploaded_sha256(name) => {
+                    Finding::new(date, name, "mismatch", Some(format!("{} holds {}", id, sha256)))
+                }
+                Ok(RemoteCopy::Present { .. }) => Finding::new(date, name, "ok", None),
+                Ok(RemoteCopy::Missing) => Finding::new(date, name, "missing", Some(format!("{} is gone", id))),
+                Ok(RemoteCopy::Unchecked) => {
+                    Finding::new(date, name, "unchecked", Some("the sink can't look uploads up".to_string()))
+                }
+                Err(e) => Finding::new(date, name, "unchecked", Some(format!("{:#}", e))),
+            };
+            if fix && finding.broken() {
+                match &good {
+                    Some(path) => match reupload(config, date, path, &image.mime_type, sink).await {
+                        // A damaged copy that wasn't overwritten would still pass for the crossword
+                        Ok(new) if finding.state == "mismatch" && new != *id => match sink.remove(id).await {
+                            Ok(()) => finding.fixed = Some(format!("uploaded again as {}, {} removed", new, id)),
+                            Err(e) => {
+                                finding.fixed = Some(format!("uploaded again as {}", new));
+                                finding.detail = Some(format!("{} is still there: {:#}", id, e));
+                            }
+                        },
+                        Ok(new) => finding.fixed = Some(format!("uploaded again as {}", new)),
+                        Err(e) => finding.detail = Some(format!("{:#}", e)),
+                    },
+                    None => finding.detail = Some("no good copy left to upload".to_string()),
+                }
+            }
+            findings.push(finding);
+        }
+    }
+    Ok(findings)
+}
+
+/// The saved file's finding, and the file when it matches the manifest
+fn check_local(config: &Config, date: NaiveDate, image: &CachedImage) -> Result<(Finding, Option<PathBuf>)> {
+    let Some(path) = archive::archived_file(config, date)? else {
+        return Ok((Finding::new(date, LOCAL, "missing", None), None));
+    };
+    let data = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if sha256(&data) != image.sha256 {
+        let detail = format!("{} differs from the archived image", path.display());
+        return Ok((Finding::new(date, LOCAL, "mismatch", Some(detail)), None));
+    }
+    Ok((Finding::new(date, LOCAL, "ok", None), Some(path)))
+}
+
+/// Puts the saved file back from the image cache, or downloads it again when the cached
+/// copy is gone or damaged too. Says where it came from.
+async fn restore_local(
+    client: &dyn HttpClient,
+    config: &Config,
+    date: NaiveDate,
+    image: &CachedImage,
+) -> Result<(PathBuf, String)> {
+    if let Some(path) = archive::archived_file(config, date)? {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    let cached = ImageCache::open(&config.output_dir)?
+        .get("crossword", date)
+        .and_then(|(object, _)| fs::read(object).ok())
+        .filter(|data| sha256(data) == image.sha256);
+    if cached.is_some() {
+        let path = archive::archive(config, date)?.context("The image cache lost the crossword")?;
+        return Ok((path, "restored from the image cache".to_string()));
+    }
+
+    console::progress!("Downloading {} again, as no good copy is left", date);
+    let download = crossword::fetch_crossword(client, date, config).await?;
+    // Storing the new download forgot where the old one went
+    let mut cache = ImageCache::open(&config.output_dir)?;
+    for (sink, id) in &image.uploads {
+        cache.record_delivery("crossword", date, sink, id, image.uploaded_sha256(sink))?;
+    }
+    let data = fs::read(&download.filename)?;
+    let how = match sha256(&data) == image.sha256 {
+        true => "downloaded again from the site",
+        false => "downloaded again from the site, which now serves a different image",
+    };
+    Ok((PathBuf::from(download.filename), how.to_string()))
+}
+
+/// Uploads `path` to `sink` again and records the new ID
+async fn reupload(
+    config: &Config,
+    date: NaiveDate,
+    path: &Path,
+    mime_type: &str,
+    sink: &Arc<dyn Sink>,
+) -> Result<String> {
+    let artifact = Artifact::new(path.to_string_lossy(), mime_type).dated(date);
+    let deliveries = pipeline::run(artifact, Vec::new(), vec![sink.clone()]).await?;
+    let delivery = deliveries.first().context("The upload returned no file")?;
+    ImageCache::open(&config.output_dir)?.record_upload("crossword", date, &delivery.sink, &delivery.id)?;
+    Ok(delivery.id.clone())
+}
+
+pub fn render(findings: &[Finding], format: OutputFormat) -> Result<String> {
+    let fields = |finding: &Finding| {
+        [
+            finding.date.to_string(),
+            finding.target.clone(),
+            finding.state.to_string(),
+            finding.fixed.clone().unwrap_or_default(),
+            finding.detail.clone().unwrap_or_default(),
+        ]
+    };
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(findings)?),
+        OutputFormat::Csv => {
+            let mut lines = vec!["date,target,state,fixed,detail".to_string()];
+            for finding in findings {
+                lines.push(fields(finding).iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+            }
+            Ok(lines.join("\n"))
+        }
+        OutputFormat::Table => {
+            let mut lines: Vec<String> = findings
+                .iter()
+                .map(|finding| {
+                    let [date, target, state, fixed, detail] = fields(finding);
+                    let mut line = format!("{} {}: {}", date, target, state);
+                    for note in [detail, fixed].iter().filter(|note| !note.is_empty()) {
+                        line += &format!(", {}", note);
+                    }
+                    line
+                })
+                .collect();
+            let count = |state| findings.iter().filter(|finding| finding.state == state).count();
+            let fixed = findings.iter().filter(|finding| finding.fixed.is_some()).count();
+            lines.push(format!(
+                "{} copies audited: {} ok, {} missing, {} mismatched, {} unchecked, {} fixed",
+                findings.len(),
+                count("ok"),
+                count("missing"),
+                count("mismatch"),
+                count("unchecked"),
+                fixed
+            ));
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpRequest, HttpResponse};
+    use async_trait::async_trait;
+    use chrono::Datelike;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    /// Holds the copies it was given, by ID, and uploads under new ones
+    struct MemorySink {
+        copies: Mutex<BTreeMap<String, String>>,
+    }
+
+    #[async_trait]
+    impl Sink for MemorySink {
+        fn name(&self) -> &str {
+            "drive"
+        }
+
+        async fn upload(&self, artifact: &Artifact) -> Result<String> {
+            let mut copies = self.copies.lock().unwrap();
+            let id = format!("file-{}", copies.len() + 1);
+            copies.insert(id.clone(), sha256(&fs::read(&artifact.path)?));
+            Ok(id)
+        }
+
+        async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+            Ok(match self.copies.lock().unwrap().get(id) {
+                Some(sha256) => RemoteCopy::Present { sha256: Some(sha256.clone()) },
+                None => RemoteCopy::Missing,
+            })
+        }
+
+        async fn remove(&self, id: &str) -> Result<()> {
+            self.copies.lock().unwrap().remove(id);
+            Ok(())
+        }
+    }
+
+    struct Offline;
+
+    #[async_trait]
+    impl HttpClient for Offline {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            Err(anyhow::anyhow!("Unexpected request to {}", request.url))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_restores_and_reuploads() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        for day in [19, 20, 21] {
+            let data = format!("puzzle {}", day).into_bytes();
+            fs::write(dir.path().join(format!("crossword_2024-03-{}.jpg", day)), &data).unwrap();
+            cache.store("crossword", date(day), &data, "image/jpeg").unwrap();
+            cache.record_upload("crossword", date(day), "drive", &format!("old-{}", day)).unwrap();
+            cache.record_upload("crossword", date(day), "s3", "https://bucket/link").unwrap();
+        }
+        // The 19th is still in Drive, as a processed variant; the 20th was deleted there and
+        // damaged locally; the 21st was damaged in Drive
+        let variant = sha256(b"puzzle 19, sharpened");
+        cache.record_delivery("crossword", date(19), "drive", "old-19", &variant).unwrap();
+        let sink = Arc::new(MemorySink { copies: Mutex::new(BTreeMap::new()) });
+        sink.copies.lock().unwrap().insert("old-19".to_string(), variant);
+        sink.copies.lock().unwrap().insert("old-21".to_string(), sha256(b"truncated"));
+        fs::write(dir.path().join("crossword_2024-03-20.jpg"), b"truncated").unwrap();
+        let sinks: Vec<Arc<dyn Sink>> = vec![sink.clone()];
+
+        let findings = audit(&Offline, &config, &sinks, false).await.unwrap();
+        let states: Vec<_> = findings.iter().map(|f| (f.date.day0() + 1, f.target.as_str(), f.state)).collect();
+        assert_eq!(
+            states,
+            [
+                (19, "local", "ok"),
+                (19, "drive", "ok"),
+                (19, "s3", "unchecked"),
+                (20, "local", "mismatch"),
+                (20, "drive", "missing"),
+                (20, "s3", "unchecked"),
+                (21, "local", "ok"),
+                (21, "drive", "mismatch"),
+                (21, "s3", "unchecked"),
+            ]
+        );
+
+        let findings = audit(&Offline, &config, &sinks, true).await.unwrap();
+        assert!(findings.iter().all(|finding| !finding.broken()));
+        assert_eq!(findings[3].fixed.as_deref(), Some("restored from the image cache"));
+        assert_eq!(findings[4].fixed.as_deref(), Some("uploaded again as file-3"));
+        assert_eq!(fs::read(dir.path().join("crossword_2024-03-20.jpg")).unwrap(), b"puzzle 20");
+        let cache = ImageCache::open(dir.path()).unwrap();
+        assert_eq!(cache.entry("crossword", date(20)).unwrap().uploads["drive"], "file-3");
+        assert_eq!(findings[7].fixed.as_deref(), Some("uploaded again as file-4, old-21 removed"));
+        assert!(!sink.copies.lock().unwrap().contains_key("old-21"));
+
+        let report = render(&findings, OutputFormat::Table).unwrap();
+        assert!(report.ends_with("9 copies audited: 3 ok, 1 missing, 2 mismatched, 3 unchecked, 3 fixed"));
+    }
+}
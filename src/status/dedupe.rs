@@ -0,0 +1,287 @@
+//! Clearing out the extra copies that runs done again left in the Drive folder, as every
+//! upload to Drive adds a file even when one of the same name is there. Files are grouped by
+//! the date their name was made for, and within a date by name or identical content; one copy
+//! of each group is kept and the rest go to the trash, from where Drive can restore them for
+//! 30 days.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use super::{csv_field, OutputFormat};
+use crate::cache::ImageCache;
+use crate::config::Config;
+use crate::console;
+use crate::drive::{DriveFile, DriveSink};
+use crate::naming;
+
+/// What became of one copy in a group of duplicates
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Duplicate {
+    pub date: NaiveDate,
+    pub name: String,
+    pub id: String,
+    /// `kept`, `trashed`, `would trash` on a dry run, or `failed`
+    pub action: &'static str,
+    /// Why the copy was kept, or why trashing it failed
+    pub detail: Option<String>,
+}
+
+/// Copies of one file, and the one to keep
+#[derive(Debug)]
+struct Group {
+    keep: DriveFile,
+    /// Why `keep` is the best copy
+    reason: &'static str,
+    duplicates: Vec<DriveFile>,
+}
+
+/// The date each name the configured template gives the crossword stands for, by its stem,
+/// for every date in the cache. The puzzle number and setter are filled in as recorded, so
+/// names like `crossword_#10234_2024-03-20.jpg` are found too.
+fn template_dates(config: &Config, cache: &ImageCache) -> Result<HashMap<String, NaiveDate>> {
+    let locale = naming::parse_locale(&config.locale)?;
+    let mut dates = HashMap::new();
+    for (date, image) in cache.entries("crossword") {
+        let name = naming::render_puzzle_filename(&config.filename_template, locale, date, "jpg", &image.puzzle)?;
+        dates.insert(stem(&name).to_string(), date);
+    }
+    Ok(dates)
+}
+
+fn stem(name: &str) -> &str {
+    Path::new(name).file_stem().and_then(|stem| stem.to_str()).unwrap_or(name)
+}
+
+/// Groups the folder's files into copies of the same file, leaving out files without a date
+/// and files with no copies. A file's date is the one the template made its name for, or else
+/// the first `YYYY-MM-DD` in it, as in reports.
+fn group(
+    files: Vec<DriveFile>,
+    template_dates: &HashMap<String, NaiveDate>,
+) -> BTreeMap<NaiveDate, Vec<Vec<DriveFile>>> {
+    let mut dates: BTreeMap<NaiveDate, Vec<Vec<DriveFile>>> = BTreeMap::new();
+    for file in files {
+        let date = template_dates.get(stem(&file.name)).copied();
+        let Some(date) = date.or_else(|| naming::date_in(&file.name)) else {
+            continue;
+        };
+        let groups = dates.entry(date).or_default();
+        let same_content = |other: &DriveFile| file.sha256.is_some() && other.sha256 == file.sha256;
+        let same = |other: &DriveFile| other.name == file.name || same_content(other);
+        match groups.iter_mut().find(|group| group.iter().any(same)) {
+            Some(group) => group.push(file),
+            None => groups.push(vec![file]),
+        }
+    }
+    for groups in dates.values_mut() {
+        groups.retain(|group| group.len() > 1);
+    }
+    dates.retain(|_, groups| !groups.is_empty());
+    dates
+}
+
+/// Picks the copy to keep: one matching the archived crossword, the one the manifest links
+/// to if it is among them; otherwise the manifest's copy; otherwise the oldest, whose link is
+/// the one most likely to have been shared
+fn choose(cache: &ImageCache, date: NaiveDate, mut copies: Vec<DriveFile>) -> Group {
+    let archived = cache.entry("crossword", date);
+    let sha256 = archived.map(|image| image.sha256.as_str());
+    let recorded = archived.and_then(|image| image.uploads.get("drive")).map(String::as_str);
+    // Oldest first, so ties go to the copy that has been shared longest
+    copies.sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+
+    let matching = |file: &DriveFile| sha256.is_some() && file.sha256.as_deref() == sha256;
+    let (index, reason) = if let Some(index) = copies
+        .iter()
+        .position(|file| matching(file) && Some(file.id.as_str()) == recorded)
+        .or_else(|| copies.iter().position(matching))
+    {
+        (index, "matches the archive")
+    } else if let Some(index) = copies.iter().position(|file| Some(file.id.as_str()) == recorded) {
+        (index, "in the manifest")
+    } else {
+        (0, "oldest")
+    };
+    let keep = copies.remove(index);
+    Group {
+        keep,
+        reason,
+        duplicates: copies,
+    }
+}
+
+/// Trashes the duplicates in the Drive folder, or with `dry_run` only reports them. When the
+/// copy kept is the archived crossword, or the one the manifest linked to went to the trash,
+/// the manifest is pointed at the copy kept.
+pub async fn dedupe(config: &Config, drive: &DriveSink, dry_run: bool) -> Result<Vec<Duplicate>> {
+    let mut cache = ImageCache::open(&config.output_dir)?;
+    let files = drive.files().await?;
+    console::progress!("Found {} files in the Drive folder", files.len());
+
+    let mut rows = Vec::new();
+    for (date, groups) in group(files, &template_dates(config, &cache)?) {
+        for copies in groups {
+            let group = choose(&cache, date, copies);
+            rows.push(Duplicate {
+                date,
+                name: group.keep.name.clone(),
+                id: group.keep.id.clone(),
+                action: "kept",
+                detail: Some(group.reason.to_string()),
+            });
+            let recorded = cache.entry("crossword", date).and_then(|image| image.uploads.get("drive")).cloned();
+            let mut recorded_trashed = false;
+            for duplicate in group.duplicates {
+                let (action, detail) = match dry_run {
+                    true => ("would trash", None),
+                    false => match drive.trash(&duplicate.id).await {
+                        Ok(()) => {
+                            recorded_trashed |= recorded.as_ref() == Some(&duplicate.id);
+                            ("trashed", None)
+                        }
+                        Err(e) => ("failed", Some(format!("{:#}", e))),
+                    },
+                };
+                rows.push(Duplicate {
+                    date,
+                    name: duplicate.name,
+                    id: duplicate.id,
+                    action,
+                    detail,
+                });
+            }
+            // The manifest follows the archived copy, and never links to one in the trash
+            let relink = group.reason == "matches the archive" || recorded_trashed;
+            if !dry_run && relink && recorded.as_ref() != Some(&group.keep.id) {
+                cache.record_upload("crossword", date, "drive", &group.keep.id)?;
+            }
+        }
+    }
+    Ok(rows)
+}
+
+pub fn render(rows: &[Duplicate], format: OutputFormat) -> Result<String> {
+    let fields = |row: &Duplicate| {
+        [
+            row.date.to_string(),
+            row.name.clone(),
+            row.id.clone(),
+            row.action.to_string(),
+            row.detail.clone().unwrap_or_default(),
+        ]
+    };
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(rows)?),
+        OutputFormat::Csv => {
+            let mut lines = vec!["date,name,id,action,detail".to_string()];
+            for row in rows {
+                lines.push(fields(row).iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+            }
+            Ok(lines.join("\n"))
+        }
+        OutputFormat::Table => {
+            let mut lines: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let [date, name, id, action, detail] = fields(row);
+                    match detail.is_empty() {
+                        true => format!("{} {} ({}): {}", date, name, id, action),
+                        false => format!("{} {} ({}): {}, {}", date, name, id, action, detail),
+                    }
+                })
+                .collect();
+            let count = |action| rows.iter().filter(|row| row.action == action).count();
+            lines.push(format!(
+                "{} duplicates of {} files: {} trashed, {} to trash, {} failed",
+                rows.len() - count("kept"),
+                count("kept"),
+                count("trashed"),
+                count("would trash"),
+                count("failed")
+            ));
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use sha2::{Digest, Sha256};
+
+    fn file(id: &str, name: &str, content: &[u8], day: u32) -> DriveFile {
+        DriveFile {
+            id: id.to_string(),
+            name: name.to_string(),
+            sha256: Some(format!("{:x}", Sha256::digest(content))),
+            created: Utc.with_ymd_and_hms(2024, 3, day, 6, 0, 0).single(),
+        }
+    }
+
+    #[test]
+    fn test_keeps_the_archived_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date, b"fixed", "image/jpeg").unwrap();
+        cache.record_upload("crossword", date, "drive", "wrong-crop").unwrap();
+
+        let files = vec![
+            file("wrong-crop", "crossword_2024-03-20.jpg", b"wrong", 20),
+            file("rerun", "crossword_2024-03-20.jpg", b"fixed", 21),
+            file("rerun-again", "crossword_2024-03-20.jpg", b"fixed", 22),
+            file("report", "report_2024-03-20.html", b"report", 20),
+            file("other-day", "crossword_2024-03-21.jpg", b"fixed", 21),
+            file("no-date", "notes.txt", b"notes", 21),
+        ];
+        let mut groups = group(files, &HashMap::new());
+        assert_eq!(groups.len(), 1);
+        let copies = groups.remove(&date).unwrap().remove(0);
+        let group = choose(&cache, date, copies);
+        assert_eq!((group.keep.id.as_str(), group.reason), ("rerun", "matches the archive"));
+        let trashed: Vec<&str> = group.duplicates.iter().map(|file| file.id.as_str()).collect();
+        assert_eq!(trashed, ["wrong-crop", "rerun-again"]);
+    }
+
+    #[test]
+    fn test_same_content_under_another_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let cache = ImageCache::open(dir.path()).unwrap();
+        let files = vec![
+            file("first", "crossword_2024-03-20.jpg", b"crossword", 20),
+            file("renamed", "Crossword 2024-03-20.jpg", b"crossword", 21),
+        ];
+        let copies = group(files, &HashMap::new()).remove(&date).unwrap().remove(0);
+        let group = choose(&cache, date, copies);
+        // Nothing archived to go by, so the copy shared longest stays
+        assert_eq!((group.keep.id.as_str(), group.reason), ("first", "oldest"));
+        assert_eq!(group.duplicates.len(), 1);
+    }
+
+    #[test]
+    fn test_dates_from_the_filename_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            filename_template: "Crossword_%A_%d-%b-%Y".to_string(),
+            ..Config::default()
+        };
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date, b"crossword", "image/jpeg").unwrap();
+
+        let files = vec![
+            file("first", "Crossword_Wednesday_20-Mar-2024.jpg", b"crossword", 20),
+            file("again", "Crossword_Wednesday_20-Mar-2024.jpg", b"crossword", 21),
+            file("unknown", "Crossword_Thursday_21-Mar-2024.jpg", b"other", 21),
+        ];
+        let groups = group(files, &template_dates(&config, &cache).unwrap());
+        assert_eq!(groups.keys().collect::<Vec<_>>(), [&date]);
+        assert_eq!(groups[&date][0].len(), 2);
+    }
+}
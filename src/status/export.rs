@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::csv_field;
+use crate::archive;
+use crate::cache::ImageCache;
+use crate::config::Config;
+use crate::imaging;
+use crate::naming;
+use crate::report;
+
+/// How the manifest is written out
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// One archived crossword, as recorded in the image cache or found in the output directory
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    pub date: NaiveDate,
+    /// The copy in the output directory; empty when only the cache still has it
+    pub file: Option<String>,
+    pub size: Option<u64>,
+    pub sha256: String,
+    pub mime_type: String,
+    /// Link to open the uploaded copy
+    pub link: Option<String>,
+    /// Every sink the image was uploaded to
+    pub uploaded_to: Vec<String>,
+    /// The puzzle number and setter read off the image, with `[ocr]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub setter: Option<String>,
+}
+
+/// Every date ever archived into the output directory, oldest first. The image cache has
+/// them all unless it was cleared or the files came from before it was kept, so crosswords
+/// saved in the directory that it doesn't know are added from there.
+pub fn manifest(config: &Config) -> Result<Vec<ManifestEntry>> {
+    let cache = ImageCache::open(&config.output_dir)?;
+    let mut entries = Vec::new();
+    for (date, image) in cache.entries("crossword") {
+        let file = archive::archived_file(config, date)?;
+        let size = match (&file, cache.get("crossword", date)) {
+            (Some(file), _) => fs::metadata(file).ok(),
+            (None, Some((object, _))) => fs::metadata(object).ok(),
+            (None, None) => None,
+        };
+        entries.push(ManifestEntry {
+            date,
+            file: file.map(|file| file.display().to_string()),
+            size: size.map(|metadata| metadata.len()),
+            sha256: image.sha256.clone(),
+            mime_type: image.mime_type.clone(),
+            link: image.uploads.iter().find_map(|(sink, id)| report::file_url(sink, id)),
+            uploaded_to: image.uploads.keys().cloned().collect(),
+            number: image.puzzle.number,
+            setter: image.puzzle.setter.clone(),
+        });
+    }
+    entries.extend(uncached_files(config, &cache)?);
+    entries.sort_by_key(|entry| entry.date);
+    Ok(entries)
+}
+
+/// The crosswords saved in the output directory on dates the image cache has nothing for,
+/// one per date. Without a record of them, nothing is known of uploads or the puzzle.
+fn uncached_files(config: &Config, cache: &ImageCache) -> Result<Vec<ManifestEntry>> {
+    let dir = match fs::read_dir(&config.output_dir) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", config.output_dir.display())),
+    };
+    let mut files = BTreeMap::new();
+    for entry in dir {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        let Some((mime_type, _)) = imaging::EXTENSIONS.iter().find(|(_, known)| *known == extension) else {
+            continue;
+        };
+        let Some(date) = file_date(&config.filename_template, name) else {
+            continue;
+        };
+        if path.is_file() && cache.entry("crossword", date).is_none() {
+            files.entry(date).or_insert((path, *mime_type));
+        }
+    }
+    files
+        .into_iter()
+        .map(|(date, (path, mime_type))| {
+            let data = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok(ManifestEntry {
+                date,
+                file: Some(path.display().to_string()),
+                size: Some(data.len() as u64),
+                sha256: format!("{:x}", Sha256::digest(&data)),
+                mime_type: mime_type.to_string(),
+                link: None,
+                uploaded_to: Vec::new(),
+                number: None,
+                setter: None,
+            })
+        })
+        .collect()
+}
+
+/// The date a saved file's name was made for: read back with the filename template when it
+/// has no puzzle details in it, or else the first `YYYY-MM-DD` in the name
+fn file_date(template: &str, name: &str) -> Option<NaiveDate> {
+    let stem = Path::new(name).file_stem()?.to_str()?;
+    let parsed = match template.contains('{') {
+        true => None,
+        false => NaiveDate::parse_from_str(stem, template).ok(),
+    };
+    parsed.or_else(|| naming::date_in(name))
+}
+
+pub fn render(entries: &[ManifestEntry], format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(entries)?),
+        ExportFormat::Csv => {
+            let mut lines = vec!["date,file,size,sha256,mime_type,link,uploaded_to,number,setter".to_string()];
+            for entry in entries {
+                let fields = [
+                    entry.date.to_string(),
+                    entry.file.clone().unwrap_or_default(),
+                    entry.size.map(|size| size.to_string()).unwrap_or_default(),
+                    entry.sha256.clone(),
+                    entry.mime_type.clone(),
+                    entry.link.clone().unwrap_or_default(),
+                    entry.uploaded_to.join(" "),
+                    entry.number.map(|number| number.to_string()).unwrap_or_default(),
+                    entry.setter.clone().unwrap_or_default(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                lines.push(fields.join(","));
+            }
+            // Spreadsheets expect the last row to be terminated too
+            Ok(lines.join("\r\n") + "\r\n")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PuzzleInfo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_csv() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(21), b"second", "image/png").unwrap();
+        cache.store("crossword", date(20), b"first", "image/jpeg").unwrap();
+        cache.store("cartoon", date(20), b"cartoon", "image/jpeg").unwrap();
+        cache.record_upload("crossword", date(20), "drive", "file-id").unwrap();
+        let puzzle = PuzzleInfo {
+            number: Some(10234),
+            setter: Some("Kulkarni, A.".to_string()),
+        };
+        cache.record_puzzle("crossword", date(20), &puzzle).unwrap();
+        fs::write(dir.path().join("crossword_2024-03-20.jpg"), b"first").unwrap();
+
+        let csv = render(&manifest(&config).unwrap(), ExportFormat::Csv).unwrap();
+        assert!(csv.ends_with("\r\n"));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "date,file,size,sha256,mime_type,link,uploaded_to,number,setter");
+        let first = format!(
+            "2024-03-20,{},5,{},image/jpeg,https://drive.google.com/file/d/file-id/view,drive,10234,\"Kulkarni, A.\"",
+            dir.path().join("crossword_2024-03-20.jpg").display(),
+            "a7937b64b8caa58f03721bb6bacf5c78cb235febe0e70b1b84cd99541461a08e",
+        );
+        assert_eq!(lines[1], first);
+        // Only in the cache
+        assert!(lines[2].starts_with("2024-03-21,,6,"));
+    }
+
+    #[test]
+    fn test_files_the_cache_does_not_know() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            filename_template: "Crossword_%A_%d-%b-%Y".to_string(),
+            ..Config::default()
+        };
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), b"cached", "image/jpeg").unwrap();
+        fs::write(dir.path().join("Crossword_Wednesday_20-Mar-2024.jpg"), b"cached").unwrap();
+        fs::write(dir.path().join("Crossword_Tuesday_19-Mar-2024.png"), b"older").unwrap();
+        fs::write(dir.path().join("crossword_2024-03-18.jpg"), b"oldest").unwrap();
+        fs::write(dir.path().join("report_2024-03-17.html"), b"report").unwrap();
+
+        let entries = manifest(&config).unwrap();
+        let dates: Vec<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+        assert_eq!(dates, [date(18), date(19), date(20)]);
+        let older = &entries[1];
+        assert_eq!((older.size, older.mime_type.as_str()), (Some(5), "image/png"));
+        assert_eq!(older.sha256, format!("{:x}", Sha256::digest(b"older")));
+        assert!(older.file.as_ref().unwrap().ends_with("Crossword_Tuesday_19-Mar-2024.png"));
+        assert!(older.uploaded_to.is_empty() && older.link.is_none());
+    }
+}
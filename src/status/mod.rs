@@ -0,0 +1,217 @@
+//! A row per date for commands covering a range of dates, printed as an aligned table to
+//! read, or as JSON or CSV to feed to something else.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use comfy_table::{presets, CellAlignment, Table};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::archive;
+use crate::backfill::{BackfillState, Outcome};
+use crate::cache::ImageCache;
+use crate::config::Config;
+use crate::crossword::{pending::PendingUploads, Region};
+use crate::learning::History;
+use crate::report;
+
+pub mod audit;
+pub mod dedupe;
+pub mod export;
+pub mod queue;
+pub mod runs;
+
+/// How per-date results are printed
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// What happened to, or is known about, one date
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DateRow {
+    pub date: NaiveDate,
+    pub status: &'static str,
+    pub page: Option<u32>,
+    /// Size of the saved image in bytes
+    pub size: Option<u64>,
+    /// Link to the uploaded copy, or the saved file when it wasn't uploaded anywhere
+    pub destination: Option<String>,
+    pub error: Option<String>,
+}
+
+impl DateRow {
+    /// A row for `date` whose crossword is saved at `file`, if anywhere
+    pub fn new(cache: &ImageCache, date: NaiveDate, status: &'static str, file: Option<&Path>) -> Self {
+        let uploaded = cache
+            .entry("crossword", date)
+            .and_then(|image| image.uploads.iter().find_map(|(sink, id)| report::file_url(sink, id)));
+        Self {
+            date,
+            status,
+            page: None,
+            size: file.and_then(|file| fs::metadata(file).ok()).map(|metadata| metadata.len()),
+            destination: uploaded.or_else(|| file.map(|file| file.display().to_string())),
+            error: None,
+        }
+    }
+}
+
+/// What the output directory holds for every date from `from` to `to`. Reads only; nothing
+/// is restored from the cache or fetched.
+pub fn rows(config: &Config, from: NaiveDate, to: NaiveDate) -> Result<Vec<DateRow>> {
+    let cache = ImageCache::open(&config.output_dir)?;
+    let pending = PendingUploads::load(&PendingUploads::path(&config.output_dir))?;
+    let history = History::load(&History::path(&config.output_dir))?;
+    let backfill = BackfillState::load(&BackfillState::path(&config.output_dir))?;
+    let matches = history.regions.get("crossword").map(Vec::as_slice).unwrap_or_default();
+
+    let mut rows = Vec::new();
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        let saved = archive::archived_file(config, date)?;
+        let status = if pending.contains(Region::crossword(config).name, date) {
+            "pending upload"
+        } else if saved.is_some() {
+            "archived"
+        } else if cache.get("crossword", date).is_some() {
+            "cached"
+        } else if config.publication.skipped(date, config.puzzle).is_some() {
+            "no crossword"
+        } else {
+            match backfill.dates.get(&date).map(|state| state.outcome) {
+                Some(Outcome::Failed) => "failed",
+                Some(Outcome::Cancelled) => "cancelled",
+                _ => "missing",
+            }
+        };
+        let error = match status {
+            "failed" | "cancelled" => backfill.dates.get(&date).and_then(|state| state.error.clone()),
+            _ => None,
+        };
+        rows.push(DateRow {
+            page: matches.iter().find(|record| record.date == date).map(|record| record.page),
+            error,
+            ..DateRow::new(&cache, date, status, saved.as_deref())
+        });
+    }
+    Ok(rows)
+}
+
+pub fn render(rows: &[DateRow], format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Table => Ok(table(rows)),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(rows)?),
+        OutputFormat::Csv => Ok(csv(rows)),
+    }
+}
+
+/// Aligned columns; errors are left to the caller, as they don't fit on one line
+fn table(rows: &[DateRow]) -> String {
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING).set_header(["DATE", "STATUS", "PAGE", "SIZE", "DESTINATION"]);
+    for row in rows {
+        table.add_row([
+            row.date.to_string(),
+            row.status.to_string(),
+            row.page.map(|page| page.to_string()).unwrap_or_default(),
+            row.size.map(human_size).unwrap_or_default(),
+            row.destination.clone().unwrap_or_default(),
+        ]);
+    }
+    for column in table.column_iter_mut() {
+        let right = matches!(column.index, 2 | 3);
+        column.set_padding((0, if column.index == 4 { 0 } else { 2 }));
+        column.set_cell_alignment(if right { CellAlignment::Right } else { CellAlignment::Left });
+    }
+    table.lines().map(|line| line.trim_end().to_string()).collect::<Vec<_>>().join("\n")
+}
+
+fn csv(rows: &[DateRow]) -> String {
+    let mut lines = vec!["date,status,page,size,destination,error".to_string()];
+    for row in rows {
+        let fields = [
+            row.date.to_string(),
+            row.status.to_string(),
+            row.page.map(|page| page.to_string()).unwrap_or_default(),
+            row.size.map(|size| size.to_string()).unwrap_or_default(),
+            row.destination.clone().unwrap_or_default(),
+            row.error.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        lines.push(fields.join(","));
+    }
+    lines.join("\n")
+}
+
+/// Quotes a field when it holds a separator, quote or line break, as RFC 4180 asks
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::MatchRecord;
+    use crate::types::Rect;
+    use tempfile::TempDir;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_rows_and_formats() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        fs::write(dir.path().join("crossword_2024-03-20.jpg"), vec![0; 2048]).unwrap();
+        let mut cache = ImageCache::open(dir.path()).unwrap();
+        cache.store("crossword", date(20), &[0; 2048], "image/jpeg").unwrap();
+        cache.record_upload("crossword", date(20), "drive", "file-id").unwrap();
+        let mut history = History::default();
+        let rect = Rect { x1: 0, y1: 1625, x2: 1000, y2: 2775 };
+        history.record("crossword", MatchRecord { date: date(20), page: 2, rect, dimensions: None });
+        history.save(&History::path(dir.path())).unwrap();
+
+        let mut rows = rows(&config, date(20), date(21)).unwrap();
+        assert_eq!(rows[0].status, "archived");
+        assert_eq!(rows[0].page, Some(2));
+        assert_eq!(rows[0].size, Some(2048));
+        assert_eq!(rows[1].status, "missing");
+        assert_eq!(rows[1].destination, None);
+
+        assert_eq!(
+            render(&rows, OutputFormat::Table).unwrap(),
+            "DATE        STATUS    PAGE    SIZE  DESTINATION\n\
+             2024-03-20  archived     2  2.0 KB  https://drive.google.com/file/d/file-id/view\n\
+             2024-03-21  missing"
+        );
+
+        rows[1].status = "failed";
+        rows[1].error = Some("Could not find crossword, on any page".to_string());
+        let csv = render(&rows, OutputFormat::Csv).unwrap();
+        assert_eq!(csv.lines().nth(2), Some("2024-03-21,failed,,,,\"Could not find crossword, on any page\""));
+
+        let json: serde_json::Value = serde_json::from_str(&render(&rows, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[0]["size"], 2048);
+        assert!(json[1]["page"].is_null());
+    }
+}
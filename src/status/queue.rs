@@ -0,0 +1,242 @@
+//! The dates still waiting for another attempt: saved crosswords whose uploads failed, and
+//! dates a backfill couldn't download. Either can be cancelled, or put back in line.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use super::{csv_field, OutputFormat};
+use crate::backfill::{BackfillState, Outcome};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::crossword::{pending::PendingUploads, Region};
+
+/// One date waiting for another attempt
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueEntry {
+    pub date: NaiveDate,
+    /// What is waiting: the `upload` of a saved crossword, or the `download` of the date
+    pub kind: &'static str,
+    pub reason: String,
+    /// When the last attempt failed, where that was recorded
+    pub failed_at: Option<DateTime<Utc>>,
+    /// The run that picks it up next
+    pub retried_by: &'static str,
+    /// The earliest that run tries it again; unset once cancelled
+    pub next_attempt: Option<DateTime<Utc>>,
+}
+
+/// Every queued date, oldest first, uploads before downloads on the same date
+pub fn entries(config: &Config) -> Result<Vec<QueueEntry>> {
+    let pending = PendingUploads::load(&PendingUploads::path(&config.output_dir))?;
+    let backfill = BackfillState::load(&BackfillState::path(&config.output_dir))?;
+    let now = config.clock.now();
+
+    let mut entries: Vec<QueueEntry> = pending
+        .runs
+        .get(Region::crossword(config).name)
+        .into_iter()
+        .flatten()
+        .map(|(date, run)| QueueEntry {
+            date: *date,
+            kind: "upload",
+            reason: run
+                .failures
+                .iter()
+                .map(|failure| format!("{}: {}", failure.sink, failure.error))
+                .chain((!run.delivered.is_empty()).then(|| {
+                    let delivered: Vec<&str> = run.delivered.keys().map(String::as_str).collect();
+                    format!("already uploaded to {}", delivered.join(", "))
+                }))
+                .collect::<Vec<_>>()
+                .join("; "),
+            failed_at: None,
+            retried_by: "the next run for the date",
+            next_attempt: Some(now.with_timezone(&Utc)),
+        })
+        .collect();
+    for (date, state) in &backfill.dates {
+        let (retried_by, next_attempt) = match state.outcome {
+            Outcome::Downloaded => continue,
+            Outcome::Failed => (
+                "the next backfill over the date",
+                Some(config.backfill.first_request(now).with_timezone(&Utc)),
+            ),
+            Outcome::Cancelled => ("none, cancelled", None),
+        };
+        entries.push(QueueEntry {
+            date: *date,
+            kind: "download",
+            reason: state.error.clone().unwrap_or_default(),
+            failed_at: Some(state.at),
+            retried_by,
+            next_attempt,
+        });
+    }
+    entries.sort_by_key(|entry| (entry.date, entry.kind != "upload"));
+    Ok(entries)
+}
+
+/// Takes `date` out of the queue: its failed uploads are forgotten, and backfills leave it be
+/// until it is requeued. Says what was cancelled.
+pub fn cancel(config: &Config, date: NaiveDate) -> Result<Vec<String>> {
+    let mut cancelled = Vec::new();
+    let pending_path = PendingUploads::path(&config.output_dir);
+    let mut pending = PendingUploads::load(&pending_path)?;
+    if pending.remove(Region::crossword(config).name, date).is_some() {
+        pending.save(&pending_path)?;
+        cancelled.push(format!("the re-upload of {}", date));
+    }
+
+    let backfill_path = BackfillState::path(&config.output_dir);
+    let mut backfill = BackfillState::load(&backfill_path)?;
+    if let Some(state) = backfill.dates.get_mut(&date).filter(|state| state.outcome == Outcome::Failed) {
+        state.outcome = Outcome::Cancelled;
+        backfill.save(&backfill_path)?;
+        cancelled.push(format!("the download of {} by backfills", date));
+    }
+    Ok(cancelled)
+}
+
+/// Puts `date` back in line for the next backfill over it, whether it failed, was cancelled
+/// or was downloaded before. Returns whether the backfill state had it.
+pub fn requeue(config: &Config, date: NaiveDate) -> Result<bool> {
+    let path = BackfillState::path(&config.output_dir);
+    let mut backfill = BackfillState::load(&path)?;
+    if backfill.dates.remove(&date).is_none() {
+        return Ok(false);
+    }
+    backfill.save(&path)?;
+    Ok(true)
+}
+
+pub fn render(entries: &[QueueEntry], format: OutputFormat) -> Result<String> {
+    let time = |at: Option<DateTime<Utc>>| at.map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+    let fields = |entry: &QueueEntry| {
+        [
+            entry.date.to_string(),
+            entry.kind.to_string(),
+            time(entry.failed_at),
+            entry.retried_by.to_string(),
+            time(entry.next_attempt),
+            entry.reason.clone(),
+        ]
+    };
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(entries)?),
+        OutputFormat::Csv => {
+            let mut lines = vec!["date,kind,failed_at,retried_by,next_attempt,reason".to_string()];
+            for entry in entries {
+                lines.push(fields(entry).iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+            }
+            Ok(lines.join("\n"))
+        }
+        OutputFormat::Table if entries.is_empty() => Ok("Nothing is queued".to_string()),
+        OutputFormat::Table => {
+            let lines: Vec<String> = entries
+                .iter()
+                .map(|entry| {
+                    let [date, kind, failed_at, retried_by, next_attempt, reason] = fields(entry);
+                    let failed_at = match failed_at.is_empty() {
+                        true => String::new(),
+                        false => format!(", failed {} UTC", failed_at),
+                    };
+                    let next_attempt = match next_attempt.is_empty() {
+                        true => String::new(),
+                        false => format!(", from {} UTC", next_attempt),
+                    };
+                    format!("{} {}{}, next: {}{}\n  {}", date, kind, failed_at, retried_by, next_attempt, reason)
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::{BackfillConfig, DateState};
+    use crate::clock::{FixedClock, RunClock};
+    use crate::crossword::pending::PendingRun;
+    use crate::pipeline::{Artifact, UploadFailure};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_cancel_and_requeue() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            backfill: BackfillConfig {
+                window: Some("02:00-05:00".parse().unwrap()),
+                ..BackfillConfig::default()
+            },
+            clock: RunClock(Arc::new(FixedClock::at("2024-03-21T12:00:00+05:30"))),
+            ..Config::default()
+        };
+        let mut pending = PendingUploads::default();
+        pending.insert(
+            "crossword",
+            date(20),
+            PendingRun {
+                filename: "crossword_2024-03-20.jpg".to_string(),
+                mime_type: "image/jpeg".to_string(),
+                page: 2,
+                crossword_url: String::new(),
+                image_url: String::new(),
+                failures: vec![UploadFailure {
+                    sink: "drive".to_string(),
+                    artifact: Artifact::new("crossword_2024-03-20.jpg", "image/jpeg"),
+                    error: "HTTP 503".to_string(),
+                }],
+                delivered: BTreeMap::from([("s3".to_string(), "crosswords/2024-03-20.jpg".to_string())]),
+                confidence: Some(100),
+                puzzle: Default::default(),
+                needs_review: false,
+            },
+        );
+        pending.save(&PendingUploads::path(dir.path())).unwrap();
+        let mut backfill = BackfillState::default();
+        for (day, outcome) in [(18, Outcome::Failed), (19, Outcome::Downloaded)] {
+            let error = Some("Edition not published".to_string()).filter(|_| outcome == Outcome::Failed);
+            let at = "2024-03-21T00:00:00Z".parse().unwrap();
+            backfill.dates.insert(date(day), DateState { outcome, error, at });
+        }
+        backfill.save(&BackfillState::path(dir.path())).unwrap();
+
+        let queued = entries(&config).unwrap();
+        let queued: Vec<_> = queued.iter().map(|entry| (entry.date, entry.kind, entry.reason.as_str())).collect();
+        assert_eq!(
+            queued,
+            [
+                (date(18), "download", "Edition not published"),
+                (date(20), "upload", "drive: HTTP 503; already uploaded to s3")
+            ]
+        );
+        // The upload goes with any run for its date, the download waits for the backfill window
+        assert_eq!(
+            render(&entries(&config).unwrap(), OutputFormat::Table).unwrap(),
+            "2024-03-18 download, failed 2024-03-21 00:00 UTC, next: the next backfill over the date, \
+             from 2024-03-21 20:30 UTC\n  Edition not published\n\
+             2024-03-20 upload, next: the next run for the date, from 2024-03-21 06:30 UTC\n  \
+             drive: HTTP 503; already uploaded to s3"
+        );
+
+        assert_eq!(cancel(&config, date(18)).unwrap().len(), 1);
+        assert_eq!(cancel(&config, date(20)).unwrap().len(), 1);
+        let queued = entries(&config).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!((queued[0].retried_by, queued[0].next_attempt), ("none, cancelled", None));
+
+        assert!(requeue(&config, date(18)).unwrap());
+        assert!(requeue(&config, date(19)).unwrap());
+        assert!(!requeue(&config, date(21)).unwrap());
+        assert!(entries(&config).unwrap().is_empty());
+    }
+}
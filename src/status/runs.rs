@@ -0,0 +1,238 @@
+//! A record of every run in the output directory, for spotting a scraper that needs
+//! attention: how often runs succeed, where they spend their time, why they fail and which
+//! pages the crossword has been turning up on.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::crossword::{self, Download};
+use crate::error::hints;
+use crate::storage;
+use crate::telemetry::phases::PhaseTiming;
+
+/// File in the output directory the runs are recorded in
+const RUNS_FILE: &str = ".run-history.json";
+
+/// Runs kept; a few years of daily runs with their retries
+const MAX_RUNS: usize = 2000;
+
+/// Failure class of errors without a known remedy
+const OTHER_FAILURE: &str = "Other";
+
+/// How one run went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub date: NaiveDate,
+    pub finished_at: DateTime<Utc>,
+    /// `Downloaded`, `Retrying` or `Failed`, as in the run logs
+    pub status: String,
+    /// What kind of failure it was, e.g. `NotYetPublished`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(default)]
+    pub timings: Vec<PhaseTiming>,
+}
+
+impl RunRecord {
+    pub fn new(
+        date: NaiveDate,
+        finished_at: DateTime<Utc>,
+        result: &Result<Download>,
+        timings: &[PhaseTiming],
+    ) -> Self {
+        Self {
+            date,
+            finished_at,
+            status: crossword::status(result).to_string(),
+            failure: result.as_ref().err().map(|e| {
+                hints::kinds(e)
+                    .first()
+                    .map_or_else(|| OTHER_FAILURE.to_string(), |kind| format!("{:?}", kind))
+            }),
+            page: result.as_ref().ok().map(|download| download.page),
+            timings: timings.to_vec(),
+        }
+    }
+}
+
+/// Every recorded run, oldest first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunLog {
+    pub runs: Vec<RunRecord>,
+}
+
+impl RunLog {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(RUNS_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                serde_json::from_str(&content).with_context(|| format!("Failed to parse run history {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read run history {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("Failed to write run history {}", path.display()))
+    }
+
+    pub fn record(&mut self, record: RunRecord) {
+        self.runs.push(record);
+        let excess = self.runs.len().saturating_sub(MAX_RUNS);
+        self.runs.drain(..excess);
+    }
+
+    /// Success rate, average time per phase, failures by class and pages by month
+    pub fn render_stats(&self) -> String {
+        let mut out = String::new();
+        let total = self.runs.len();
+        let count = |status: &str| self.runs.iter().filter(|run| run.status == status).count();
+        let downloaded = count("Downloaded");
+        out.push_str(&format!(
+            "Runs ({} since {})\n",
+            total,
+            self.runs.first().map(|run| run.finished_at.date_naive().to_string()).unwrap_or_default()
+        ));
+        out.push_str(&format!(
+            "  success rate: {:.0}% ({} downloaded, {} retrying, {} failed)\n",
+            downloaded as f64 * 100.0 / total.max(1) as f64,
+            downloaded,
+            count("Retrying"),
+            count("Failed")
+        ));
+
+        let mut phases: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for timing in self.runs.iter().flat_map(|run| &run.timings) {
+            let (sum, runs) = phases.entry(timing.phase.as_str()).or_default();
+            *sum += timing.elapsed_ms;
+            *runs += 1;
+        }
+        if !phases.is_empty() {
+            out.push_str("  average per phase:\n");
+            for (phase, (sum, runs)) in phases {
+                out.push_str(&format!("    {:<20} {:>7} ms  ({} runs)\n", phase, sum / runs, runs));
+            }
+        }
+
+        let mut failures: BTreeMap<&str, usize> = BTreeMap::new();
+        for failure in self.runs.iter().filter_map(|run| run.failure.as_deref()) {
+            *failures.entry(failure).or_default() += 1;
+        }
+        if !failures.is_empty() {
+            out.push_str("  most common failures:\n");
+            let mut failures: Vec<_> = failures.into_iter().collect();
+            failures.sort_by_key(|(class, runs)| (Reverse(*runs), *class));
+            for (class, runs) in failures {
+                out.push_str(&format!("    {:<20} {:>7}\n", class, runs));
+            }
+        }
+
+        // A page per edition date, from its latest successful run
+        let mut pages: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+        for run in &self.runs {
+            if let Some(page) = run.page {
+                pages.insert(run.date, page);
+            }
+        }
+        let mut months: BTreeMap<String, BTreeMap<u32, usize>> = BTreeMap::new();
+        for (date, page) in pages {
+            *months.entry(date.format("%Y-%m").to_string()).or_default().entry(page).or_default() += 1;
+        }
+        if !months.is_empty() {
+            out.push_str("  crossword pages by month:\n");
+            for (month, pages) in months {
+                let pages: Vec<String> = pages.iter().map(|(page, dates)| format!("page {}: {}", page, dates)).collect();
+                out.push_str(&format!("    {}  {}\n", month, pages.join(", ")));
+            }
+        }
+        out
+    }
+}
+
+/// Adds the run to the output directory's run history; like the other records a failure is
+/// only worth a warning
+pub fn record(config: &Config, date: NaiveDate, result: &Result<Download>, timings: &[PhaseTiming]) {
+    let path = RunLog::path(&config.output_dir);
+    let recorded = RunLog::load(&path).and_then(|mut log| {
+        log.record(RunRecord::new(date, config.clock.now().with_timezone(&Utc), result, timings));
+        log.save(&path)
+    });
+    if let Err(e) = recorded {
+        tracing::warn!("Could not record the run: {:#}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{FixedClock, RunClock};
+    use crate::error::CrosswordError;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn download(page: u32) -> Download {
+        Download {
+            filename: "/tmp/crossword.jpg".to_string(),
+            mime_type: "image/jpeg",
+            page,
+            crossword_url: String::new(),
+            image_url: String::new(),
+            comparison: None,
+            timings: Vec::new(),
+            link: None,
+            uploads: BTreeMap::new(),
+            puzzle: Default::default(),
+            confidence: None,
+            needs_review: false,
+            storage: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn timing(phase: &str, elapsed_ms: u64) -> PhaseTiming {
+        PhaseTiming { phase: phase.to_string(), elapsed_ms }
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let dir = TempDir::new().unwrap();
+        let config = Config {
+            output_dir: dir.path().to_path_buf(),
+            clock: RunClock(Arc::new(FixedClock::at("2024-03-02T06:00:00+05:30"))),
+            ..Config::default()
+        };
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        record(&config, date(2, 28), &Ok(download(3)), &[timing("page_scan", 400)]);
+        let unpublished = CrosswordError::EditionNotPublished { date: date(3, 1) };
+        record(&config, date(3, 1), &Err(unpublished.into()), &[timing("page_scan", 100)]);
+        record(&config, date(3, 1), &Ok(download(2)), &[timing("page_scan", 300), timing("upload:drive", 900)]);
+        record(&config, date(3, 2), &Err(anyhow::anyhow!("connection reset")), &[]);
+
+        let log = RunLog::load(&RunLog::path(dir.path())).unwrap();
+        assert_eq!(log.runs.len(), 4);
+        assert_eq!(log.runs[1].failure.as_deref(), Some("NotYetPublished"));
+        assert_eq!(log.runs[1].status, "Retrying");
+        assert_eq!(log.runs[3].finished_at.to_rfc3339(), "2024-03-02T00:30:00+00:00");
+
+        let stats = log.render_stats();
+        assert!(stats.contains("  success rate: 50% (2 downloaded, 1 retrying, 1 failed)\n"), "{}", stats);
+        assert!(stats.contains("    page_scan                266 ms  (3 runs)\n"), "{}", stats);
+        assert!(stats.contains("    upload:drive             900 ms  (1 runs)\n"));
+        assert!(stats.contains("    NotYetPublished            1\n    Other                      1\n"));
+        assert!(stats.contains("    2024-02  page 3: 1\n    2024-03  page 2: 1\n"));
+    }
+}
@@ -0,0 +1,199 @@
+//! Upgrades the state files in an output directory when their layout changes, before
+//! anything reads them. The directory records its schema version in `.state-version`; each
+//! migration moves it up by one, and a directory written by a newer version is refused
+//! rather than half understood.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::console;
+use crate::crossword::pending::PendingUploads;
+use crate::error::CrosswordError;
+use crate::learning::History;
+
+/// File in the output directory recording the schema version of its state
+const VERSION_FILE: &str = ".state-version";
+
+/// One step up the schema
+struct Migration {
+    /// The version the directory is at afterwards
+    version: u32,
+    description: &'static str,
+    /// Returns whether anything had to change
+    apply: fn(&Path) -> Result<bool>,
+}
+
+/// Every migration, oldest first; version 0 is a directory from before versions were kept
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "counted the pages of matches recorded before page stats were kept",
+        apply: rebuild_page_stats,
+    },
+    Migration {
+        version: 2,
+        description: "filed the pending uploads under the daily crossword",
+        apply: nest_pending_uploads,
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StateVersion {
+    version: u32,
+    /// Version of the tool that last migrated the directory
+    written_by: String,
+}
+
+/// The schema version this build writes
+pub fn latest() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Brings the state in `output_dir` up to [`latest`], recording the version after each
+/// migration so an interrupted upgrade carries on where it stopped. Fails without touching
+/// anything when a newer version of the tool wrote the directory.
+pub fn run(output_dir: &Path) -> Result<()> {
+    // A directory the first run is yet to create has nothing to upgrade
+    if !output_dir.is_dir() {
+        return Ok(());
+    }
+    let path = output_dir.join(VERSION_FILE);
+    let current = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str::<StateVersion>(&content)
+            .with_context(|| format!("Failed to parse state version {}", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => StateVersion {
+            version: 0,
+            written_by: String::new(),
+        },
+        Err(e) => return Err(e).with_context(|| format!("Failed to read state version {}", path.display())),
+    };
+    if current.version > latest() {
+        return Err(CrosswordError::NewerState {
+            dir: output_dir.display().to_string(),
+            found: current.version,
+            supported: latest(),
+            written_by: current.written_by,
+        }
+        .into());
+    }
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current.version) {
+        let changed = (migration.apply)(output_dir).with_context(|| {
+            format!("Failed to migrate {} to state version {}", output_dir.display(), migration.version)
+        })?;
+        if changed {
+            console::progress!("Upgraded the state in {}: {}", output_dir.display(), migration.description);
+        }
+        let version = StateVersion {
+            version: migration.version,
+            written_by: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        super::write_atomic(&path, serde_json::to_string_pretty(&version)?.as_bytes())
+            .with_context(|| format!("Failed to write state version {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn rebuild_page_stats(output_dir: &Path) -> Result<bool> {
+    let path = History::path(output_dir);
+    let mut history = History::load(&path)?;
+    let before = history.pages.clone();
+    history.rebuild_page_stats();
+    if history.pages == before {
+        return Ok(false);
+    }
+    history.save(&path)?;
+    Ok(true)
+}
+
+/// Pending uploads were keyed by date alone until the Sunday cryptic could have its own; the
+/// ones from before were all the daily crossword's
+fn nest_pending_uploads(output_dir: &Path) -> Result<bool> {
+    let path = PendingUploads::path(output_dir);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read pending uploads {}", path.display())),
+    };
+    let mut ledger: serde_json::Value =
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse pending uploads {}", path.display()))?;
+    let Some(runs) = ledger.get_mut("runs").and_then(serde_json::Value::as_object_mut) else {
+        return Ok(false);
+    };
+    if !runs.keys().any(|key| key.parse::<NaiveDate>().is_ok()) {
+        return Ok(false);
+    }
+    let dated = std::mem::take(runs);
+    runs.insert("crossword".to_string(), serde_json::Value::Object(dated));
+    super::write_atomic(&path, serde_json::to_string_pretty(&ledger)?.as_bytes())
+        .with_context(|| format!("Failed to write pending uploads {}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::hints::{self, ErrorKind};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrates_once() {
+        let dir = TempDir::new().unwrap();
+        let rect = r#"{"x1": 4, "y1": 1672, "x2": 997, "y2": 2778}"#;
+        let old_history = format!(
+            r#"{{"regions": {{"crossword": [
+                {{"date": "2024-03-19", "page": 2, "rect": {rect}}},
+                {{"date": "2024-03-20", "page": 2, "rect": {rect}}}
+            ]}}}}"#
+        );
+        fs::write(History::path(dir.path()), old_history).unwrap();
+
+        run(dir.path()).unwrap();
+        let history = History::load(&History::path(dir.path())).unwrap();
+        let stats = &history.pages["crossword"][&2];
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.last_seen.to_string(), "2024-03-20");
+        let version = fs::read_to_string(dir.path().join(VERSION_FILE)).unwrap();
+        let version: StateVersion = serde_json::from_str(&version).unwrap();
+        assert_eq!(version.version, latest());
+
+        // Already at the latest version, nothing is counted twice
+        run(dir.path()).unwrap();
+        assert_eq!(History::load(&History::path(dir.path())).unwrap().pages["crossword"][&2].hits, 2);
+    }
+
+    #[test]
+    fn test_files_pending_uploads_by_puzzle() {
+        let dir = TempDir::new().unwrap();
+        let old_pending = r#"{"runs": {"2024-03-20": {
+            "filename": "crossword_2024-03-20.jpg", "mime_type": "image/jpeg", "page": 2,
+            "crossword_url": "", "image_url": "", "failures": []
+        }}}"#;
+        fs::write(PendingUploads::path(dir.path()), old_pending).unwrap();
+
+        run(dir.path()).unwrap();
+        let pending = PendingUploads::load(&PendingUploads::path(dir.path())).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        assert_eq!(pending.get("crossword", date).unwrap().page, 2);
+        assert!(!pending.contains(crate::config::SUNDAY_CRYPTIC, date));
+        assert!(!nest_pending_uploads(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_refuses_newer_state() {
+        let dir = TempDir::new().unwrap();
+        let newer = StateVersion {
+            version: latest() + 1,
+            written_by: "9.0.0".to_string(),
+        };
+        fs::write(dir.path().join(VERSION_FILE), serde_json::to_string(&newer).unwrap()).unwrap();
+
+        let error = run(dir.path()).unwrap_err();
+        assert!(error.to_string().contains("written by version 9.0.0"));
+        assert_eq!(hints::kinds(&error), vec![ErrorKind::NewerState]);
+        assert!(run(&dir.path().join("not-created-yet")).is_ok());
+    }
+}
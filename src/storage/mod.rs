@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{chown, PermissionsExt};
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+use crate::config::PermissionsConfig;
+
+pub mod migrations;
+
+/// Writes a downloaded file, then applies the configured mode and ownership to it
+/// and to the directory it lives in
+pub fn save(path: &Path, contents: &[u8], permissions: &PermissionsConfig) -> Result<()> {
+    let file = write_temp(path, contents)?;
+    keep_permissions(&file, path)?;
+    apply(file.path(), permissions.file_mode, permissions)?;
+    move_into_place(file, path)?;
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        apply(dir, permissions.dir_mode, permissions)?;
+    }
+    Ok(())
+}
+
+/// Writes `contents` to `path` by way of a uniquely named file beside it, so that runs for
+/// the same date racing each other (a Lambda retry and a manual run) never see or leave
+/// behind a half-written file; the last complete write wins
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    persist(write_temp(path, contents)?, path)
+}
+
+/// Like [`write_atomic`], for a file holding secrets: it is readable by its owner alone from
+/// the moment it is created, whatever the umask
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut file = new_temp_file(path, 0o600)?;
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    move_into_place(file, path)
+}
+
+/// A new file next to `path`, named after it with a random suffix, e.g.
+/// `.crossword_2024-03-20.jpg.Xa3k9Q.tmp`, that is removed unless persisted
+pub fn temp_file(path: &Path) -> Result<NamedTempFile> {
+    // Subject to the umask like any other new file, rather than private to the owner
+    new_temp_file(path, 0o666)
+}
+
+fn new_temp_file(path: &Path, mode: u32) -> Result<NamedTempFile> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().with_context(|| format!("{} has no file name", path.display()))?;
+    tempfile::Builder::new()
+        .prefix(&format!(".{}.", name.to_string_lossy()))
+        .suffix(".tmp")
+        .permissions(fs::Permissions::from_mode(mode))
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))
+}
+
+/// Moves a finished temporary file into place in one step. A file it replaces keeps its
+/// mode, so a config or key made private stays private.
+pub fn persist(file: NamedTempFile, path: &Path) -> Result<()> {
+    keep_permissions(&file, path)?;
+    move_into_place(file, path)
+}
+
+/// Gives the temporary file the permissions of the file at `path`, if there is one
+fn keep_permissions(file: &NamedTempFile, path: &Path) -> Result<()> {
+    let Ok(existing) = fs::metadata(path) else {
+        return Ok(());
+    };
+    fs::set_permissions(file.path(), existing.permissions())
+        .with_context(|| format!("Failed to keep the mode of {}", path.display()))
+}
+
+fn move_into_place(file: NamedTempFile, path: &Path) -> Result<()> {
+    file.as_file()
+        .sync_all()
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    file.persist(path)
+        .map_err(|e| e.error)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn write_temp(path: &Path, contents: &[u8]) -> Result<NamedTempFile> {
+    let mut file = temp_file(path)?;
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(file)
+}
+
+fn apply(path: &Path, mode: Option<u32>, permissions: &PermissionsConfig) -> Result<()> {
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set mode {:o} on {}", mode, path.display()))?;
+    }
+
+    if permissions.uid.is_some() || permissions.gid.is_some() {
+        chown(path, permissions.uid, permissions.gid).with_context(|| {
+            format!(
+                "Failed to change owner of {} (changing ownership usually requires root)",
+                path.display()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_save_without_permissions_keeps_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crossword.jpg");
+
+        save(&path, b"image", &PermissionsConfig::default()).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"image");
+    }
+
+    #[test]
+    fn test_save_applies_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("archive");
+        fs::create_dir(&archive).unwrap();
+        let path = archive.join("crossword.jpg");
+        let permissions = PermissionsConfig {
+            file_mode: Some(0o640),
+            dir_mode: Some(0o750),
+            ..Default::default()
+        };
+
+        save(&path, b"image", &permissions).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o7777, 0o640);
+        assert_eq!(fs::metadata(&archive).unwrap().mode() & 0o7777, 0o750);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_the_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "bot_token = \"old\"").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomic(&path, b"bot_token = \"new\"").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"bot_token = \"new\"");
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o7777, 0o600);
+    }
+
+    #[test]
+    fn test_concurrent_writes_leave_one_complete_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crossword_2024-03-20.jpg");
+
+        let handles: Vec<_> = (0..8u8)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || write_atomic(&path, &vec![writer; 64 * 1024]).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 64 * 1024);
+        assert!(contents.iter().all(|byte| *byte == contents[0]));
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_save_to_current_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crossword.jpg");
+        let owner = fs::metadata(dir.path()).unwrap();
+        // Changing to the owner we already have is allowed without root
+        let permissions = PermissionsConfig {
+            uid: Some(owner.uid()),
+            gid: Some(owner.gid()),
+            ..Default::default()
+        };
+
+        save(&path, b"image", &permissions).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().uid(), owner.uid());
+    }
+}
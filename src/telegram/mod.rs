@@ -0,0 +1,159 @@
+//! Following long uploads from a phone: a Telegram message once an upload passes a share of
+//! its bytes, edited to say how it ended. Uploads that finish before then send nothing, so
+//! only slow uplinks make any noise. With `announce`, every downloaded crossword is also
+//! announced with the run's message.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::console;
+use crate::credentials::keyring;
+
+const API_URL: &str = "https://api.telegram.org";
+
+/// Environment variable read for the bot token when the config doesn't set one
+pub const TOKEN_ENV: &str = "TELEGRAM_BOT_TOKEN";
+
+/// The `[telegram]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    /// Token from @BotFather; defaults to `TELEGRAM_BOT_TOKEN`, then the OS keyring
+    pub bot_token: Option<String>,
+    /// Chat the bot writes to: a user or group ID, or `@channel`
+    pub chat_id: String,
+    /// Share of an upload, in percent, after which it is reported
+    #[serde(default = "default_progress_percent")]
+    pub progress_percent: u8,
+    /// Send the run's message, from the `[message]` template, once the crossword is downloaded
+    #[serde(default)]
+    pub announce: bool,
+}
+
+fn default_progress_percent() -> u8 {
+    50
+}
+
+impl TelegramConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.chat_id.is_empty() {
+            problems.push("[telegram] chat_id is empty".to_string());
+        }
+        if !(1..=99).contains(&self.progress_percent) {
+            problems.push(format!("[telegram] progress_percent {} is not between 1 and 99", self.progress_percent));
+        }
+        if self.bot_token.is_none() && std::env::var(TOKEN_ENV).is_err() {
+            match keyring::find(keyring::TELEGRAM_BOT_TOKEN) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    problems.push(format!("[telegram] needs a bot_token, {} or a token in the OS keyring", TOKEN_ENV))
+                }
+                Err(e) => problems.push(format!("[telegram] has no bot_token or {}, and {:#}", TOKEN_ENV, e)),
+            }
+        }
+        problems
+    }
+
+    fn bot_token(&self) -> Result<String> {
+        if let Some(token) = &self.bot_token {
+            return Ok(token.clone());
+        }
+        if let Ok(token) = std::env::var(TOKEN_ENV) {
+            return Ok(token);
+        }
+        keyring::find(keyring::TELEGRAM_BOT_TOKEN)?
+            .with_context(|| format!("{} is not set and the OS keyring holds no bot token", TOKEN_ENV))
+    }
+}
+
+/// The message following one upload, sent once the upload is far enough along
+pub struct UploadMessage {
+    telegram: TelegramConfig,
+    /// What is being uploaded where, e.g. `crossword_2024-03-20.jpg to drive`
+    subject: String,
+    message_id: Option<i64>,
+}
+
+impl UploadMessage {
+    pub fn new(telegram: TelegramConfig, subject: String) -> Self {
+        Self { telegram, subject, message_id: None }
+    }
+
+    /// Whether an upload with `percent` of its bytes sent should be reported now
+    fn due(&self, percent: u8) -> bool {
+        self.message_id.is_none() && percent >= self.telegram.progress_percent
+    }
+
+    /// Notes that `percent` of the bytes have been sent
+    pub async fn progress(&mut self, percent: u8) -> Result<()> {
+        if !self.due(percent) {
+            return Ok(());
+        }
+        let text = format!("Uploading {}: {}% done", self.subject, percent);
+        let response = call(&self.telegram, "sendMessage", &[("text", text)]).await?;
+        self.message_id = response["result"]["message_id"].as_i64();
+        console::progress!("Reported the upload of {} to Telegram", self.subject);
+        Ok(())
+    }
+
+    /// Says how the upload ended, if it was reported at all
+    pub async fn finish(&self, outcome: &Result<String>) -> Result<()> {
+        let Some(message_id) = self.message_id else {
+            return Ok(());
+        };
+        let text = match outcome {
+            Ok(_) => format!("Uploaded {}", self.subject),
+            Err(e) => format!("Upload of {} failed: {:#}", self.subject, e),
+        };
+        call(&self.telegram, "editMessageText", &[("message_id", message_id.to_string()), ("text", text)])
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Sends `text` to the chat as a message of its own
+pub async fn send(telegram: &TelegramConfig, text: &str) -> Result<()> {
+    call(telegram, "sendMessage", &[("text", text.to_string())]).await.map(|_| ())
+}
+
+async fn call(telegram: &TelegramConfig, method: &str, fields: &[(&str, String)]) -> Result<Value> {
+    let mut form = vec![("chat_id", telegram.chat_id.clone())];
+    form.extend(fields.iter().cloned());
+    let body = reqwest::Client::new()
+        .post(format!("{}/bot{}/{}", API_URL, telegram.bot_token()?, method))
+        .form(&form)
+        .send()
+        .await
+        .context("Failed to reach Telegram")?
+        .error_for_status()
+        .with_context(|| format!("Telegram refused {}", method))?
+        .text()
+        .await?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reported_once_past_threshold() {
+        let telegram: TelegramConfig = toml::from_str(
+            r#"bot_token = "123:abc"
+chat_id = "42""#,
+        )
+        .unwrap();
+        assert!(telegram.validate().is_empty());
+
+        let mut message = UploadMessage::new(telegram, "crossword_2024-03-20.pdf to drive".to_string());
+        assert!(!message.due(0));
+        assert!(!message.due(49));
+        assert!(message.due(62));
+        message.message_id = Some(7);
+        assert!(!message.due(87));
+
+        let eager: TelegramConfig = toml::from_str("chat_id = \"\"\nprogress_percent = 100\nbot_token = \"x\"").unwrap();
+        assert_eq!(eager.validate().len(), 2);
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::IsTerminal;
+use std::time::Duration;
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+use crate::console;
+
+#[cfg(feature = "otlp")]
+mod otlp;
+pub mod phases;
+
+/// Environment variables that turn on OTLP export, as defined by the OpenTelemetry spec
+#[cfg_attr(not(feature = "otlp"), allow(dead_code))]
+const OTLP_ENDPOINT_VARS: &[&str] = &[
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT",
+];
+
+/// Whether the standard OTEL variables ask for export, looked up through `lookup`
+#[cfg_attr(not(feature = "otlp"), allow(dead_code))]
+fn otlp_requested(lookup: impl Fn(&str) -> Option<String>) -> bool {
+    let disabled = lookup("OTEL_SDK_DISABLED").is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    !disabled && OTLP_ENDPOINT_VARS.iter().any(|var| lookup(var).is_some_and(|v| !v.is_empty()))
+}
+
+/// The log level for `-v`s and `-q`s on the command line: info by default, each `-v` one
+/// level more detailed and each `-q` one less, from nothing at all up to trace, so `-q -v`
+/// cancel out
+pub fn cli_level(verbose: u8, quiet: u8) -> LevelFilter {
+    // Least to most detailed, with info in the middle
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::OFF,
+        LevelFilter::ERROR,
+        LevelFilter::WARN,
+        LevelFilter::INFO,
+        LevelFilter::DEBUG,
+        LevelFilter::TRACE,
+    ];
+    let level = 3 + i32::from(verbose) - i32::from(quiet);
+    LEVELS[level.clamp(0, LEVELS.len() as i32 - 1) as usize]
+}
+
+/// The log level on Lambda, from the function's `AWS_LAMBDA_LOG_LEVEL` setting (info when
+/// unset or not understood)
+pub fn lambda_level(lookup: impl Fn(&str) -> Option<String>) -> LevelFilter {
+    lookup("AWS_LAMBDA_LOG_LEVEL")
+        .and_then(|level| match level.to_ascii_uppercase().as_str() {
+            // Lambda's own name for the most severe level
+            "FATAL" => Some(LevelFilter::ERROR),
+            level => level.parse().ok(),
+        })
+        .unwrap_or(LevelFilter::INFO)
+}
+
+/// Sets up log output at `level`, plus OTLP traces and metrics when built with the `otlp`
+/// feature and an `OTEL_EXPORTER_OTLP_*ENDPOINT` variable is set. Other crates only log
+/// their warnings and errors, however detailed `level` is.
+pub fn init(level: LevelFilter) {
+    let filter = Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+        .with_default(level.min(LevelFilter::WARN));
+    let fmt_layer = fmt::layer()
+        .with_target(false)
+        .without_time()
+        .with_ansi(std::io::stdout().is_terminal() && std::io::stderr().is_terminal())
+        .with_writer(console::Output)
+        .with_filter(filter);
+
+    let registry = tracing_subscriber::registry().with(fmt_layer);
+
+    #[cfg(feature = "otlp")]
+    {
+        if otlp_requested(|key| std::env::var(key).ok()) {
+            match otlp::install() {
+                Ok(layer) => {
+                    registry.with(layer).init();
+                    return;
+                }
+                Err(e) => eprintln!("Failed to set up OTLP export: {:#}", e),
+            }
+        }
+    }
+
+    registry.init();
+}
+
+/// Pushes buffered spans and metrics; call before a Lambda invocation returns
+pub fn flush() {
+    #[cfg(feature = "otlp")]
+    otlp::flush();
+}
+
+/// Flushes and stops the exporters at process exit
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    otlp::shutdown();
+}
+
+/// Records the outcome and duration of one crossword run
+pub fn record_run(success: bool, elapsed: Duration) {
+    #[cfg(feature = "otlp")]
+    otlp::record_run(success, elapsed);
+
+    #[cfg(not(feature = "otlp"))]
+    let _ = (success, elapsed);
+}
+
+/// Counts a service account key Google refused, so alerts can ask for it to be rotated
+pub fn record_credentials_rejected(account: &str) {
+    #[cfg(feature = "otlp")]
+    otlp::record_credentials_rejected(account);
+    #[cfg(not(feature = "otlp"))]
+    let _ = account;
+}
+
+/// Records how long one phase of a run took
+fn record_phase(phase: &str, elapsed: Duration) {
+    #[cfg(feature = "otlp")]
+    otlp::record_phase(phase, elapsed);
+    #[cfg(not(feature = "otlp"))]
+    let _ = (phase, elapsed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn test_otlp_requested_by_endpoint() {
+        assert!(otlp_requested(env(&[("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318")])));
+        assert!(otlp_requested(env(&[("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", "https://api.honeycomb.io")])));
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(cli_level(0, 0), LevelFilter::INFO);
+        assert_eq!(cli_level(2, 0), LevelFilter::TRACE);
+        assert_eq!(cli_level(0, 1), LevelFilter::WARN);
+        assert_eq!(cli_level(0, 3), LevelFilter::OFF);
+        assert_eq!(cli_level(0, 9), LevelFilter::OFF);
+        assert_eq!(cli_level(1, 0), LevelFilter::DEBUG);
+        assert_eq!(cli_level(5, 0), LevelFilter::TRACE);
+        assert_eq!(cli_level(1, 1), LevelFilter::INFO);
+        assert_eq!(cli_level(1, 2), LevelFilter::WARN);
+        assert_eq!(lambda_level(env(&[])), LevelFilter::INFO);
+        assert_eq!(lambda_level(env(&[("AWS_LAMBDA_LOG_LEVEL", "DEBUG")])), LevelFilter::DEBUG);
+        assert_eq!(lambda_level(env(&[("AWS_LAMBDA_LOG_LEVEL", "FATAL")])), LevelFilter::ERROR);
+        assert_eq!(lambda_level(env(&[("AWS_LAMBDA_LOG_LEVEL", "loud")])), LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_otlp_not_requested() {
+        assert!(!otlp_requested(env(&[])));
+        assert!(!otlp_requested(env(&[("OTEL_EXPORTER_OTLP_ENDPOINT", "")])));
+        assert!(!otlp_requested(env(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318"),
+            ("OTEL_SDK_DISABLED", "true"),
+        ])));
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::{MetricExporter, SpanExporter};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+struct Providers {
+    tracer: SdkTracerProvider,
+    meter: SdkMeterProvider,
+}
+
+struct Instruments {
+    runs: Counter<u64>,
+    duration: Histogram<f64>,
+    phase_duration: Histogram<f64>,
+    credentials_rejected: Counter<u64>,
+}
+
+static PROVIDERS: OnceLock<Providers> = OnceLock::new();
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+/// Builds the OTLP exporters; endpoint, headers and protocol come from the standard OTEL env vars
+pub fn install<S>() -> Result<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    // OTEL_SERVICE_NAME and OTEL_RESOURCE_ATTRIBUTES still take precedence
+    let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+
+    let tracer = SdkTracerProvider::builder()
+        .with_batch_exporter(SpanExporter::builder().with_http().build()?)
+        .with_resource(resource.clone())
+        .build();
+    let meter = SdkMeterProvider::builder()
+        .with_periodic_exporter(MetricExporter::builder().with_http().build()?)
+        .with_resource(resource)
+        .build();
+
+    global::set_meter_provider(meter.clone());
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer.tracer(SERVICE_NAME));
+    PROVIDERS.get_or_init(|| Providers { tracer, meter });
+
+    Ok(layer)
+}
+
+fn instruments() -> &'static Instruments {
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter(SERVICE_NAME);
+        Instruments {
+            runs: meter
+                .u64_counter("crossword.runs")
+                .with_description("Crossword runs by outcome")
+                .build(),
+            duration: meter
+                .f64_histogram("crossword.run.duration")
+                .with_unit("s")
+                .with_description("Wall time of a crossword run")
+                .build(),
+            phase_duration: meter
+                .f64_histogram("crossword.phase.duration")
+                .with_unit("s")
+                .with_description("Wall time of one phase of a run: page scan, image download, an upload, ...")
+                .build(),
+            credentials_rejected: meter
+                .u64_counter("crossword.credentials.rejected")
+                .with_description("Service account keys Google refused; the key needs rotating")
+                .build(),
+        }
+    })
+}
+
+pub fn record_run(success: bool, elapsed: Duration) {
+    let outcome = [KeyValue::new("outcome", if success { "success" } else { "failure" })];
+    let instruments = instruments();
+    instruments.runs.add(1, &outcome);
+    instruments.duration.record(elapsed.as_secs_f64(), &outcome);
+}
+
+pub fn record_phase(phase: &str, elapsed: Duration) {
+    instruments()
+        .phase_duration
+        .record(elapsed.as_secs_f64(), &[KeyValue::new("phase", phase.to_string())]);
+}
+
+pub fn record_credentials_rejected(account: &str) {
+    instruments()
+        .credentials_rejected
+        .add(1, &[KeyValue::new("account", account.to_string())]);
+}
+
+pub fn flush() {
+    if let Some(providers) = PROVIDERS.get() {
+        if let Err(e) = providers.tracer.force_flush() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+        if let Err(e) = providers.meter.force_flush() {
+            eprintln!("Failed to flush metrics: {}", e);
+        }
+    }
+}
+
+pub fn shutdown() {
+    if let Some(providers) = PROVIDERS.get() {
+        providers.tracer.shutdown().ok();
+        providers.meter.shutdown().ok();
+    }
+}
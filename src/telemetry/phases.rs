@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long one phase of a run took, e.g. `page_scan` or `upload:drive`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub elapsed_ms: u64,
+}
+
+type Recorder = Arc<Mutex<Vec<PhaseTiming>>>;
+
+tokio::task_local! {
+    static RECORDER: Recorder;
+}
+
+/// Runs `future`, collecting every phase recorded inside it, including in tasks
+/// spawned through [`propagate`]
+pub async fn collect<F: Future>(future: F) -> (F::Output, Vec<PhaseTiming>) {
+    let recorder = Recorder::default();
+    let output = RECORDER.scope(recorder.clone(), future).await;
+    let timings = std::mem::take(&mut *recorder.lock().unwrap());
+    (output, timings)
+}
+
+/// Carries the current collection into a future that is about to be spawned as its own task
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let recorder = RECORDER.try_with(Arc::clone).ok();
+    async move {
+        match recorder {
+            Some(recorder) => RECORDER.scope(recorder, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Phases recorded so far in the current collection
+pub fn snapshot() -> Vec<PhaseTiming> {
+    RECORDER.try_with(|recorder| recorder.lock().unwrap().clone()).unwrap_or_default()
+}
+
+/// Adds a phase to the current collection, if any, and to the phase duration metric
+pub fn record(phase: impl Into<String>, elapsed: Duration) {
+    let phase = phase.into();
+    super::record_phase(&phase, elapsed);
+    let timing = PhaseTiming {
+        phase,
+        elapsed_ms: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+    };
+    RECORDER.try_with(|recorder| recorder.lock().unwrap().push(timing)).ok();
+}
+
+/// Awaits `future`, recording how long it took as `phase`
+pub async fn time<F: Future>(phase: impl Into<String>, future: F) -> F::Output {
+    let start = Instant::now();
+    let output = future.await;
+    record(phase, start.elapsed());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collects_phases_across_spawned_tasks() {
+        let ((), timings) = collect(async {
+            time("scan", tokio::time::sleep(Duration::from_millis(20))).await;
+            tokio::spawn(propagate(async { record("upload:drive", Duration::from_millis(5)) }))
+                .await
+                .unwrap();
+            // Not carried over, so not collected
+            tokio::spawn(async { record("elsewhere", Duration::ZERO) }).await.unwrap();
+            assert_eq!(snapshot().len(), 2);
+        })
+        .await;
+
+        let phases: Vec<&str> = timings.iter().map(|timing| timing.phase.as_str()).collect();
+        assert_eq!(phases, vec!["scan", "upload:drive"]);
+        assert!(timings[0].elapsed_ms >= 20);
+        assert_eq!(timings[1].elapsed_ms, 5);
+    }
+
+    #[test]
+    fn test_record_outside_collection_is_harmless() {
+        record("scan", Duration::from_millis(1));
+        assert!(snapshot().is_empty());
+    }
+}
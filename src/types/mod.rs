@@ -1,18 +1,194 @@
-use chrono::NaiveDate;
+use chrono::{Datelike, Days, NaiveDate, Weekday};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize)]
+use crate::clock::{Clock, SystemClock};
+use crate::diagnostics::explain::ExplainFormat;
+use crate::imaging::compare::Comparison;
+use crate::pipeline::quota::StorageQuota;
+use crate::telemetry::phases::PhaseTiming;
+
+/// An invocation's options; everything but the date mirrors a CLI option or config setting
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct LambdaInput {
     pub date: Option<String>,
+    /// First date of a range to download one after the other, instead of `date`
+    pub from: Option<String>,
+    /// Last date of the range, inclusive; defaults to today
+    pub to: Option<String>,
+    /// Download and save without uploading anywhere
+    pub no_upload: bool,
+    /// Names of the storage targets to upload to, e.g. `["drive"]`; all of them when unset
+    pub storage: Option<Vec<String>>,
+    /// Pages to scan for the crossword, such as `"1-6"`
+    pub page_range: Option<String>,
+    /// A `[layouts.<name>]` layout to locate the crossword with instead of `[target]`
+    pub layout: Option<String>,
+    /// Add how every scanned area scored to the output, as `"table"` or `"json"`
+    pub explain: Option<ExplainFormat>,
+    /// Earlier invocations for this date that found the crossword not published yet, as
+    /// passed back from their output's `retry`
+    pub attempt: u32,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct LambdaOutput {
     pub message: String,
+    /// The date the run was for; unset for a range, whose dates are listed in `dates`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+    /// Empty when the run stopped before saving anything
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub filename: String,
+    /// Page of the edition the crossword was found on, unless its URL was given by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// The image the crossword was downloaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    /// The ID each storage target gave the upload, such as the Drive file ID, by target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub uploads: BTreeMap<String, String>,
+    /// False when the run ran out of time and stopped early; invoke again to finish
+    #[serde(default = "complete")]
+    pub complete: bool,
+    /// Set when the date had already been archived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Comparison>,
+    /// How long each phase of the run took
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<PhaseTiming>,
+    /// The candidate ranking, when the input asked for `explain`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    /// Expiring link to the crossword, in `[signed_url]` mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Whose turn it is to solve, when `[rotation]` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solver: Option<String>,
+    /// The puzzle number and setter read off the crossword, when `[ocr]` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub puzzle: Option<PuzzleInfo>,
+    /// How sure detection is that it found the crossword, from 0 to 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+    /// Set when the confidence fell below `[review]`'s threshold and the crossword went to the
+    /// review folder
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub needs_review: bool,
+    /// Space left on each storage target that reports it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub storage: Vec<StorageQuota>,
+    /// When to invoke again, because the crossword isn't published yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryHint>,
+    /// Signs of trouble in a run that still succeeded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    /// How each date went, when the input gave a range
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dates: Vec<DateOutput>,
+}
+
+/// One date of a range: its own output, or why it failed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DateOutput {
+    pub date: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<LambdaOutput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The next attempt on the `[retry]` schedule, for a scheduler such as Step Functions to wait for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryHint {
+    /// Pass as `attempt` in the next invocation's input
+    pub attempt: u32,
+    pub after_secs: u64,
+}
+
+fn complete() -> bool {
+    true
+}
+
+/// For `skip_serializing_if`, leaving flags that aren't set out of the files written
+pub fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// What the crossword's header line says about it, as far as it could be read
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleInfo {
+    /// The crossword's running number, e.g. 10234
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<u32>,
+    /// Who set it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setter: Option<String>,
+}
+
+impl PuzzleInfo {
+    pub fn is_empty(&self) -> bool {
+        self.number.is_none() && self.setter.is_none()
+    }
+}
+
+/// Something that didn't stop the run but may mean it went wrong, e.g. the wrong area
+/// downloaded; kept apart from errors so it isn't buried in the logs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// No single area matched, so two neighbouring ones were stitched together
+    Stitched,
+    /// Detection was skipped for a URL given by hand
+    ManualSource,
+    /// The image differs from the archived copy it replaced
+    ChangedImage,
+    /// The region keeps turning up away from where its profile expects it
+    ProfileDrift,
+    /// Uploads that failed on an earlier run went through on this one
+    UploadRetried,
+    /// A feature or the report couldn't be produced
+    Skipped,
+    /// Notion or Airtable couldn't be updated
+    LogFailed,
+    /// The message announcing the crossword couldn't be sent
+    AnnounceFailed,
+    /// The crossword couldn't be emailed to the `[email]` recipients
+    EmailFailed,
+    /// The image is far from the region's usual size
+    UnusualDimensions,
+    /// Detection scored below `[review]`'s threshold, so the crossword went to the review folder
+    NeedsReview,
+    /// A storage target is past `[quota]`'s warn_percent
+    StorageLow,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Rect {
     pub x1: i32,
     pub y1: i32,
@@ -20,9 +196,168 @@ pub struct Rect {
     pub y2: i32,
 }
 
+/// Where the crossword is expected on the page map and how far each edge may drift
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetProfile {
+    pub expected: Rect,
+    pub tolerance: Rect,
+}
+
+impl Default for TargetProfile {
+    fn default() -> Self {
+        Self {
+            expected: Rect {
+                x1: 0,
+                y1: 1625,
+                x2: 1000,
+                y2: 2775,
+            },
+            tolerance: Rect {
+                x1: 5,
+                y1: 50,
+                x2: 10,
+                y2: 50,
+            },
+        }
+    }
+}
+
+/// A URL found by hand to download instead of detecting the crossword on the page map
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManualSource {
+    /// An e-paper article page, whose image is downloaded as usual
+    Article(String),
+    /// The image itself
+    Image(String),
+}
+
+/// Kinds of pages the site shows instead of the requested content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interstitial {
+    Maintenance,
+    Captcha,
+    LoginWall,
+}
+
+impl std::fmt::Display for Interstitial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            Interstitial::Maintenance => "a maintenance notice",
+            Interstitial::Captcha => "a CAPTCHA challenge",
+            Interstitial::LoginWall => "a login wall",
+        };
+        f.write_str(description)
+    }
+}
+
+/// First edition available in the e-paper archive; earlier dates are unlikely to exist
+pub fn archive_start() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2019, 1, 1).unwrap()
+}
+
+/// Checks that a date can plausibly have a crossword, returning a warning for suspicious dates.
+///
+/// Future dates are rejected unless `allow_future` is set, since the site has nothing to
+/// serve for them and the page scan would only end in "crossword not found".
+pub fn validate_date(date: NaiveDate, today: NaiveDate, allow_future: bool) -> Result<Option<String>, String> {
+    if date > today && !allow_future {
+        return Err(format!(
+            "{} is in the future (today is {}); pass --allow-future to try anyway",
+            date, today
+        ));
+    }
+
+    if date < archive_start() {
+        return Ok(Some(format!(
+            "{} is before the e-paper archive starts ({}); the crossword is probably not available",
+            date,
+            archive_start()
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Accepts absolute http(s) URLs, as pasted from the browser
+pub fn parse_url(s: &str) -> Result<String, String> {
+    match reqwest::Url::parse(s) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(s.to_string()),
+        Ok(url) => Err(format!("Unsupported URL scheme '{}'; use an http(s) URL", url.scheme())),
+        Err(e) => Err(format!("Invalid URL: {}", e)),
+    }
+}
+
+/// Formats accepted besides ISO, all day first as the newspaper prints its dates
+const DAY_FIRST_FORMATS: &[&str] = &["%d-%m-%Y", "%d/%m/%Y", "%d.%m.%Y", "%d %b %Y", "%d %B %Y"];
+
+/// Parses `2024-03-20`, or the same date written day first: `20-03-2024`, `20/03/2024`,
+/// `20.03.2024`, `20 Mar 2024` or `20 March 2024`. Month-first dates are never tried, so
+/// `03/04/2024` is always the 3rd of April. Relative dates count from today on the local clock;
+/// see [`parse_date_on`].
 pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
-    NaiveDate::parse_from_str(s, "%Y-%m-%d")
-        .map_err(|e| format!("Invalid date format. Please use YYYY-MM-DD: {}", e))
+    parse_date_on(s, SystemClock.today())
+}
+
+/// Like [`parse_date`], also taking a date relative to `today`: `today`, `yesterday`,
+/// `last-sunday` (or any other day of the week; the latest one before today) and `-N` for
+/// N days ago
+pub fn parse_date_on(s: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let s = s.trim();
+    if let Some(date) = parse_relative(&s.to_ascii_lowercase(), today) {
+        return date;
+    }
+    // A two-digit year could be any century, so it must be written in full
+    let is_year = |year: &str| year.len() == 4 && year.bytes().all(|b| b.is_ascii_digit());
+    if s.split('-').next().is_some_and(is_year) {
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(date);
+        }
+    }
+    let split = s.len().saturating_sub(4);
+    let (rest, year) = if s.is_char_boundary(split) { s.split_at(split) } else { (s, "") };
+    if is_year(year) && !rest.ends_with(|c: char| c.is_ascii_digit()) {
+        if let Some(date) = DAY_FIRST_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(s, format).ok())
+        {
+            return Ok(date);
+        }
+    }
+    Err(format!(
+        "Invalid date format '{}'. Please use YYYY-MM-DD, or day first like 20-03-2024, 20/03/2024 or 20 Mar 2024",
+        s
+    ))
+}
+
+fn parse_relative(s: &str, today: NaiveDate) -> Option<Result<NaiveDate, String>> {
+    let days_ago = |days: u64| {
+        today
+            .checked_sub_days(Days::new(days))
+            .ok_or_else(|| format!("'{}' is too far back", s))
+    };
+    match s {
+        "today" => Some(Ok(today)),
+        "yesterday" => Some(days_ago(1)),
+        _ => {
+            if let Some(weekday) = s.strip_prefix("last-") {
+                let weekday: Weekday = weekday.parse().ok()?;
+                let back = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday() - 1) % 7 + 1;
+                return Some(days_ago(u64::from(back)));
+            }
+            let days = s.strip_prefix('-')?;
+            if days.is_empty() || !days.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            Some(days.parse().map_err(|_| format!("'{}' is too far back", s)).and_then(days_ago))
+        }
+    }
+}
+
+/// Parses a `YYYY-MM` month into its first day
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d")
+        .map_err(|e| format!("Invalid month format. Please use YYYY-MM: {}", e))
 }
 
 #[cfg(test)]
@@ -68,6 +403,37 @@ mod tests {
         assert_ne!(rect1, rect3);
     }
 
+    #[test]
+    fn test_validate_date_today_and_past() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        assert_eq!(validate_date(today, today, false), Ok(None));
+        assert_eq!(validate_date(today.pred_opt().unwrap(), today, false), Ok(None));
+    }
+
+    #[test]
+    fn test_validate_date_rejects_future() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let tomorrow = today.succ_opt().unwrap();
+        let error = validate_date(tomorrow, today, false).unwrap_err();
+        assert!(error.contains("2024-03-21 is in the future"));
+        assert!(error.contains("--allow-future"));
+    }
+
+    #[test]
+    fn test_validate_date_allows_future_with_override() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let tomorrow = today.succ_opt().unwrap();
+        assert_eq!(validate_date(tomorrow, today, true), Ok(None));
+    }
+
+    #[test]
+    fn test_validate_date_warns_before_archive() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let old = NaiveDate::from_ymd_opt(2010, 6, 1).unwrap();
+        let warning = validate_date(old, today, false).unwrap().unwrap();
+        assert!(warning.contains("before the e-paper archive starts"));
+    }
+
     #[test]
     fn test_parse_date_valid() {
         let date_str = "2024-03-20";
@@ -93,4 +459,58 @@ mod tests {
             assert!(result.is_err());
         }
     }
+
+    #[test]
+    fn test_parse_date_day_first() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        for input in ["20-03-2024", "20/03/2024", "20.03.2024", "20 Mar 2024", "20 march 2024", "5/4/2024"] {
+            let expected = if input == "5/4/2024" { NaiveDate::from_ymd_opt(2024, 4, 5).unwrap() } else { date };
+            assert_eq!(parse_date(input), Ok(expected), "{}", input);
+        }
+
+        // Month first, or a year that could be any century
+        for input in ["03/20/2024", "20-03-24", "20/03/20245", "2024/03/20", "20 März"] {
+            assert!(parse_date(input).unwrap_err().contains("day first"), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_relative_date() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        for (input, expected) in [
+            ("today", date(20)),
+            ("Yesterday", date(19)),
+            ("-0", date(20)),
+            ("-7", date(13)),
+            ("last-sunday", date(17)),
+            ("last-wed", date(13)),
+            ("last-thursday", date(14)),
+        ] {
+            assert_eq!(parse_date_on(input, today), Ok(expected), "{}", input);
+        }
+
+        // Never today itself, even on a Sunday
+        assert_eq!(parse_date_on("last-sunday", date(17)), Ok(date(10)));
+        for input in ["last-someday", "-", "-1x", "+1", "tomorrow"] {
+            assert!(parse_date_on(input, today).unwrap_err().contains("day first"), "{}", input);
+        }
+        assert!(parse_date_on("-99999999999", today).unwrap_err().contains("too far back"));
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let url = "https://www.ehitavada.com/article.php?mid=Mpage_2024-03-20_2b";
+        assert_eq!(parse_url(url), Ok(url.to_string()));
+        assert!(parse_url("ftp://example.com/crossword.jpg").unwrap_err().contains("Unsupported URL scheme"));
+        assert!(parse_url("article.php?mid=2b").unwrap_err().contains("Invalid URL"));
+    }
+
+    #[test]
+    fn test_parse_month() {
+        assert_eq!(parse_month("2024-03"), Ok(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()));
+        assert!(parse_month("2024-13").is_err());
+        assert!(parse_month("2024-03-20").is_err());
+    }
 } 
\ No newline at end of file
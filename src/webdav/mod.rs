@@ -0,0 +1,232 @@
+//! Uploading to any WebDAV server, such as Nextcloud, ownCloud or a NAS, for households that
+//! keep their files there. The server signs in with a username and password; the password
+//! comes from `WEBDAV_PASSWORD` or an SSM parameter, and can be an app password.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tokio::sync::OnceCell;
+
+use crate::config::Config;
+use crate::console;
+use crate::credentials::CredentialSource;
+use crate::http::backoff::{self, BackoffConfig};
+use crate::pipeline::service::{is_status, is_transient, ServiceError};
+use crate::pipeline::{Artifact, RemoteCopy, Sink, DRY_RUN_ID};
+
+/// Environment variable holding the password, read before `password_parameter`
+pub const PASSWORD_ENV: &str = "WEBDAV_PASSWORD";
+
+/// The `[webdav]` section of the config
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebDavConfig {
+    /// URL of the folder the crosswords are saved in, e.g. Nextcloud's
+    /// `https://cloud.example.com/remote.php/dav/files/<user>/Crosswords`
+    pub url: String,
+    pub username: String,
+    /// SSM parameter holding the password, when `WEBDAV_PASSWORD` isn't set
+    pub password_parameter: Option<String>,
+}
+
+impl WebDavConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match Url::parse(&self.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.host_str().is_some() => {}
+            _ => problems.push(format!("[webdav] url '{}' must be an http(s) URL of a folder", self.url)),
+        }
+        if self.username.trim().is_empty() {
+            problems.push("[webdav] needs a username".to_string());
+        }
+        if self.password_parameter.is_none() && std::env::var(PASSWORD_ENV).is_err() {
+            problems.push(format!("[webdav] needs a password_parameter or {}", PASSWORD_ENV));
+        }
+        problems
+    }
+}
+
+/// Uploads pipeline artifacts to the configured WebDAV folder
+pub struct WebDavSink {
+    config: WebDavConfig,
+    password: OnceCell<String>,
+    client: reqwest::Client,
+    backoff: BackoffConfig,
+    dry_run: bool,
+}
+
+impl WebDavSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let webdav = config.webdav.clone().context("[webdav] is not configured")?;
+        Ok(Self {
+            config: webdav,
+            password: OnceCell::new(),
+            client: reqwest::Client::new(),
+            backoff: config.backoff.clone(),
+            dry_run: false,
+        })
+    }
+
+    /// Prints what would be uploaded instead of writing to the server
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The configured folder URL with `segments` appended, each escaped
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url =
+            Url::parse(&self.config.url).with_context(|| format!("Invalid [webdav] url '{}'", self.config.url))?;
+        {
+            let mut path = url.path_segments_mut().map_err(|()| anyhow::anyhow!("[webdav] url can't take a path"))?;
+            path.pop_if_empty().extend(segments);
+        }
+        Ok(url)
+    }
+
+    /// URL of the artifact's folder, if it goes in a subfolder, and of the file
+    fn urls(&self, artifact: &Artifact) -> Result<(Option<Url>, Url)> {
+        let name = Path::new(&artifact.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Invalid filename")?;
+        match &artifact.folder {
+            Some(folder) => Ok((Some(self.url(&[folder])?), self.url(&[folder, name])?)),
+            None => Ok((None, self.url(&[name])?)),
+        }
+    }
+
+    /// The password, fetched once per run
+    async fn password(&self) -> Result<&str> {
+        let password = self
+            .password
+            .get_or_try_init(|| async {
+                if let Ok(password) = std::env::var(PASSWORD_ENV) {
+                    return anyhow::Ok(password);
+                }
+                let parameter = self
+                    .config
+                    .password_parameter
+                    .clone()
+                    .with_context(|| format!("{} is not set and [webdav] has no password_parameter", PASSWORD_ENV))?;
+                let password = CredentialSource::Ssm { parameter }.provider().fetch().await?;
+                Ok(password.trim().to_string())
+            })
+            .await?;
+        Ok(password)
+    }
+
+    async fn send(&self, method: Method, url: &Url, body: Option<Vec<u8>>) -> Result<StatusCode> {
+        let mut request = self
+            .client
+            .request(method.clone(), url.clone())
+            .basic_auth(&self.config.username, Some(self.password().await?));
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/octet-stream").body(body);
+        }
+        let status = request.send().await?.status();
+        match status.is_success() {
+            true => Ok(status),
+            false => Err(ServiceError {
+                service: "The WebDAV server",
+                status: status.as_u16(),
+                message: format!("{} {}", method, url),
+            }
+            .into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for WebDavSink {
+    fn name(&self) -> &str {
+        "webdav"
+    }
+
+    async fn upload(&self, artifact: &Artifact) -> Result<String> {
+        let (folder, url) = self.urls(artifact)?;
+        if self.dry_run {
+            console::progress!("Dry run: would upload {} to {}", artifact.path, url);
+            return Ok(DRY_RUN_ID.to_string());
+        }
+
+        let body = fs::read(&artifact.path).with_context(|| format!("Failed to read {}", artifact.path))?;
+        backoff::retry(&self.backoff, "WebDAV upload", is_transient, || async {
+            // A PUT doesn't create missing folders; MKCOL on one that exists answers 405
+            if let Some(folder) = &folder {
+                match self.send(Method::from_bytes(b"MKCOL")?, folder, None).await {
+                    Err(e) if !is_status(&e, 405) => return Err(e),
+                    _ => {}
+                }
+            }
+            // PUT replaces a file of the same name, so a run done again doesn't add a copy
+            self.send(Method::PUT, &url, Some(body.clone())).await
+        })
+        .await
+        .with_context(|| format!("Failed to upload to {}", url))?;
+        console::progress!("File uploaded to {}", url);
+        Ok(url.to_string())
+    }
+
+    async fn inspect(&self, id: &str) -> Result<RemoteCopy> {
+        if id == DRY_RUN_ID {
+            return Ok(RemoteCopy::Missing);
+        }
+        let url = Url::parse(id).with_context(|| format!("'{}' is not a WebDAV URL", id))?;
+        // WebDAV has no standard checksum property, so only presence is checked
+        match self.send(Method::HEAD, &url, None).await {
+            Ok(_) => Ok(RemoteCopy::Present { sha256: None }),
+            Err(e) if is_status(&e, 404) => Ok(RemoteCopy::Missing),
+            Err(e) => Err(e).with_context(|| format!("Failed to look up {}", id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::service;
+
+    fn webdav(url: &str) -> WebDavConfig {
+        WebDavConfig {
+            url: url.to_string(),
+            username: "asha".to_string(),
+            password_parameter: Some("/hitavada-crossword/webdav-password".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(webdav("https://cloud.example.com/remote.php/dav/files/asha/Crosswords").validate().is_empty());
+        assert_eq!(webdav("cloud.example.com/Crosswords").validate().len(), 1);
+        let anonymous = WebDavConfig {
+            username: String::new(),
+            ..webdav("http://nas.local:5005/Crosswords")
+        };
+        assert_eq!(anonymous.validate().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_urls() {
+        for folder in ["https://cloud.example.com/dav/Family Files", "https://cloud.example.com/dav/Family Files/"] {
+            let config = Config {
+                webdav: Some(webdav(folder)),
+                ..Config::default()
+            };
+            let sink = WebDavSink::new(&config).unwrap().dry_run(true);
+            let artifact = service::test_artifact(None);
+            assert_eq!(sink.upload(&artifact).await.unwrap(), DRY_RUN_ID);
+            let (none, url) = sink.urls(&artifact).unwrap();
+            assert!(none.is_none());
+            assert_eq!(url.as_str(), "https://cloud.example.com/dav/Family%20Files/crossword_2024-03-20.jpg");
+
+            let (folder, url) = sink.urls(&service::test_artifact(Some("originals"))).unwrap();
+            assert_eq!(folder.unwrap().as_str(), "https://cloud.example.com/dav/Family%20Files/originals");
+            assert!(url.as_str().ends_with("/Family%20Files/originals/crossword_2024-03-20.jpg"));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Runs the command line as a user would, against a recorded edition
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dry_run(args: &[&str]) -> Output {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_slice_2024-03-20.json");
+    Command::new(env!("CARGO_BIN_EXE_hitavada-crossword-downloader"))
+        .current_dir(dir.path())
+        .env_remove("AWS_LAMBDA_RUNTIME_API")
+        .env("OUTPUT_DIR", dir.path())
+        .env("GOOGLE_DRIVE_FOLDER_ID", "1AbCdEfGhIjKlMnOpQrStUvWxYz012345")
+        .args(["--dry-run", "--date", "2024-03-20", "--replay-http"])
+        .arg(&fixture)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_explain_json_has_stdout_to_itself() {
+    for quiet in [false, true] {
+        let args = match quiet {
+            true => vec!["--explain", "json", "-q"],
+            false => vec!["--explain", "json"],
+        };
+        let output = dry_run(&args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let rows: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(rows.as_array().is_some_and(|rows| !rows.is_empty()));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Crossword found on page 2"));
+    }
+
+    // Alongside the run's own JSON, the report moves to standard error
+    let output = dry_run(&["--explain", "json", "--json"]);
+    let resolved: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(resolved["page"], 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"score\""));
+}